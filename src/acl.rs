@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Write access control for the namespace. An ACL record at `/acl/<id>`
+//! names a principal key and the op kinds it may perform on the keys that
+//! match each of its patterns. Enforcement is an optional strict mode: the
+//! records in the final state of a verified log are used to check every
+//! entry again and an entry is rejected if an op in it isn't allowed for any
+//! principal that signed it. A log without ACL records allows everything so
+//! the controller needs a record for the keys it changes too.
+use crate::{
+    error::LogError,
+    pipeline::{Pipeline, Stage, Verification},
+    Entry, Error, Key, KeyPattern, Kvp, Log, Op, OpId, Value, WaccEngine,
+};
+use multikey::Multikey;
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+
+/// the branch the ACL records are stored under
+pub const ACL: &str = "/acl/";
+
+/// An AclRule allows the op kinds on the keys matching the pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclRule {
+    /// the keys the rule applies to
+    pub pattern: KeyPattern,
+    /// the kinds of ops allowed
+    pub ops: Vec<OpId>,
+}
+
+impl AclRule {
+    /// true if the rule allows the op
+    pub fn allows(&self, op: &Op) -> bool {
+        self.ops.contains(&OpId::from(op)) && self.pattern.matches(op.path_ref())
+    }
+}
+
+/// An AclRecord gives the rules for one principal
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclRecord {
+    /// the public key of the principal
+    pub principal: Multikey,
+    /// the rules, any one of them can allow an op
+    pub rules: Vec<AclRule>,
+}
+
+impl AclRecord {
+    /// create a record for the principal that doesn't allow anything yet
+    pub fn new(principal: &Multikey) -> Self {
+        Self {
+            principal: principal.clone(),
+            rules: Vec::default(),
+        }
+    }
+
+    /// allow the op kinds on the keys matching the pattern
+    pub fn with_rule(mut self, pattern: &KeyPattern, ops: &[OpId]) -> Self {
+        self.rules.push(AclRule {
+            pattern: pattern.clone(),
+            ops: ops.to_vec(),
+        });
+        self
+    }
+
+    /// true if the record allows the op
+    pub fn allows(&self, op: &Op) -> bool {
+        self.rules.iter().any(|r| r.allows(op))
+    }
+
+    /// get the op that stores the record under the id
+    pub fn to_op<S: AsRef<str>>(&self, id: S) -> Result<Op, Error> {
+        let mut key = Key::try_from(ACL)?;
+        key.push(format!("/{}", Key::escape_segment(id)))?;
+        Ok(Op::Update(key, Value::Data(self.clone().into())))
+    }
+}
+
+impl From<AclRecord> for Vec<u8> {
+    fn from(val: AclRecord) -> Self {
+        let mut v = Vec::default();
+        // add in the principal
+        v.append(&mut val.principal.into());
+        // add in the rules
+        v.append(&mut Varuint(val.rules.len()).into());
+        for rule in val.rules {
+            v.append(&mut rule.pattern.as_key().clone().into());
+            v.append(&mut Varuint(rule.ops.len()).into());
+            rule.ops.into_iter().for_each(|op| v.append(&mut op.into()));
+        }
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for AclRecord {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (record, _) = Self::try_decode_from(bytes)?;
+        Ok(record)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for AclRecord {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        let (principal, ptr) =
+            Multikey::try_decode_from(bytes).map_err(|e| LogError::InvalidAcl(e.to_string()))?;
+        let (num_rules, mut ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let mut rules = Vec::with_capacity((*num_rules).min(ptr.len()));
+        for _ in 0..*num_rules {
+            let (pattern, p) = Key::try_decode_from(ptr)?;
+            let (num_ops, mut p) = Varuint::<usize>::try_decode_from(p)?;
+            let mut ops = Vec::with_capacity((*num_ops).min(p.len()));
+            for _ in 0..*num_ops {
+                let (op, q) = OpId::try_decode_from(p)?;
+                ops.push(op);
+                p = q;
+            }
+            rules.push(AclRule {
+                pattern: pattern.into(),
+                ops,
+            });
+            ptr = p;
+        }
+        Ok((Self { principal, rules }, ptr))
+    }
+}
+
+/// The ACL records found in a kvp
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Acl {
+    records: Vec<(Key, AclRecord)>,
+}
+
+impl Acl {
+    /// read the ACL records under [`ACL`] in the kvp
+    pub fn from_kvp(kvp: &Kvp) -> Result<Self, Error> {
+        let branch = Key::try_from(ACL)?;
+        let records = kvp
+            .iter()
+            .filter(|(key, _)| branch.parent_of(key))
+            .map(|(key, value)| match value {
+                Value::Data(b) => Ok((key.clone(), AclRecord::try_from(b.as_slice())?)),
+                _ => Err(LogError::InvalidAcl(key.to_string()).into()),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self { records })
+    }
+
+    /// get the records with the keys they are stored at
+    pub fn records(&self) -> impl Iterator<Item = (&Key, &AclRecord)> {
+        self.records.iter().map(|(k, r)| (k, r))
+    }
+
+    /// true if there are no records, which allows everything
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// check that every op in the entry is allowed for a principal that
+    /// signed it
+    pub fn check_entry(&self, entry: &Entry) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let signers: Vec<&AclRecord> = self
+            .records
+            .iter()
+            .map(|(_, r)| r)
+            .filter(|r| entry.signed_by(&r.principal))
+            .collect();
+        for op in entry.ops() {
+            if !signers.iter().any(|r| r.allows(op)) {
+                return Err(LogError::AclDenied(op.path(), OpId::from(op).as_str().to_string()).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rejects entries with ops the ACL doesn't allow
+#[derive(Clone, Debug, Default)]
+pub struct AclCheck {
+    acl: Acl,
+}
+
+impl AclCheck {
+    /// create the stage enforcing the ACL
+    pub fn new(acl: Acl) -> Self {
+        Self { acl }
+    }
+}
+
+impl Stage for AclCheck {
+    fn name(&self) -> &str {
+        "acl"
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        self.acl.check_entry(v.entry)
+    }
+}
+
+impl Log {
+    /// verify all entries in the log in the strict ACL mode. the log is
+    /// verified once to get the ACL records in its final state and then
+    /// every entry is verified again against them.
+    pub fn verify_with_acl(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_, Error> {
+        let acl = Acl::from_kvp(&self.verify_all()?.kvp)?;
+        Ok(self.verify_with_pipeline(
+            Pipeline::default().insert_before("apply", AclCheck::new(acl)),
+            &WaccEngine,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{Apply, Structure};
+    use crate::{entry, log, Script};
+    use multicid::Vlad;
+    use multikey::{EncodedMultikey, Views};
+
+    fn key(s: &str) -> Multikey {
+        EncodedMultikey::try_from(s).unwrap().to_inner()
+    }
+
+    fn sign(key: &Multikey) -> impl FnMut(&mut Entry) -> Result<Vec<u8>, Error> + '_ {
+        move |e| {
+            let ev: Vec<u8> = e.clone().into();
+            Ok(key.sign_view().unwrap().sign(&ev, false, None).unwrap().into())
+        }
+    }
+
+    #[test]
+    fn test_acl() {
+        let owner = key("fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0");
+        let writer = key("fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8");
+
+        let everything = AclRecord::new(&owner)
+            .with_rule(&KeyPattern::default(), &[OpId::Noop, OpId::Update, OpId::Delete]);
+        let update_docs = AclRecord::new(&writer)
+            .with_rule(&KeyPattern::try_from("/docs/").unwrap(), &[OpId::Update]);
+        let bytes: Vec<u8> = update_docs.clone().into();
+        assert_eq!(update_docs, AclRecord::try_from(bytes.as_slice()).unwrap());
+
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&everything.to_op("owner").unwrap())
+            .add_op(&update_docs.to_op("writer").unwrap())
+            .try_build(sign(&owner))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/docs/a".try_into().unwrap(), Value::Str("a".to_string())))
+            .try_build(sign(&writer))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Delete("/docs/a".try_into().unwrap()))
+            .try_build(sign(&writer))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3)
+            .try_build()
+            .unwrap();
+
+        let mut kvp = Kvp::default();
+        kvp.apply_entry_ops(&e1).unwrap();
+        let acl = Acl::from_kvp(&kvp).unwrap();
+        assert_eq!(2, acl.records().count());
+        acl.check_entry(&e1).unwrap();
+        acl.check_entry(&e2).unwrap();
+        // the writer can't delete
+        assert!(acl.check_entry(&e3).is_err());
+
+        let p = Pipeline::new()
+            .with_stage(Structure)
+            .with_stage(AclCheck::new(acl))
+            .with_stage(Apply);
+        let results: Vec<_> = log.verify_with_pipeline(p, &WaccEngine).collect();
+        assert!(results[1].is_ok());
+        assert!(results.last().unwrap().is_err());
+    }
+}
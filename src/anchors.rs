@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{
+    error::LogError,
+    pipeline::{Pipeline, Stage, Verification},
+    Entry, Error, Kvp, Log, Op, TimeAnchor, WaccEngine,
+};
+use multicid::Cid;
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+
+/// the tag that prefixes the anchor payloads committed to chains
+pub const PAYLOAD_TAG: &[u8] = b"plog";
+
+/// An AnchorProvider commits the head cid of a log to an external chain or
+/// notary and later verifies that the commitment was included. The receipt
+/// is a [`TimeAnchor`] attesting to the head so it can be recorded in the log
+/// under the reserved "/time/" branch.
+pub trait AnchorProvider {
+    /// the name of the source recorded in the anchors, e.g. "btc"
+    fn source(&self) -> &str;
+
+    /// commit the cid and return the anchor receipt
+    fn anchor(&self, head: &Cid) -> Result<TimeAnchor, Error>;
+
+    /// verify that the anchor was included by the external source
+    fn verify(&self, anchor: &TimeAnchor) -> Result<(), Error>;
+}
+
+/// get the anchoring digest of the cid, the sha2-256 multihash of the
+/// encoded cid. this is what timestamping services such as OpenTimestamps
+/// are asked to attest to.
+pub fn digest(cid: &Cid) -> Result<Multihash, Error> {
+    let bytes: Vec<u8> = cid.clone().into();
+    Ok(mh::Builder::new_from_bytes(Codec::Sha2256, &bytes)?.try_build()?)
+}
+
+/// get the payload committed to a chain for the given cid. it is the payload
+/// tag followed by the anchoring digest of the cid so it fits in a bitcoin
+/// OP_RETURN output or in ethereum calldata.
+pub fn payload(cid: &Cid) -> Result<Vec<u8>, Error> {
+    let mut v = PAYLOAD_TAG.to_vec();
+    v.append(&mut digest(cid)?.into());
+    Ok(v)
+}
+
+/// A ChainClient submits payloads to a chain and fetches them back. This is
+/// the part of a chain anchor that talks to a node or an indexer.
+pub trait ChainClient {
+    /// submit the payload in a transaction and return the block time in
+    /// seconds since the unix epoch along with the transaction proof, such as
+    /// the transaction id and its merkle inclusion proof
+    fn submit(&self, payload: &[u8]) -> Result<(u64, Vec<u8>), Error>;
+
+    /// get the payload of the confirmed transaction in the proof
+    fn fetch(&self, proof: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// An AnchorProvider that commits to a blockchain through a ChainClient
+pub struct ChainAnchor<C: ChainClient> {
+    source: String,
+    client: C,
+}
+
+impl<C: ChainClient> ChainAnchor<C> {
+    /// create a chain anchor with the given source name
+    pub fn new<S: AsRef<str>>(source: S, client: C) -> Self {
+        Self {
+            source: source.as_ref().to_string(),
+            client,
+        }
+    }
+
+    /// create an anchor that commits to bitcoin in an OP_RETURN output
+    pub fn bitcoin(client: C) -> Self {
+        Self::new("btc", client)
+    }
+
+    /// create an anchor that commits to ethereum in transaction calldata
+    pub fn ethereum(client: C) -> Self {
+        Self::new("eth", client)
+    }
+}
+
+impl<C: ChainClient> AnchorProvider for ChainAnchor<C> {
+    fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn anchor(&self, head: &Cid) -> Result<TimeAnchor, Error> {
+        let (timestamp, proof) = self.client.submit(&payload(head)?)?;
+        Ok(TimeAnchor {
+            source: self.source.clone(),
+            timestamp,
+            attests: head.clone(),
+            proof,
+        })
+    }
+
+    fn verify(&self, anchor: &TimeAnchor) -> Result<(), Error> {
+        if anchor.source != self.source {
+            return Err(LogError::AnchorFailed(format!("unexpected source {}", anchor.source)).into());
+        }
+        if self.client.fetch(&anchor.proof)? != payload(&anchor.attests)? {
+            return Err(LogError::AnchorMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+/// A verification stage that checks the anchor receipts recorded under the
+/// reserved "/time/" branch by each entry with the provider for their source.
+/// Entries recording a receipt from an unknown source fail verification.
+#[derive(Default)]
+pub struct AnchorCheck {
+    providers: Vec<Box<dyn AnchorProvider>>,
+}
+
+impl AnchorCheck {
+    /// create a stage with no providers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add the provider that verifies the receipts from its source
+    pub fn with_provider<P: AnchorProvider + 'static>(mut self, provider: P) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    /// verify the anchor receipts recorded by the entry
+    pub fn check_entry(&self, entry: &Entry) -> Result<(), Error> {
+        for op in entry.ops() {
+            let anchor = match TimeAnchor::from_op(op) {
+                Some(anchor) => anchor?,
+                None => continue,
+            };
+            let provider = self
+                .providers
+                .iter()
+                .find(|p| p.source() == anchor.source)
+                .ok_or_else(|| LogError::AnchorFailed(format!("unknown source {}", anchor.source)))?;
+            provider.verify(&anchor)?;
+        }
+        Ok(())
+    }
+}
+
+impl Stage for AnchorCheck {
+    fn name(&self) -> &str {
+        "anchors"
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        self.check_entry(v.entry)
+    }
+}
+
+impl Log {
+    /// commit the head of the log with the given provider
+    pub fn anchor_head(&self, provider: &dyn AnchorProvider) -> Result<TimeAnchor, Error> {
+        provider.anchor(&self.head)
+    }
+
+    /// commit the head of the log with the given provider and get the op
+    /// that records the receipt under the reserved "/time/" branch, named
+    /// after the source, to add to the next entry
+    pub fn anchor_head_op(&self, provider: &dyn AnchorProvider) -> Result<Op, Error> {
+        self.anchor_head(provider)?.to_op(provider.source())
+    }
+
+    /// verify all entries in the log, also checking the anchor receipts they
+    /// record before their mutations are applied
+    pub fn verify_with_anchors(
+        &self,
+        check: AnchorCheck,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_ {
+        self.verify_with_pipeline(Pipeline::default().insert_before("apply", check), &WaccEngine)
+    }
+
+    /// verify that the anchor attests to an entry in this log and that the
+    /// provider included it
+    pub fn verify_anchor(
+        &self,
+        provider: &dyn AnchorProvider,
+        anchor: &TimeAnchor,
+    ) -> Result<(), Error> {
+        if !self.entries.contains_key(&anchor.attests) {
+            return Err(LogError::EntryNotFound(anchor.attests.clone()).into());
+        }
+        provider.verify(anchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, pipeline::{Apply, Structure}, Script};
+    use multicid::Vlad;
+    use std::cell::RefCell;
+
+    // a chain that keeps the transactions in memory, the proof is the index
+    #[derive(Default)]
+    struct MemoryChain {
+        txs: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl ChainClient for MemoryChain {
+        fn submit(&self, payload: &[u8]) -> Result<(u64, Vec<u8>), Error> {
+            let mut txs = self.txs.borrow_mut();
+            txs.push(payload.to_vec());
+            Ok((1_700_000_000, vec![txs.len() as u8 - 1]))
+        }
+
+        fn fetch(&self, proof: &[u8]) -> Result<Vec<u8>, Error> {
+            self.txs
+                .borrow()
+                .get(proof[0] as usize)
+                .cloned()
+                .ok_or(LogError::AnchorFailed("no such transaction".to_string()).into())
+        }
+    }
+
+    #[test]
+    fn test_chain_anchor() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        let btc = ChainAnchor::bitcoin(MemoryChain::default());
+        let anchor = log.anchor_head(&btc).unwrap();
+        assert_eq!("btc", anchor.source);
+        assert_eq!(e2.cid(), anchor.attests);
+        assert!(payload(&e2.cid()).unwrap().len() <= 80);
+        log.verify_anchor(&btc, &anchor).unwrap();
+
+        // an anchor claiming a different entry doesn't verify
+        let mut forged = anchor.clone();
+        forged.attests = e1.cid();
+        assert!(log.verify_anchor(&btc, &forged).is_err());
+
+        // an anchor from another source doesn't verify
+        let eth = ChainAnchor::ethereum(MemoryChain::default());
+        assert!(log.verify_anchor(&eth, &anchor).is_err());
+    }
+
+    #[test]
+    fn test_anchor_check() {
+        let chain = MemoryChain::default();
+        let btc = ChainAnchor::bitcoin(MemoryChain::default());
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        // record the receipt for the head in the next entry
+        let op = log.anchor_head_op(&btc).unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&op)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+        let mut expected = PAYLOAD_TAG.to_vec();
+        expected.append(&mut digest(&e1.cid()).unwrap().into());
+        assert_eq!(expected, payload(&e1.cid()).unwrap());
+
+        let pipeline = |check: AnchorCheck| {
+            Pipeline::new()
+                .with_stage(Structure)
+                .with_stage(check)
+                .with_stage(Apply)
+        };
+        let check = AnchorCheck::new().with_provider(btc);
+        assert!(log.verify_with_pipeline(pipeline(check), &WaccEngine).all(|r| r.is_ok()));
+
+        // a chain that never saw the receipt rejects it
+        let check = AnchorCheck::new().with_provider(ChainAnchor::bitcoin(chain));
+        assert!(log.verify_with_pipeline(pipeline(check), &WaccEngine).any(|r| r.is_err()));
+
+        // receipts from unknown sources are rejected
+        assert!(AnchorCheck::new().check_entry(&e2).is_err());
+        AnchorCheck::new().check_entry(&e1).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: FSL-1.1
+//! [`Arbitrary`] implementations for fuzzing. The generated values are always
+//! well formed so they encode and decode cleanly; fuzz targets exercise the
+//! decoders by mutating the encoded bytes of generated entries and logs.
+use crate::{
+    entry, log, seal::SEAL_SCHEME, Entry, Key, Lipmaa, Log, Op, Recipient, Script, Value,
+};
+use ::arbitrary::{Arbitrary, Error, Result, Unstructured};
+use multicid::{cid, vlad, Cid, Vlad};
+use multicodec::Codec;
+use multihash::mh;
+use multikey::nonce;
+
+/// the most entries generated for a log
+pub const MAX_ARBITRARY_ENTRIES: usize = 16;
+
+// map any build error to an arbitrary error
+fn format_err<E>(_: E) -> Error {
+    Error::IncorrectFormat
+}
+
+// generate a cid over arbitrary data
+fn arbitrary_cid(u: &mut Unstructured<'_>) -> Result<Cid> {
+    let data = <&[u8]>::arbitrary(u)?;
+    let hash = mh::Builder::new_from_bytes(Codec::Sha3256, data)
+        .and_then(|b| b.try_build())
+        .map_err(format_err)?;
+    cid::Builder::new(Codec::Cidv1)
+        .with_target_codec(Codec::Raw)
+        .with_hash(&hash)
+        .try_build()
+        .map_err(format_err)
+}
+
+// generate a vlad from an arbitrary nonce and cid
+fn arbitrary_vlad(u: &mut Unstructured<'_>) -> Result<Vlad> {
+    let nonce = nonce::Builder::new_from_bytes(<&[u8]>::arbitrary(u)?)
+        .try_build()
+        .map_err(format_err)?;
+    vlad::Builder::default()
+        .with_nonce(&nonce)
+        .with_cid(&arbitrary_cid(u)?)
+        .try_build()
+        .map_err(format_err)
+}
+
+impl<'a> Arbitrary<'a> for Key {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let segments: Vec<String> = u
+            .arbitrary_iter::<String>()?
+            .filter(|s| s.as_ref().map(|s| !s.is_empty()).unwrap_or(true))
+            .collect::<Result<_>>()?;
+        Key::from_segments(&segments, bool::arbitrary(u)?).map_err(format_err)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4u8)? {
+            0 => Self::Nil,
+            1 => Self::Str(String::arbitrary(u)?),
+            2 => Self::Data(Vec::arbitrary(u)?),
+            3 => Self::PlogRef {
+                vlad: arbitrary_vlad(u)?,
+                entry_cid: arbitrary_cid(u)?,
+                key: Key::arbitrary(u)?,
+            },
+            _ => Self::Encrypted {
+                scheme: SEAL_SCHEME,
+                recipients: Vec::<(Vec<u8>, Vec<u8>, Vec<u8>)>::arbitrary(u)?
+                    .into_iter()
+                    .map(|(id, ephemeral, wrapped_key)| Recipient {
+                        id,
+                        ephemeral,
+                        wrapped_key,
+                    })
+                    .collect(),
+                ciphertext: Vec::arbitrary(u)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Op {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2u8)? {
+            0 => Self::Noop(Key::arbitrary(u)?),
+            1 => Self::Delete(Key::arbitrary(u)?),
+            _ => Self::Update(Key::arbitrary(u)?, Value::arbitrary(u)?),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Script {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2u8)? {
+            0 => Self::Bin(Key::arbitrary(u)?, Vec::arbitrary(u)?),
+            1 => Self::Code(Key::arbitrary(u)?, String::arbitrary(u)?),
+            _ => Self::Cid(Key::arbitrary(u)?, arbitrary_cid(u)?),
+        })
+    }
+}
+
+// build the entry with arbitrary contents on top of the builder
+fn arbitrary_entry(u: &mut Unstructured<'_>, builder: entry::Builder) -> Result<Entry> {
+    let mut builder = builder
+        .with_ops(&Vec::<Op>::arbitrary(u)?)
+        .with_unlock(&Script::arbitrary(u)?);
+    if bool::arbitrary(u)? {
+        builder = builder.with_locks(&Vec::<Script>::arbitrary(u)?);
+    }
+    if bool::arbitrary(u)? {
+        builder = builder.with_path_compression();
+    }
+    let proof = Vec::<u8>::arbitrary(u)?;
+    builder.try_build(|_| Ok(proof.clone())).map_err(format_err)
+}
+
+impl<'a> Arbitrary<'a> for Entry {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let builder = entry::Builder::default()
+            .with_vlad(&arbitrary_vlad(u)?)
+            .with_prev(&arbitrary_cid(u)?)
+            .with_seqno(u64::arbitrary(u)?)
+            .with_lipmaa(&arbitrary_cid(u)?);
+        arbitrary_entry(u, builder)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Log {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        // the entries are linked by their prev and lipmaa links
+        let vlad = arbitrary_vlad(u)?;
+        let mut entries =
+            vec![arbitrary_entry(u, entry::Builder::default().with_vlad(&vlad))?];
+        for seqno in 1..u.int_in_range(1..=MAX_ARBITRARY_ENTRIES)? as u64 {
+            let mut builder = entry::Builder::from(&entries[seqno as usize - 1]);
+            if seqno.is_lipmaa() {
+                builder = builder.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(arbitrary_entry(u, builder)?);
+        }
+        entries
+            .iter()
+            .fold(
+                log::Builder::new()
+                    .with_vlad(&vlad)
+                    .with_first_lock(&Script::arbitrary(u)?),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .map_err(format_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arbitrary_round_trip() {
+        for seed in 0..32u8 {
+            let data: Vec<u8> = (0..1024u32)
+                .map(|i| (i as u8).wrapping_mul(seed).wrapping_add(seed))
+                .collect();
+            let mut u = Unstructured::new(&data);
+            if let Ok(entry) = Entry::arbitrary(&mut u) {
+                let bytes: Vec<u8> = entry.clone().into();
+                assert_eq!(entry, Entry::try_from(bytes.as_slice()).unwrap());
+            }
+            let mut u = Unstructured::new(&data);
+            if let Ok(log) = Log::arbitrary(&mut u) {
+                log.verify_continuity().unwrap();
+                let bytes: Vec<u8> = log.clone().into();
+                assert_eq!(log, Log::try_from(bytes.as_slice()).unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: FSL-1.1
+//! plog inspects and manages provenance logs. Logs are read from and written
+//! to files holding either the multibase encoded log or the raw log bytes.
+//!
+//! ```text
+//! plog inspect <log>                      dump the entries and their ops
+//! plog verify <log>                       verify every entry in the log
+//! plog kvp <log>                          print the final namespace state
+//! plog append <log> <description> <key>   build, sign and append an entry
+//! plog export <log> [base58|base16|bytes|json]
+//! ```
+//!
+//! The entry description for `append` is a JSON or TOML (by file extension)
+//! file. Script files are relative to the description file and binary values
+//! are multibase encoded:
+//!
+//! ```toml
+//! [[ops]]
+//! op = "update"
+//! key = "/pubkey"
+//! data = "fba24..."
+//!
+//! [[ops]]
+//! op = "delete"
+//! key = "/ephemeral"
+//!
+//! [unlock]
+//! code = "unlock.wast"
+//! ```
+use ::serde::Deserialize;
+use multibase::Base;
+use multikey::{EncodedMultikey, Views};
+use provenance_log::{
+    entry, error::EntryError, script, EncodedLog, Key, Log, Op, OpId, Script, Value,
+};
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "usage:
+    plog inspect <log>
+    plog verify <log>
+    plog kvp <log>
+    plog append <log> <description> <key>
+    plog export <log> [base58|base16|bytes|json]";
+
+#[derive(Deserialize)]
+struct EntryDesc {
+    #[serde(default)]
+    ops: Vec<OpDesc>,
+    // the lock scripts of the head entry are kept if this is missing
+    locks: Option<Vec<ScriptDesc>>,
+    unlock: ScriptDesc,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum OpDesc {
+    Noop {
+        key: String,
+    },
+    Delete {
+        key: String,
+    },
+    Update {
+        key: String,
+        str: Option<String>,
+        data: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ScriptDesc {
+    path: Option<String>,
+    code: Option<PathBuf>,
+    bin: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["inspect", log] => inspect(&read_log(log)?),
+        ["verify", log] => verify(&read_log(log)?),
+        ["kvp", log] => kvp(&read_log(log)?),
+        ["append", log, desc, key] => append(log, desc, key),
+        ["export", log] => export(&read_log(log)?, "base58"),
+        ["export", log, format] => export(&read_log(log)?, format),
+        _ => Err(USAGE.into()),
+    }
+}
+
+// read a log from a file with the multibase encoded log or the raw bytes
+fn read_log(path: &str) -> Result<Log> {
+    let bytes = fs::read(path)?;
+    if let Ok(s) = std::str::from_utf8(&bytes) {
+        if let Ok(log) = EncodedLog::try_from(s.trim()) {
+            return Ok(log.to_inner());
+        }
+    }
+    Ok(Log::try_from(bytes.as_slice())?)
+}
+
+fn inspect(log: &Log) -> Result<()> {
+    println!("vlad: {:?}", log.vlad);
+    println!("foot: {:?}", log.foot());
+    println!("head: {:?}", log.head());
+    for entry in log.iter() {
+        println!("{:?}", entry);
+        for op in entry.ops() {
+            match op {
+                Op::Update(_, v) => {
+                    println!("\t{} {} = {}", OpId::from(op).as_str(), op.path_ref(), v)
+                }
+                _ => println!("\t{} {}", OpId::from(op).as_str(), op.path_ref()),
+            }
+        }
+        for lock in entry.locks() {
+            println!("\tlock {}", lock);
+        }
+    }
+    Ok(())
+}
+
+fn verify(log: &Log) -> Result<()> {
+    for result in log.verify() {
+        let (count, entry, _) = result?;
+        println!("#{} ok after {} checks", entry.seqno(), count);
+    }
+    Ok(())
+}
+
+fn kvp(log: &Log) -> Result<()> {
+    let mut state = None;
+    for result in log.verify() {
+        let (_, _, kvp) = result?;
+        state = Some(kvp);
+    }
+    if let Some(kvp) = state {
+        println!("{}", kvp);
+    }
+    Ok(())
+}
+
+fn append(log_path: &str, desc_path: &str, key_path: &str) -> Result<()> {
+    let mut log = read_log(log_path)?;
+    let text = fs::read_to_string(desc_path)?;
+    let desc: EntryDesc = match Path::new(desc_path).extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&text)?,
+        _ => serde_json::from_str(&text)?,
+    };
+    let dir = Path::new(desc_path).parent().unwrap_or(Path::new("."));
+    let key = EncodedMultikey::try_from(fs::read_to_string(key_path)?.trim())?.to_inner();
+
+    if !log.entries.contains_key(log.head()) {
+        return Err("the log has no head entry".into());
+    }
+    let mut builder = entry::Builder::default()
+        .with_log(&log)
+        .with_unlock(&build_script(dir, &desc.unlock)?);
+    for op in &desc.ops {
+        builder = builder.add_op(&build_op(op)?);
+    }
+    if let Some(locks) = &desc.locks {
+        let locks = locks
+            .iter()
+            .map(|s| build_script(dir, s))
+            .collect::<Result<Vec<_>>>()?;
+        builder = builder.with_locks(&locks);
+    }
+
+    // sign the entry without the proof with the key
+    let entry = builder.try_build(|e| {
+        let msg = e.canonical_bytes();
+        let ms = key
+            .sign_view()
+            .and_then(|v| v.sign(&msg, false, None))
+            .map_err(|err| EntryError::SignFailed(err.to_string()))?;
+        Ok(ms.into())
+    })?;
+    log.try_append(&entry)?;
+    fs::write(log_path, format!("{}\n", EncodedLog::new(Base::Base58Btc, log)))?;
+    println!("{:?}", entry);
+    Ok(())
+}
+
+fn export(log: &Log, format: &str) -> Result<()> {
+    match format {
+        "base58" => println!("{}", EncodedLog::new(Base::Base58Btc, log.clone())),
+        "base16" => println!("{}", EncodedLog::new(Base::Base16Lower, log.clone())),
+        "bytes" => {
+            let bytes: Vec<u8> = log.clone().into();
+            std::io::stdout().write_all(&bytes)?;
+        }
+        "json" => println!("{}", serde_json::to_string_pretty(log)?),
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn build_op(desc: &OpDesc) -> Result<Op> {
+    Ok(match desc {
+        OpDesc::Noop { key } => Op::Noop(Key::try_from(key.as_str())?),
+        OpDesc::Delete { key } => Op::Delete(Key::try_from(key.as_str())?),
+        OpDesc::Update { key, str, data } => {
+            let value = match (str, data) {
+                (Some(s), None) => Value::Str(s.clone()),
+                (None, Some(d)) => Value::Data(multibase::decode(d)?.1),
+                (None, None) => Value::Nil,
+                _ => return Err(format!("{} has both a str and a data value", key).into()),
+            };
+            Op::Update(Key::try_from(key.as_str())?, value)
+        }
+    })
+}
+
+fn build_script(dir: &Path, desc: &ScriptDesc) -> Result<Script> {
+    let builder = match (&desc.code, &desc.bin) {
+        (Some(code), None) => script::Builder::from_code_file(&dir.join(code)),
+        (None, Some(bin)) => script::Builder::from_bin_file(&dir.join(bin)),
+        _ => return Err("a script needs exactly one of code or bin".into()),
+    };
+    let path = match &desc.path {
+        Some(p) => Key::try_from(p.as_str())?,
+        None => Key::default(),
+    };
+    Ok(builder.with_path(&path).try_build()?)
+}
@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Content-addressed block storage. A [`BlockStore`] gets and puts blocks of
+//! bytes by their cid and is the one storage interface the rest of the crate
+//! plugs into: the code of scripts referenced by [`Script::Cid`] is resolved
+//! from it, checkpoints are stored in it and CAR archives are imported into
+//! it. [`MemoryBlockStore`] keeps the blocks in memory.
+use crate::{Checkpoint, Error, Key, Script, ScriptResolver};
+use multicid::{cid, Cid, Vlad};
+use multicodec::Codec;
+use multihash::mh;
+use std::collections::BTreeMap;
+
+/// the hash codec used for the cids of raw blocks
+pub const BLOCK_HASH_CODEC: Codec = Codec::Sha3512;
+
+/// get the cid of a raw block
+pub fn raw_cid(block: &[u8]) -> Result<Cid, Error> {
+    Ok(cid::Builder::new(Codec::Cidv1)
+        .with_target_codec(Codec::Raw)
+        .with_hash(&mh::Builder::new_from_bytes(BLOCK_HASH_CODEC, block)?.try_build()?)
+        .try_build()?)
+}
+
+/// A BlockStore stores blocks of bytes by their cid
+pub trait BlockStore {
+    /// get the block with the cid, None if the store doesn't have it
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, Error>;
+
+    /// put the block in the store under the cid
+    fn put(&mut self, cid: &Cid, block: &[u8]) -> Result<(), Error>;
+
+    /// check if the store has the block with the cid
+    fn has(&self, cid: &Cid) -> Result<bool, Error> {
+        Ok(self.get(cid)?.is_some())
+    }
+}
+
+/// A BlockStore that keeps the blocks in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBlockStore {
+    blocks: BTreeMap<Cid, Vec<u8>>,
+}
+
+impl MemoryBlockStore {
+    /// get the number of blocks in the store
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// check if the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// get an iterator over the cids and blocks in the store
+    pub fn iter(&self) -> impl Iterator<Item = (&Cid, &Vec<u8>)> {
+        self.blocks.iter()
+    }
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.blocks.get(cid).cloned())
+    }
+
+    fn put(&mut self, cid: &Cid, block: &[u8]) -> Result<(), Error> {
+        self.blocks.insert(cid.clone(), block.to_vec());
+        Ok(())
+    }
+
+    fn has(&self, cid: &Cid) -> Result<bool, Error> {
+        Ok(self.blocks.contains_key(cid))
+    }
+}
+
+/// put the code of the script in the store under its cid scoped to the vlad
+/// and return the cid to reference it with in a [`Script::Cid`]
+pub fn put_script(store: &mut dyn BlockStore, vlad: &Vlad, script: &Script) -> Result<Cid, Error> {
+    let cid = script.scoped_cid(vlad)?;
+    let code: &[u8] = match script {
+        Script::Bin(_, b) => b,
+        Script::Code(_, c) => c.as_bytes(),
+        Script::Cid(..) => unreachable!("scoped_cid fails for cid scripts"),
+    };
+    store.put(&cid, code)?;
+    Ok(cid)
+}
+
+/// A ScriptResolver that loads the code of scripts from a BlockStore. The
+/// code must match its cid scoped to the vlad of the log.
+pub struct BlockScriptResolver<'a> {
+    store: &'a dyn BlockStore,
+    vlad: Vlad,
+}
+
+impl<'a> BlockScriptResolver<'a> {
+    /// create a resolver for the scripts of the log with the vlad
+    pub fn new(store: &'a dyn BlockStore, vlad: &Vlad) -> Self {
+        Self {
+            store,
+            vlad: vlad.clone(),
+        }
+    }
+}
+
+impl ScriptResolver for BlockScriptResolver<'_> {
+    fn resolve(&self, cid: &Cid) -> Option<Script> {
+        let code = self.store.get(cid).ok()??;
+        // wasm binaries are binary scripts, anything else is code
+        let script = if code.starts_with(b"\0asm") {
+            Script::Bin(Key::default(), code)
+        } else {
+            match String::from_utf8(code) {
+                Ok(s) => Script::Code(Key::default(), s),
+                Err(e) => Script::Bin(Key::default(), e.into_bytes()),
+            }
+        };
+        script.check_scoped_cid(&self.vlad, cid).ok()?;
+        Some(script)
+    }
+}
+
+impl Checkpoint {
+    /// put the checkpoint in the store and return its cid
+    pub fn put_block(&self, store: &mut dyn BlockStore) -> Result<Cid, Error> {
+        let block: Vec<u8> = self.clone().into();
+        let cid = raw_cid(&block)?;
+        store.put(&cid, &block)?;
+        Ok(cid)
+    }
+
+    /// get the checkpoint with the cid from the store
+    pub fn try_from_block_store(store: &dyn BlockStore, cid: &Cid) -> Result<Self, Error> {
+        let block = store
+            .get(cid)?
+            .ok_or_else(|| Error::BlockNotFound(cid.clone()))?;
+        if raw_cid(&block)? != *cid {
+            return Err(Error::BlockMismatch(cid.clone()));
+        }
+        Self::try_from(block.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{checkpoint, entry, Kvp};
+
+    #[test]
+    fn test_memory_block_store() {
+        let mut store = MemoryBlockStore::default();
+        let cid = raw_cid(b"block").unwrap();
+        assert!(!store.has(&cid).unwrap());
+        assert_eq!(None, store.get(&cid).unwrap());
+        store.put(&cid, b"block").unwrap();
+        assert!(store.has(&cid).unwrap());
+        assert_eq!(Some(b"block".to_vec()), store.get(&cid).unwrap());
+        assert_eq!(1, store.len());
+
+        // scripts are resolved by their scoped cid
+        let vlad = Vlad::default();
+        let script = Script::Code(Key::default(), "(module)".to_string());
+        let cid = put_script(&mut store, &vlad, &script).unwrap();
+        let resolver = BlockScriptResolver::new(&store, &vlad);
+        assert_eq!(Some(script), resolver.resolve(&cid));
+        // a block that doesn't match its scoped cid isn't resolved
+        store.put(&cid, b"(module $evil)").unwrap();
+        let resolver = BlockScriptResolver::new(&store, &vlad);
+        assert_eq!(None, resolver.resolve(&cid));
+
+        // checkpoints are stored by the cid of their encoding
+        let e1 = entry::Builder::default()
+            .with_vlad(&vlad)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let cp = checkpoint::Builder::new(&e1, &Kvp::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let cid = cp.put_block(&mut store).unwrap();
+        assert!(cp == Checkpoint::try_from_block_store(&store, &cid).unwrap());
+        store.put(&cid, b"garbage").unwrap();
+        assert!(matches!(
+            Checkpoint::try_from_block_store(&store, &cid),
+            Err(Error::BlockMismatch(_))
+        ));
+    }
+}
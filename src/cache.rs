@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{Error, Key, Script, ScriptEngine, Stk};
+use multicodec::Codec;
+use multihash::mh;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+use wacc::Pairs;
+
+/// the default number of compiled scripts kept in a cache
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// A ScriptCache keeps the compiled form of recently used scripts keyed by
+/// the hash of the encoded script. When the cache is full the least recently
+/// used script is dropped.
+pub struct ScriptCache<M> {
+    capacity: usize,
+    inner: Mutex<Inner<M>>,
+}
+
+struct Inner<M> {
+    modules: HashMap<Vec<u8>, M>,
+    // the hashes in order of use, least recently used first
+    order: VecDeque<Vec<u8>>,
+}
+
+impl<M: Clone> ScriptCache<M> {
+    /// create a cache holding at most `capacity` compiled scripts
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Inner {
+                modules: HashMap::default(),
+                order: VecDeque::default(),
+            }),
+        }
+    }
+
+    /// get the compiled script from the cache or compile it with the given
+    /// closure and add it to the cache
+    pub fn get_or_try_insert<F>(&self, script: &Script, compile: F) -> Result<M, Error>
+    where
+        F: FnOnce(&Script) -> Result<M, Error>,
+    {
+        let bytes: Vec<u8> = script.clone().into();
+        let hash: Vec<u8> = mh::Builder::new_from_bytes(Codec::Sha3256, &bytes)?
+            .try_build()?
+            .into();
+
+        if let Some(m) = self.lookup(&hash) {
+            return Ok(m);
+        }
+
+        // compile without holding the lock
+        let m = compile(script)?;
+        if self.capacity > 0 {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.modules.insert(hash.clone(), m.clone()).is_none() {
+                inner.order.push_back(hash);
+                while inner.order.len() > self.capacity {
+                    if let Some(old) = inner.order.pop_front() {
+                        inner.modules.remove(&old);
+                    }
+                }
+            }
+        }
+        Ok(m)
+    }
+
+    /// the number of compiled scripts in the cache
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().modules.len()
+    }
+
+    /// true if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// drop all of the compiled scripts
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.modules.clear();
+        inner.order.clear();
+    }
+
+    fn lookup(&self, hash: &[u8]) -> Option<M> {
+        let mut inner = self.inner.lock().unwrap();
+        let m = inner.modules.get(hash).cloned()?;
+        // move the hash to the most recently used end
+        if let Some(i) = inner.order.iter().position(|h| h == hash) {
+            let h = inner.order.remove(i).unwrap();
+            inner.order.push_back(h);
+        }
+        Some(m)
+    }
+}
+
+impl<M: Clone> Default for ScriptCache<M> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// A CompilingEngine splits running a script into compiling it and running
+/// the compiled module so the compiled modules can be cached
+pub trait CompilingEngine {
+    /// the compiled form of a script
+    type Module: Clone;
+
+    /// compile the script
+    fn compile(&self, script: &Script) -> Result<Self::Module, Error>;
+
+    /// run the named function in the compiled script
+    #[allow(clippy::too_many_arguments)]
+    fn run_module(
+        &self,
+        module: &Self::Module,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error>;
+}
+
+/// A ScriptEngine that compiles each distinct script once and runs the
+/// cached module every time the script appears in the log
+pub struct CachingEngine<E: CompilingEngine> {
+    engine: E,
+    cache: ScriptCache<E::Module>,
+}
+
+impl<E: CompilingEngine> CachingEngine<E> {
+    /// create a caching engine with the default cache size
+    pub fn new(engine: E) -> Self {
+        Self::with_capacity(engine, DEFAULT_CAPACITY)
+    }
+
+    /// create a caching engine that holds at most `capacity` compiled scripts
+    pub fn with_capacity(engine: E, capacity: usize) -> Self {
+        Self {
+            engine,
+            cache: ScriptCache::new(capacity),
+        }
+    }
+
+    /// get the cache of compiled scripts
+    pub fn cache(&self) -> &ScriptCache<E::Module> {
+        &self.cache
+    }
+}
+
+impl<E: CompilingEngine> ScriptEngine for CachingEngine<E> {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let module = self
+            .cache
+            .get_or_try_insert(script, |s| self.engine.compile(s))?;
+        self.engine
+            .run_module(&module, func, current, proposed, pstack, rstack, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::LOCK_FUNC, Kvp};
+    use std::cell::Cell;
+
+    #[derive(Default)]
+    struct Counter {
+        compiles: Cell<usize>,
+        runs: Cell<usize>,
+    }
+
+    impl CompilingEngine for Counter {
+        type Module = Vec<u8>;
+
+        fn compile(&self, script: &Script) -> Result<Self::Module, Error> {
+            self.compiles.set(self.compiles.get() + 1);
+            Ok(script.clone().into())
+        }
+
+        fn run_module(
+            &self,
+            _module: &Self::Module,
+            _func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            _rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            self.runs.set(self.runs.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_caching_engine() {
+        let s1 = Script::Bin(Key::default(), b"one".to_vec());
+        let s2 = Script::Bin(Key::default(), b"two".to_vec());
+        let s3 = Script::Bin(Key::default(), b"three".to_vec());
+        let kvp = Kvp::default();
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+
+        let engine = CachingEngine::with_capacity(Counter::default(), 2);
+        for s in [&s1, &s1, &s2, &s1, &s3, &s1, &s2] {
+            engine
+                .run(s, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &Key::default())
+                .unwrap();
+        }
+        assert_eq!(7, engine.engine.runs.get());
+        // s1, s2, s3 and then s2 again after it was evicted by s3
+        assert_eq!(4, engine.engine.compiles.get());
+        assert_eq!(2, engine.cache().len());
+
+        engine.cache().clear();
+        assert!(engine.cache().is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: FSL-1.1
+//! CAR (content addressable archive) v1 import and export. A CAR file is a
+//! DAG-CBOR header listing the root cids followed by the blocks, each as a
+//! varuint length, the cid and the block bytes:
+//!
+//! ```text
+//! varuint(len) | { roots: [cid], version: 1 } | (varuint(len) | cid | block)*
+//! ```
+//!
+//! Importing copies the blocks into a [`BlockStore`] as they are. The blocks
+//! are checked against their cids when they are decoded, e.g. by
+//! [`Log::try_from_block_store`].
+use crate::{
+    dag_cbor::{as_array, as_u64, dag_err, decode_link, field, from_slice, link, text, to_vec},
+    BlockStore, Error, Log, MemoryBlockStore,
+};
+use multicid::Cid;
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+use serde_cbor::Value as Cbor;
+use std::collections::BTreeMap;
+
+/// the CAR format version this supports
+pub const CAR_VERSION: u64 = 1;
+
+/// encode the roots and the blocks as a CAR file
+pub fn write_car(roots: &[Cid], blocks: &[(Cid, Vec<u8>)]) -> Result<Vec<u8>, Error> {
+    let mut m = BTreeMap::new();
+    m.insert(text("roots"), Cbor::Array(roots.iter().map(link).collect()));
+    m.insert(text("version"), Cbor::Integer(CAR_VERSION.into()));
+    let header = to_vec(&Cbor::Map(m))?;
+    let mut v: Vec<u8> = Varuint(header.len()).into();
+    v.extend_from_slice(&header);
+    for (cid, block) in blocks {
+        let mut cid: Vec<u8> = cid.clone().into();
+        v.append(&mut Varuint(cid.len() + block.len()).into());
+        v.append(&mut cid);
+        v.extend_from_slice(block);
+    }
+    Ok(v)
+}
+
+// split off the next length prefixed section
+fn section(bytes: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let (len, ptr) = Varuint::<usize>::try_decode_from(bytes)?;
+    if *len > ptr.len() {
+        return Err(dag_err("truncated car section"));
+    }
+    Ok(ptr.split_at(*len))
+}
+
+/// put the blocks in the CAR file into the store and return the roots
+pub fn read_car(bytes: &[u8], store: &mut dyn BlockStore) -> Result<Vec<Cid>, Error> {
+    let (header, mut ptr) = section(bytes)?;
+    let m = from_slice(header)?;
+    if as_u64(field(&m, "version")?)? != CAR_VERSION {
+        return Err(dag_err("unsupported car version"));
+    }
+    let roots = as_array(field(&m, "roots")?)?
+        .iter()
+        .map(decode_link)
+        .collect::<Result<Vec<_>, Error>>()?;
+    while !ptr.is_empty() {
+        let (s, p) = section(ptr)?;
+        let (cid, block) = Cid::try_decode_from(s)?;
+        store.put(&cid, block)?;
+        ptr = p;
+    }
+    Ok(roots)
+}
+
+impl Log {
+    /// export the DAG-CBOR blocks of the log as a CAR file rooted at the log
+    /// root block
+    pub fn to_car(&self) -> Result<Vec<u8>, Error> {
+        let blocks = self.to_dag_cbor_blocks()?;
+        write_car(&[blocks[0].0.clone()], &blocks)
+    }
+
+    /// import the log from a CAR file created with [`Log::to_car`]
+    pub fn try_from_car(bytes: &[u8]) -> Result<Self, Error> {
+        let mut store = MemoryBlockStore::default();
+        let roots = read_car(bytes, &mut store)?;
+        let root = roots.first().ok_or_else(|| dag_err("car has no roots"))?;
+        Self::try_from_block_store(&store, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_car_round_trip() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        let car = log.to_car().unwrap();
+        let decoded = Log::try_from_car(&car).unwrap();
+        assert_eq!(log.head, decoded.head);
+        assert_eq!(log.entries, decoded.entries);
+
+        // the blocks land in the store under their cids
+        let mut store = MemoryBlockStore::default();
+        let roots = read_car(&car, &mut store).unwrap();
+        assert_eq!(3, store.len());
+        let decoded = Log::try_from_block_store(&store, &roots[0]).unwrap();
+        assert_eq!(log.entries, decoded.entries);
+
+        // a truncated car is rejected
+        assert!(Log::try_from_car(&car[..car.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Multibase strings with a short checksum appended. Long encoded logs get
+//! truncated or mangled when they are passed around in chat or email and the
+//! checksum catches that before the string is decoded.
+use crate::{EncodedEntry, EncodedLog, Entry, Error, Log};
+use multibase::Base;
+use multicodec::Codec;
+use multihash::mh;
+
+/// the separator between the multibase string and the checksum
+pub const CHECKSUM_SEPARATOR: char = '.';
+
+/// the number of hash bytes in the checksum
+pub const CHECKSUM_LEN: usize = 5;
+
+// calculate the checksum of the multibase string
+fn checksum(s: &str) -> Result<String, Error> {
+    let hash: Vec<u8> = mh::Builder::new_from_bytes(Codec::Sha3256, s.as_bytes())?
+        .try_build()?
+        .into();
+    // the digest is at the end of the encoded multihash
+    let digest = &hash[hash.len() - CHECKSUM_LEN..];
+    Ok(multibase::encode(Base::Base32Lower, digest)[1..].to_string())
+}
+
+// append the checksum to the multibase string
+fn append_checksum(s: String) -> Result<String, Error> {
+    let sum = checksum(&s)?;
+    Ok(format!("{}{}{}", s, CHECKSUM_SEPARATOR, sum))
+}
+
+// split off the checksum and make sure it matches
+fn strip_checksum(s: &str) -> Result<&str, Error> {
+    let (s, sum) = s
+        .rsplit_once(CHECKSUM_SEPARATOR)
+        .ok_or(Error::MissingChecksum)?;
+    if checksum(s)? != sum {
+        return Err(Error::ChecksumMismatch);
+    }
+    Ok(s)
+}
+
+impl Log {
+    /// encode the log as a multibase string with a checksum appended
+    pub fn to_string_checked(&self) -> Result<String, Error> {
+        append_checksum(EncodedLog::new(Base::Base58Btc, self.clone()).to_string())
+    }
+
+    /// decode a log from a multibase string created by
+    /// [`Log::to_string_checked`] after checking the checksum
+    pub fn from_str_checked(s: &str) -> Result<Self, Error> {
+        Ok(EncodedLog::try_from(strip_checksum(s.trim())?)?.to_inner())
+    }
+}
+
+impl Entry {
+    /// encode the entry as a multibase string with a checksum appended
+    pub fn to_string_checked(&self) -> Result<String, Error> {
+        append_checksum(EncodedEntry::new(Base::Base58Btc, self.clone()).to_string())
+    }
+
+    /// decode an entry from a multibase string created by
+    /// [`Entry::to_string_checked`] after checking the checksum
+    pub fn from_str_checked(s: &str) -> Result<Self, Error> {
+        Ok(EncodedEntry::try_from(strip_checksum(s.trim())?)?.to_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_checked_strings() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        let s = log.to_string_checked().unwrap();
+        assert_eq!(log.head, Log::from_str_checked(&s).unwrap().head);
+        let s = e1.to_string_checked().unwrap();
+        assert_eq!(e1, Entry::from_str_checked(&s).unwrap());
+
+        // a truncated string fails the checksum
+        let (body, sum) = s.rsplit_once(CHECKSUM_SEPARATOR).unwrap();
+        let truncated = format!("{}{}{}", &body[..body.len() - 1], CHECKSUM_SEPARATOR, sum);
+        assert!(matches!(
+            Entry::from_str_checked(&truncated),
+            Err(Error::ChecksumMismatch)
+        ));
+        assert!(matches!(Entry::from_str_checked(body), Err(Error::MissingChecksum)));
+    }
+}
@@ -0,0 +1,389 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{error::LogError, Entry, Error, Key, Kvp, Script, Value};
+use core::fmt;
+use multibase::Base;
+use multicid::{Cid, EncodedCid, Vlad};
+use multitrait::TryDecodeFrom;
+use multiutil::{BaseEncoded, EncodingInfo, Varbytes, Varuint};
+use std::collections::BTreeMap;
+
+/// the current version of checkpoints this supports
+pub const CHECKPOINT_VERSION: u64 = 1;
+
+/// a base encoded checkpoint
+pub type EncodedCheckpoint = BaseEncoded<Checkpoint>;
+
+/// A Checkpoint is a compact snapshot of the verified state of a Log just
+/// after the entry with the given seqno was applied. It contains the kvp
+/// state, the lock scripts that govern the next entry, and the cid of the
+/// entry. Like an Entry it carries a proof over the rest of its fields so
+/// verifiers that trust the signer can start verifying from the checkpoint
+/// instead of the foot of the log.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// the checkpoint version
+    pub(crate) version: u64,
+    /// the vlad of the log
+    pub(crate) vlad: Vlad,
+    /// the seqno of the entry the checkpoint was taken at
+    pub(crate) seqno: u64,
+    /// the cid of the entry the checkpoint was taken at
+    pub(crate) head: Cid,
+    /// the kvp state after the entry was applied
+    pub(crate) kvp: BTreeMap<Key, Value>,
+    /// the lock scripts that govern the next entry
+    pub(crate) locks: Vec<Script>,
+    /// the proof over the checkpoint with an empty proof
+    pub(crate) proof: Vec<u8>,
+}
+
+impl Checkpoint {
+    /// get the vlad of the log
+    pub fn vlad(&self) -> Vlad {
+        self.vlad.clone()
+    }
+
+    /// get the seqno of the entry the checkpoint was taken at
+    pub fn seqno(&self) -> u64 {
+        self.seqno
+    }
+
+    /// get the cid of the entry the checkpoint was taken at
+    pub fn head(&self) -> Cid {
+        self.head.clone()
+    }
+
+    /// get an iterator over the keys and values in the checkpoint state
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.kvp.iter()
+    }
+
+    /// get an iterator over the lock scripts that govern the next entry
+    pub fn locks(&self) -> impl Iterator<Item = &Script> {
+        self.locks.iter()
+    }
+
+    /// get the proof
+    pub fn proof(&self) -> &[u8] {
+        &self.proof
+    }
+
+    /// get the kvp state in the checkpoint
+    pub fn kvp(&self) -> Kvp {
+        Kvp::from(self.kvp.clone())
+    }
+
+    /// Check the proof by calling the `verify_proof` closure with the
+    /// serialized checkpoint the proof was generated over, which has an empty
+    /// proof, and the proof itself
+    pub fn verify_proof<F>(&self, verify_proof: F) -> Result<(), Error>
+    where
+        F: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        let unsigned: Vec<u8> = Self {
+            proof: Vec::default(),
+            ..self.clone()
+        }
+        .into();
+        verify_proof(&unsigned, &self.proof)
+    }
+}
+
+impl EncodingInfo for Checkpoint {
+    fn preferred_encoding() -> Base {
+        Base::Base16Lower
+    }
+
+    fn encoding(&self) -> Base {
+        Self::preferred_encoding()
+    }
+}
+
+impl From<Checkpoint> for Vec<u8> {
+    fn from(val: Checkpoint) -> Self {
+        let mut v = Vec::default();
+        // add in the version
+        v.append(&mut Varuint(val.version).into());
+        // add in the vlad
+        v.append(&mut val.vlad.clone().into());
+        // add in the seqno
+        v.append(&mut Varuint(val.seqno).into());
+        // add in the head cid
+        v.append(&mut val.head.clone().into());
+        // add in the number of key-value pairs
+        v.append(&mut Varuint(val.kvp.len()).into());
+        // add in the key-value pairs
+        val.kvp.iter().for_each(|(key, value)| {
+            v.append(&mut key.clone().into());
+            v.append(&mut value.clone().into());
+        });
+        // add in the number of lock scripts
+        v.append(&mut Varuint(val.locks.len()).into());
+        // add in the lock scripts
+        val.locks
+            .iter()
+            .for_each(|script| v.append(&mut script.clone().into()));
+        // add in the proof
+        v.append(&mut Varbytes(val.proof.clone()).into());
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Checkpoint {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (cp, _) = Self::try_decode_from(bytes)?;
+        Ok(cp)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for Checkpoint {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the version
+        let (version, ptr) = Varuint::<u64>::try_decode_from(bytes)?;
+        let version = version.to_inner();
+        if version != CHECKPOINT_VERSION {
+            return Err(LogError::InvalidCheckpointVersion(version).into());
+        }
+        // decode the vlad
+        let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+        // decode the seqno
+        let (seqno, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let seqno = seqno.to_inner();
+        // decode the head cid
+        let (head, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the number of key-value pairs
+        let (num_pairs, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        // decode the key-value pairs
+        let mut kvp = BTreeMap::new();
+        let mut p = ptr;
+        for _ in 0..*num_pairs {
+            let (key, ptr) = Key::try_decode_from(p)?;
+            let (value, ptr) = Value::try_decode_from(ptr)?;
+            kvp.insert(key, value);
+            p = ptr;
+        }
+        // decode the number of lock scripts
+        let (num_locks, ptr) = Varuint::<usize>::try_decode_from(p)?;
+        // decode the lock scripts
+        let mut locks = Vec::with_capacity(*num_locks);
+        let mut p = ptr;
+        for _ in 0..*num_locks {
+            let (lock, ptr) = Script::try_decode_from(p)?;
+            locks.push(lock);
+            p = ptr;
+        }
+        // decode the proof
+        let (proof, ptr) = Varbytes::try_decode_from(p)?;
+        let proof = proof.to_inner();
+
+        Ok((
+            Self {
+                version,
+                vlad,
+                seqno,
+                head,
+                kvp,
+                locks,
+                proof,
+            },
+            ptr,
+        ))
+    }
+}
+
+impl fmt::Debug for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "checkpoint - #{}\n\t{}\n\tpairs: {}, locks: {}",
+            self.seqno,
+            EncodedCid::new(Base::Base32Lower, self.head.clone()),
+            self.kvp.len(),
+            self.locks.len()
+        )
+    }
+}
+
+/// Builder for Checkpoint objects
+#[derive(Clone)]
+pub struct Builder {
+    vlad: Vlad,
+    seqno: u64,
+    head: Cid,
+    kvp: BTreeMap<Key, Value>,
+    locks: Vec<Script>,
+}
+
+impl Builder {
+    /// create a builder for a checkpoint taken from a verified entry and the
+    /// kvp state after the entry was applied
+    pub fn new(entry: &Entry, kvp: &Kvp) -> Self {
+        Self {
+            vlad: entry.vlad(),
+            seqno: entry.seqno(),
+            head: entry.cid(),
+            kvp: kvp.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            locks: entry.locks.clone(),
+        }
+    }
+
+    /// Build the Checkpoint and then call the `gen_proof` closure to generate
+    /// the proof over the serialized checkpoint
+    pub fn try_build<F>(&self, mut gen_proof: F) -> Result<Checkpoint, Error>
+    where
+        F: FnMut(&mut Checkpoint) -> Result<Vec<u8>, Error>,
+    {
+        let mut cp = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            vlad: self.vlad.clone(),
+            seqno: self.seqno,
+            head: self.head.clone(),
+            kvp: self.kvp.clone(),
+            locks: self.locks.clone(),
+            proof: Vec::default(),
+        };
+
+        // call the gen_proof closure to create and store the proof data
+        cp.proof = gen_proof(&mut cp)?;
+
+        Ok(cp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, pipeline, Op, Pipeline, WaccEngine};
+
+    fn test_log() -> (Vec<Entry>, log::Log) {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_lock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("foo".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/bar".try_into().unwrap(), Value::Data(vec![1, 2, 3])))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Delete("/foo".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3)
+            .try_build()
+            .unwrap();
+        (vec![e1, e2, e3], log)
+    }
+
+    fn structural() -> Pipeline {
+        Pipeline::new()
+            .with_stage(pipeline::Structure)
+            .with_stage(pipeline::Apply)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (entries, log) = test_log();
+        let (_, entry, kvp) = log
+            .verify_with_pipeline(structural(), &WaccEngine)
+            .nth(1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entries[1], entry);
+
+        let cp = Builder::new(&entry, &kvp)
+            .try_build(|_| Ok(b"signed".to_vec()))
+            .unwrap();
+        assert_eq!(1, cp.seqno());
+        assert_eq!(entries[1].cid(), cp.head());
+        assert_eq!(2, cp.iter().count());
+        assert_eq!(1, cp.locks().count());
+
+        let v: Vec<u8> = cp.clone().into();
+        assert_eq!(cp, Checkpoint::try_from(v.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_verify_from_checkpoint() {
+        let (entries, log) = test_log();
+        let (_, entry, kvp) = log
+            .verify_with_pipeline(structural(), &WaccEngine)
+            .next()
+            .unwrap()
+            .unwrap();
+        let cp = Builder::new(&entry, &kvp)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let results: Vec<_> = log
+            .verify_from_checkpoint(&cp, |_, _| Ok(()), structural(), &WaccEngine)
+            .unwrap()
+            .collect();
+        assert_eq!(2, results.len());
+        let (_, entry, kvp) = results.last().unwrap().clone().unwrap();
+        assert_eq!(entries[2], entry);
+        // "/foo" was deleted and "/bar" remains
+        assert_eq!(1, kvp.len());
+
+        // a checkpoint whose proof doesn't check out isn't trusted
+        assert!(log
+            .verify_from_checkpoint(
+                &cp,
+                |_, _| Err(LogError::CheckpointMismatch.into()),
+                structural(),
+                &WaccEngine
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        let (entries, _) = test_log();
+        let cp = Builder::new(&entries[0], &Kvp::default())
+            .try_build(|cp| {
+                let v: Vec<u8> = cp.clone().into();
+                Ok(v.iter().rev().cloned().collect())
+            })
+            .unwrap();
+        // the closure gets the bytes the proof was generated over
+        let check = |signed: &[u8], proof: &[u8]| -> Result<(), Error> {
+            if signed.iter().rev().eq(proof.iter()) {
+                Ok(())
+            } else {
+                Err(LogError::CheckpointMismatch.into())
+            }
+        };
+        cp.verify_proof(check).unwrap();
+
+        // a forged state doesn't match the proof
+        let mut forged = cp.clone();
+        forged.locks.clear();
+        assert!(forged.verify_proof(check).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_mismatch() {
+        let (entries, log) = test_log();
+        let kvp = Kvp::default();
+        let mut cp = Builder::new(&entries[0], &kvp)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        cp.seqno = 2;
+        assert!(log
+            .verify_from_checkpoint(&cp, |_, _| Ok(()), structural(), &WaccEngine)
+            .is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Compression of encoded entries and logs. Logs with wasm lock scripts in
+//! every entry are large and highly redundant so they compress well. The
+//! compressed payload is prefixed with [`ZSTD_CODE`] as a varuint so readers
+//! can tell it apart from the native encoding, which starts with the log or
+//! entry sigil, and the decoding functions accept either.
+use crate::{Entry, Error, Log};
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+use std::io::Read;
+
+/// the multicodec prefix of zstd compressed payloads. zstd doesn't have an
+/// entry in the multicodec table so this is in the private use range.
+pub const ZSTD_CODE: u64 = 0x300001;
+
+/// the compression level used by default
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// the largest payload decompression will produce
+pub const MAX_DECOMPRESSED_LEN: u64 = 256 * 1024 * 1024;
+
+fn compression_err<S: ToString>(s: S) -> Error {
+    Error::Compression(s.to_string())
+}
+
+/// compress the bytes and prefix them with [`ZSTD_CODE`]
+pub fn compress(bytes: &[u8], level: i32) -> Result<Vec<u8>, Error> {
+    let mut v: Vec<u8> = Varuint(ZSTD_CODE).into();
+    v.append(&mut zstd::encode_all(bytes, level).map_err(compression_err)?);
+    Ok(v)
+}
+
+/// decompress the bytes if they are prefixed with [`ZSTD_CODE`], otherwise
+/// return them as they are
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let ptr = match Varuint::<u64>::try_decode_from(bytes) {
+        Ok((code, ptr)) if code.to_inner() == ZSTD_CODE => ptr,
+        _ => return Ok(bytes.to_vec()),
+    };
+    let mut v = Vec::default();
+    zstd::Decoder::new(ptr)
+        .map_err(compression_err)?
+        .take(MAX_DECOMPRESSED_LEN + 1)
+        .read_to_end(&mut v)
+        .map_err(compression_err)?;
+    if v.len() as u64 > MAX_DECOMPRESSED_LEN {
+        return Err(compression_err("decompressed payload is too large"));
+    }
+    Ok(v)
+}
+
+impl Log {
+    /// encode the log and compress it
+    pub fn to_compressed_bytes(&self) -> Result<Vec<u8>, Error> {
+        let bytes: Vec<u8> = self.clone().into();
+        compress(&bytes, DEFAULT_LEVEL)
+    }
+
+    /// decode a log from compressed or uncompressed bytes
+    pub fn try_from_compressed(bytes: &[u8]) -> Result<Self, Error> {
+        Self::try_from(decompress(bytes)?.as_slice())
+    }
+}
+
+impl Entry {
+    /// encode the entry and compress it
+    pub fn to_compressed_bytes(&self) -> Result<Vec<u8>, Error> {
+        compress(&self.canonical_bytes(), DEFAULT_LEVEL)
+    }
+
+    /// decode an entry from compressed or uncompressed bytes
+    pub fn try_from_compressed(bytes: &[u8]) -> Result<Self, Error> {
+        Self::try_from(decompress(bytes)?.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Key, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_compression() {
+        // a big, redundant lock script like a wasm module
+        let lock = Script::Bin(Key::default(), vec![0x61; 4096]);
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_lock(&lock)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&lock)
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        let raw: Vec<u8> = log.clone().into();
+        let compressed = log.to_compressed_bytes().unwrap();
+        assert!(compressed.len() < raw.len() / 10);
+        assert_eq!(log, Log::try_from_compressed(&compressed).unwrap());
+        // uncompressed bytes are decoded as they are
+        assert_eq!(log, Log::try_from_compressed(&raw).unwrap());
+
+        let compressed = e1.to_compressed_bytes().unwrap();
+        assert_eq!(e1, Entry::try_from_compressed(&compressed).unwrap());
+
+        // a corrupted payload is rejected
+        let len = compressed.len();
+        assert!(Entry::try_from_compressed(&compressed[..len - 4]).is_err());
+    }
+}
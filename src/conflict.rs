@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Conflicting ops are two or more ops in one entry that change the same
+//! key, e.g. an Update of `/x` followed by a Delete of `/x`. The ops are
+//! applied in order so the last one wins, which is easy to get wrong. An
+//! [`OpConflicts`] policy makes the choice explicit when entries are built
+//! and the [`RejectConflicts`] stage enforces it when they are verified.
+use crate::{
+    error::EntryError,
+    pipeline::{Pipeline, Stage, Verification},
+    Entry, Error, Key, Kvp, Log, Op, WaccEngine,
+};
+use std::collections::BTreeSet;
+
+/// How ops in one entry that change the same key are handled
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OpConflicts {
+    /// the ops are applied in order so the last op on a key wins
+    #[default]
+    LastWins,
+    /// entries with more than one op changing a key are rejected
+    Reject,
+}
+
+impl OpConflicts {
+    /// check the ops against the policy. Noop ops don't change anything so
+    /// they never conflict.
+    pub fn check<'a>(&self, ops: impl Iterator<Item = &'a Op>) -> Result<(), Error> {
+        if *self == Self::LastWins {
+            return Ok(());
+        }
+        match conflict(ops) {
+            Some(key) => Err(EntryError::ConflictingOps(key).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// get the first key changed by more than one of the ops
+pub fn conflict<'a>(ops: impl Iterator<Item = &'a Op>) -> Option<Key> {
+    let mut changed = BTreeSet::new();
+    ops.filter(|op| !matches!(op, Op::Noop(_)))
+        .find(|&op| !changed.insert(op.path_ref()))
+        .map(|op| op.path())
+}
+
+/// Rejects entries with more than one op changing a key
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RejectConflicts;
+
+impl Stage for RejectConflicts {
+    fn name(&self) -> &str {
+        "conflicts"
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        OpConflicts::Reject.check(v.entry.ops())
+    }
+}
+
+impl Log {
+    /// verify all entries in the log, also rejecting entries with more than
+    /// one op changing a key
+    pub fn verify_rejecting_conflicts(
+        &self,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_ {
+        self.verify_with_pipeline(
+            Pipeline::default().insert_before("apply", RejectConflicts),
+            &WaccEngine,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry, log,
+        pipeline::{Apply, Structure},
+        Script, Value,
+    };
+    use multicid::Vlad;
+
+    #[test]
+    fn test_conflicts() {
+        let x: Key = "/x".try_into().unwrap();
+        let ops = [
+            Op::Update(x.clone(), Value::Str("a".to_string())),
+            Op::Noop(x.clone()),
+            Op::Delete(x.clone()),
+        ];
+        assert_eq!(Some(x.clone()), conflict(ops.iter()));
+        assert_eq!(None, conflict(ops[..2].iter()));
+        OpConflicts::LastWins.check(ops.iter()).unwrap();
+        assert!(OpConflicts::Reject.check(ops.iter()).is_err());
+
+        // the builder checks the policy
+        let builder = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&ops[0])
+            .add_op(&ops[2]);
+        assert!(builder
+            .clone()
+            .with_conflicts(OpConflicts::Reject)
+            .try_build(|_| Ok(Vec::default()))
+            .is_err());
+        let e1 = builder.try_build(|_| Ok(Vec::default())).unwrap();
+
+        // and the stage rejects the entry when verifying
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        let p = Pipeline::new()
+            .with_stage(Structure)
+            .with_stage(RejectConflicts)
+            .with_stage(Apply);
+        let results: Vec<_> = log.verify_with_pipeline(p, &WaccEngine).collect();
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
+    }
+}
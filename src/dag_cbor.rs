@@ -0,0 +1,466 @@
+// SPDX-License-Identifier: FSL-1.1
+//! DAG-CBOR encoding of entries and logs. The native encoding of an entry is
+//! a compact varuint format and the cids of entries are calculated over those
+//! bytes even though they declare [`Codec::DagCbor`] as their target codec.
+//! This module encodes the same data as real DAG-CBOR blocks so IPLD tooling
+//! can walk them and the cids of the blocks match the codec they declare.
+//!
+//! Migration: the proofs in existing logs are over the native encoding and
+//! the prev and lipmaa cids inside an entry are native cids, so they are
+//! carried over as they are in the `prev_cid` and `lipmaa_cid` bytes fields.
+//! Native cids are never encoded as links since no block has them as its
+//! cid. A DAG-CBOR entry decodes back into the exact native entry, so every
+//! existing log can be exported to blocks with [`Log::to_dag_cbor_blocks`]
+//! and imported again without re-signing. The log root block links to the
+//! DAG-CBOR cids of its entries and each entry block links to the blocks of
+//! its prev and lipmaa entries in its `prev` and `lipmaa` fields.
+use crate::{
+    entry, error::LogError, log, BlockStore, Entry, Error, Key, Log, Op, OpId, Recipient, Script,
+    ScriptId, Value,
+};
+use multicid::{cid, Cid, Vlad};
+use multicodec::Codec;
+use multihash::mh;
+use multitrait::{Null, TryDecodeFrom};
+use serde_cbor::Value as Cbor;
+use std::collections::BTreeMap;
+
+/// the CBOR tag for IPLD links
+pub const LINK_TAG: u64 = 42;
+
+/// the hash codec used for the cids of DAG-CBOR blocks
+pub const HASH_CODEC: Codec = Codec::Sha3512;
+
+/// get the DAG-CBOR cid of the block
+pub fn block_cid(block: &[u8]) -> Result<Cid, Error> {
+    Ok(cid::Builder::new(Codec::Cidv1)
+        .with_target_codec(Codec::DagCbor)
+        .with_hash(&mh::Builder::new_from_bytes(HASH_CODEC, block)?.try_build()?)
+        .try_build()?)
+}
+
+impl Entry {
+    /// encode the entry as a DAG-CBOR block on its own. the blocks of the
+    /// prev and lipmaa entries aren't known so the block doesn't link to
+    /// them, [`Log::to_dag_cbor_blocks`] encodes entries with the links.
+    pub fn to_dag_cbor(&self) -> Result<Vec<u8>, Error> {
+        self.encode_dag_cbor(None, None)
+    }
+
+    // encode the entry linking to the DAG-CBOR cids of the blocks of its prev
+    // and lipmaa entries, if they are known
+    fn encode_dag_cbor(&self, prev: Option<&Cid>, lipmaa: Option<&Cid>) -> Result<Vec<u8>, Error> {
+        let mut m = BTreeMap::new();
+        m.insert(text("version"), Cbor::Integer(self.version.into()));
+        m.insert(text("vlad"), Cbor::Bytes(self.vlad.clone().into()));
+        m.insert(text("prev"), prev.map(link).unwrap_or(Cbor::Null));
+        m.insert(text("prev_cid"), native(&self.prev));
+        m.insert(text("lipmaa"), lipmaa.map(link).unwrap_or(Cbor::Null));
+        m.insert(text("lipmaa_cid"), native(&self.lipmaa));
+        m.insert(text("seqno"), Cbor::Integer(self.seqno.into()));
+        m.insert(text("ops"), Cbor::Array(self.ops.iter().map(op).collect()));
+        m.insert(text("locks"), Cbor::Array(self.locks.iter().map(script).collect()));
+        m.insert(text("unlock"), script(&self.unlock));
+        m.insert(text("proof"), Cbor::Bytes(self.proof.clone()));
+        to_vec(&Cbor::Map(m))
+    }
+
+    /// decode the entry from a DAG-CBOR block
+    pub fn try_from_dag_cbor(block: &[u8]) -> Result<Self, Error> {
+        let m = from_slice(block)?;
+        let mut builder = entry::Builder::default()
+            .with_vlad(&Vlad::try_from(as_bytes(field(&m, "vlad")?)?)?)
+            .with_prev(&decode_native(field(&m, "prev_cid")?)?)
+            .with_seqno(as_u64(field(&m, "seqno")?)?)
+            .with_unlock(&decode_script(field(&m, "unlock")?)?);
+        let lipmaa = decode_native(field(&m, "lipmaa_cid")?)?;
+        if !lipmaa.is_null() {
+            builder = builder.with_lipmaa(&lipmaa);
+        }
+        for o in as_array(field(&m, "ops")?)? {
+            builder = builder.add_op(&decode_op(o)?);
+        }
+        for l in as_array(field(&m, "locks")?)? {
+            builder = builder.add_lock(&decode_script(l)?);
+        }
+        let proof = as_bytes(field(&m, "proof")?)?.to_vec();
+        let mut entry = builder.try_build(|_| Ok(proof.clone()))?;
+        entry.version = as_u64(field(&m, "version")?)?;
+        // make sure the version is one the native encoding supports
+        Entry::try_from(entry.canonical_bytes().as_slice())
+    }
+
+    /// get the cid of the DAG-CBOR block of the entry on its own, see
+    /// [`Entry::to_dag_cbor`]
+    pub fn dag_cbor_cid(&self) -> Result<Cid, Error> {
+        block_cid(&self.to_dag_cbor()?)
+    }
+}
+
+impl Log {
+    /// encode the log as DAG-CBOR blocks. the first block is the root of the
+    /// log and the rest are the entries, each with its cid.
+    pub fn to_dag_cbor_blocks(&self) -> Result<Vec<(Cid, Vec<u8>)>, Error> {
+        let mut blocks = Vec::default();
+        let mut links = BTreeMap::new();
+        // the entries go from the foot to the head so the blocks of the
+        // entries they link to are already made
+        for entry in self.iter() {
+            let block = entry.encode_dag_cbor(links.get(&entry.prev), links.get(&entry.lipmaa))?;
+            let cid = block_cid(&block)?;
+            links.insert(entry.cid(), cid.clone());
+            blocks.push((cid, block));
+        }
+        let block_link = |c: &Cid| links.get(c).map(link).unwrap_or(Cbor::Null);
+
+        let mut m = BTreeMap::new();
+        m.insert(text("version"), Cbor::Integer(self.version.into()));
+        m.insert(text("vlad"), Cbor::Bytes(self.vlad.clone().into()));
+        m.insert(text("first_lock"), script(&self.first_lock));
+        m.insert(text("foot"), block_link(&self.foot));
+        m.insert(text("head"), block_link(&self.head));
+        m.insert(
+            text("entries"),
+            Cbor::Array(blocks.iter().map(|(c, _)| link(c)).collect()),
+        );
+        let root = to_vec(&Cbor::Map(m))?;
+        blocks.insert(0, (block_cid(&root)?, root));
+        Ok(blocks)
+    }
+
+    /// decode the log from the DAG-CBOR blocks created by
+    /// [`Log::to_dag_cbor_blocks`]
+    pub fn try_from_dag_cbor_blocks(blocks: &[(Cid, Vec<u8>)]) -> Result<Self, Error> {
+        let (root_cid, root) = blocks.first().ok_or(LogError::MissingEntries)?;
+        check_block(root_cid, root)?;
+        let m = from_slice(root)?;
+        let mut builder = log::Builder::new()
+            .with_vlad(&Vlad::try_from(as_bytes(field(&m, "vlad")?)?)?)
+            .with_first_lock(&decode_script(field(&m, "first_lock")?)?);
+        let foot = decode_link(field(&m, "foot")?)?;
+        let head = decode_link(field(&m, "head")?)?;
+        for l in as_array(field(&m, "entries")?)? {
+            let cid = decode_link(l)?;
+            let (_, block) = blocks
+                .iter()
+                .find(|(c, _)| *c == cid)
+                .ok_or_else(|| dag_err("missing entry block"))?;
+            check_block(&cid, block)?;
+            let entry = Entry::try_from_dag_cbor(block)?;
+            if cid == foot {
+                builder = builder.with_foot(&entry.cid());
+            }
+            if cid == head {
+                builder = builder.with_head(&entry.cid());
+            }
+            builder = builder.append_entry(&entry);
+        }
+        builder.try_build()
+    }
+
+    /// put the DAG-CBOR blocks of the log in the store and return the cid of
+    /// the root block
+    pub fn put_blocks(&self, store: &mut dyn BlockStore) -> Result<Cid, Error> {
+        let blocks = self.to_dag_cbor_blocks()?;
+        for (cid, block) in &blocks {
+            store.put(cid, block)?;
+        }
+        Ok(blocks[0].0.clone())
+    }
+
+    /// get the log with the root block cid from the store
+    pub fn try_from_block_store(store: &dyn BlockStore, root: &Cid) -> Result<Self, Error> {
+        let get = |cid: &Cid| {
+            store
+                .get(cid)?
+                .ok_or_else(|| Error::BlockNotFound(cid.clone()))
+        };
+        let block = get(root)?;
+        let m = from_slice(&block)?;
+        let mut blocks = vec![(root.clone(), block)];
+        for l in as_array(field(&m, "entries")?)? {
+            let cid = decode_link(l)?;
+            let block = get(&cid)?;
+            blocks.push((cid, block));
+        }
+        Self::try_from_dag_cbor_blocks(&blocks)
+    }
+}
+
+pub(crate) fn dag_err<S: ToString>(s: S) -> Error {
+    Error::DagCbor(s.to_string())
+}
+
+pub(crate) fn to_vec(v: &Cbor) -> Result<Vec<u8>, Error> {
+    serde_cbor::to_vec(v).map_err(dag_err)
+}
+
+pub(crate) fn from_slice(block: &[u8]) -> Result<BTreeMap<Cbor, Cbor>, Error> {
+    match serde_cbor::from_slice(block).map_err(dag_err)? {
+        Cbor::Map(m) => Ok(m),
+        _ => Err(dag_err("expected a map")),
+    }
+}
+
+// make sure the block hashes to its cid
+fn check_block(cid: &Cid, block: &[u8]) -> Result<(), Error> {
+    if block_cid(block)? != *cid {
+        return Err(dag_err("block doesn't match its cid"));
+    }
+    Ok(())
+}
+
+pub(crate) fn text(s: &str) -> Cbor {
+    Cbor::Text(s.to_string())
+}
+
+// native cids are encoded as bytes and null cids as null
+fn native(cid: &Cid) -> Cbor {
+    if cid.is_null() {
+        return Cbor::Null;
+    }
+    Cbor::Bytes(cid.clone().into())
+}
+
+fn decode_native(v: &Cbor) -> Result<Cid, Error> {
+    match v {
+        Cbor::Null => Ok(Cid::null()),
+        Cbor::Bytes(b) => Ok(Cid::try_decode_from(b)?.0),
+        _ => Err(dag_err("expected a cid")),
+    }
+}
+
+// null cids are encoded as null, all others as links
+pub(crate) fn link(cid: &Cid) -> Cbor {
+    if cid.is_null() {
+        return Cbor::Null;
+    }
+    // links have the identity multibase prefix
+    let mut b = vec![0];
+    b.append(&mut cid.clone().into());
+    Cbor::Tag(LINK_TAG, Box::new(Cbor::Bytes(b)))
+}
+
+fn op(o: &Op) -> Cbor {
+    let mut m = BTreeMap::new();
+    m.insert(text("op"), text(OpId::from(o).as_str()));
+    m.insert(text("key"), text(o.path_ref().as_str()));
+    if let Op::Update(_, v) = o {
+        let v = match v {
+            Value::Nil => Cbor::Null,
+            Value::Str(s) => Cbor::Text(s.clone()),
+            Value::Data(b) => Cbor::Bytes(b.clone()),
+            Value::Encrypted {
+                scheme,
+                recipients,
+                ciphertext,
+            } => {
+                let mut e = BTreeMap::new();
+                e.insert(text("scheme"), Cbor::Bytes((*scheme).into()));
+                let recipients = recipients
+                    .iter()
+                    .map(|r| {
+                        let mut rm = BTreeMap::new();
+                        rm.insert(text("id"), Cbor::Bytes(r.id.clone()));
+                        rm.insert(text("epk"), Cbor::Bytes(r.ephemeral.clone()));
+                        rm.insert(text("key"), Cbor::Bytes(r.wrapped_key.clone()));
+                        Cbor::Map(rm)
+                    })
+                    .collect();
+                e.insert(text("recipients"), Cbor::Array(recipients));
+                e.insert(text("ciphertext"), Cbor::Bytes(ciphertext.clone()));
+                Cbor::Map(e)
+            }
+            Value::PlogRef {
+                vlad,
+                entry_cid,
+                key,
+            } => {
+                let mut r = BTreeMap::new();
+                r.insert(text("vlad"), Cbor::Bytes(vlad.clone().into()));
+                r.insert(text("entry"), native(entry_cid));
+                r.insert(text("key"), text(key.as_str()));
+                Cbor::Map(r)
+            }
+        };
+        m.insert(text("value"), v);
+    }
+    Cbor::Map(m)
+}
+
+fn script(s: &Script) -> Cbor {
+    let mut m = BTreeMap::new();
+    m.insert(text("type"), text(ScriptId::from(s).as_str()));
+    m.insert(text("path"), text(s.path_ref().as_str()));
+    let data = match s {
+        Script::Bin(_, b) => Cbor::Bytes(b.clone()),
+        Script::Code(_, c) => Cbor::Text(c.clone()),
+        Script::Cid(_, c) => link(c),
+    };
+    m.insert(text("data"), data);
+    Cbor::Map(m)
+}
+
+pub(crate) fn field<'a>(m: &'a BTreeMap<Cbor, Cbor>, name: &str) -> Result<&'a Cbor, Error> {
+    m.get(&text(name))
+        .ok_or_else(|| dag_err(format!("missing field {}", name)))
+}
+
+pub(crate) fn as_u64(v: &Cbor) -> Result<u64, Error> {
+    match v {
+        Cbor::Integer(i) => u64::try_from(*i).map_err(dag_err),
+        _ => Err(dag_err("expected an integer")),
+    }
+}
+
+fn as_str(v: &Cbor) -> Result<&str, Error> {
+    match v {
+        Cbor::Text(s) => Ok(s),
+        _ => Err(dag_err("expected text")),
+    }
+}
+
+fn as_bytes(v: &Cbor) -> Result<&[u8], Error> {
+    match v {
+        Cbor::Bytes(b) => Ok(b),
+        _ => Err(dag_err("expected bytes")),
+    }
+}
+
+pub(crate) fn as_array(v: &Cbor) -> Result<&[Cbor], Error> {
+    match v {
+        Cbor::Array(a) => Ok(a),
+        _ => Err(dag_err("expected an array")),
+    }
+}
+
+pub(crate) fn decode_link(v: &Cbor) -> Result<Cid, Error> {
+    match v {
+        Cbor::Null => Ok(Cid::null()),
+        Cbor::Tag(LINK_TAG, b) => match as_bytes(b)?.split_first() {
+            Some((0, b)) => Ok(Cid::try_decode_from(b)?.0),
+            _ => Err(dag_err("invalid link")),
+        },
+        _ => Err(dag_err("expected a link")),
+    }
+}
+
+fn decode_op(v: &Cbor) -> Result<Op, Error> {
+    let m = match v {
+        Cbor::Map(m) => m,
+        _ => return Err(dag_err("expected a map")),
+    };
+    let key = Key::try_from(as_str(field(m, "key")?)?)?;
+    match OpId::try_from(as_str(field(m, "op")?)?)? {
+        OpId::Noop => Ok(Op::Noop(key)),
+        OpId::Delete => Ok(Op::Delete(key)),
+        OpId::Update => {
+            let value = match field(m, "value")? {
+                Cbor::Null => Value::Nil,
+                Cbor::Text(s) => Value::Str(s.clone()),
+                Cbor::Bytes(b) => Value::Data(b.clone()),
+                // references to other logs are the maps with a vlad
+                Cbor::Map(r) if r.contains_key(&text("vlad")) => Value::PlogRef {
+                    vlad: Vlad::try_from(as_bytes(field(r, "vlad")?)?)?,
+                    entry_cid: decode_native(field(r, "entry")?)?,
+                    key: Key::try_from(as_str(field(r, "key")?)?)?,
+                },
+                Cbor::Map(e) => decode_encrypted(e)?,
+                _ => return Err(dag_err("invalid value")),
+            };
+            Ok(Op::Update(key, value))
+        }
+    }
+}
+
+fn decode_encrypted(m: &BTreeMap<Cbor, Cbor>) -> Result<Value, Error> {
+    let (scheme, _) = Codec::try_decode_from(as_bytes(field(m, "scheme")?)?)?;
+    let recipients = as_array(field(m, "recipients")?)?
+        .iter()
+        .map(|r| match r {
+            Cbor::Map(rm) => Ok(Recipient {
+                id: as_bytes(field(rm, "id")?)?.to_vec(),
+                ephemeral: as_bytes(field(rm, "epk")?)?.to_vec(),
+                wrapped_key: as_bytes(field(rm, "key")?)?.to_vec(),
+            }),
+            _ => Err(dag_err("expected a map")),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Encrypted {
+        scheme,
+        recipients,
+        ciphertext: as_bytes(field(m, "ciphertext")?)?.to_vec(),
+    })
+}
+
+fn decode_script(v: &Cbor) -> Result<Script, Error> {
+    let m = match v {
+        Cbor::Map(m) => m,
+        _ => return Err(dag_err("expected a map")),
+    };
+    let path = Key::try_from(as_str(field(m, "path")?)?)?;
+    let data = field(m, "data")?;
+    match ScriptId::try_from(as_str(field(m, "type")?)?)? {
+        ScriptId::Bin => Ok(Script::Bin(path, as_bytes(data)?.to_vec())),
+        ScriptId::Code => Ok(Script::Code(path, as_str(data)?.to_string())),
+        ScriptId::Cid => Ok(Script::Cid(path, decode_link(data)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_dag_cbor() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::Code(Key::default(), "(module)".to_string()))
+            .add_lock(&Script::Bin(Key::try_from("/foo/").unwrap(), vec![1, 2, 3]))
+            .add_op(&Op::Update("/foo/bar".try_into().unwrap(), Value::Str("baz".to_string())))
+            .add_op(&Op::Update("/foo/nil".try_into().unwrap(), Value::Nil))
+            .add_op(&Op::Delete("/foo/old".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let block = e1.to_dag_cbor().unwrap();
+        let decoded = Entry::try_from_dag_cbor(&block).unwrap();
+        assert_eq!(e1, decoded);
+        // the native cid is unchanged
+        assert_eq!(e1.cid(), decoded.cid());
+        assert_eq!(block_cid(&block).unwrap(), e1.dag_cbor_cid().unwrap());
+
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+        let blocks = log.to_dag_cbor_blocks().unwrap();
+        assert_eq!(3, blocks.len());
+        let decoded = Log::try_from_dag_cbor_blocks(&blocks).unwrap();
+        assert_eq!(log.head, decoded.head);
+        assert_eq!(log.foot, decoded.foot);
+        assert_eq!(log.entries, decoded.entries);
+
+        // the entry blocks link to the blocks of their prev entries
+        let root = from_slice(&blocks[0].1).unwrap();
+        let mut next = decode_link(field(&root, "head").unwrap()).unwrap();
+        let mut walked = Vec::default();
+        while !next.is_null() {
+            let (_, block) = blocks.iter().find(|(c, _)| *c == next).unwrap();
+            walked.push(Entry::try_from_dag_cbor(block).unwrap().cid());
+            next = decode_link(field(&from_slice(block).unwrap(), "prev").unwrap()).unwrap();
+        }
+        assert_eq!(vec![e2.cid(), e1.cid()], walked);
+
+        // a tampered block is rejected
+        let mut tampered = blocks.clone();
+        tampered[1].1.push(0);
+        assert!(Log::try_from_dag_cbor_blocks(&tampered).is_err());
+    }
+}
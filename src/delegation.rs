@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Delegations let the controller of a log grant another key the right to
+//! change part of it. A grant is recorded at `/delegations/<id>` and names
+//! the delegate key, the [`KeyPattern`] of the keys it may change and the
+//! last seqno it is good for. The lock scripts decide whether a delegate's
+//! signature is accepted at all, the [`Delegations`] stage then rejects
+//! entries signed by a delegate that change keys outside of its grant.
+use crate::{
+    error::LogError,
+    pipeline::{Pipeline, Stage, Verification},
+    Entry, Error, Key, KeyPattern, Kvp, Log, Op, Value, WaccEngine,
+};
+use multikey::Multikey;
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+
+/// the branch the delegation records are stored under
+pub const DELEGATIONS: &str = "/delegations/";
+
+/// A Delegation grants the delegate key the right to change the keys that
+/// match the pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delegation {
+    /// the public key of the delegate
+    pub delegate: Multikey,
+    /// the keys the delegate may change
+    pub pattern: KeyPattern,
+    /// the last seqno the delegation is good for, None if it doesn't expire
+    pub expiry: Option<u64>,
+}
+
+impl Delegation {
+    /// create a delegation that doesn't expire
+    pub fn new(delegate: &Multikey, pattern: &KeyPattern) -> Self {
+        Self {
+            delegate: delegate.clone(),
+            pattern: pattern.clone(),
+            expiry: None,
+        }
+    }
+
+    /// set the last seqno the delegation is good for
+    pub fn with_expiry(mut self, seqno: u64) -> Self {
+        self.expiry = Some(seqno);
+        self
+    }
+
+    /// true if the delegate signed the entry
+    pub fn signed(&self, entry: &Entry) -> bool {
+        entry.signed_by(&self.delegate)
+    }
+
+    /// check that the grant allows the entry. `id` is the key the grant is
+    /// recorded at and is only used in the errors.
+    pub fn check(&self, id: &Key, entry: &Entry) -> Result<(), Error> {
+        if let Some(expiry) = self.expiry {
+            if entry.seqno() > expiry {
+                return Err(LogError::DelegationExpired(id.clone(), expiry).into());
+            }
+        }
+        if let Some(op) = entry.ops().find(|op| !self.pattern.matches(op.path_ref())) {
+            return Err(LogError::DelegationNotAllowed(id.clone(), op.path()).into());
+        }
+        Ok(())
+    }
+}
+
+impl From<Delegation> for Vec<u8> {
+    fn from(val: Delegation) -> Self {
+        let mut v = Vec::default();
+        // add in the delegate key
+        v.append(&mut val.delegate.into());
+        // add in the pattern
+        v.append(&mut val.pattern.as_key().clone().into());
+        // add in the expiry, a zero flag means it doesn't expire and a one
+        // flag is followed by the last seqno
+        match val.expiry {
+            Some(expiry) => {
+                v.append(&mut Varuint(1u64).into());
+                v.append(&mut Varuint(expiry).into());
+            }
+            None => v.append(&mut Varuint(0u64).into()),
+        }
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Delegation {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (delegation, _) = Self::try_decode_from(bytes)?;
+        Ok(delegation)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for Delegation {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        let (delegate, ptr) = Multikey::try_decode_from(bytes)
+            .map_err(|e| LogError::InvalidDelegation(e.to_string()))?;
+        let (pattern, ptr) = Key::try_decode_from(ptr)?;
+        let (flag, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let (expiry, ptr) = match *flag {
+            0 => (None, ptr),
+            1 => {
+                let (expiry, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+                (Some(*expiry), ptr)
+            }
+            f => {
+                let msg = format!("invalid expiry flag {}", f);
+                return Err(LogError::InvalidDelegation(msg).into());
+            }
+        };
+        Ok((
+            Self {
+                delegate,
+                pattern: pattern.into(),
+                expiry,
+            },
+            ptr,
+        ))
+    }
+}
+
+/// get the key the delegation with the id is recorded at
+pub fn delegation_key<S: AsRef<str>>(id: S) -> Result<Key, Error> {
+    let mut key = Key::try_from(DELEGATIONS)?;
+    key.push(format!("/{}", Key::escape_segment(id)))?;
+    Ok(key)
+}
+
+/// get the op that records the delegation under the id
+pub fn grant<S: AsRef<str>>(id: S, delegation: &Delegation) -> Result<Op, Error> {
+    Ok(Op::Update(delegation_key(id)?, Value::Data(delegation.clone().into())))
+}
+
+/// get the op that revokes the delegation with the id
+pub fn revoke<S: AsRef<str>>(id: S) -> Result<Op, Error> {
+    Ok(Op::Delete(delegation_key(id)?))
+}
+
+/// get the delegations recorded in the kvp with the keys they are recorded at
+pub fn delegations(kvp: &Kvp) -> Result<Vec<(Key, Delegation)>, Error> {
+    let branch = Key::try_from(DELEGATIONS)?;
+    kvp.iter()
+        .filter(|(key, _)| branch.parent_of(key))
+        .map(|(key, value)| match value {
+            Value::Data(b) => Ok((key.clone(), Delegation::try_from(b.as_slice())?)),
+            _ => Err(LogError::InvalidDelegation(key.to_string()).into()),
+        })
+        .collect()
+}
+
+/// Rejects entries signed by a delegate that aren't allowed by its grant.
+/// The grants are read from the state before the entry is applied so an
+/// entry can't widen its own grant.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Delegations;
+
+impl Stage for Delegations {
+    fn name(&self) -> &str {
+        "delegations"
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        for (id, delegation) in delegations(v.kvp)? {
+            if delegation.signed(v.entry) {
+                delegation.check(&id, v.entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Log {
+    /// verify all entries in the log, also enforcing the delegation grants
+    /// before their mutations are applied
+    pub fn verify_with_delegations(
+        &self,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_ {
+        self.verify_with_pipeline(Pipeline::default().insert_before("apply", Delegations), &WaccEngine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry, log,
+        pipeline::{Apply, Structure},
+        Script,
+    };
+    use multicid::Vlad;
+    use multikey::{EncodedMultikey, Views};
+
+    #[test]
+    fn test_delegations() {
+        let owner = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0",
+        )
+        .unwrap()
+        .to_inner();
+        let delegate = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8",
+        )
+        .unwrap()
+        .to_inner();
+        let sign = |key: &Multikey| {
+            let key = key.clone();
+            move |e: &mut Entry| -> Result<Vec<u8>, Error> {
+                let ev: Vec<u8> = e.clone().into();
+                Ok(key.sign_view().unwrap().sign(&ev, false, None).unwrap().into())
+            }
+        };
+
+        let grant_op = grant(
+            "bob",
+            &Delegation::new(&delegate, &KeyPattern::try_from("/bob/").unwrap()).with_expiry(2),
+        )
+        .unwrap();
+        assert_eq!("/delegations/bob", grant_op.path().as_str());
+        if let Op::Update(_, Value::Data(b)) = &grant_op {
+            let d = Delegation::try_from(b.as_slice()).unwrap();
+            assert_eq!(Some(2), d.expiry);
+        }
+
+        // the largest and no expiry both round trip
+        let d = Delegation::new(&delegate, &KeyPattern::default()).with_expiry(u64::MAX);
+        let v: Vec<u8> = d.clone().into();
+        assert_eq!(d, Delegation::try_from(v.as_slice()).unwrap());
+        let d = Delegation::new(&delegate, &KeyPattern::default());
+        let v: Vec<u8> = d.clone().into();
+        assert_eq!(d, Delegation::try_from(v.as_slice()).unwrap());
+
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&grant_op)
+            .try_build(sign(&owner))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/bob/name".try_into().unwrap(), Value::Str("bob".to_string())))
+            .try_build(sign(&delegate))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/alice/name".try_into().unwrap(), Value::Str("bob".to_string())))
+            .try_build(sign(&delegate))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3)
+            .try_build()
+            .unwrap();
+
+        // the owner and the delegate within its grant are fine but the
+        // delegate can't change keys outside of it
+        let p = Pipeline::new()
+            .with_stage(Structure)
+            .with_stage(Delegations)
+            .with_stage(Apply);
+        let results: Vec<_> = log.verify_with_pipeline(p, &WaccEngine).collect();
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(results[2].is_err());
+    }
+}
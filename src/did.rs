@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Projection of the namespace of a verified log into a W3C DID Document.
+//! The conventional keys are mapped as follows:
+//!
+//! ```text
+//! ─┬─ "/"
+//!  ├─── "pubkey"              verification method "#pubkey"
+//!  ├─── "ephemeral"           verification method "#ephemeral"
+//!  ╰─┬─ "services/"
+//!    ╰─┬─ "<name>/"           service "#<name>"
+//!      ├─── "type"            the service type
+//!      ╰─── "endpoint"        the service endpoint
+//! ```
+use crate::{error::LogError, profiles::identity, Error, Key, Kvp, Log, Value};
+use multibase::Base;
+use multicid::{EncodedVlad, Vlad};
+use multikey::{EncodedMultikey, Multikey};
+use multitrait::TryDecodeFrom;
+
+/// the did method name
+pub const METHOD: &str = "plog";
+
+/// the branch holding the services
+pub const SERVICES: &str = "/services/";
+
+/// the verification method type for multikey public keys
+pub const MULTIKEY_TYPE: &str = "Multikey";
+
+/// get the did for the log with the given vlad
+pub fn did(vlad: &Vlad) -> String {
+    format!("did:{}:{}", METHOD, EncodedVlad::new(Base::Base32Lower, vlad.clone()))
+}
+
+/// A public key that can be used to verify the did subject
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct VerificationMethod {
+    /// the did url of the method
+    pub id: String,
+    /// the type of the method
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub type_: String,
+    /// the did of the controller
+    pub controller: String,
+    /// the multibase encoded multikey
+    pub public_key_multibase: String,
+}
+
+/// A service endpoint for the did subject
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Service {
+    /// the did url of the service
+    pub id: String,
+    /// the type of the service
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub type_: String,
+    /// the endpoint of the service
+    pub service_endpoint: String,
+}
+
+/// A W3C DID Document
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct DidDocument {
+    /// the did of the subject
+    pub id: String,
+    /// the public keys of the subject
+    pub verification_method: Vec<VerificationMethod>,
+    /// the ids of the verification methods used for authentication
+    pub authentication: Vec<String>,
+    /// the services of the subject
+    pub service: Vec<Service>,
+}
+
+impl DidDocument {
+    /// build the did document from the namespace of the log with the vlad
+    pub fn from_kvp(vlad: &Vlad, kvp: &Kvp) -> Result<Self, Error> {
+        let id = did(vlad);
+        let mut doc = Self {
+            id: id.clone(),
+            ..Default::default()
+        };
+
+        // the current signing key is used for authentication
+        for (path, name) in [(identity::PUBKEY, "pubkey"), (identity::EPHEMERAL, "ephemeral")] {
+            let key = Key::try_from(path)?;
+            if let Some((_, value)) = kvp.iter().find(|(k, _)| **k == key) {
+                let method = VerificationMethod {
+                    id: format!("{}#{}", id, name),
+                    type_: MULTIKEY_TYPE.to_string(),
+                    controller: id.clone(),
+                    public_key_multibase: multikey(value)?,
+                };
+                if path == identity::PUBKEY {
+                    doc.authentication.push(method.id.clone());
+                }
+                doc.verification_method.push(method);
+            }
+        }
+
+        // services are read from the leaves of each branch under /services/
+        let services = Key::try_from(SERVICES)?;
+        let mut names: Vec<String> = Vec::default();
+        for (k, _) in kvp.iter() {
+            if let Some(name) = service_name(&services, k) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        for name in names {
+            let leaf = |l: &str| -> Result<Option<String>, Error> {
+                let key = Key::try_from(format!("{}{}/{}", SERVICES, name, l))?;
+                Ok(kvp.iter().find(|(k, _)| **k == key).and_then(|(_, v)| match v {
+                    Value::Str(s) => Some(s.clone()),
+                    _ => None,
+                }))
+            };
+            if let (Some(type_), Some(service_endpoint)) = (leaf("type")?, leaf("endpoint")?) {
+                doc.service.push(Service {
+                    id: format!("{}#{}", id, name),
+                    type_,
+                    service_endpoint,
+                });
+            }
+        }
+
+        Ok(doc)
+    }
+}
+
+impl Log {
+    /// verify the log and build the did document from its final state
+    pub fn did_document(&self) -> Result<DidDocument, Error> {
+        let mut kvp = None;
+        for result in self.verify() {
+            let (_, _, k) = result?;
+            kvp = Some(k);
+        }
+        let kvp = kvp.ok_or(LogError::MissingEntries)?;
+        DidDocument::from_kvp(&self.vlad, &kvp)
+    }
+}
+
+// encode a value holding a multikey as a multibase string
+fn multikey(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::Data(b) => {
+            let (mk, _) = Multikey::try_decode_from(b)
+                .map_err(|e| LogError::VerifyFailed(e.to_string()))?;
+            Ok(EncodedMultikey::new(Base::Base58Btc, mk).to_string())
+        }
+        _ => Err(LogError::VerifyFailed("key value is not a multikey".to_string()).into()),
+    }
+}
+
+// get the name of the service branch a key is under
+fn service_name(services: &Key, key: &Key) -> Option<String> {
+    let s = key.as_str().strip_prefix(services.as_str())?;
+    let (name, _) = s.split_once('/')?;
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_did_document() {
+        let mk = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0"
+        )
+        .unwrap()
+        .to_inner();
+        let kvp = Kvp::from(BTreeMap::from([
+            (Key::try_from(identity::PUBKEY).unwrap(), Value::Data(mk.clone().into())),
+            (
+                Key::try_from("/services/relay/type").unwrap(),
+                Value::Str("MessagingService".to_string()),
+            ),
+            (
+                Key::try_from("/services/relay/endpoint").unwrap(),
+                Value::Str("https://relay.example.com".to_string()),
+            ),
+            // a service without an endpoint is left out
+            (
+                Key::try_from("/services/broken/type").unwrap(),
+                Value::Str("Broken".to_string()),
+            ),
+        ]));
+
+        let vlad = Vlad::default();
+        let doc = DidDocument::from_kvp(&vlad, &kvp).unwrap();
+        assert_eq!(did(&vlad), doc.id);
+        assert!(doc.id.starts_with("did:plog:"));
+        assert_eq!(1, doc.verification_method.len());
+        assert_eq!(
+            EncodedMultikey::new(Base::Base58Btc, mk).to_string(),
+            doc.verification_method[0].public_key_multibase
+        );
+        assert_eq!(vec![format!("{}#pubkey", doc.id)], doc.authentication);
+        assert_eq!(1, doc.service.len());
+        assert_eq!("https://relay.example.com", doc.service[0].service_endpoint);
+    }
+}
@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Selective disclosure of values. Instead of the value itself, the entry
+//! records a salted hash commitment of it, stored as the encoded multihash
+//! in a data value. The value and salt travel out-of-band and anyone holding
+//! the log can check a disclosed value against the commitment without the
+//! value ever being in the log:
+//!
+//! ```text
+//! commitment = hash(salt || encoded value)
+//! ```
+use crate::{
+    error::{KvpError, ValueError},
+    Error, Key, Log, Op, Value,
+};
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+use multitrait::TryDecodeFrom;
+use multiutil::CodecInfo;
+use rand::RngCore;
+
+/// the hash codec used for new commitments
+pub const COMMITMENT_CODEC: Codec = Codec::Sha3256;
+
+/// the number of random salt bytes in new disclosures
+pub const SALT_LEN: usize = 32;
+
+/// A value and the salt of its commitment, disclosed out-of-band
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disclosure {
+    /// the committed value
+    pub value: Value,
+    /// the salt hashed along with the value
+    pub salt: Vec<u8>,
+}
+
+impl Disclosure {
+    /// create a disclosure of the value with a random salt
+    pub fn new(value: &Value) -> Self {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self::with_salt(value, &salt)
+    }
+
+    /// create a disclosure of the value with the given salt
+    pub fn with_salt(value: &Value, salt: &[u8]) -> Self {
+        Self {
+            value: value.clone(),
+            salt: salt.to_vec(),
+        }
+    }
+
+    /// get the commitment hash of the value using the hash codec
+    pub fn commitment(&self, codec: Codec) -> Result<Multihash, Error> {
+        let mut v = self.salt.clone();
+        v.append(&mut self.value.clone().into());
+        Ok(mh::Builder::new_from_bytes(codec, &v)?.try_build()?)
+    }
+
+    /// get the op that records the commitment of the value at the key
+    pub fn commit_op(&self, key: &Key) -> Result<Op, Error> {
+        let hash = self.commitment(COMMITMENT_CODEC)?;
+        Ok(Op::Update(key.clone(), Value::Data(hash.into())))
+    }
+
+    /// check the disclosure against the committed value
+    pub fn verify(&self, committed: &Value) -> Result<(), Error> {
+        let hash = match committed {
+            Value::Data(b) => match Multihash::try_decode_from(b) {
+                Ok((hash, ptr)) if ptr.is_empty() => hash,
+                _ => return Err(ValueError::NotACommitment.into()),
+            },
+            _ => return Err(ValueError::NotACommitment.into()),
+        };
+        if self.commitment(hash.codec())? != hash {
+            return Err(ValueError::CommitmentMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+impl Log {
+    /// check the disclosure against the commitment at the key in the
+    /// verified state at the head of the log
+    pub fn verify_disclosure(&self, key: &Key, disclosure: &Disclosure) -> Result<(), Error> {
+        let committed = self
+            .head_value(key)?
+            .ok_or_else(|| KvpError::KeyNotFound(key.to_string()))?;
+        disclosure.verify(&committed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disclosure() {
+        let key = Key::try_from("/email").unwrap();
+        let d = Disclosure::new(&Value::Str("zig@example.com".to_string()));
+        assert_eq!(SALT_LEN, d.salt.len());
+        let committed = match d.commit_op(&key).unwrap() {
+            Op::Update(k, v) => {
+                assert_eq!(key, k);
+                v
+            }
+            _ => panic!("expected an update"),
+        };
+        // the value isn't in the commitment
+        assert!(!committed.as_ref().windows(3).any(|w| w == b"zig"));
+        d.verify(&committed).unwrap();
+
+        // a different value or salt doesn't match
+        let other = Disclosure::with_salt(&Value::Str("zag@example.com".to_string()), &d.salt);
+        assert!(matches!(
+            other.verify(&committed),
+            Err(Error::Value(ValueError::CommitmentMismatch))
+        ));
+        let other = Disclosure::with_salt(&d.value, b"salt");
+        assert!(other.verify(&committed).is_err());
+        assert!(matches!(
+            d.verify(&d.value),
+            Err(Error::Value(ValueError::NotACommitment))
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Graphviz DOT output of the log structure. Each entry is a node labeled
+//! with its seqno and cid, prev links are solid edges and lipmaa links are
+//! dashed edges. Links to entries that aren't in the log point at a red
+//! placeholder node so forks and lipmaa linking mistakes stand out.
+use crate::Log;
+use multibase::Base;
+use multicid::{Cid, EncodedCid};
+use multitrait::Null;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// the number of trailing characters of the encoded cid shown in labels
+pub const CID_LABEL_LEN: usize = 8;
+
+// get the short label for the cid
+fn cid_label(cid: &Cid) -> String {
+    let s = EncodedCid::new(Base::Base32Lower, cid.clone()).to_string();
+    s[s.len().saturating_sub(CID_LABEL_LEN)..].to_string()
+}
+
+impl Log {
+    /// get the Graphviz DOT graph of the log entries and their links
+    pub fn to_dot(&self) -> String {
+        // number the nodes in seqno order so the output is stable
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(cid, e)| (e.seqno(), (*cid).clone()));
+        let mut nodes: BTreeMap<&Cid, usize> = BTreeMap::default();
+        for (cid, _) in &entries {
+            let n = nodes.len();
+            nodes.insert(cid, n);
+        }
+
+        let mut dot = String::default();
+        // writing to a String can't fail
+        let _ = writeln!(dot, "digraph plog {{");
+        let _ = writeln!(dot, "\trankdir=RL");
+        for (cid, entry) in &entries {
+            let mut attrs = format!("label=\"{}\\n{}\"", entry.seqno(), cid_label(cid));
+            if **cid == self.head {
+                attrs.push_str(" shape=doublecircle");
+            } else if **cid == self.foot {
+                attrs.push_str(" shape=box");
+            }
+            let _ = writeln!(dot, "\tn{} [{}]", nodes[cid], attrs);
+        }
+
+        let mut missing: BTreeMap<Cid, usize> = BTreeMap::default();
+        for (cid, entry) in &entries {
+            for (link, style) in [
+                (entry.prev_ref(), "solid"),
+                (entry.lipmaa_ref(), "dashed color=blue"),
+            ] {
+                if link.is_null() {
+                    continue;
+                }
+                let target = match nodes.get(link) {
+                    Some(n) => format!("n{}", n),
+                    None => {
+                        let n = missing.len();
+                        format!("m{}", *missing.entry(link.clone()).or_insert(n))
+                    }
+                };
+                let _ = writeln!(dot, "\tn{} -> {} [style={}]", nodes[cid], target, style);
+            }
+        }
+        for (cid, n) in &missing {
+            let _ = writeln!(
+                dot,
+                "\tm{} [label=\"?\\n{}\" color=red style=dashed]",
+                n,
+                cid_label(cid)
+            );
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{entry, log, Lipmaa, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_to_dot() {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for seqno in 1..5u64 {
+            let mut b = entry::Builder::from(entries.last().unwrap()).with_unlock(&Script::default());
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(Vec::default())).unwrap());
+        }
+        let log = entries
+            .iter()
+            .fold(
+                log::Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+
+        let dot = log.to_dot();
+        assert!(dot.starts_with("digraph plog {"));
+        assert_eq!(5, dot.matches("[label=").count());
+        assert_eq!(4, dot.matches("style=solid").count());
+        // only seqno 4 has a lipmaa link, back to seqno 1
+        assert!(dot.contains("\tn4 -> n1 [style=dashed color=blue]"));
+        assert!(!dot.contains("color=red"));
+    }
+}
@@ -0,0 +1,378 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{
+    error::{LogError, ScriptError},
+    script::WasmKind,
+    Error, Key, Script, Stk,
+};
+use wacc::{prelude::StoreLimitsBuilder, vm, Pairs};
+
+/// the name of the function called in unlock scripts
+pub const UNLOCK_FUNC: &str = "for_great_justice";
+
+/// the name of the function called in lock scripts
+pub const LOCK_FUNC: &str = "move_every_zig";
+
+/// The names of the functions called in the unlock and lock scripts when
+/// verifying a Log. Scripts compiled by other toolchains can export them
+/// under conventional names instead of the default ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPoints {
+    /// the name of the function called in unlock scripts
+    pub unlock: String,
+    /// the name of the function called in lock scripts
+    pub lock: String,
+}
+
+impl EntryPoints {
+    /// create entry points with the given function names
+    pub fn new(unlock: &str, lock: &str) -> Self {
+        Self {
+            unlock: unlock.to_string(),
+            lock: lock.to_string(),
+        }
+    }
+}
+
+impl Default for EntryPoints {
+    fn default() -> Self {
+        Self::new(UNLOCK_FUNC, LOCK_FUNC)
+    }
+}
+
+/// A ScriptEngine executes the lock and unlock scripts while verifying a Log.
+/// The `current` pairs is the state the script can read from, the `proposed`
+/// pairs is the Entry being verified. Scripts communicate through the
+/// parameter and return stacks, a lock script succeeds if it leaves a
+/// success value on the top of the return stack.
+pub trait ScriptEngine {
+    /// run the named function in the script
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error>;
+
+    /// run the named function in the script and add the lines the script
+    /// logged to `log`. engines that don't capture script logs just run the
+    /// script.
+    #[allow(clippy::too_many_arguments)]
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let _ = log;
+        self.run(script, func, current, proposed, pstack, rstack, context)
+    }
+
+    /// run the named function in the script with at most `fuel` units of
+    /// fuel, adding the lines the script logged to `log`, and return the fuel
+    /// the script consumed. fuel is whatever deterministic measure of work
+    /// the engine meters, e.g. wasm fuel or the rhai operation count. an
+    /// engine that runs out fails the script with [`ScriptError::OutOfFuel`].
+    /// engines that don't meter execution run the script and report that it
+    /// consumed no fuel when there is no budget and fail with
+    /// [`ScriptError::Unmetered`] when there is one, rather than letting a
+    /// script run past a budget they can't enforce.
+    #[allow(clippy::too_many_arguments)]
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        if fuel.is_some() {
+            return Err(ScriptError::Unmetered.into());
+        }
+        self.run_with_log(script, func, current, proposed, pstack, rstack, context, log)?;
+        Ok(0)
+    }
+}
+
+/// The default ScriptEngine that runs WASM scripts in the wacc vm. The wacc
+/// vm doesn't meter the scripts it runs so it rejects a fuel budget, use the
+/// `InterpEngine` of the `wasm-interp` feature to verify with one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WaccEngine;
+
+impl ScriptEngine for WaccEngine {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let mut log = Vec::default();
+        self.run_with_log(script, func, current, proposed, pstack, rstack, context, &mut log)
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        // the wacc vm only runs core wasm modules
+        if script.wasm_kind() == Some(WasmKind::Component) {
+            return Err(ScriptError::UnsupportedComponent.into());
+        }
+
+        let ctx = vm::Context {
+            current,
+            proposed,
+            pstack,
+            rstack,
+            check_count: 0,
+            write_idx: 0,
+            context: context.to_string(), // set the branch path for branch()
+            log: Vec::default(),
+            limiter: StoreLimitsBuilder::new()
+                .memory_size(1 << 16)
+                .instances(2)
+                .memories(1)
+                .build(),
+        };
+
+        let mut instance = vm::Builder::new()
+            .with_context(ctx)
+            .with_bytes(script.clone())
+            .try_build()
+            .map_err(LogError::Wacc)?;
+
+        // run the script and keep what it logged even if it failed
+        let result = instance.run(func);
+        log.extend(instance.context().log.iter().cloned());
+        if let Some(e) = result.err() {
+            return Err(LogError::Wacc(e).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A ScriptEngine that runs core wasm module scripts with one engine and
+/// wasm component scripts with another, so that an engine for wit defined
+/// component scripts can be used alongside the engine for module scripts.
+/// This crate has no component engine, one has to be provided with
+/// [`DispatchEngine::with_component_engine`]. Until then component scripts
+/// fail with [`ScriptError::NoComponentEngine`].
+pub struct DispatchEngine<'a> {
+    module: &'a dyn ScriptEngine,
+    component: Option<&'a dyn ScriptEngine>,
+}
+
+impl<'a> DispatchEngine<'a> {
+    /// create a dispatching engine that runs module scripts with the given
+    /// engine and rejects component scripts
+    pub fn new(module: &'a dyn ScriptEngine) -> Self {
+        Self {
+            module,
+            component: None,
+        }
+    }
+
+    /// set the engine that runs component scripts
+    pub fn with_component_engine(mut self, component: &'a dyn ScriptEngine) -> Self {
+        self.component = Some(component);
+        self
+    }
+
+    // get the engine that runs the script
+    fn engine_for(&self, script: &Script) -> Result<&'a dyn ScriptEngine, Error> {
+        match script.wasm_kind() {
+            Some(WasmKind::Component) => {
+                Ok(self.component.ok_or(ScriptError::NoComponentEngine)?)
+            }
+            _ => Ok(self.module),
+        }
+    }
+}
+
+impl ScriptEngine for DispatchEngine<'_> {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let engine = self.engine_for(script)?;
+        engine.run(script, func, current, proposed, pstack, rstack, context)
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let engine = self.engine_for(script)?;
+        engine.run_with_log(script, func, current, proposed, pstack, rstack, context, log)
+    }
+
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        let engine = self.engine_for(script)?;
+        engine.run_metered(script, func, current, proposed, pstack, rstack, context, log, fuel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Kvp;
+    use std::cell::Cell;
+
+    #[derive(Default)]
+    struct Counter {
+        runs: Cell<usize>,
+    }
+
+    impl ScriptEngine for Counter {
+        fn run(
+            &self,
+            _script: &Script,
+            _func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            _rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            self.runs.set(self.runs.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let component = Script::Bin(Key::default(), b"\0asm\x0d\0\x01\0".to_vec());
+        let module = Script::Bin(Key::default(), b"\0asm\x01\0\0\0".to_vec());
+        let kvp = Kvp::default();
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+
+        // the wacc engine rejects components up front
+        assert!(WaccEngine
+            .run(&component, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &Key::default())
+            .is_err());
+
+        let modules = Counter::default();
+        let components = Counter::default();
+        let engine = DispatchEngine::new(&modules);
+        let root = Key::default();
+        assert!(matches!(
+            engine.run(&component, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &root),
+            Err(Error::Script(ScriptError::NoComponentEngine))
+        ));
+
+        let engine = engine.with_component_engine(&components);
+        engine
+            .run(&component, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &Key::default())
+            .unwrap();
+        engine
+            .run(&module, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &Key::default())
+            .unwrap();
+        assert_eq!(1, modules.runs.get());
+        assert_eq!(1, components.runs.get());
+    }
+    #[test]
+    fn test_unmetered_fuel() {
+        // a lock that never returns
+        let spin = Script::Code(
+            Key::default(),
+            r#"(module
+                (func $main (export "move_every_zig") (param) (result i32)
+                    (loop $spin (br $spin))
+                    i32.const 1
+                )
+                (memory (export "memory") 1)
+            )"#
+            .to_string(),
+        );
+        let kvp = Kvp::default();
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+        let mut log = Vec::default();
+
+        // the wacc vm can't enforce the budget so it refuses to run the
+        // script instead of spinning forever
+        assert!(matches!(
+            WaccEngine.run_metered(
+                &spin,
+                LOCK_FUNC,
+                &kvp,
+                &kvp,
+                &mut pstack,
+                &mut rstack,
+                &Key::default(),
+                &mut log,
+                Some(1_000),
+            ),
+            Err(Error::Script(ScriptError::Unmetered))
+        ));
+
+        // and engines that don't meter run the script when there is no budget
+        let counter = Counter::default();
+        let consumed = counter
+            .run_metered(
+                &spin,
+                LOCK_FUNC,
+                &kvp,
+                &kvp,
+                &mut pstack,
+                &mut rstack,
+                &Key::default(),
+                &mut log,
+                None,
+            )
+            .unwrap();
+        assert_eq!(0, consumed);
+        assert_eq!(1, counter.runs.get());
+    }
+}
@@ -1,5 +1,9 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{error::EntryError, Error, Key, Lipmaa, Op, Script, Value};
+use crate::{
+    conflict::OpConflicts,
+    error::{decode_field, EntryError},
+    script, Error, Key, Limits, Lipmaa, Log, Op, OpId, Script, ScriptId, Value, ValueId,
+};
 use core::fmt;
 use multibase::Base;
 use multicid::{cid, Cid, EncodedCid, Vlad};
@@ -7,7 +11,7 @@ use multicodec::Codec;
 use multihash::mh;
 use multitrait::{Null, TryDecodeFrom};
 use multiutil::{BaseEncoded, CodecInfo, EncodingInfo, Varbytes, Varuint};
-use std::{convert::From, cmp::Ordering};
+use std::{convert::From, cmp::Ordering, sync::OnceLock};
 
 /// the multicodec sigil for a provenance entry
 pub const SIGIL: Codec = Codec::ProvenanceLogEntry;
@@ -15,6 +19,10 @@ pub const SIGIL: Codec = Codec::ProvenanceLogEntry;
 /// the current version of provenance entries this supports
 pub const ENTRY_VERSION: u64 = 1;
 
+/// the version of provenance entries that store each distinct key-path once
+/// in a table and refer to it by index in the ops and scripts
+pub const ENTRY_VERSION_PATHS: u64 = 2;
+
 /// the list of keys for the fields in an entry
 pub const ENTRY_FIELDS: &[&str] = &[
     "/entry/",
@@ -24,6 +32,7 @@ pub const ENTRY_FIELDS: &[&str] = &[
     "/entry/lipmaa",
     "/entry/seqno",
     "/entry/ops",
+    "/entry/locks",
     "/entry/unlock",
     "/entry/proof",
 ];
@@ -31,6 +40,19 @@ pub const ENTRY_FIELDS: &[&str] = &[
 /// a base encoded provenance entry
 pub type EncodedEntry = BaseEncoded<Entry>;
 
+// the memoized cid of an entry. it is ignored when comparing entries so an
+// entry that has calculated its cid is equal to one that hasn't.
+#[derive(Clone, Default)]
+pub(crate) struct CidCache(OnceLock<Cid>);
+
+impl PartialEq for CidCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CidCache {}
+
 /// An Entry represents a single state change associated with a key/value pair
 /// in a provenance log.
 #[derive(Clone, Eq, PartialEq)]
@@ -58,6 +80,8 @@ pub struct Entry {
     /// closure to the `try_build` function that gets called with the complete
     /// serialized Entry to generate this data.
     pub(crate) proof: Vec<u8>,
+    /// the cid of the entry, calculated the first time it is needed
+    pub(crate) cached_cid: CidCache,
 }
 
 impl Ord for Entry {
@@ -106,6 +130,10 @@ impl wacc::Pairs for Entry {
                     Value::Data(data) => Some(wacc::Value::Bin{ hint: key.to_string(), data }),
                     Value::Str(s) => Some(wacc::Value::Str{ hint: key.to_string(), data: s }),
                     Value::Nil => None,
+                    // sealed values are opaque to the scripts
+                    v @ Value::Encrypted { .. } => Some(wacc::Value::Bin{ hint: key.to_string(), data: v.into() }),
+                    // so are references to other logs
+                    v @ Value::PlogRef { .. } => Some(wacc::Value::Bin{ hint: key.to_string(), data: v.into() }),
                 }
             },
             None => None,
@@ -132,20 +160,34 @@ impl From<Entry> for Vec<u8> {
         v.append(&mut val.lipmaa.clone().into());
         // add in the seqno
         v.append(&mut Varuint(val.seqno).into());
+        // add in the path table if the entry uses one
+        let paths = match val.version {
+            ENTRY_VERSION_PATHS => {
+                let paths = PathTable::from(&val);
+                v.append(&mut paths.clone().into());
+                Some(paths)
+            }
+            _ => None,
+        };
         // add in the number of ops
         v.append(&mut Varuint(val.ops.len()).into());
         // add in the ops
-        val.ops
-            .iter()
-            .for_each(|op| v.append(&mut op.clone().into()));
+        val.ops.iter().for_each(|op| match &paths {
+            Some(paths) => v.append(&mut paths.encode_op(op)),
+            None => v.append(&mut op.clone().into()),
+        });
         // first add the number of keys
         v.append(&mut Varuint(val.locks.len()).into());
         // add in the locks
-        val.locks
-            .iter()
-            .for_each(|script| v.append(&mut script.clone().into()));
+        val.locks.iter().for_each(|script| match &paths {
+            Some(paths) => v.append(&mut paths.encode_script(script)),
+            None => v.append(&mut script.clone().into()),
+        });
         // add in the unlock script
-        v.append(&mut val.unlock.clone().into());
+        match &paths {
+            Some(paths) => v.append(&mut paths.encode_script(&val.unlock)),
+            None => v.append(&mut val.unlock.clone().into()),
+        }
         // add in the proof
         v.append(&mut Varbytes(val.proof.clone()).into());
         v
@@ -166,35 +208,47 @@ impl<'a> TryDecodeFrom<'a> for Entry {
 
     fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
         // decode the sigil
-        let (sigil, ptr) = Codec::try_decode_from(bytes)?;
+        let (sigil, ptr) = decode_field(bytes, bytes, "sigil", Codec::try_decode_from)?;
         if sigil != SIGIL {
             return Err(EntryError::MissingSigil.into());
         }
         // decode the version
-        let (version, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let (version, ptr) = decode_field(bytes, ptr, "version", Varuint::<u64>::try_decode_from)?;
         let version = version.to_inner();
-        if version != ENTRY_VERSION {
-            return Err(EntryError::InvalidVersion(1).into());
+        if version != ENTRY_VERSION && version != ENTRY_VERSION_PATHS {
+            return Err(EntryError::InvalidVersion(version as usize).into());
         }
         // decode the vlad
-        let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+        let (vlad, ptr) = decode_field(bytes, ptr, "vlad", Vlad::try_decode_from)?;
         // decode the prev cid
-        let (prev, ptr) = Cid::try_decode_from(ptr)?;
+        let (prev, ptr) = decode_field(bytes, ptr, "prev", Cid::try_decode_from)?;
         // decode the lipmaa cid
-        let (lipmaa, ptr) = Cid::try_decode_from(ptr)?;
+        let (lipmaa, ptr) = decode_field(bytes, ptr, "lipmaa", Cid::try_decode_from)?;
         // decode the seqno
-        let (seqno, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let (seqno, ptr) = decode_field(bytes, ptr, "seqno", Varuint::<u64>::try_decode_from)?;
         let seqno = seqno.to_inner();
+        // decode the path table if the entry has one
+        let (paths, ptr) = match version {
+            ENTRY_VERSION_PATHS => {
+                let (paths, ptr) = decode_field(bytes, ptr, "paths", PathTable::try_decode_from)?;
+                (Some(paths), ptr)
+            }
+            _ => (None, ptr),
+        };
         // decode the number of ops
-        let (num_ops, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let (num_ops, ptr) = decode_field(bytes, ptr, "ops", Varuint::<usize>::try_decode_from)?;
         // decode the ops
         let (ops, ptr) = match *num_ops {
             0 => (Vec::default(), ptr),
             _ => {
                 let mut ops = Vec::with_capacity(*num_ops);
                 let mut p = ptr;
-                for _ in 0..*num_ops {
-                    let (op, ptr) = Op::try_decode_from(p)?;
+                for i in 0..*num_ops {
+                    let (op, ptr) =
+                        decode_field(bytes, p, format_args!("ops[{}]", i), |p| match &paths {
+                            Some(paths) => paths.decode_op(p),
+                            None => Op::try_decode_from(p),
+                        })?;
                     ops.push(op);
                     p = ptr;
                 }
@@ -202,15 +256,20 @@ impl<'a> TryDecodeFrom<'a> for Entry {
             }
         };
         // decode the number of lock scripts
-        let (num_locks, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let (num_locks, ptr) =
+            decode_field(bytes, ptr, "locks", Varuint::<usize>::try_decode_from)?;
         // decode the ops
         let (locks, ptr) = match *num_locks {
             0 => (Vec::default(), ptr),
             _ => {
                 let mut locks = Vec::with_capacity(*num_locks);
                 let mut p = ptr;
-                for _ in 0..*num_locks {
-                    let (lock, ptr) = Script::try_decode_from(p)?;
+                for i in 0..*num_locks {
+                    let (lock, ptr) =
+                        decode_field(bytes, p, format_args!("locks[{}]", i), |p| match &paths {
+                            Some(paths) => paths.decode_script(p),
+                            None => Script::try_decode_from(p),
+                        })?;
                     locks.push(lock);
                     p = ptr;
                 }
@@ -218,25 +277,37 @@ impl<'a> TryDecodeFrom<'a> for Entry {
             }
         };
         // decode the unlock script
-        let (unlock, ptr) = Script::try_decode_from(ptr)?;
+        let (unlock, ptr) = decode_field(bytes, ptr, "unlock", |p| match &paths {
+            Some(paths) => paths.decode_script(p),
+            None => Script::try_decode_from(p),
+        })?;
         // decode the proof
-        let (proof, ptr) = Varbytes::try_decode_from(ptr)?;
+        let (proof, ptr) = decode_field(bytes, ptr, "proof", Varbytes::try_decode_from)?;
         let proof = proof.to_inner();
 
-        Ok((
-            Self {
-                version,
-                vlad,
-                prev,
-                lipmaa,
-                seqno,
-                ops,
-                locks,
-                unlock,
-                proof,
-            },
-            ptr,
-        ))
+        let entry = Self {
+            version,
+            vlad,
+            prev,
+            lipmaa,
+            seqno,
+            ops,
+            locks,
+            unlock,
+            proof,
+            cached_cid: CidCache::default(),
+        };
+
+        // make sure the entry re-encodes to the exact bytes it was decoded
+        // from so that the cid we calculate matches the sender's
+        let canonical = entry.canonical_bytes();
+        if canonical != bytes[..bytes.len() - ptr.len()] {
+            return Err(EntryError::NonCanonicalEncoding.into());
+        }
+        // we already have the bytes so calculate the cid now
+        let _ = entry.cached_cid.0.set(cid_of(&canonical));
+
+        Ok((entry, ptr))
     }
 }
 
@@ -316,10 +387,45 @@ impl Entry {
                     .for_each(|op| v.append(&mut op.clone().into()));
                 Some(Value::Data(v))
             }
-            // TODO: make this accessible via an iterator
-            //"/entry/locks" => Some(Value::Data(self.locks.clone().into())),
+            "/entry/locks" => {
+                let mut v = Vec::new();
+                v.append(&mut Varuint(self.locks.len()).into());
+                self.locks
+                    .iter()
+                    .for_each(|lock| v.append(&mut lock.clone().into()));
+                Some(Value::Data(v))
+            }
             "/entry/unlock" => Some(Value::Data(self.unlock.clone().into())),
             "/entry/proof" => Some(Value::Data(self.proof.clone())),
+            // the proofs for each check of a joined proof are at
+            // /entry/proof/<index>
+            s if s.starts_with("/entry/proof/") => {
+                let index = s["/entry/proof/".len()..].parse::<usize>().ok()?;
+                let proofs = crate::proof::split_proofs(&self.proof)?;
+                Some(Value::Data(proofs.get(index)?.clone()))
+            }
+            // the individual lock scripts are at /entry/locks/<index>
+            s if s.starts_with("/entry/locks/") => {
+                let index = s["/entry/locks/".len()..].parse::<usize>().ok()?;
+                let lock = self.locks.get(index)?;
+                Some(Value::Data(lock.clone().into()))
+            }
+            // the individual ops are at /entry/ops/<index> with their path
+            // and value at /entry/ops/<index>/path and /entry/ops/<index>/value
+            s if s.starts_with("/entry/ops/") => {
+                let mut parts = s["/entry/ops/".len()..].splitn(2, '/');
+                let index = parts.next()?.parse::<usize>().ok()?;
+                let op = self.ops.get(index)?;
+                match parts.next() {
+                    None => Some(Value::Data(op.clone().into())),
+                    Some("path") => Some(Value::Str(op.path_ref().to_string())),
+                    Some("value") => match op {
+                        Op::Update(_, value) => Some(value.clone()),
+                        _ => None,
+                    },
+                    Some(_) => None,
+                }
+            }
             _ => None,
         }
     }
@@ -329,6 +435,16 @@ impl Entry {
         self.prev.clone()
     }
 
+    /// Get a reference to the cid of the previous entry
+    pub fn prev_ref(&self) -> &Cid {
+        &self.prev
+    }
+
+    /// Get a reference to the lipmaa link
+    pub fn lipmaa_ref(&self) -> &Cid {
+        &self.lipmaa
+    }
+
     /// Get the sequence number of the entry
     pub fn seqno(&self) -> u64 {
         self.seqno
@@ -339,6 +455,11 @@ impl Entry {
         self.vlad.clone()
     }
 
+    /// Get a reference to the vlad for the whole p.log
+    pub fn vlad_ref(&self) -> &Vlad {
+        &self.vlad
+    }
+
     /// get an iterator over the operations in the entry
     pub fn ops(&self) -> impl Iterator<Item = &Op> {
         self.ops.iter()
@@ -349,19 +470,20 @@ impl Entry {
         self.locks.iter()
     }
 
-    /// get the cid of this entry
+    /// get the canonical encoding of this entry. decoding rejects any bytes
+    /// that aren't canonical so these are always the bytes the entry was
+    /// decoded from.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.clone().into()
+    }
+
+    /// get the cid of this entry. it is calculated the first time it is
+    /// asked for and then remembered.
     pub fn cid(&self) -> Cid {
-        let v: Vec<u8> = self.clone().into();
-        cid::Builder::new(Codec::Cidv1)
-            .with_target_codec(Codec::DagCbor)
-            .with_hash(
-                &mh::Builder::new_from_bytes(Codec::Sha3512, v.as_slice())
-                    .unwrap()
-                    .try_build()
-                    .unwrap(),
-            )
-            .try_build()
-            .unwrap()
+        self.cached_cid
+            .0
+            .get_or_init(|| cid_of(&self.canonical_bytes()))
+            .clone()
     }
 
     /// get the longest common branch context from the ops
@@ -370,11 +492,11 @@ impl Entry {
             Key::default()
         } else {
             // get the first branch
-            let mut ctx = self.ops.first().unwrap().clone().path().branch();
+            let mut ctx = self.ops.first().unwrap().path_ref().branch();
 
             // got through the rest looking for the shortest one
             for k in self.ops.iter() {
-                ctx = k.path().branch().longest_common_branch(&ctx);
+                ctx = k.path_ref().branch().longest_common_branch(&ctx);
             }
             ctx
         }
@@ -405,13 +527,15 @@ impl Entry {
         // go through the set of mutation operations to figure out which lock scripts govern the
         // proposed mutations
         for op in ops {
-            //println!("checking op {}", op.path());
+            #[cfg(feature = "tracing")]
+            tracing::trace!(path = %op.path_ref(), "finding the locks governing op");
             for lock in &locks_in {
                 // if the lock is a leaf, then parent_of is true if the op path is teh same
                 // if the lock is a branch, then parent_of is true if the other path is a child
                 // of the branch
-                if lock.path().parent_of(&op.path()) && !locks_tmp.contains(lock) {
-                    //println!("adding lock {} because of op {}", lock.path(), op.path());
+                if lock.path_ref().parent_of(op.path_ref()) && !locks_tmp.contains(lock) {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(lock = %lock.path_ref(), path = %op.path_ref(), "lock governs op");
                     locks_tmp.push(lock.clone());
                 }
             }
@@ -433,6 +557,550 @@ impl Entry {
     }
 }
 
+// calculate the cid of the encoded entry bytes
+fn cid_of(bytes: &[u8]) -> Cid {
+    cid::Builder::new(Codec::Cidv1)
+        .with_target_codec(Codec::DagCbor)
+        .with_hash(
+            &mh::Builder::new_from_bytes(Codec::Sha3512, bytes)
+                .unwrap()
+                .try_build()
+                .unwrap(),
+        )
+        .try_build()
+        .unwrap()
+}
+
+// skip over varbytes, returning the data without copying it and the rest
+fn skip_varbytes(bytes: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let (len, ptr) = Varuint::<usize>::try_decode_from(bytes)?;
+    if ptr.len() < *len {
+        return Err(EntryError::Truncated.into());
+    }
+    Ok(ptr.split_at(*len))
+}
+
+// skips over an encoded object and returns the rest of the bytes
+type Skip = fn(&[u8]) -> Result<&[u8], Error>;
+
+// skip over the key-path of an op or script
+fn skip_path(bytes: &[u8]) -> Result<&[u8], Error> {
+    Ok(skip_varbytes(bytes)?.1)
+}
+
+// skip over the index of a key-path in a path table
+fn skip_path_index(bytes: &[u8]) -> Result<&[u8], Error> {
+    Ok(Varuint::<usize>::try_decode_from(bytes)?.1)
+}
+
+// skip over an encoded op
+fn skip_op(bytes: &[u8]) -> Result<&[u8], Error> {
+    skip_op_with(bytes, skip_path)
+}
+
+// skip over an encoded op that refers to a path table
+fn skip_op_indexed(bytes: &[u8]) -> Result<&[u8], Error> {
+    skip_op_with(bytes, skip_path_index)
+}
+
+fn skip_op_with(bytes: &[u8], skip_key: Skip) -> Result<&[u8], Error> {
+    let (id, ptr) = OpId::try_decode_from(bytes)?;
+    // skip the key
+    let ptr = skip_key(ptr)?;
+    match id {
+        OpId::Update => {
+            // skip the value
+            let (id, rest) = ValueId::try_decode_from(ptr)?;
+            match id {
+                ValueId::Nil => Ok(rest),
+                // sealed values have more than one field
+                ValueId::Encrypted => Ok(Value::try_decode_from(ptr)?.1),
+                _ => Ok(skip_varbytes(rest)?.1),
+            }
+        }
+        _ => Ok(ptr),
+    }
+}
+
+// skip over an encoded script
+fn skip_script(bytes: &[u8]) -> Result<&[u8], Error> {
+    skip_script_with(bytes, skip_path)
+}
+
+// skip over an encoded script that refers to a path table
+fn skip_script_indexed(bytes: &[u8]) -> Result<&[u8], Error> {
+    skip_script_with(bytes, skip_path_index)
+}
+
+fn skip_script_with(bytes: &[u8], skip_key: Skip) -> Result<&[u8], Error> {
+    let (sigil, ptr) = Codec::try_decode_from(bytes)?;
+    if sigil != script::SIGIL {
+        return Err(crate::error::ScriptError::MissingSigil.into());
+    }
+    let (id, ptr) = ScriptId::try_decode_from(ptr)?;
+    // skip the key
+    let ptr = skip_key(ptr)?;
+    match id {
+        ScriptId::Cid => Ok(Cid::try_decode_from(ptr)?.1),
+        _ => Ok(skip_varbytes(ptr)?.1),
+    }
+}
+
+// split off the bytes of count encoded objects
+fn split_objects<'a>(
+    bytes: &'a [u8],
+    count: usize,
+    skip: fn(&'a [u8]) -> Result<&'a [u8], Error>,
+) -> Result<(&'a [u8], &'a [u8]), Error> {
+    let mut ptr = bytes;
+    for _ in 0..count {
+        ptr = skip(ptr)?;
+    }
+    Ok(bytes.split_at(bytes.len() - ptr.len()))
+}
+
+// the table of distinct key-paths in a version 2 entry. the paths are sorted
+// and each one is stored as the number of bytes it shares with the path
+// before it followed by the rest of the path, so paths under the same deep
+// branch only store the branch once.
+#[derive(Clone, Default)]
+struct PathTable(Vec<Key>);
+
+impl From<&Entry> for PathTable {
+    fn from(entry: &Entry) -> Self {
+        let mut paths: Vec<Key> = entry
+            .ops
+            .iter()
+            .map(|op| op.path_ref())
+            .chain(entry.locks.iter().map(|lock| lock.path_ref()))
+            .chain(std::iter::once(entry.unlock.path_ref()))
+            .cloned()
+            .collect();
+        paths.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        paths.dedup();
+        Self(paths)
+    }
+}
+
+impl From<PathTable> for Vec<u8> {
+    fn from(val: PathTable) -> Self {
+        let mut v = Vec::default();
+        // add in the number of paths
+        v.append(&mut Varuint(val.0.len()).into());
+        let mut last = "";
+        for path in &val.0 {
+            let path = path.as_str();
+            // find the shared prefix ending on a char boundary
+            let mut shared = last
+                .bytes()
+                .zip(path.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            while !path.is_char_boundary(shared) {
+                shared -= 1;
+            }
+            // add in the shared length and the rest of the path
+            v.append(&mut Varuint(shared).into());
+            v.append(&mut Varbytes(path.as_bytes()[shared..].to_vec()).into());
+            last = path;
+        }
+        v
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for PathTable {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the number of paths
+        let (num_paths, mut ptr) = Varuint::<usize>::try_decode_from(bytes)?;
+        let mut paths: Vec<Key> = Vec::with_capacity((*num_paths).min(ptr.len()));
+        let mut last = String::default();
+        for _ in 0..*num_paths {
+            // decode the shared length and the rest of the path
+            let (shared, p) = Varuint::<usize>::try_decode_from(ptr)?;
+            let (rest, p) = Varbytes::try_decode_from(p)?;
+            if *shared > last.len() || !last.is_char_boundary(*shared) {
+                return Err(EntryError::InvalidPathIndex(*shared).into());
+            }
+            let mut path = last[..*shared].to_string();
+            path.push_str(&String::from_utf8(rest.to_inner())?);
+            paths.push(Key::try_from(path.as_str())?);
+            last = path;
+            ptr = p;
+        }
+        Ok((Self(paths), ptr))
+    }
+}
+
+impl PathTable {
+    fn index(&self, path: &Key) -> Varuint<usize> {
+        // every path in the entry is in the table
+        Varuint(self.0.iter().position(|p| p == path).unwrap_or_default())
+    }
+
+    fn decode_path<'a>(&self, bytes: &'a [u8]) -> Result<(Key, &'a [u8]), Error> {
+        let (i, ptr) = Varuint::<usize>::try_decode_from(bytes)?;
+        let path = self.0.get(*i).ok_or(EntryError::InvalidPathIndex(*i))?;
+        Ok((path.clone(), ptr))
+    }
+
+    fn encode_op(&self, op: &Op) -> Vec<u8> {
+        let mut v = Vec::default();
+        // add in the operation
+        v.append(&mut OpId::from(op).into());
+        // add in the path index
+        v.append(&mut self.index(op.path_ref()).into());
+        if let Op::Update(_, value) = op {
+            // add in the value data
+            v.append(&mut value.clone().into());
+        }
+        v
+    }
+
+    fn decode_op<'a>(&self, bytes: &'a [u8]) -> Result<(Op, &'a [u8]), Error> {
+        // decode the operation id
+        let (id, ptr) = decode_field(bytes, bytes, "id", OpId::try_decode_from)?;
+        let (key, ptr) = decode_field(bytes, ptr, "key", |p| self.decode_path(p))?;
+        match id {
+            OpId::Noop => Ok((Op::Noop(key), ptr)),
+            OpId::Delete => Ok((Op::Delete(key), ptr)),
+            OpId::Update => {
+                let (value, ptr) = decode_field(bytes, ptr, "value", Value::try_decode_from)?;
+                Ok((Op::Update(key, value), ptr))
+            }
+        }
+    }
+
+    fn encode_script(&self, script: &Script) -> Vec<u8> {
+        let mut v = Vec::default();
+        // add in the script sigil
+        v.append(&mut script::SIGIL.into());
+        // add in the script id
+        v.append(&mut ScriptId::from(script).into());
+        // add in the path index
+        v.append(&mut self.index(script.path_ref()).into());
+        match script {
+            Script::Bin(_, b) => v.append(&mut Varbytes(b.clone()).into()),
+            Script::Code(_, s) => v.append(&mut Varbytes(s.as_bytes().to_vec()).into()),
+            Script::Cid(_, c) => v.append(&mut c.clone().into()),
+        }
+        v
+    }
+
+    fn decode_script<'a>(&self, bytes: &'a [u8]) -> Result<(Script, &'a [u8]), Error> {
+        // decode the sigil
+        let (sigil, ptr) = decode_field(bytes, bytes, "sigil", Codec::try_decode_from)?;
+        if sigil != script::SIGIL {
+            return Err(crate::error::ScriptError::MissingSigil.into());
+        }
+        // decode the script id
+        let (id, ptr) = decode_field(bytes, ptr, "id", ScriptId::try_decode_from)?;
+        let (key, ptr) = decode_field(bytes, ptr, "path", |p| self.decode_path(p))?;
+        match id {
+            ScriptId::Bin => {
+                let (b, ptr) = decode_field(bytes, ptr, "bin", Varbytes::try_decode_from)?;
+                Ok((Script::Bin(key, b.to_inner()), ptr))
+            }
+            ScriptId::Code => {
+                let (s, ptr) = decode_field(bytes, ptr, "code", |p| {
+                    let (s, p) = Varbytes::try_decode_from(p)?;
+                    Ok::<_, Error>((String::from_utf8(s.to_inner())?, p))
+                })?;
+                Ok((Script::Code(key, s), ptr))
+            }
+            ScriptId::Cid => {
+                let (c, ptr) = decode_field(bytes, ptr, "cid", Cid::try_decode_from)?;
+                Ok((Script::Cid(key, c), ptr))
+            }
+        }
+    }
+}
+
+/// An EntryRef is a borrowed view of an encoded Entry. Decoding one only
+/// parses the vlad, links and seqno and records where the ops, lock scripts,
+/// unlock script and proof are in the encoded bytes. Those are decoded when
+/// they are asked for, so walking the links or calculating the cids of many
+/// entries doesn't copy every op value, script and proof.
+#[derive(Clone)]
+pub struct EntryRef<'a> {
+    bytes: &'a [u8],
+    version: u64,
+    vlad: Vlad,
+    prev: Cid,
+    lipmaa: Cid,
+    seqno: u64,
+    paths: Option<PathTable>,
+    num_ops: usize,
+    ops: &'a [u8],
+    num_locks: usize,
+    locks: &'a [u8],
+    unlock: &'a [u8],
+    proof: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for EntryRef<'a> {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (er, _) = Self::try_decode_from(bytes)?;
+        Ok(er)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for EntryRef<'a> {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the sigil
+        let (sigil, ptr) = Codec::try_decode_from(bytes)?;
+        if sigil != SIGIL {
+            return Err(EntryError::MissingSigil.into());
+        }
+        // decode the version
+        let (version, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let version = version.to_inner();
+        if version != ENTRY_VERSION && version != ENTRY_VERSION_PATHS {
+            return Err(EntryError::InvalidVersion(version as usize).into());
+        }
+        // decode the vlad
+        let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+        // decode the prev cid
+        let (prev, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the lipmaa cid
+        let (lipmaa, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the seqno
+        let (seqno, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let seqno = seqno.to_inner();
+        // decode the path table if the entry has one
+        let (paths, ptr) = match version {
+            ENTRY_VERSION_PATHS => {
+                let (paths, ptr) = PathTable::try_decode_from(ptr)?;
+                (Some(paths), ptr)
+            }
+            _ => (None, ptr),
+        };
+        let (skip_op, skip_script): (Skip, Skip) = match paths {
+            Some(_) => (skip_op_indexed, skip_script_indexed),
+            None => (skip_op, skip_script),
+        };
+        // find the ops
+        let (num_ops, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let (ops, ptr) = split_objects(ptr, *num_ops, skip_op)?;
+        // find the lock scripts
+        let (num_locks, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let (locks, ptr) = split_objects(ptr, *num_locks, skip_script)?;
+        // find the unlock script
+        let (unlock, ptr) = split_objects(ptr, 1, skip_script)?;
+        // find the proof
+        let (proof, ptr) = skip_varbytes(ptr)?;
+
+        let er = Self {
+            bytes: &bytes[..bytes.len() - ptr.len()],
+            version,
+            vlad,
+            prev,
+            lipmaa,
+            seqno,
+            paths,
+            num_ops: *num_ops,
+            ops,
+            num_locks: *num_locks,
+            locks,
+            unlock,
+            proof,
+        };
+        Ok((er, ptr))
+    }
+}
+
+impl fmt::Debug for EntryRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} - #{} (ref)\n\t{}\n\t{}",
+            SIGIL,
+            self.seqno,
+            EncodedCid::new(Base::Base32Lower, self.cid()),
+            EncodedCid::new(Base::Base32Lower, self.prev.clone())
+        )
+    }
+}
+
+impl<'a> EntryRef<'a> {
+    /// get the encoded bytes of the entry
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Get the entry version
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Get the vlad for the whole p.log
+    pub fn vlad(&self) -> &Vlad {
+        &self.vlad
+    }
+
+    /// Get the cid of the previous entry if there is one
+    pub fn prev(&self) -> &Cid {
+        &self.prev
+    }
+
+    /// Get the lipmaa link if there is one
+    pub fn lipmaa(&self) -> &Cid {
+        &self.lipmaa
+    }
+
+    /// Get the sequence number of the entry
+    pub fn seqno(&self) -> u64 {
+        self.seqno
+    }
+
+    /// get an iterator that decodes the operations in the entry
+    pub fn ops(&self) -> impl Iterator<Item = Result<Op, Error>> + 'a {
+        let mut ptr = self.ops;
+        let paths = self.paths.clone();
+        (0..self.num_ops).map(move |_| {
+            let (op, p) = match &paths {
+                Some(paths) => paths.decode_op(ptr)?,
+                None => Op::try_decode_from(ptr)?,
+            };
+            ptr = p;
+            Ok(op)
+        })
+    }
+
+    /// get an iterator that decodes the lock scripts
+    pub fn locks(&self) -> impl Iterator<Item = Result<Script, Error>> + 'a {
+        let mut ptr = self.locks;
+        let paths = self.paths.clone();
+        (0..self.num_locks).map(move |_| {
+            let (lock, p) = match &paths {
+                Some(paths) => paths.decode_script(ptr)?,
+                None => Script::try_decode_from(ptr)?,
+            };
+            ptr = p;
+            Ok(lock)
+        })
+    }
+
+    /// decode the unlock script
+    pub fn unlock(&self) -> Result<Script, Error> {
+        match &self.paths {
+            Some(paths) => Ok(paths.decode_script(self.unlock)?.0),
+            None => Script::try_from(self.unlock),
+        }
+    }
+
+    /// get the proof without copying it
+    pub fn proof(&self) -> &'a [u8] {
+        self.proof
+    }
+
+    /// get the cid of the entry calculated over the encoded bytes
+    pub fn cid(&self) -> Cid {
+        cid_of(self.bytes)
+    }
+
+    /// decode the owned Entry, this also checks that the encoding is
+    /// canonical
+    pub fn to_owned(&self) -> Result<Entry, Error> {
+        Entry::try_from(self.bytes)
+    }
+}
+
+/// An UnsignedEntry is an Entry without a proof. It is encoded like an Entry
+/// with an empty proof so it can be moved to the machine that produces the
+/// proof and the proof attached long after the entry was drafted.
+#[derive(Clone, Eq, PartialEq)]
+pub struct UnsignedEntry(Entry);
+
+impl CodecInfo for UnsignedEntry {
+    /// Return that we are a ProvenanceEntry object
+    fn preferred_codec() -> Codec {
+        SIGIL
+    }
+
+    /// Return the same
+    fn codec(&self) -> Codec {
+        Self::preferred_codec()
+    }
+}
+
+impl EncodingInfo for UnsignedEntry {
+    fn preferred_encoding() -> Base {
+        Base::Base16Lower
+    }
+
+    fn encoding(&self) -> Base {
+        Self::preferred_encoding()
+    }
+}
+
+impl From<UnsignedEntry> for Vec<u8> {
+    fn from(val: UnsignedEntry) -> Self {
+        val.0.into()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for UnsignedEntry {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (ue, _) = Self::try_decode_from(bytes)?;
+        Ok(ue)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for UnsignedEntry {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the entry and make sure it doesn't have a proof
+        let (entry, ptr) = Entry::try_decode_from(bytes)?;
+        if !entry.proof.is_empty() {
+            return Err(EntryError::AlreadySigned.into());
+        }
+        Ok((Self(entry), ptr))
+    }
+}
+
+impl fmt::Debug for UnsignedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} - #{} (unsigned)\n\t{}",
+            SIGIL,
+            self.0.seqno,
+            EncodedCid::new(Base::Base32Lower, self.0.prev())
+        )
+    }
+}
+
+impl UnsignedEntry {
+    /// get the bytes the proof is generated over. these are the canonical
+    /// bytes of the entry with an empty proof.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        self.0.canonical_bytes()
+    }
+
+    /// get the entry without its proof
+    pub fn entry(&self) -> &Entry {
+        &self.0
+    }
+
+    /// attach the proof to get the finished Entry
+    pub fn attach_proof(self, proof: &[u8]) -> Entry {
+        let mut entry = self.0;
+        entry.proof = proof.to_vec();
+        // the proof changes the cid
+        entry.cached_cid = CidCache::default();
+        entry
+    }
+}
+
 /// Builder for Entry objects
 #[derive(Clone)]
 pub struct Builder {
@@ -444,6 +1112,8 @@ pub struct Builder {
     ops: Vec<Op>,
     locks: Vec<Script>,
     unlock: Option<Script>,
+    limits: Limits,
+    conflicts: OpConflicts,
 }
 
 impl Default for Builder {
@@ -457,6 +1127,8 @@ impl Default for Builder {
             ops: Vec::default(),
             locks: Vec::default(),
             unlock: None,
+            limits: Limits::default(),
+            conflicts: OpConflicts::default(),
         }
     }
 }
@@ -473,18 +1145,56 @@ impl From<&Entry> for Builder {
             ops: Vec::default(),
             locks: entry.locks.clone(),
             unlock: None,
+            limits: Limits::default(),
+            conflicts: OpConflicts::default(),
         }
     }
 }
 
 impl Builder {
+    /// Store each distinct key-path once in a table at the start of the
+    /// entry and refer to it by index. This makes entries with many ops
+    /// under deep branches smaller. The entry is built with version
+    /// [`ENTRY_VERSION_PATHS`] which older decoders reject.
+    pub fn with_path_compression(mut self) -> Self {
+        self.version = ENTRY_VERSION_PATHS;
+        self
+    }
+
+    /// Set up the builder for the next entry after the head of the log. The
+    /// vlad, prev link, seqno and lock scripts come from the head entry and
+    /// the lipmaa link is looked up in the log when the seqno needs one.
+    pub fn with_log(mut self, log: &Log) -> Self {
+        match log.entries.get(&log.head) {
+            Some(head) => {
+                let next = Self::from(head);
+                self.vlad = next.vlad;
+                self.prev = next.prev;
+                self.seqno = next.seqno;
+                self.locks = next.locks;
+            }
+            None => {
+                self.vlad = Some(log.vlad.clone());
+                self.seqno = Some(0);
+            }
+        }
+        let seqno = self.seqno.unwrap_or_default();
+        if seqno.is_lipmaa() {
+            // a missing entry leaves the link unset so try_build fails
+            self.lipmaa = log.get_by_seqno(seqno.lipmaa()).map(Entry::cid);
+        }
+        self
+    }
+
     /// Set the Vlad
     pub fn with_vlad(mut self, vlad: &Vlad) -> Self {
         self.vlad = Some(vlad.clone());
         self
     }
 
-    /// Set the prev Cid
+    /// Set the prev Cid. This is the head of the log the entry is built
+    /// against so [`crate::Log::try_append`] rejects the entry with
+    /// [`crate::error::LogError::StaleHead`] if the head has moved on since.
     pub fn with_prev(mut self, cid: &Cid) -> Self {
         self.prev = Some(cid.clone());
         self
@@ -532,6 +1242,18 @@ impl Builder {
         self
     }
 
+    /// Set the size limits the entry is checked against when it is built
+    pub fn with_limits(mut self, limits: &Limits) -> Self {
+        self.limits = *limits;
+        self
+    }
+
+    /// Set how ops changing the same key are handled when the entry is built
+    pub fn with_conflicts(mut self, conflicts: OpConflicts) -> Self {
+        self.conflicts = conflicts;
+        self
+    }
+
     /// Build the Entry from the provided data and then call the `gen_proof`
     /// closure to generate a lock script and proof
     pub fn try_build<F>(&self, mut gen_proof: F) -> Result<Entry, Error>
@@ -560,13 +1282,146 @@ impl Builder {
             locks: self.locks.clone(),
             unlock,
             proof: Vec::default(),
+            cached_cid: CidCache::default(),
         };
 
+        // check the ops and limits before generating the proof and then the
+        // limits again with it
+        self.conflicts.check(entry.ops())?;
+        self.limits.check_contents(&entry)?;
+
         // call the gen_proof closure to create and store the proof data
         entry.proof = gen_proof(&mut entry)?;
+        // forget any cid calculated by gen_proof before the proof was set
+        entry.cached_cid = CidCache::default();
+        self.limits.check(&entry)?;
 
         Ok(entry)
     }
+
+    /// Build the Entry without a proof. The proof is generated later over
+    /// [`UnsignedEntry::signing_bytes`] and attached with
+    /// [`UnsignedEntry::attach_proof`].
+    pub fn try_build_unsigned(&self) -> Result<UnsignedEntry, Error> {
+        Ok(UnsignedEntry(self.try_build(|_| Ok(Vec::default()))?))
+    }
+
+    /// Build as many consecutive Entries as it takes to keep each one at or
+    /// under `max_entry_bytes` when encoded. The ops are split in order
+    /// across the entries, each entry links to the one before it and the
+    /// `gen_proof` closure is called for each entry. Only lipmaa links to
+    /// entries built here are set automatically so this is for building the
+    /// first entries of a log, use [`Builder::try_build_chunked_with_log`] to
+    /// append to an existing one.
+    pub fn try_build_chunked<F>(
+        &self,
+        max_entry_bytes: usize,
+        gen_proof: F,
+    ) -> Result<Vec<Entry>, Error>
+    where
+        F: FnMut(&mut Entry) -> Result<Vec<u8>, Error>,
+    {
+        self.build_chunks(max_entry_bytes, gen_proof, &|_| None)
+    }
+
+    /// Build consecutive Entries like [`Builder::try_build_chunked`] to
+    /// append to the log, looking up the lipmaa links to entries before the
+    /// first one in the log like [`Builder::with_log`] does
+    pub fn try_build_chunked_with_log<F>(
+        &self,
+        log: &Log,
+        max_entry_bytes: usize,
+        gen_proof: F,
+    ) -> Result<Vec<Entry>, Error>
+    where
+        F: FnMut(&mut Entry) -> Result<Vec<u8>, Error>,
+    {
+        self.build_chunks(max_entry_bytes, gen_proof, &|seqno| {
+            log.get_by_seqno(seqno).map(Entry::cid)
+        })
+    }
+
+    // build the chunks, looking up the lipmaa links to entries that weren't
+    // built here with the lookup
+    fn build_chunks<F>(
+        &self,
+        max_entry_bytes: usize,
+        mut gen_proof: F,
+        lookup: &dyn Fn(u64) -> Option<Cid>,
+    ) -> Result<Vec<Entry>, Error>
+    where
+        F: FnMut(&mut Entry) -> Result<Vec<u8>, Error>,
+    {
+        let mut entries: Vec<Entry> = Vec::default();
+        let mut rest = self.ops.as_slice();
+        loop {
+            // set up the builder for the next entry without any ops
+            let b = match entries.last() {
+                None => {
+                    let mut b = self.clone().with_ops(&[]);
+                    let seqno = b.seqno.unwrap_or_default();
+                    if b.lipmaa.is_none() && seqno.is_lipmaa() {
+                        b.lipmaa = lookup(seqno.lipmaa());
+                    }
+                    b
+                }
+                Some(prev) => {
+                    // keep the version and checks we were set up with
+                    let mut b = Builder::from(prev)
+                        .with_limits(&self.limits)
+                        .with_conflicts(self.conflicts);
+                    b.version = self.version;
+                    b.unlock = self.unlock.clone();
+                    let seqno = prev.seqno() + 1;
+                    if seqno.is_lipmaa() {
+                        let built = entries.iter().find(|e| e.seqno() == seqno.lipmaa());
+                        let lipmaa = match built {
+                            Some(e) => e.cid(),
+                            None => lookup(seqno.lipmaa()).ok_or(EntryError::MissingLipmaaLink)?,
+                        };
+                        b = b.with_lipmaa(&lipmaa);
+                    }
+                    b
+                }
+            };
+
+            // fit as many ops as we can without the proof
+            let empty = b.try_build(|_| Ok(Vec::default()))?;
+            let base = Vec::<u8>::from(empty).len() - Vec::<u8>::from(Varuint(0usize)).len();
+            let mut ops_len = 0;
+            let mut n = 0;
+            while n < rest.len() {
+                let op_len = Vec::<u8>::from(rest[n].clone()).len();
+                let count_len = Vec::<u8>::from(Varuint(n + 1)).len();
+                if base + count_len + ops_len + op_len > max_entry_bytes {
+                    break;
+                }
+                ops_len += op_len;
+                n += 1;
+            }
+
+            // build the entry, giving ops back if the proof doesn't fit
+            let entry = loop {
+                if n == 0 && !rest.is_empty() {
+                    return Err(EntryError::TooLarge(max_entry_bytes).into());
+                }
+                let entry = b.clone().with_ops(&rest[..n]).try_build(&mut gen_proof)?;
+                if entry.canonical_bytes().len() <= max_entry_bytes {
+                    break entry;
+                }
+                if n == 0 {
+                    return Err(EntryError::TooLarge(max_entry_bytes).into());
+                }
+                n -= 1;
+            };
+            entries.push(entry);
+
+            rest = &rest[n..];
+            if rest.is_empty() {
+                return Ok(entries);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -631,6 +1486,359 @@ mod tests {
         assert_eq!(format!("{}", entry2.context()), "/".to_string());
     }
 
+    #[test]
+    fn test_builder_with_log() {
+        let mut entries = vec![Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        let mut log = crate::log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&entries[0])
+            .try_build()
+            .unwrap();
+        for seqno in 1..6u64 {
+            // the lipmaa links are filled in from the log
+            let entry = Builder::default()
+                .with_log(&log)
+                .with_unlock(&Script::default())
+                .try_build(|_| Ok(Vec::default()))
+                .unwrap();
+            assert_eq!(seqno, entry.seqno());
+            assert_eq!(log.head(), &entry.prev());
+            if seqno.is_lipmaa() {
+                assert_eq!(entries[seqno.lipmaa() as usize].cid(), entry.lipmaa);
+            }
+            log.entries.insert(entry.cid(), entry.clone());
+            log.set_head_checked(&entry.cid()).unwrap();
+            entries.push(entry);
+        }
+
+        // the lipmaa entry has to be in the log
+        log.set_head_checked(&entries[3].cid()).unwrap();
+        log.entries.remove(&entries[1].cid());
+        assert!(Builder::default()
+            .with_log(&log)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let entry = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+
+        let bytes = entry.canonical_bytes();
+        let decoded = Entry::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(bytes, decoded.canonical_bytes());
+        assert_eq!(entry.cid(), decoded.cid());
+
+        // re-encode the version with a non-minimal varint
+        let sigil: Vec<u8> = SIGIL.into();
+        let mut padded = sigil.clone();
+        padded.extend_from_slice(&[0x81, 0x00]);
+        padded.extend_from_slice(&bytes[sigil.len() + 1..]);
+        assert!(Entry::try_from(padded.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_try_build_chunked() {
+        let ops: Vec<Op> = (0..10)
+            .map(|i| Op::Update(Key::try_from(format!("/{}", i)).unwrap(), Value::Data(vec![i; 100])))
+            .collect();
+        let entries = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .with_ops(&ops)
+            .try_build_chunked(400, |_| Ok(vec![0; 64]))
+            .unwrap();
+        assert!(entries.len() > 3);
+
+        let mut chunked = Vec::default();
+        for (i, e) in entries.iter().enumerate() {
+            assert!(e.canonical_bytes().len() <= 400);
+            assert_eq!(i as u64, e.seqno());
+            if i > 0 {
+                assert_eq!(entries[i - 1].cid(), e.prev());
+            }
+            if e.seqno().is_lipmaa() {
+                assert_eq!(entries[e.seqno().lipmaa() as usize].cid(), e.lipmaa);
+            }
+            chunked.extend(e.ops().cloned());
+        }
+        assert_eq!(ops, chunked);
+
+        // an op that doesn't fit in any entry fails
+        assert!(Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .with_ops(&ops)
+            .try_build_chunked(100, |_| Ok(Vec::default()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_try_build_chunked_with_log() {
+        let mut existing = vec![Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for _ in 0..2 {
+            let next = Builder::from(existing.last().unwrap())
+                .with_unlock(&Script::default())
+                .try_build(|_| Ok(Vec::default()))
+                .unwrap();
+            existing.push(next);
+        }
+        let log = existing
+            .iter()
+            .fold(crate::log::Builder::new(), |b, e| b.append_entry(e))
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .try_build()
+            .unwrap();
+        assert_eq!(2, log.head_entry().unwrap().seqno());
+
+        // bulk append from the head at seqno 2, the chunks reach back into
+        // the log for their lipmaa links
+        let ops: Vec<Op> = (0..10)
+            .map(|i| Op::Update(Key::try_from(format!("/{}", i)).unwrap(), Value::Data(vec![i; 100])))
+            .collect();
+        let builder = Builder::default()
+            .with_log(&log)
+            .with_unlock(&Script::default())
+            .with_ops(&ops);
+        let entries = builder
+            .try_build_chunked_with_log(&log, 400, |_| Ok(Vec::default()))
+            .unwrap();
+        let all: Vec<Entry> = existing.into_iter().chain(entries.clone()).collect();
+        assert!(entries.iter().any(|e| e.seqno() == 4 && e.seqno().is_lipmaa()));
+        for (i, e) in all.iter().enumerate().skip(3) {
+            assert_eq!(i as u64, e.seqno());
+            assert_eq!(all[i - 1].cid(), e.prev());
+            if e.seqno().is_lipmaa() {
+                assert_eq!(all[e.seqno().lipmaa() as usize].cid(), e.lipmaa);
+            }
+        }
+
+        // without the log the links to the entries before the chunks are missing
+        assert!(builder.try_build_chunked(400, |_| Ok(Vec::default())).is_err());
+    }
+
+    #[test]
+    fn test_try_build_chunked_settings() {
+        let op = |i: u8, len: usize| {
+            Op::Update(Key::try_from(format!("/{}", i)).unwrap(), Value::Data(vec![i; len]))
+        };
+        let ops: Vec<Op> = (0..10).map(|i| op(i, 100)).collect();
+        let builder = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .with_path_compression();
+
+        // every chunk keeps the version
+        let entries = builder
+            .clone()
+            .with_ops(&ops)
+            .try_build_chunked(400, |_| Ok(Vec::default()))
+            .unwrap();
+        assert!(entries.len() > 1);
+        assert!(entries.iter().all(|e| e.version() == ENTRY_VERSION_PATHS));
+
+        // the limits are checked in the later chunks
+        let mut limits = Limits::default();
+        limits.max_value_bytes = 200;
+        let mut big = ops.clone();
+        big.push(op(10, 300));
+        assert!(builder
+            .clone()
+            .with_limits(&limits)
+            .with_ops(&big)
+            .try_build_chunked(400, |_| Ok(Vec::default()))
+            .is_err());
+
+        // and so is the conflict policy
+        let mut dup = ops.clone();
+        dup.push(op(10, 1));
+        dup.push(op(10, 2));
+        assert!(builder
+            .clone()
+            .with_ops(&dup)
+            .try_build_chunked(400, |_| Ok(Vec::default()))
+            .is_ok());
+        assert!(builder
+            .with_conflicts(OpConflicts::Reject)
+            .with_ops(&dup)
+            .try_build_chunked(400, |_| Ok(Vec::default()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_cid_memoized() {
+        let mut early = None;
+        let entry = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|e| {
+                // the cid before the proof is set must not be remembered
+                early = Some(e.cid());
+                Ok(b"proof".to_vec())
+            })
+            .unwrap();
+        assert_ne!(early.unwrap(), entry.cid());
+        assert_eq!(cid_of(&entry.canonical_bytes()), entry.cid());
+
+        // decoding remembers the cid and doesn't change equality
+        let decoded = Entry::try_from(entry.canonical_bytes().as_slice()).unwrap();
+        assert!(decoded.cached_cid.0.get().is_some());
+        assert_eq!(entry, decoded);
+        assert_eq!(entry.cid(), decoded.cid());
+    }
+
+    #[test]
+    fn test_entry_ref() {
+        let lock = Script::Cid(Key::try_from("/foo").unwrap(), Cid::null());
+        let e1 = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_lock(&lock)
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Data(vec![1; 100])))
+            .add_op(&Op::Update("/bar".try_into().unwrap(), Value::Nil))
+            .add_op(&Op::Delete("/baz".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut bytes = e1.canonical_bytes();
+        bytes.append(&mut e2.canonical_bytes());
+
+        let (r1, ptr) = EntryRef::try_decode_from(&bytes).unwrap();
+        let (r2, ptr) = EntryRef::try_decode_from(ptr).unwrap();
+        assert!(ptr.is_empty());
+
+        assert_eq!(e1.seqno(), r1.seqno());
+        assert_eq!(&e1.vlad(), r1.vlad());
+        assert_eq!(b"proof", r1.proof());
+        assert_eq!(e1.cid(), r1.cid());
+        assert_eq!(&e1.cid(), r2.prev());
+        assert_eq!(e1.ops, r1.ops().collect::<Result<Vec<_>, _>>().unwrap());
+        assert_eq!(e1.locks, r1.locks().collect::<Result<Vec<_>, _>>().unwrap());
+        assert_eq!(e1.unlock, r1.unlock().unwrap());
+        assert_eq!(e1, r1.to_owned().unwrap());
+        assert_eq!(e2, r2.to_owned().unwrap());
+
+        // a truncated entry fails
+        let b1 = e1.canonical_bytes();
+        assert!(EntryRef::try_from(&b1[..b1.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_path_compression() {
+        let branch = "/services/relay/endpoints/primary/";
+        let builder = (0..10).fold(
+            Builder::default()
+                .with_vlad(&Vlad::default())
+                .with_unlock(&Script::default())
+                .add_lock(&Script::Cid(Key::try_from(branch).unwrap(), Cid::null())),
+            |b, i| {
+                let key = Key::try_from(format!("{}é{}", branch, i)).unwrap();
+                b.add_op(&Op::Update(key.clone(), Value::Str(format!("{}", i))))
+                    .add_op(&Op::Delete(key))
+            },
+        );
+        let plain = builder.try_build(|_| Ok(Vec::default())).unwrap();
+        let compressed = builder
+            .clone()
+            .with_path_compression()
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert_eq!(ENTRY_VERSION_PATHS, compressed.version);
+
+        let plain_bytes = plain.canonical_bytes();
+        let bytes = compressed.canonical_bytes();
+        assert!(bytes.len() < plain_bytes.len());
+
+        // the decoded entry has the same ops and scripts
+        let decoded = Entry::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(compressed, decoded);
+        assert_eq!(plain.ops, decoded.ops);
+        assert_eq!(plain.locks, decoded.locks);
+
+        let r = EntryRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(plain.ops, r.ops().collect::<Result<Vec<_>, _>>().unwrap());
+        assert_eq!(plain.locks, r.locks().collect::<Result<Vec<_>, _>>().unwrap());
+        assert_eq!(plain.unlock, r.unlock().unwrap());
+    }
+
+    #[test]
+    fn test_detached_proof() {
+        let builder = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("bar".to_string())));
+        let unsigned = builder.try_build_unsigned().unwrap();
+
+        // move the unsigned entry to the signer and back
+        let bytes: Vec<u8> = unsigned.clone().into();
+        let received = UnsignedEntry::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(unsigned, received);
+        let proof = received.signing_bytes().iter().rev().cloned().collect::<Vec<_>>();
+
+        // the same as generating the proof when building
+        let signed = builder
+            .try_build(|e| Ok(e.canonical_bytes().iter().rev().cloned().collect()))
+            .unwrap();
+        let attached = unsigned.attach_proof(&proof);
+        assert_eq!(signed, attached);
+        assert_eq!(signed.cid(), attached.cid());
+
+        // a signed entry isn't an unsigned entry
+        let bytes = signed.canonical_bytes();
+        assert!(UnsignedEntry::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_decode_error_offsets() {
+        let value = Value::Data(vec![0xaa; 10]);
+        let op = Op::Update("/bar".try_into().unwrap(), value.clone());
+        let entry = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("foo".to_string())))
+            .add_op(&op)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let bytes = entry.canonical_bytes();
+
+        // find where the value of the second op starts
+        let op_bytes: Vec<u8> = op.into();
+        let value_bytes: Vec<u8> = value.into();
+        let op_start = bytes
+            .windows(op_bytes.len())
+            .position(|w| w == op_bytes.as_slice())
+            .unwrap();
+        let value_start = op_start + op_bytes.len() - value_bytes.len();
+
+        // cut the entry off in the middle of the value
+        match Entry::try_from(&bytes[..value_start + 3]) {
+            Err(Error::Decode { field, offset, .. }) => {
+                assert_eq!("ops[1].value", field);
+                assert_eq!(value_start, offset);
+            }
+            e => panic!("expected a decode error, got {:?}", e),
+        }
+    }
+
     #[test]
     fn test_entry_iter() {
         let vlad = Vlad::default();
@@ -656,6 +1864,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_lock_values() {
+        let lock1 = Script::Code(Key::try_from("/").unwrap(), "(module)".to_string());
+        let lock2 = Script::Code(Key::try_from("/foo/").unwrap(), "(module)".to_string());
+        let entry = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_lock(&lock1)
+            .add_lock(&lock2)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let mut expected = Vec::new();
+        expected.append(&mut Varuint(2usize).into());
+        for (i, lock) in entry.locks().enumerate() {
+            let key = Key::try_from(format!("/entry/locks/{}", i).as_str()).unwrap();
+            let bytes: Vec<u8> = lock.clone().into();
+            assert_eq!(Some(Value::Data(bytes.clone())), entry.get_value(&key));
+            expected.extend(bytes);
+        }
+        let key = Key::try_from("/entry/locks").unwrap();
+        assert_eq!(Some(Value::Data(expected)), entry.get_value(&key));
+        let key = Key::try_from("/entry/locks/2").unwrap();
+        assert_eq!(None, entry.get_value(&key));
+        let key = Key::try_from("/entry/locks/x").unwrap();
+        assert_eq!(None, entry.get_value(&key));
+    }
+
+    #[test]
+    fn test_op_values() {
+        let entry = Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                Key::try_from("/foo").unwrap(),
+                Value::Str("bar".to_string()),
+            ))
+            .add_op(&Op::Delete(Key::try_from("/baz").unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let get = |k: &str| entry.get_value(&Key::try_from(k).unwrap());
+        assert_eq!(Some(Value::Str("/foo".to_string())), get("/entry/ops/0/path"));
+        assert_eq!(Some(Value::Str("bar".to_string())), get("/entry/ops/0/value"));
+        assert_eq!(Some(Value::Str("/baz".to_string())), get("/entry/ops/1/path"));
+        let op: Vec<u8> = Op::Delete(Key::try_from("/baz").unwrap()).into();
+        assert_eq!(Some(Value::Data(op)), get("/entry/ops/1"));
+        // deletes have no value
+        assert_eq!(None, get("/entry/ops/1/value"));
+        assert_eq!(None, get("/entry/ops/2/path"));
+        assert_eq!(None, get("/entry/ops/0/other"));
+
+        // the scripts see the same values
+        match wacc::Pairs::get(&entry, "/entry/ops/0/path") {
+            Some(wacc::Value::Str { data, .. }) => assert_eq!("/foo", data),
+            _ => panic!("expected a string value"),
+        }
+    }
+
     #[test]
     fn test_sort_locks_change_lock_order() {
         let vlad = Vlad::default();
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Equivocation is signing two different entries at the same place in a log:
+//! the same vlad, prev and seqno. A fork created by replicas racing to append
+//! is harmless but a controller that signs both sides of one is presenting
+//! different histories to different parties. An [`EquivocationProof`] holds
+//! the two entries so anyone can check the misbehavior against their own copy
+//! of the log.
+use crate::{error::LogError, log, Entry, Error, Log, ScriptEngine, WaccEngine};
+use multicid::Cid;
+use multitrait::{Null, TryDecodeFrom};
+use multiutil::Varuint;
+
+/// the current version of equivocation proofs this supports
+pub const EQUIVOCATION_PROOF_VERSION: u64 = 1;
+
+/// Two different entries that claim the same place in a log
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EquivocationProof {
+    /// the conflicting entry with the smaller cid
+    pub first: Entry,
+    /// the conflicting entry with the larger cid
+    pub second: Entry,
+}
+
+impl EquivocationProof {
+    /// create the proof from two conflicting entries
+    pub fn new(a: &Entry, b: &Entry) -> Result<Self, Error> {
+        let (first, second) = if a.cid() <= b.cid() { (a, b) } else { (b, a) };
+        let proof = Self {
+            first: first.clone(),
+            second: second.clone(),
+        };
+        proof.check()?;
+        Ok(proof)
+    }
+
+    /// get the cid of the entry both entries link to
+    pub fn prev(&self) -> Cid {
+        self.first.prev()
+    }
+
+    /// get the seqno both entries claim
+    pub fn seqno(&self) -> u64 {
+        self.first.seqno()
+    }
+
+    /// check that the entries are different entries with the same vlad, prev
+    /// and seqno. this doesn't check that they are validly signed.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.first.cid() == self.second.cid()
+            || self.first.vlad_ref() != self.second.vlad_ref()
+            || self.first.prev_ref() != self.second.prev_ref()
+            || self.first.seqno() != self.second.seqno()
+        {
+            return Err(LogError::NotEquivocation.into());
+        }
+        Ok(())
+    }
+
+    /// verify the proof against the log: both entries must be valid
+    /// successors of the entry they link to in the log
+    pub fn verify(&self, log: &Log) -> Result<(), Error> {
+        self.verify_with_engine(log, &WaccEngine)
+    }
+
+    /// verify the proof against the log running the scripts with the engine
+    pub fn verify_with_engine(&self, log: &Log, engine: &dyn ScriptEngine) -> Result<(), Error> {
+        self.check()?;
+        if *self.first.vlad_ref() != log.vlad {
+            return Err(LogError::WrongLog.into());
+        }
+        self.verify_successor(log, &self.first, engine)?;
+        self.verify_successor(log, &self.second, engine)
+    }
+
+    // verify the entry on top of the branch of the log ending at its prev
+    fn verify_successor(&self, log: &Log, entry: &Entry, engine: &dyn ScriptEngine) -> Result<(), Error> {
+        let mut builder = log::Builder::new()
+            .with_vlad(&log.vlad)
+            .with_first_lock(&log.first_lock)
+            .with_entry_points(log.entry_points());
+        if let Some(cp) = &log.checkpoint {
+            builder = builder.with_checkpoint(cp);
+        }
+        if !entry.prev_ref().is_null() {
+            for e in log.branch(entry.prev_ref())?.entries {
+                builder = builder.append_entry(e);
+            }
+        }
+        let branch = builder.append_entry(entry).try_build()?;
+        for ret in branch.verify_with_engine(engine) {
+            ret?;
+        }
+        Ok(())
+    }
+}
+
+impl From<EquivocationProof> for Vec<u8> {
+    fn from(val: EquivocationProof) -> Self {
+        let mut v = Vec::default();
+        // add in the version
+        v.append(&mut Varuint(EQUIVOCATION_PROOF_VERSION).into());
+        // add in the two entries
+        v.append(&mut val.first.into());
+        v.append(&mut val.second.into());
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for EquivocationProof {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (proof, _) = Self::try_decode_from(bytes)?;
+        Ok(proof)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for EquivocationProof {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        let (version, ptr) = Varuint::<u64>::try_decode_from(bytes)?;
+        if *version != EQUIVOCATION_PROOF_VERSION {
+            return Err(LogError::InvalidVersion(*version).into());
+        }
+        let (first, ptr) = Entry::try_decode_from(ptr)?;
+        let (second, ptr) = Entry::try_decode_from(ptr)?;
+        Ok((Self::new(&first, &second)?, ptr))
+    }
+}
+
+impl Log {
+    /// find every pair of entries in the log that claim the same place in it
+    pub fn detect_equivocation(&self) -> Vec<EquivocationProof> {
+        let mut proofs = Vec::default();
+        for fork in self.forks() {
+            let children: Vec<&Entry> = fork
+                .children
+                .iter()
+                .filter_map(|c| self.entries.get(c))
+                .collect();
+            for (i, a) in children.iter().enumerate() {
+                for b in &children[i + 1..] {
+                    if let Ok(proof) = EquivocationProof::new(a, b) {
+                        proofs.push(proof);
+                    }
+                }
+            }
+        }
+        proofs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, Op, Script, Value};
+    use multicid::Vlad;
+
+    fn child(prev: &Entry, value: &str) -> Entry {
+        entry::Builder::from(prev)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str(value.to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_detect_equivocation() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2a = child(&e1, "a");
+        let e2b = child(&e1, "b");
+        let e3a = child(&e2a, "c");
+
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2b)
+            .append_entry(&e2a)
+            .append_entry(&e3a)
+            .try_build()
+            .unwrap();
+
+        let proofs = log.detect_equivocation();
+        assert_eq!(1, proofs.len());
+        assert_eq!(e1.cid(), proofs[0].prev());
+        assert_eq!(1, proofs[0].seqno());
+        assert_eq!(proofs[0], EquivocationProof::new(&e2b, &e2a).unwrap());
+
+        // the proof round trips through its encoding
+        let bytes: Vec<u8> = proofs[0].clone().into();
+        assert_eq!(proofs[0], EquivocationProof::try_from(bytes.as_slice()).unwrap());
+
+        // entries at different places in the log don't equivocate
+        assert!(EquivocationProof::new(&e2a, &e3a).is_err());
+        assert!(EquivocationProof::new(&e2a, &e2a).is_err());
+    }
+}
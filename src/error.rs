@@ -34,6 +34,9 @@ pub enum Error {
     /// Multihash Error
     #[error(transparent)]
     Multihash(#[from] multihash::Error),
+    /// Multikey Error
+    #[error(transparent)]
+    Multikey(#[from] multikey::Error),
     /// Multitrait Error
     #[error(transparent)]
     Multitrait(#[from] multitrait::Error),
@@ -44,6 +47,157 @@ pub enum Error {
     /// Utf8 error
     #[error(transparent)]
     Utf8(#[from] std::string::FromUtf8Error),
+
+    /// Bytes left over after strictly decoding an object
+    #[error("{0} trailing bytes after the encoded object")]
+    TrailingBytes(usize),
+    /// The bytes aren't the canonical encoding of the object
+    #[error("the encoding is not canonical")]
+    NonCanonical,
+
+    /// Invalid protobuf message
+    #[cfg(feature = "proto")]
+    #[error("invalid protobuf message: {0}")]
+    Proto(String),
+
+    /// A checked string doesn't have a checksum
+    #[error("the string is missing its checksum")]
+    MissingChecksum,
+    /// A checked string doesn't match its checksum
+    #[error("the string doesn't match its checksum")]
+    ChecksumMismatch,
+
+    /// Invalid DAG-CBOR block
+    #[cfg(feature = "dag_cbor")]
+    #[error("invalid dag-cbor block: {0}")]
+    DagCbor(String),
+
+    /// Compressing or decompressing a payload failed
+    #[cfg(feature = "zstd")]
+    #[error("compression failed: {0}")]
+    Compression(String),
+
+    /// A block isn't in the block store
+    #[error("block not found")]
+    BlockNotFound(multicid::Cid),
+    /// A block doesn't hash to its cid
+    #[error("block doesn't match its cid")]
+    BlockMismatch(multicid::Cid),
+
+    /// Replicating over libp2p failed
+    #[cfg(feature = "p2p")]
+    #[error("p2p failed: {0}")]
+    P2p(String),
+
+    /// Reading or writing a log store failed
+    #[cfg(feature = "sqlite")]
+    #[error("storage failed: {0}")]
+    Storage(String),
+
+    /// Verification was cancelled while verifying the entry with the seqno
+    #[error("verification was cancelled during entry {0}")]
+    Cancelled(u64),
+    /// Verifying the entry with the seqno took longer than its budget
+    #[error("verification of entry {0} exceeded its time budget")]
+    EntryTimeout(u64),
+    /// The verification passed its deadline while verifying the entry
+    #[error("verification passed its deadline during entry {0}")]
+    DeadlineExceeded(u64),
+    /// An entry failed verification
+    #[error(transparent)]
+    Verify(#[from] VerifyError),
+    /// Decoding a field failed
+    #[error("{field} at offset {offset}: {source}")]
+    Decode {
+        /// the path to the field, e.g. "ops[3].value"
+        field: String,
+        /// the offset of the field from the start of the outermost encoding
+        offset: usize,
+        /// the error from decoding the field
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// add the name of the field being decoded and its offset to the error.
+    /// the names of nested fields are joined with '.' and their offsets are
+    /// added up.
+    pub(crate) fn at(self, field: impl std::fmt::Display, offset: usize) -> Self {
+        match self {
+            Self::Decode {
+                field: f,
+                offset: o,
+                source,
+            } => Self::Decode {
+                field: format!("{}.{}", field, f),
+                offset: offset + o,
+                source,
+            },
+            e => Self::Decode {
+                field: field.to_string(),
+                offset,
+                source: Box::new(e),
+            },
+        }
+    }
+}
+
+/// decode the field at ptr, adding the field name and its offset from start
+/// to any error
+pub(crate) fn decode_field<'a, T, E>(
+    start: &[u8],
+    ptr: &'a [u8],
+    field: impl std::fmt::Display,
+    decode: impl FnOnce(&'a [u8]) -> Result<(T, &'a [u8]), E>,
+) -> Result<(T, &'a [u8]), Error>
+where
+    Error: From<E>,
+{
+    decode(ptr).map_err(|e| Error::from(e).at(field, start.len() - ptr.len()))
+}
+
+/// The verification phase an entry failed in
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum VerifyPhase {
+    /// the checks of the entry against the previous entry
+    Structure,
+    /// running the unlock script
+    Unlock,
+    /// running the lock scripts
+    Lock,
+    /// applying the ops to the kvp
+    Apply,
+    /// a custom pipeline stage with the name
+    Stage(String),
+    /// outside of the stages, e.g. the verification was interrupted
+    Internal,
+}
+
+impl std::fmt::Display for VerifyPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Structure => write!(f, "structure"),
+            Self::Unlock => write!(f, "unlock"),
+            Self::Lock => write!(f, "lock"),
+            Self::Apply => write!(f, "apply"),
+            Self::Stage(name) => write!(f, "{}", name),
+            Self::Internal => write!(f, "internal"),
+        }
+    }
+}
+
+/// The failure of an entry during log verification
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("entry {seqno} failed verification in the {phase} phase: {source}")]
+pub struct VerifyError {
+    /// the seqno of the entry
+    pub seqno: u64,
+    /// the cid of the entry
+    pub entry_cid: multicid::Cid,
+    /// the phase the entry failed in
+    pub phase: VerifyPhase,
+    /// the error from the phase
+    pub source: Box<Error>,
 }
 
 /// ProvenanceEntry Errors created by this library
@@ -71,12 +225,45 @@ pub enum EntryError {
     /// Proof generator error
     #[error("proof generation failed: {0}")]
     ProofGenerationFailed(#[from] std::fmt::Error),
+    /// Entry bytes don't re-encode to the same bytes
+    #[error("entry encoding is not canonical")]
+    NonCanonicalEncoding,
+    /// Entry bytes end before the end of a field
+    #[error("entry is truncated")]
+    Truncated,
+    /// A path table refers to a path that isn't in it
+    #[error("invalid key-path index {0}")]
+    InvalidPathIndex(usize),
+    /// An op doesn't fit in an entry of the maximum size
+    #[error("an entry can't be built within {0} bytes")]
+    TooLarge(usize),
+    /// An unsigned entry already has a proof
+    #[error("entry already has a proof")]
+    AlreadySigned,
+    /// An entry can't be carried by a transport
+    #[error("entry can't be carried by the transport: {0}")]
+    ProfileViolation(String),
     /// Entries are read-only
     #[error("Entry objects are read-only")]
     ReadOnly,
     /// Signing the entry failed
     #[error("Signing the entry failed {0}")]
     SignFailed(String),
+    /// The entry has more ops than the limit
+    #[error("entry has {0} ops, the limit is {1}")]
+    TooManyOps(usize, usize),
+    /// The entry has more lock scripts than the limit
+    #[error("entry has {0} lock scripts, the limit is {1}")]
+    TooManyLocks(usize, usize),
+    /// A value in the entry is larger than the limit
+    #[error("the value at {0} is {1} bytes, the limit is {2}")]
+    ValueTooLarge(crate::Key, usize, usize),
+    /// The encoded entry is larger than the limit
+    #[error("entry is {0} bytes, the limit is {1}")]
+    EntryTooLarge(usize, usize),
+    /// More than one op in the entry changes the key
+    #[error("more than one op changes {0}")]
+    ConflictingOps(crate::Key),
 }
 
 /// Key errors created by this library
@@ -92,6 +279,24 @@ pub enum KeyError {
     /// Key is not a branch
     #[error("key is not a branch")]
     NotABranch,
+    /// Invalid escape sequence in a key segment
+    #[error("invalid escape sequence in key: {0}")]
+    InvalidEscape(String),
+    /// Empty key segment
+    #[error("key segments cannot be empty")]
+    EmptySegment,
+    /// Key has more segments than the key profile allows
+    #[error("key {0} is deeper than the max of {1} segments")]
+    TooDeep(String, usize),
+    /// Key segment is longer than the key profile allows
+    #[error("key segment {0} is longer than the max of {1} chars")]
+    SegmentTooLong(String, usize),
+    /// Key segment has a char the key profile doesn't allow
+    #[error("key segment {0} has forbidden char {1:?}")]
+    ForbiddenChar(String, char),
+    /// Key segment isn't in the normalization form of the key profile
+    #[error("key segment {0} is not in {1:?} form")]
+    NotNormalized(String, crate::key_profile::Normalization),
 }
 
 /// Errors created by this library
@@ -107,12 +312,18 @@ pub enum KvpError {
     /// Empty undo stack
     #[error("empty undo stack")]
     EmptyUndoStack,
+    /// Empty redo stack
+    #[error("empty redo stack")]
+    EmptyRedoStack,
     /// No Entry Attributes on the undo stack
     #[error("no entry attributes on undo stack")]
     NoEntryAttributes,
     /// Failed to insert kvp
     #[error("kvp insert failed")]
     FailedInsert,
+    /// The key has no value
+    #[error("no value at key {0}")]
+    KeyNotFound(String),
 }
 
 /// ProvenanceLog Errors created by this library
@@ -152,6 +363,90 @@ pub enum LogError {
     /// Duplicate log entry
     #[error("duplicate log entry")]
     DuplicateEntry(multicid::Cid),
+    /// Entry not found in the log
+    #[error("entry not found")]
+    EntryNotFound(multicid::Cid),
+    /// Entry was built against a head that is no longer the log head
+    #[error("entry was built against a stale head")]
+    StaleHead(multicid::Cid),
+    /// The entries aren't different entries at the same place in the log
+    #[error("the entries don't equivocate")]
+    NotEquivocation,
+    /// The referenced log couldn't be resolved
+    #[error("referenced log couldn't be resolved")]
+    UnresolvedLog,
+    /// Fewer trusted witnesses signed the entry than the policy requires
+    #[error("{0} of {1} required witnesses signed the entry")]
+    NotEnoughWitnesses(usize, usize),
+    /// The delegation record couldn't be decoded
+    #[error("invalid delegation record: {0}")]
+    InvalidDelegation(String),
+    /// The entry was proved by a delegate after its grant expired
+    #[error("delegation {0} expired at seqno {1}")]
+    DelegationExpired(crate::Key, u64),
+    /// The entry was proved by a delegate not granted the key
+    #[error("delegation {0} doesn't allow changing {1}")]
+    DelegationNotAllowed(crate::Key, crate::Key),
+    /// The ACL record couldn't be decoded
+    #[error("invalid acl record: {0}")]
+    InvalidAcl(String),
+    /// No principal that signed the entry is allowed the op
+    #[error("acl doesn't allow {1} of {0}")]
+    AclDenied(crate::Key, String),
+    /// No entry with the given seqno in the log
+    #[error("no entry with seqno {0}")]
+    SeqnoNotFound(u64),
+    /// Checkpoint doesn't match the log
+    #[error("checkpoint doesn't match the log")]
+    CheckpointMismatch,
+    /// Invalid log version
+    #[error("invalid log version {0}")]
+    InvalidVersion(u64),
+    /// Invalid checkpoint version
+    #[error("invalid checkpoint version {0}")]
+    InvalidCheckpointVersion(u64),
+    /// An entry refers to a script that isn't in the log's script table
+    #[error("invalid script table index {0}")]
+    InvalidScriptIndex(usize),
+    /// A sync message has an unknown kind
+    #[error("invalid sync message kind {0}")]
+    InvalidSyncMessage(u64),
+    /// A sync message is for a different log
+    #[error("sync message is for a different log")]
+    WrongLog,
+    /// Reading from a reader failed
+    #[error("read failed: {0}")]
+    ReadFailed(String),
+    /// Writing to a file failed
+    #[error("write failed: {0}")]
+    WriteFailed(String),
+    /// Committing a head to an anchor provider failed
+    #[error("anchoring failed: {0}")]
+    AnchorFailed(String),
+    /// The anchor doesn't commit to the expected entry
+    #[error("anchor doesn't commit to the entry")]
+    AnchorMismatch,
+    /// A verification receipt doesn't link to the one before it
+    #[error("verification receipt chain is broken at entry {0}")]
+    BrokenReceiptChain(u64),
+    /// A verification receipt doesn't match the result of verifying the log
+    #[error("verification receipt for entry {0} doesn't match the log")]
+    ReceiptMismatch(u64),
+    /// An entry has a different vlad than the log
+    #[error("entry {0} has a different vlad than the log")]
+    VladMismatch(u64),
+    /// A different log claims the vlad of a registered log
+    #[error("a different log claims the vlad {0}")]
+    VladCollision(String),
+    /// An entry's lipmaa link doesn't point to the right entry
+    #[error("entry {0} has an invalid lipmaa link")]
+    InvalidLipmaaLink(u64),
+    /// Missing key for a key rotation
+    #[error("missing key")]
+    MissingKey,
+    /// The key being rotated out isn't the current pubkey
+    #[error("the old key is not the current pubkey")]
+    StaleKey,
     /// Missing lock script for the first entry
     #[error("Missing lock script for the first entry")]
     MissingFirstEntryLockScript,
@@ -206,6 +501,49 @@ pub enum ScriptError {
     /// invalid wasm script magic value
     #[error("invalid wasm script")]
     InvalidScriptMagic,
+    /// The engine doesn't support wasm component scripts
+    #[error("wasm component scripts are not supported by this engine")]
+    UnsupportedComponent,
+    /// A wasm component script was run without a component engine
+    #[error("no engine is configured for wasm component scripts")]
+    NoComponentEngine,
+    /// The script cid isn't the cid of the script bound to the vlad
+    #[error("the script cid is not bound to the vlad")]
+    ScopedCidMismatch,
+    /// The script referenced by cid isn't in the content store
+    #[error("the script for {1} could not be resolved from its cid")]
+    Unresolved(multicid::Cid, crate::Key),
+    /// Verification was deferred until the script referenced by cid is
+    /// available
+    #[error("verification deferred until the script for {1} is available")]
+    Deferred(multicid::Cid, crate::Key),
+    /// The policy rules couldn't be parsed
+    #[error("invalid policy: {0}")]
+    InvalidPolicy(String),
+    /// The scripts run for an entry consumed more fuel than its budget
+    #[error("the scripts consumed {0} fuel, the budget is {1}")]
+    OutOfFuel(u64, u64),
+    /// A fuel budget was set but the engine can't meter the scripts it runs
+    #[error("the script engine can't meter scripts to enforce a fuel budget")]
+    Unmetered,
+    /// The binary script couldn't be disassembled
+    #[error("failed to disassemble script: {0}")]
+    Disassembly(String),
+    /// The binary script isn't valid wasm
+    #[error("invalid wasm script: {0}")]
+    InvalidWasm(String),
+    /// The binary script imports a function the vm doesn't link
+    #[error("the script imports {0}::{1} which is not linked by the vm")]
+    ForbiddenImport(String, String),
+    /// The binary script doesn't export the function it is called through
+    #[error("the script does not export the function {0}")]
+    MissingExport(String),
+    /// The binary script uses floating point
+    #[error("the script uses floating point")]
+    FloatingPoint,
+    /// The wasm interpreter failed to load or run the script
+    #[error("wasm interpreter error: {0}")]
+    Interp(String),
 }
 
 /// Errors created by this library
@@ -218,4 +556,34 @@ pub enum ValueError {
     /// Invalid value type name
     #[error("invalid value type name {0}")]
     InvalidValueName(String),
+    /// The value isn't an encoded commitment hash
+    #[error("value is not a commitment")]
+    NotACommitment,
+    /// The disclosed value and salt don't match the commitment
+    #[error("disclosed value does not match the commitment")]
+    CommitmentMismatch,
+    /// Failed to encrypt a sealed value
+    #[error("failed to seal value")]
+    SealFailed,
+    /// Failed to decrypt a sealed value
+    #[error("failed to unseal value")]
+    UnsealFailed,
+    /// The value isn't encrypted
+    #[error("value is not encrypted")]
+    NotEncrypted,
+    /// The key isn't one of the recipients of the sealed value
+    #[error("key is not a recipient of the value")]
+    NotARecipient,
+    /// The encryption scheme isn't supported
+    #[error("unsupported encryption scheme {0:?}")]
+    UnsupportedScheme(multicodec::Codec),
+    /// The key type can't be used to seal or unseal values
+    #[error("unsupported sealing key {0:?}")]
+    UnsupportedSealKey(multicodec::Codec),
+    /// The key or ephemeral key is malformed
+    #[error("invalid sealing key")]
+    InvalidSealKey,
+    /// The value isn't a reference to another log
+    #[error("value is not a plog reference")]
+    NotAPlogRef,
 }
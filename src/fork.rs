@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{error::LogError, Entry, Error, Key, Log, Op, Value};
+use multicid::Cid;
+use multitrait::Null;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the key naming the key that holds the priority of a branch for the
+/// [`PriorityKey`] fork choice rule
+pub const FORK_CHOICE_KEY: &str = "/fork-choice";
+
+/// A Fork is a point in the log where more than one entry links to the same
+/// previous entry
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fork {
+    /// the cid of the entry that has more than one child
+    pub prev: Cid,
+    /// the cids of the competing child entries
+    pub children: Vec<Cid>,
+}
+
+/// A Branch is a linear sub-chain of the log from the foot, or the checkpoint
+/// of a compacted log, to a head
+#[derive(Clone, Debug)]
+pub struct Branch<'a> {
+    /// the cid of the last entry in the branch
+    pub head: Cid,
+    /// the entries in the branch ordered from foot to head
+    pub entries: Vec<&'a Entry>,
+}
+
+/// A ForkChoice rule selects the canonical head among competing branches
+pub trait ForkChoice {
+    /// returns the cid of the head of the canonical branch
+    fn choose(&self, log: &Log, branches: &[Branch<'_>]) -> Option<Cid>;
+}
+
+/// The default fork choice rule selects the longest branch and breaks ties by
+/// choosing the smallest head cid so the choice is deterministic
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn choose(&self, _log: &Log, branches: &[Branch<'_>]) -> Option<Cid> {
+        branches
+            .iter()
+            .max_by(|a, b| {
+                a.entries
+                    .len()
+                    .cmp(&b.entries.len())
+                    .then_with(|| b.head.cmp(&a.head))
+            })
+            .map(|b| b.head.clone())
+    }
+}
+
+/// The first-seen fork choice rule selects the branch that was received
+/// first. At the point where the branches diverge, the branch whose entry
+/// appears earliest in the order the entries were received wins. Entries that
+/// were never seen count as received last and ties are broken by choosing the
+/// smallest head cid.
+#[derive(Clone, Debug, Default)]
+pub struct FirstSeen {
+    seen: BTreeMap<Cid, usize>,
+}
+
+impl FirstSeen {
+    /// create the rule from the cids of the entries in the order they were
+    /// received
+    pub fn new(order: &[Cid]) -> Self {
+        let mut rule = Self::default();
+        order.iter().for_each(|cid| rule.observe(cid));
+        rule
+    }
+
+    /// record that the entry with the cid was received
+    pub fn observe(&mut self, cid: &Cid) {
+        let next = self.seen.len();
+        self.seen.entry(cid.clone()).or_insert(next);
+    }
+
+    // the positions the entries of the branch were received at
+    fn positions(&self, branch: &Branch<'_>) -> Vec<usize> {
+        branch
+            .entries
+            .iter()
+            .map(|e| self.seen.get(&e.cid()).copied().unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+impl ForkChoice for FirstSeen {
+    fn choose(&self, _log: &Log, branches: &[Branch<'_>]) -> Option<Cid> {
+        branches
+            .iter()
+            .map(|b| (self.positions(b), b))
+            .min_by(|(a, ab), (b, bb)| a.cmp(b).then_with(|| ab.head.cmp(&bb.head)))
+            .map(|(_, b)| b.head.clone())
+    }
+}
+
+/// The priority key fork choice rule lets the log controller rank branches.
+/// The value at [`FORK_CHOICE_KEY`] names the key holding the priority of a
+/// branch as a decimal string and the branch with the highest priority wins.
+/// Branches without a priority rank lowest and ties are broken with the
+/// [`LongestChain`] rule.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PriorityKey;
+
+impl PriorityKey {
+    // get the value at the key as of the head of the branch by finding the
+    // last op in the branch that touched it
+    fn value(log: &Log, branch: &Branch<'_>, key: &Key) -> Option<Value> {
+        for entry in branch.entries.iter().rev() {
+            for op in entry.ops().collect::<Vec<_>>().into_iter().rev() {
+                match op {
+                    Op::Update(k, v) if k == key => return Some(v.clone()),
+                    Op::Delete(k) if k == key => return None,
+                    _ => {}
+                }
+            }
+        }
+        // the state before a compacted log's checkpoint is in the checkpoint
+        log.checkpoint
+            .as_ref()
+            .and_then(|cp| cp.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone()))
+    }
+
+    /// get the priority of the branch, if it has one
+    pub fn priority(log: &Log, branch: &Branch<'_>) -> Option<u64> {
+        let key = match Self::value(log, branch, &Key::try_from(FORK_CHOICE_KEY).ok()?)? {
+            Value::Str(s) => Key::try_from(s.as_str()).ok()?,
+            _ => return None,
+        };
+        match Self::value(log, branch, &key)? {
+            Value::Str(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl ForkChoice for PriorityKey {
+    fn choose(&self, log: &Log, branches: &[Branch<'_>]) -> Option<Cid> {
+        let best = branches.iter().map(|b| Self::priority(log, b)).max()?;
+        let candidates: Vec<Branch<'_>> = branches
+            .iter()
+            .filter(|b| Self::priority(log, b) == best)
+            .cloned()
+            .collect();
+        LongestChain.choose(log, &candidates)
+    }
+}
+
+impl Log {
+    /// find all of the places where more than one entry links to the same
+    /// previous entry
+    pub fn forks(&self) -> Vec<Fork> {
+        let mut children: BTreeMap<Cid, Vec<Cid>> = BTreeMap::new();
+        for (cid, entry) in &self.entries {
+            children.entry(entry.prev_ref().clone()).or_default().push(cid.clone());
+        }
+        children
+            .into_iter()
+            .filter(|(_, c)| c.len() > 1)
+            .map(|(prev, children)| Fork { prev, children })
+            .collect()
+    }
+
+    /// get the cids of all entries that no other entry links to
+    pub fn heads(&self) -> Vec<Cid> {
+        let prevs: BTreeSet<Cid> = self.entries.values().map(|e| e.prev_ref().clone()).collect();
+        // the entries kept before the checkpoint of a compacted log aren't heads
+        let min_seqno = self.checkpoint.as_ref().map(|cp| cp.seqno()).unwrap_or(0);
+        self.entries
+            .iter()
+            .filter(|(cid, e)| e.seqno() >= min_seqno && !prevs.contains(*cid))
+            .map(|(cid, _)| cid.clone())
+            .collect()
+    }
+
+    /// get the branch ending at the given head by following the prev links
+    pub fn branch(&self, head: &Cid) -> Result<Branch<'_>, Error> {
+        let mut entries = Vec::default();
+        let mut c = head.clone();
+        loop {
+            let entry = self
+                .entries
+                .get(&c)
+                .ok_or(LogError::EntryNotFound(c.clone()))?;
+            entries.push(entry);
+            if c == self.foot {
+                break;
+            }
+            // the entries before the checkpoint may have been discarded
+            if self.checkpoint.as_ref().map(|cp| cp.head == c).unwrap_or(false) {
+                break;
+            }
+            c = entry.prev();
+            if c.is_null() {
+                break;
+            }
+        }
+        entries.reverse();
+        Ok(Branch {
+            head: head.clone(),
+            entries,
+        })
+    }
+
+    /// get every branch in the log, one for each head
+    pub fn branches(&self) -> Result<Vec<Branch<'_>>, Error> {
+        self.heads().iter().map(|h| self.branch(h)).collect()
+    }
+
+    /// select the canonical head using the given fork choice rule
+    pub fn choose_head(&self, rule: &dyn ForkChoice) -> Result<Option<Cid>, Error> {
+        let branches = self.branches()?;
+        Ok(rule.choose(self, &branches))
+    }
+
+    /// reconcile the branches of the log by moving the head to the head of
+    /// the branch chosen by the fork choice rule and return it
+    pub fn reconcile(&mut self, rule: &dyn ForkChoice) -> Result<Cid, Error> {
+        let head = self
+            .choose_head(rule)?
+            .ok_or(LogError::MissingHead)?;
+        if head != self.head {
+            self.set_head_checked(&head)?;
+        }
+        Ok(head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Op, Script, Value};
+    use multicid::Vlad;
+
+    fn child(prev: &Entry, value: &str) -> Entry {
+        entry::Builder::from(prev)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/foo".try_into().unwrap(),
+                Value::Str(value.to_string()),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_forks() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2a = child(&e1, "a");
+        let e2b = child(&e1, "b");
+        let e3a = child(&e2a, "c");
+
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2b)
+            .append_entry(&e2a)
+            .append_entry(&e3a)
+            .try_build()
+            .unwrap();
+
+        let forks = log.forks();
+        assert_eq!(1, forks.len());
+        assert_eq!(e1.cid(), forks[0].prev);
+        assert_eq!(2, forks[0].children.len());
+        assert!(forks[0].children.contains(&e2a.cid()));
+        assert!(forks[0].children.contains(&e2b.cid()));
+
+        let heads = log.heads();
+        assert_eq!(2, heads.len());
+        assert!(heads.contains(&e2b.cid()));
+        assert!(heads.contains(&e3a.cid()));
+
+        let branch = log.branch(&e2b.cid()).unwrap();
+        assert_eq!(vec![&e1, &e2b], branch.entries);
+
+        assert_eq!(Some(e3a.cid()), log.choose_head(&LongestChain).unwrap());
+    }
+
+    #[test]
+    fn test_fork_choice_rules() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                FORK_CHOICE_KEY.try_into().unwrap(),
+                Value::Str("/priority".to_string()),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2a = child(&e1, "a");
+        let e3a = child(&e2a, "c");
+        let e2b = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/priority".try_into().unwrap(),
+                Value::Str("10".to_string()),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let mut log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2b)
+            .append_entry(&e2a)
+            .append_entry(&e3a)
+            .try_build()
+            .unwrap();
+
+        // the longer branch wins by default
+        assert_eq!(Some(e3a.cid()), log.choose_head(&LongestChain).unwrap());
+
+        // the branch received first wins
+        let rule = FirstSeen::new(&[e1.cid(), e2b.cid(), e2a.cid(), e3a.cid()]);
+        assert_eq!(Some(e2b.cid()), log.choose_head(&rule).unwrap());
+        let rule = FirstSeen::new(&[e1.cid(), e2a.cid(), e2b.cid()]);
+        assert_eq!(Some(e3a.cid()), log.choose_head(&rule).unwrap());
+
+        // the branch with the priority set wins
+        let branch = log.branch(&e2b.cid()).unwrap();
+        assert_eq!(Some(10), PriorityKey::priority(&log, &branch));
+        assert_eq!(Some(e2b.cid()), log.choose_head(&PriorityKey).unwrap());
+
+        assert_eq!(e2b.cid(), log.reconcile(&PriorityKey).unwrap());
+        assert_eq!(&e2b.cid(), log.head());
+    }
+
+    #[test]
+    fn test_no_forks() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = child(&e1, "a");
+
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        assert!(log.forks().is_empty());
+        assert_eq!(vec![e2.cid()], log.heads());
+        assert_eq!(Some(e2.cid()), log.choose_head(&LongestChain).unwrap());
+    }
+}
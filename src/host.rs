@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Host functions extend the vocabulary of the scripts without forking this
+//! crate, e.g. to add a `check_merkle_proof` or a `check_timestamp`. The wacc
+//! vm links a fixed set of imports so the functions are reached through the
+//! reserved `/host/` keys instead: a script that reads
+//! `/host/<name>/<arg>/<arg>...` gets the value computed by the function
+//! registered under the name from the arguments and the pairs the script can
+//! see. Run the scripts with a [`HostEngine`] to make the functions
+//! available.
+use crate::{Error, Key, Script, ScriptEngine, Stk};
+use std::collections::BTreeMap;
+use wacc::Pairs;
+
+/// the branch the host functions are called through
+pub const HOST: &str = "/host/";
+
+/// A HostFunction computes a value for a script
+pub trait HostFunction {
+    /// call the function with the arguments from the key and the pairs the
+    /// script reading the key can see. None if there is no value.
+    fn call(&self, args: &[String], pairs: &dyn Pairs) -> Option<wacc::Value>;
+}
+
+impl<F> HostFunction for F
+where
+    F: Fn(&[String], &dyn Pairs) -> Option<wacc::Value>,
+{
+    fn call(&self, args: &[String], pairs: &dyn Pairs) -> Option<wacc::Value> {
+        self(args, pairs)
+    }
+}
+
+/// The host functions by name
+#[derive(Default)]
+pub struct HostFunctions {
+    functions: BTreeMap<String, Box<dyn HostFunction>>,
+}
+
+impl HostFunctions {
+    /// create an empty set of host functions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// register the function under the name, replacing any function
+    /// already registered under it
+    pub fn with_function<S: AsRef<str>, F: HostFunction + 'static>(mut self, name: S, f: F) -> Self {
+        self.functions.insert(name.as_ref().to_string(), Box::new(f));
+        self
+    }
+
+    /// get the names of the registered functions
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|k| k.as_str())
+    }
+
+    /// call the function the key names, None if the key isn't under
+    /// [`HOST`] or no function is registered under the name
+    pub fn call(&self, key: &str, pairs: &dyn Pairs) -> Option<wacc::Value> {
+        if !key.starts_with(HOST) {
+            return None;
+        }
+        let segments = Key::try_from(key).ok()?.segments();
+        let (name, args) = segments.get(1).map(|n| (n, &segments[2..]))?;
+        self.functions.get(name)?.call(args, pairs)
+    }
+}
+
+// the pairs seen by a script run by a HostEngine
+struct Host<'a> {
+    pairs: &'a dyn Pairs,
+    functions: &'a HostFunctions,
+}
+
+impl Pairs for Host<'_> {
+    fn get(&self, key: &str) -> Option<wacc::Value> {
+        if key.starts_with(HOST) {
+            self.functions.call(key, self.pairs)
+        } else {
+            self.pairs.get(key)
+        }
+    }
+
+    fn put(&mut self, _key: &str, _value: &wacc::Value) -> Option<wacc::Value> {
+        None
+    }
+}
+
+/// A ScriptEngine that makes the host functions available to the scripts
+/// run by another engine
+pub struct HostEngine<'a> {
+    engine: &'a dyn ScriptEngine,
+    functions: &'a HostFunctions,
+}
+
+impl<'a> HostEngine<'a> {
+    /// create an engine that runs scripts with the given engine and the
+    /// given host functions
+    pub fn new(engine: &'a dyn ScriptEngine, functions: &'a HostFunctions) -> Self {
+        Self { engine, functions }
+    }
+
+    fn host<'b>(&'b self, pairs: &'b dyn Pairs) -> Host<'b> {
+        Host {
+            pairs,
+            functions: self.functions,
+        }
+    }
+}
+
+impl ScriptEngine for HostEngine<'_> {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        self.engine.run(
+            script,
+            func,
+            &self.host(current),
+            &self.host(proposed),
+            pstack,
+            rstack,
+            context,
+        )
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        self.engine.run_with_log(
+            script,
+            func,
+            &self.host(current),
+            &self.host(proposed),
+            pstack,
+            rstack,
+            context,
+            log,
+        )
+    }
+
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        self.engine.run_metered(
+            script,
+            func,
+            &self.host(current),
+            &self.host(proposed),
+            pstack,
+            rstack,
+            context,
+            log,
+            fuel,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kvp, Value};
+
+    #[test]
+    fn test_host_functions() {
+        // checks that the value at the key in the argument is a timestamp
+        // before the one in the second argument
+        let functions = HostFunctions::new().with_function(
+            "check_timestamp",
+            |args: &[String], pairs: &dyn Pairs| -> Option<wacc::Value> {
+                let ts = match pairs.get(&format!("/{}", args.first()?))? {
+                    wacc::Value::Str { data, .. } => data.parse::<u64>().ok()?,
+                    _ => return None,
+                };
+                let before = args.get(1)?.parse::<u64>().ok()?;
+                Some(wacc::Value::Str {
+                    hint: "check_timestamp".to_string(),
+                    data: (ts < before).to_string(),
+                })
+            },
+        );
+        assert_eq!(vec!["check_timestamp"], functions.names().collect::<Vec<_>>());
+
+        let mut pairs = BTreeMap::new();
+        pairs.insert(Key::try_from("/ts").unwrap(), Value::Str("100".to_string()));
+        let kvp = Kvp::from(pairs);
+        let host = Host {
+            pairs: &kvp,
+            functions: &functions,
+        };
+        match host.get("/host/check_timestamp/ts/200") {
+            Some(wacc::Value::Str { data, .. }) => assert_eq!("true", data),
+            _ => panic!("expected the host function to be called"),
+        }
+        assert!(host.get("/host/check_timestamp/ts").is_none());
+        assert!(host.get("/host/unknown/ts").is_none());
+        // everything else is read from the pairs
+        assert!(host.get("/ts").is_some());
+    }
+}
@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Axum handlers for serving logs over HTTP. [`router`] exposes the logs
+//! from a [`LogProvider`] at:
+//!
+//! ```text
+//! GET /plog/{vlad}              the whole log
+//! GET /plog/{vlad}/entry/{cid}  one entry
+//! GET /plog/{vlad}/kvp          the verified key-value pairs at the head
+//! ```
+//!
+//! The vlad and cid are multibase encoded. Responses are the binary
+//! encodings unless the request accepts `application/json`.
+use crate::{Entry, Error, Kvp, Log, Value};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use multicid::{Cid, Vlad};
+use multiutil::Varuint;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// the content type of the binary encodings
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// the content type of the JSON encodings
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Looks up the logs served by the gateway
+pub trait LogProvider: Send + Sync + 'static {
+    /// get the log with the vlad, if it is served
+    fn get_log(&self, vlad: &Vlad) -> Option<Log>;
+}
+
+impl LogProvider for Log {
+    fn get_log(&self, vlad: &Vlad) -> Option<Log> {
+        (self.vlad == *vlad).then(|| self.clone())
+    }
+}
+
+impl<F> LogProvider for F
+where
+    F: Fn(&Vlad) -> Option<Log> + Send + Sync + 'static,
+{
+    fn get_log(&self, vlad: &Vlad) -> Option<Log> {
+        self(vlad)
+    }
+}
+
+/// The encoding of a response
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    /// the binary encoding
+    #[default]
+    Binary,
+    /// the JSON encoding
+    Json,
+}
+
+impl Format {
+    /// pick the format the request accepts
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers.get(header::ACCEPT).and_then(|a| a.to_str().ok()) {
+            Some(accept) if accept.contains(JSON_CONTENT_TYPE) => Self::Json,
+            _ => Self::Binary,
+        }
+    }
+
+    /// get the content type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Binary => BINARY_CONTENT_TYPE,
+            Self::Json => JSON_CONTENT_TYPE,
+        }
+    }
+}
+
+/// A failed request
+#[derive(Debug)]
+pub struct HttpError(pub StatusCode, pub String);
+
+impl HttpError {
+    fn bad_request<S: ToString>(s: S) -> Self {
+        Self(StatusCode::BAD_REQUEST, s.to_string())
+    }
+
+    fn not_found<S: ToString>(s: S) -> Self {
+        Self(StatusCode::NOT_FOUND, s.to_string())
+    }
+
+    fn internal<S: ToString>(s: S) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, s.to_string())
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+fn json<T: ::serde::Serialize>(t: &T) -> Result<Vec<u8>, HttpError> {
+    serde_json::to_vec(t).map_err(HttpError::internal)
+}
+
+/// encode the log in the format
+pub fn encode_log(log: &Log, format: Format) -> Result<Vec<u8>, HttpError> {
+    match format {
+        Format::Binary => Ok(log.clone().into()),
+        Format::Json => json(log),
+    }
+}
+
+/// encode the entry in the format
+pub fn encode_entry(entry: &Entry, format: Format) -> Result<Vec<u8>, HttpError> {
+    match format {
+        Format::Binary => Ok(entry.clone().into()),
+        Format::Json => json(entry),
+    }
+}
+
+/// encode the key-value pairs in the format. the binary encoding is the
+/// number of pairs followed by each key and value, the same as in a
+/// [`crate::Checkpoint`], and the JSON encoding is an object of the values by
+/// key.
+pub fn encode_kvp(kvp: &Kvp, format: Format) -> Result<Vec<u8>, HttpError> {
+    match format {
+        Format::Binary => {
+            let mut v: Vec<u8> = Varuint(kvp.len()).into();
+            kvp.iter().for_each(|(key, value)| {
+                v.append(&mut key.clone().into());
+                v.append(&mut value.clone().into());
+            });
+            Ok(v)
+        }
+        Format::Json => {
+            let kvp: BTreeMap<String, &Value> = kvp.iter().map(|(k, v)| (k.to_string(), v)).collect();
+            json(&kvp)
+        }
+    }
+}
+
+fn decode<T>(s: &str) -> Result<T, HttpError>
+where
+    T: for<'a> TryFrom<&'a [u8], Error = multicid::Error>,
+{
+    let (_, bytes) = multibase::decode(s).map_err(HttpError::bad_request)?;
+    T::try_from(bytes.as_slice()).map_err(|e| HttpError::bad_request(Error::from(e)))
+}
+
+fn lookup<P: LogProvider>(provider: &P, vlad: &str) -> Result<Log, HttpError> {
+    let vlad: Vlad = decode(vlad)?;
+    provider
+        .get_log(&vlad)
+        .ok_or_else(|| HttpError::not_found("log not found"))
+}
+
+fn reply(format: Format, body: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, format.content_type())], body).into_response()
+}
+
+/// `GET /plog/{vlad}`
+pub async fn get_log<P: LogProvider>(
+    State(provider): State<Arc<P>>,
+    Path(vlad): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, HttpError> {
+    let format = Format::from_headers(&headers);
+    let log = lookup(provider.as_ref(), &vlad)?;
+    Ok(reply(format, encode_log(&log, format)?))
+}
+
+/// `GET /plog/{vlad}/entry/{cid}`
+pub async fn get_entry<P: LogProvider>(
+    State(provider): State<Arc<P>>,
+    Path((vlad, cid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, HttpError> {
+    let format = Format::from_headers(&headers);
+    let log = lookup(provider.as_ref(), &vlad)?;
+    let cid: Cid = decode(&cid)?;
+    let entry = log
+        .entries
+        .get(&cid)
+        .ok_or_else(|| HttpError::not_found("entry not found"))?;
+    Ok(reply(format, encode_entry(entry, format)?))
+}
+
+/// `GET /plog/{vlad}/kvp`, verifying the log first
+pub async fn get_kvp<P: LogProvider>(
+    State(provider): State<Arc<P>>,
+    Path(vlad): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, HttpError> {
+    let format = Format::from_headers(&headers);
+    let log = lookup(provider.as_ref(), &vlad)?;
+    let report = log.verify_all().map_err(HttpError::internal)?;
+    Ok(reply(format, encode_kvp(&report.kvp, format)?))
+}
+
+/// build the router serving the logs from the provider
+pub fn router<P: LogProvider>(provider: P) -> Router {
+    Router::new()
+        .route("/plog/:vlad", get(get_log::<P>))
+        .route("/plog/:vlad/entry/:cid", get(get_entry::<P>))
+        .route("/plog/:vlad/kvp", get(get_kvp::<P>))
+        .with_state(Arc::new(provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Key, Script};
+    use axum::http::HeaderValue;
+    use multibase::Base;
+
+    #[test]
+    fn test_gateway_encodings() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(Format::Binary, Format::from_headers(&headers));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        assert_eq!(Format::Json, Format::from_headers(&headers));
+
+        let bytes = encode_log(&log, Format::Binary).unwrap();
+        assert_eq!(log, Log::try_from(bytes.as_slice()).unwrap());
+        let bytes = encode_entry(&e1, Format::Binary).unwrap();
+        assert_eq!(e1, Entry::try_from(bytes.as_slice()).unwrap());
+
+        let mut pairs = BTreeMap::new();
+        pairs.insert(Key::try_from("/foo").unwrap(), Value::Str("bar".to_string()));
+        let kvp = Kvp::from(pairs);
+        let bytes = encode_kvp(&kvp, Format::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(json.get("/foo").is_some());
+        let bytes = encode_kvp(&kvp, Format::Binary).unwrap();
+        let mut expected: Vec<u8> = Varuint(1usize).into();
+        expected.append(&mut Key::try_from("/foo").unwrap().into());
+        expected.append(&mut Value::Str("bar".to_string()).into());
+        assert_eq!(expected, bytes);
+
+        // the vlad is looked up by its multibase encoding
+        let vlad: Vec<u8> = Vlad::default().into();
+        let vlad = multibase::encode(Base::Base32Lower, vlad);
+        assert_eq!(log, lookup(&log, &vlad).unwrap());
+        assert_eq!(StatusCode::BAD_REQUEST, lookup(&log, "not a vlad").unwrap_err().0);
+    }
+}
@@ -0,0 +1,495 @@
+// SPDX-License-Identifier: FSL-1.1
+//! A pure-Rust [wasmi] interpreter backend for the scripts. It is much slower
+//! than the wacc vm but doesn't need a jit compiler so it runs on small
+//! devices and targets wasmtime doesn't support. Only core wasm modules are
+//! run, either binary or in the wat text format the example scripts are
+//! written in, and they are linked against the same `wacc` imports as the
+//! wacc vm:
+//!
+//! - `_push(key, len)` pushes the value at the key-path onto the parameter
+//!   stack
+//! - `_pop()` pops the top value off of the parameter stack
+//! - `_branch(key, len, out)` writes the branch key-path of the script
+//!   followed by the key-path to memory at `out` and returns its length. It
+//!   fails in a script for a leaf key-path.
+//! - `_log(msg, len)` adds the message to the script log
+//! - `_check_eq(key, len)` checks that the value on top of the parameter
+//!   stack is equal to the value at the key-path
+//! - `_check_preimage(key, len)` checks that the value on top of the
+//!   parameter stack hashes to the multihash at the key-path
+//! - `_check_signature(key, len, msg, len)` checks that the multisig on top
+//!   of the parameter stack is a signature over the value at the message
+//!   key-path by the multikey at the key-path
+//!
+//! Each function returns 1 on success and 0 on failure, except `_branch`
+//! which returns 0 on failure. The checks pop the value they checked off of
+//! the parameter stack if they succeed. The value
+//! returned by the called function decides the result of the script: a
+//! [`Value::Success`] with the number of checks that succeeded is pushed onto
+//! the return stack if it is not zero and a [`Value::Failure`] if it is.
+//!
+//! [wasmi]: https://docs.rs/wasmi
+use crate::{error::ScriptError, script::WasmKind, Error, Key, Script, ScriptEngine, Stk};
+use multihash::{mh, Multihash};
+use multikey::{Multikey, Views};
+use multisig::Multisig;
+use multitrait::TryDecodeFrom;
+use wacc::{vm::Value, Pairs, Stack};
+use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store};
+
+/// the module the host functions are linked under
+pub const IMPORT_MODULE: &str = "wacc";
+
+// the state the host functions work on while a script runs
+struct HostState<'a> {
+    current: &'a dyn Pairs,
+    proposed: &'a dyn Pairs,
+    pstack: &'a mut Stk,
+    // the branch key-path the script runs for
+    context: Key,
+    log: Vec<String>,
+    check_count: usize,
+}
+
+impl HostState<'_> {
+    // the value at the key-path in the current pairs, falling back to the
+    // proposed entry for its `/entry/` fields
+    fn get(&self, key: &str) -> Option<Value> {
+        self.current.get(key).or_else(|| self.proposed.get(key))
+    }
+
+    // the data in the value at the key-path
+    fn data(&self, key: &str) -> Option<Vec<u8>> {
+        value_data(self.get(key)?)
+    }
+}
+
+// the bytes of a stack value
+fn value_data(value: Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Bin { data, .. } => Some(data),
+        Value::Str { data, .. } => Some(data.into_bytes()),
+        _ => None,
+    }
+}
+
+// read the utf-8 string at the pointer from the exported memory
+fn read_str(caller: &Caller<'_, HostState<'_>>, ptr: i32, len: i32) -> Option<String> {
+    let memory = memory(caller)?;
+    let mut buf = vec![0u8; usize::try_from(len).ok()?];
+    memory
+        .read(caller, usize::try_from(ptr).ok()?, &mut buf)
+        .ok()?;
+    String::from_utf8(buf).ok()
+}
+
+// the memory exported by the script
+fn memory(caller: &Caller<'_, HostState<'_>>) -> Option<wasmi::Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+// the value checked by a check is popped and counted if the check succeeded
+fn checked(mut caller: Caller<'_, HostState<'_>>, ok: bool) -> i32 {
+    if !ok {
+        return 0;
+    }
+    let state = caller.data_mut();
+    state.pstack.pop();
+    state.check_count += 1;
+    1
+}
+
+fn host_push(mut caller: Caller<'_, HostState<'_>>, ptr: i32, len: i32) -> i32 {
+    let value = match read_str(&caller, ptr, len).and_then(|key| caller.data().get(&key)) {
+        Some(value) => value,
+        None => return 0,
+    };
+    caller.data_mut().pstack.push(value);
+    1
+}
+
+fn host_pop(mut caller: Caller<'_, HostState<'_>>) -> i32 {
+    match caller.data_mut().pstack.pop() {
+        Some(_) => 1,
+        None => 0,
+    }
+}
+
+fn host_branch(mut caller: Caller<'_, HostState<'_>>, ptr: i32, len: i32, out: i32) -> i32 {
+    let context = &caller.data().context;
+    if !context.is_branch() {
+        return 0;
+    }
+    // the branch ends with a separator and the key-path may start with one
+    let path = match read_str(&caller, ptr, len) {
+        Some(key) => format!("{}{}", context, key.trim_start_matches('/')),
+        None => return 0,
+    };
+    let (memory, out) = match (memory(&caller), usize::try_from(out)) {
+        (Some(memory), Ok(out)) => (memory, out),
+        _ => return 0,
+    };
+    match (memory.write(&mut caller, out, path.as_bytes()), i32::try_from(path.len())) {
+        (Ok(()), Ok(len)) => len,
+        _ => 0,
+    }
+}
+
+fn host_log(mut caller: Caller<'_, HostState<'_>>, ptr: i32, len: i32) -> i32 {
+    match read_str(&caller, ptr, len) {
+        Some(msg) => {
+            caller.data_mut().log.push(msg);
+            1
+        }
+        None => 0,
+    }
+}
+
+fn host_check_eq(caller: Caller<'_, HostState<'_>>, ptr: i32, len: i32) -> i32 {
+    let state = caller.data();
+    let expected = read_str(&caller, ptr, len).and_then(|key| state.data(&key));
+    let value = state.pstack.top().and_then(value_data);
+    let ok = matches!((expected, value), (Some(e), Some(v)) if e == v);
+    checked(caller, ok)
+}
+
+fn host_check_preimage(caller: Caller<'_, HostState<'_>>, ptr: i32, len: i32) -> i32 {
+    let state = caller.data();
+    let hash = match read_str(&caller, ptr, len).and_then(|key| state.data(&key)) {
+        Some(hash) => hash,
+        None => return 0,
+    };
+    let preimage = match state.pstack.top().and_then(value_data) {
+        Some(preimage) => preimage,
+        None => return 0,
+    };
+    let matches = match Multihash::try_decode_from(hash.as_slice()) {
+        Ok((hash, _)) => mh::Builder::new_from_bytes(hash.codec(), &preimage)
+            .and_then(|b| b.try_build())
+            .map(|h| h == hash)
+            .unwrap_or(false),
+        Err(_) => false,
+    };
+    checked(caller, matches)
+}
+
+fn host_check_signature(
+    caller: Caller<'_, HostState<'_>>,
+    key_ptr: i32,
+    key_len: i32,
+    msg_ptr: i32,
+    msg_len: i32,
+) -> i32 {
+    let state = caller.data();
+    let key = read_str(&caller, key_ptr, key_len).and_then(|key| state.data(&key));
+    let msg = read_str(&caller, msg_ptr, msg_len).and_then(|msg| state.data(&msg));
+    let sig = state.pstack.top().and_then(value_data);
+    let (key, msg, sig) = match (key, msg, sig) {
+        (Some(key), Some(msg), Some(sig)) => (key, msg, sig),
+        _ => return 0,
+    };
+    let verified = match (
+        Multikey::try_decode_from(key.as_slice()),
+        Multisig::try_decode_from(sig.as_slice()),
+    ) {
+        (Ok((key, _)), Ok((sig, _))) => key
+            .verify_view()
+            .and_then(|v| v.verify(&sig, Some(&msg)))
+            .is_ok(),
+        _ => false,
+    };
+    checked(caller, verified)
+}
+
+/// A ScriptEngine that runs core wasm module scripts in the wasmi interpreter
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InterpEngine;
+
+impl ScriptEngine for InterpEngine {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let mut log = Vec::default();
+        self.run_with_log(script, func, current, proposed, pstack, rstack, context, &mut log)
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        self.run_metered(script, func, current, proposed, pstack, rstack, context, log, None)?;
+        Ok(())
+    }
+
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        let bin = match (script, script.wasm_kind()) {
+            (_, Some(WasmKind::Component)) => return Err(ScriptError::UnsupportedComponent.into()),
+            (Script::Bin(_, bin), Some(WasmKind::Module)) => bin.clone(),
+            (Script::Code(_, code), _) => {
+                wat::parse_str(code).map_err(|e| ScriptError::InvalidWasm(e.to_string()))?
+            }
+            _ => {
+                let e = ScriptError::InvalidWasm("not a wasm module".to_string());
+                return Err(e.into());
+            }
+        };
+        let interp_err = |e: wasmi::Error| ScriptError::Interp(e.to_string());
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bin[..]).map_err(interp_err)?;
+        let state = HostState {
+            current,
+            proposed,
+            pstack,
+            context: context.clone(),
+            log: Vec::default(),
+            check_count: 0,
+        };
+        let mut store = Store::new(&engine, state);
+        let budget = fuel.unwrap_or(u64::MAX);
+        store.set_fuel(budget).map_err(interp_err)?;
+
+        let mut linker = Linker::<HostState<'_>>::new(&engine);
+        linker
+            .func_wrap(IMPORT_MODULE, "_push", host_push)
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_pop", host_pop))
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_branch", host_branch))
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_log", host_log))
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_check_eq", host_check_eq))
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_check_preimage", host_check_preimage))
+            .and_then(|l| l.func_wrap(IMPORT_MODULE, "_check_signature", host_check_signature))
+            .map_err(|e| ScriptError::Interp(e.to_string()))?;
+
+        // run the script and keep what it logged even if it failed
+        let result = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .and_then(|instance| instance.get_typed_func::<(), i32>(&store, func))
+            .and_then(|f| f.call(&mut store, ()));
+        let consumed = budget - store.get_fuel().unwrap_or(0);
+        let state = store.into_data();
+        log.extend(state.log);
+        let ret = match result {
+            Ok(ret) => ret,
+            Err(_) if fuel.is_some() && consumed >= budget => {
+                return Err(ScriptError::OutOfFuel(consumed, budget).into());
+            }
+            Err(e) => return Err(interp_err(e).into()),
+        };
+
+        if ret != 0 {
+            rstack.push(Value::Success(state.check_count));
+        } else {
+            rstack.push(Value::Failure(format!("{} returned {}", func, ret)));
+        }
+        Ok(consumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::{LOCK_FUNC, UNLOCK_FUNC},
+        policy::tests::{build_entry, keys, kvp, sign},
+        Entry, Kvp, WaccEngine,
+    };
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    // (module
+    //   (import "wacc" "_push" (func (param i32 i32) (result i32)))
+    //   (memory (export "memory") 1)
+    //   (func (export "move_every_zig") (result i32)
+    //     i32.const 0 i32.const 4 call 0)
+    //   (data (i32.const 0) "/foo"))
+    const PUSH_FOO: &[u8] = b"\0asm\x01\0\0\0\
+        \x01\x0b\x02\x60\x02\x7f\x7f\x01\x7f\x60\x00\x01\x7f\
+        \x02\x0e\x01\x04wacc\x05_push\x00\x00\
+        \x03\x02\x01\x01\
+        \x05\x03\x01\x00\x01\
+        \x07\x1b\x02\x06memory\x02\x00\x0emove_every_zig\x00\x01\
+        \x0a\x0a\x01\x08\x00\x41\x00\x41\x04\x10\x00\x0b\
+        \x0b\x0a\x01\x00\x41\x00\x0b\x04/foo";
+
+    fn run(kvp: &Kvp, fuel: Option<u64>) -> Result<(Stk, Stk), Error> {
+        let script = Script::Bin(Key::default(), PUSH_FOO.to_vec());
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+        let mut log = Vec::default();
+        InterpEngine.run_metered(
+            &script,
+            LOCK_FUNC,
+            kvp,
+            kvp,
+            &mut pstack,
+            &mut rstack,
+            &Key::default(),
+            &mut log,
+            fuel,
+        )?;
+        Ok((pstack, rstack))
+    }
+
+    #[test]
+    fn test_interp() {
+        let kvp = Kvp::from(BTreeMap::from([(
+            Key::try_from("/foo").unwrap(),
+            crate::Value::Str("bar".to_string()),
+        )]));
+        let (pstack, rstack) = run(&kvp, None).unwrap();
+        assert!(matches!(pstack.top(), Some(Value::Str { data, .. }) if data == "bar"));
+        assert!(matches!(rstack.top(), Some(Value::Success(0))));
+
+        // the push fails without the key so the script fails
+        let (pstack, rstack) = run(&Kvp::default(), None).unwrap();
+        assert!(pstack.is_empty());
+        assert!(matches!(rstack.top(), Some(Value::Failure(_))));
+
+        // the script runs out of fuel
+        assert!(matches!(
+            run(&kvp, Some(1)),
+            Err(Error::Script(ScriptError::OutOfFuel(_, 1)))
+        ));
+
+        // a module without the function fails
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+        assert!(InterpEngine
+            .run(
+                &Script::Code(Key::default(), "(module)".to_string()),
+                LOCK_FUNC,
+                &kvp,
+                &kvp,
+                &mut pstack,
+                &mut rstack,
+                &Key::default(),
+            )
+            .is_err());
+    }
+
+    fn load_script(file_name: &str) -> Script {
+        let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        pb.push("examples");
+        pb.push("wast");
+        pb.push(file_name);
+        crate::script::Builder::from_code_file(&pb)
+            .try_build()
+            .unwrap()
+    }
+
+    // run the unlock script of the entry and then the lock with the engine
+    // and get if the lock succeeded and how many values are left on the
+    // parameter stack
+    fn outcome(
+        engine: &dyn ScriptEngine,
+        lock: &Script,
+        kvp: &Kvp,
+        entry: &Entry,
+    ) -> (bool, usize) {
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+        let context = entry.context();
+        engine
+            .run(&entry.unlock, UNLOCK_FUNC, entry, entry, &mut pstack, &mut rstack, &context)
+            .unwrap();
+        engine
+            .run(lock, LOCK_FUNC, kvp, entry, &mut pstack, &mut rstack, &context)
+            .unwrap();
+        (matches!(rstack.top(), Some(Value::Success(_))), pstack.len())
+    }
+
+    #[test]
+    fn test_example_parity() {
+        let (k1, k2) = keys();
+        let kvp = kvp(&k1, &k2);
+        let unlock = load_script("unlock.wast");
+        let first = load_script("first.wast");
+        let lock = load_script("lock.wast");
+
+        // /ephemeral holds k2 and /pubkey holds k1, there is no /recovery
+        let cases = [
+            (&first, build_entry(&unlock, |e| vec![sign(e, &k2)]), true),
+            (&first, build_entry(&unlock, |e| vec![sign(e, &k1)]), false),
+            (&lock, build_entry(&unlock, |e| vec![sign(e, &k1)]), true),
+            (&lock, build_entry(&unlock, |_| vec![b"preimage".to_vec()]), true),
+            (&lock, build_entry(&unlock, |_| vec![b"wrong".to_vec()]), false),
+        ];
+        for (lock, entry, expected) in cases {
+            let interp = outcome(&InterpEngine, lock, &kvp, &entry);
+            assert_eq!(expected, interp.0);
+            assert_eq!(outcome(&WaccEngine, lock, &kvp, &entry), interp);
+        }
+    }
+
+    #[test]
+    fn test_abi() {
+        // push /foo/bar, check it against branch("bar"), then push and pop
+        let script = Script::Code(
+            Key::default(),
+            r#"(module
+                (import "wacc" "_push" (func $push (param i32 i32) (result i32)))
+                (import "wacc" "_pop" (func $pop (result i32)))
+                (import "wacc" "_branch" (func $branch (param i32 i32 i32) (result i32)))
+                (import "wacc" "_check_eq" (func $check_eq (param i32 i32) (result i32)))
+                (func (export "move_every_zig") (result i32)
+                    (drop (call $push (i32.const 0) (i32.const 8)))
+                    (call $check_eq
+                        (i32.const 64)
+                        (call $branch (i32.const 8) (i32.const 3) (i32.const 64)))
+                    (drop (call $push (i32.const 0) (i32.const 8)))
+                    (i32.and (call $pop))
+                )
+                (memory (export "memory") 1)
+                (data (i32.const 0) "/foo/barbar")
+            )"#
+            .to_string(),
+        );
+        let kvp = Kvp::from(BTreeMap::from([(
+            Key::try_from("/foo/bar").unwrap(),
+            crate::Value::Str("baz".to_string()),
+        )]));
+        let run = |context: &str| {
+            let mut pstack = Stk::default();
+            let mut rstack = Stk::default();
+            let context = Key::try_from(context).unwrap();
+            InterpEngine
+                .run(&script, LOCK_FUNC, &kvp, &kvp, &mut pstack, &mut rstack, &context)
+                .unwrap();
+            (rstack.top(), pstack.len())
+        };
+
+        // the branch makes /foo/bar so the check passes and pops its value
+        assert!(matches!(run("/foo/"), (Some(Value::Success(1)), 0)));
+        // a different branch makes a key-path without a value
+        assert!(matches!(run("/other/"), (Some(Value::Failure(_)), 1)));
+        // and a leaf has no branch
+        assert!(matches!(run("/foo/bar"), (Some(Value::Failure(_)), 1)));
+    }
+}
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: FSL-1.1
+//! A change-data-capture journal of the namespace. Each change records the
+//! key touched by an entry and the hashes of its value before and after the
+//! entry so external indexes and materialized views can follow the state of
+//! a log without holding the values themselves.
+use crate::{
+    observer::{Observer, VerifyEvent},
+    Entry, Error, Key, Kvp, Log, Pipeline, Value, WaccEngine,
+};
+use multicid::Cid;
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+};
+
+/// the hash codec used for value hashes
+pub const VALUE_HASH_CODEC: Codec = Codec::Sha3256;
+
+/// get the hash of the encoded value
+pub fn value_hash(value: &Value) -> Result<Multihash, Error> {
+    let bytes: Vec<u8> = value.clone().into();
+    Ok(mh::Builder::new_from_bytes(VALUE_HASH_CODEC, &bytes)?.try_build()?)
+}
+
+/// A change to the value of a key made by an entry
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    /// the seqno of the entry
+    pub seqno: u64,
+    /// the cid of the entry
+    pub entry: Cid,
+    /// the key that changed
+    pub key: Key,
+    /// the hash of the value before the entry, None if the key didn't exist
+    pub old: Option<Multihash>,
+    /// the hash of the value after the entry, None if the key was deleted
+    pub new: Option<Multihash>,
+}
+
+/// A Journal passes the changes made by each entry to a sink. It is an
+/// Observer so it can be passed to [`Log::verify_with_observer`] or
+/// [`Log::try_append_with_observer`]. Entries that were already recorded
+/// are skipped when a log is verified again so each change is only passed to
+/// the sink once.
+pub struct Journal<F: Fn(&Change)> {
+    sink: F,
+    // the value hashes of the keys touched so far
+    state: RefCell<BTreeMap<Key, Multihash>>,
+    // the seqno of the last entry recorded
+    last: Cell<Option<u64>>,
+}
+
+impl<F: Fn(&Change)> Journal<F> {
+    /// create a journal passing changes to the sink
+    pub fn new(sink: F) -> Self {
+        Self {
+            sink,
+            state: RefCell::new(BTreeMap::default()),
+            last: Cell::new(None),
+        }
+    }
+
+    /// record the changes made by the entry given the kvp state after its
+    /// ops were applied
+    pub fn record(&self, entry: &Entry, kvp: &Kvp) -> Result<(), Error> {
+        let replay = self
+            .last
+            .get()
+            .map(|last| entry.seqno() <= last)
+            .unwrap_or(false);
+        let cid = entry.cid();
+        let mut state = self.state.borrow_mut();
+        let mut seen: Vec<&Key> = Vec::default();
+        for op in entry.ops() {
+            let key = op.path_ref();
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            let new = kvp.get_value(key).map(value_hash).transpose()?;
+            let old = match &new {
+                Some(h) => state.insert(key.clone(), h.clone()),
+                None => state.remove(key),
+            };
+            if !replay && old != new {
+                (self.sink)(&Change {
+                    seqno: entry.seqno(),
+                    entry: cid.clone(),
+                    key: key.clone(),
+                    old,
+                    new,
+                });
+            }
+        }
+        if !replay {
+            self.last.set(Some(entry.seqno()));
+        }
+        Ok(())
+    }
+}
+
+impl<F: Fn(&Change)> Observer for Journal<F> {
+    fn observe(&self, event: &VerifyEvent<'_>) {
+        if let VerifyEvent::OpsApplied { entry, kvp } = event {
+            // value hashing only fails for an unsupported codec
+            let _ = self.record(entry, kvp);
+        }
+    }
+}
+
+impl Log {
+    /// verify the log and get every change made to the namespace
+    pub fn journal(&self) -> Result<Vec<Change>, Error> {
+        let changes = RefCell::new(Vec::default());
+        let journal = Journal::new(|c: &Change| changes.borrow_mut().push(c.clone()));
+        for result in self.verify_with_observer(Pipeline::default(), &WaccEngine, &journal) {
+            result?;
+        }
+        drop(journal);
+        Ok(changes.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Op, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_journal() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("one".to_string())))
+            .add_op(&Op::Update("/bar".try_into().unwrap(), Value::Nil))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("two".to_string())))
+            .add_op(&Op::Update("/bar".try_into().unwrap(), Value::Nil))
+            .add_op(&Op::Delete("/missing".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut kvp = Kvp::default();
+        let changes = RefCell::new(Vec::default());
+        let journal = Journal::new(|c: &Change| changes.borrow_mut().push(c.clone()));
+        for e in [&e1, &e2] {
+            kvp.set_entry(e).unwrap();
+            kvp.apply_entry_ops(e).unwrap();
+            journal.record(e, &kvp).unwrap();
+        }
+        // replaying doesn't record the changes again
+        journal.record(&e2, &kvp).unwrap();
+        drop(journal);
+
+        let changes = changes.into_inner();
+        // unchanged values and deleting missing keys aren't changes
+        assert_eq!(3, changes.len());
+        let foo = Key::try_from("/foo").unwrap();
+        assert_eq!(foo, changes[0].key);
+        assert!(changes[0].old.is_none());
+        assert_eq!(foo, changes[2].key);
+        assert_eq!(1, changes[2].seqno);
+        assert_eq!(changes[0].new, changes[2].old);
+        assert_eq!(
+            Some(value_hash(&Value::Str("two".to_string())).unwrap()),
+            changes[2].new
+        );
+    }
+}
@@ -1,6 +1,10 @@
 // SPDX-License-Identifier: FSL-1.1
 use crate::{error::KeyError, Error};
-use std::fmt;
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+};
 use multibase::Base;
 use multitrait::TryDecodeFrom;
 use multiutil::{EncodingInfo, Varbytes};
@@ -9,18 +13,112 @@ use multiutil::{EncodingInfo, Varbytes};
 /// the separator for the parts of a key
 pub const KEY_SEPARATOR: char = '/';
 
+/// the escape character used to encode the separator inside of a key segment
+pub const KEY_ESCAPE: char = '%';
+
+/// the escape sequence for the separator character inside of a key segment
+const ESCAPED_SEPARATOR: &str = "%2F";
+
+/// the escape sequence for the escape character inside of a key segment
+const ESCAPED_ESCAPE: &str = "%25";
+
 /// The keys used to reference values in a Pairs storage. These form a path of namespaces
 /// each part separated by the separator "/" and they come in two flavors: branch or leaf
 /// A branch is a key-path that ends with the separator: "/foo/bar/baz/"
 /// A leaf is a key-path that does not end with the separator: "/foo/bar/baz"
 /// Branches identify a namespace full of leaves and a leaf identifies a single value
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+///
+/// Keys from before segments were escaped can hold a `%` that doesn't start an
+/// escape sequence. Parsing escapes it so "/foo/100%" is the same key as
+/// "/foo/100%25" but the original string is kept for the binary encoding so
+/// entries holding the old form still decode as canonical.
+#[derive(Clone, Debug)]
 pub struct Key {
     parts: Vec<String>,
     s: String, // holds the "rendered" string so we can return a &str
+    legacy: Option<String>, // the unescaped string this key was parsed from
 }
 
 impl Key {
+    /// build a key from a list of unescaped segment names. the names are
+    /// escaped so that they can contain the separator and escape characters
+    /// without changing the structure of the key-path.
+    pub fn from_segments<S: AsRef<str>>(segments: &[S], is_branch: bool) -> Result<Self, Error> {
+        let mut parts = vec!["".to_string()];
+        for segment in segments {
+            if segment.as_ref().is_empty() {
+                return Err(KeyError::EmptySegment.into());
+            }
+            parts.push(Self::escape_segment(segment));
+        }
+        if is_branch || segments.is_empty() {
+            parts.push("".to_string());
+        }
+        let s = parts.join(&KEY_SEPARATOR.to_string());
+        Ok(Self { parts, s, legacy: None })
+    }
+
+    /// escape a segment name so that it can be safely used as a part of a key
+    pub fn escape_segment<S: AsRef<str>>(segment: S) -> String {
+        let mut escaped = String::default();
+        for c in segment.as_ref().chars() {
+            match c {
+                KEY_SEPARATOR => escaped.push_str(ESCAPED_SEPARATOR),
+                KEY_ESCAPE => escaped.push_str(ESCAPED_ESCAPE),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// unescape a key segment back into the segment name
+    pub fn unescape_segment<S: AsRef<str>>(segment: S) -> Result<String, Error> {
+        let segment = segment.as_ref();
+        let mut unescaped = String::default();
+        let mut rest = segment;
+        while let Some(i) = rest.find(KEY_ESCAPE) {
+            unescaped.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if rest.starts_with(ESCAPED_SEPARATOR) {
+                unescaped.push(KEY_SEPARATOR);
+            } else if rest.starts_with(ESCAPED_ESCAPE) {
+                unescaped.push(KEY_ESCAPE);
+            } else {
+                return Err(KeyError::InvalidEscape(segment.to_string()).into());
+            }
+            rest = &rest[ESCAPED_SEPARATOR.len()..];
+        }
+        unescaped.push_str(rest);
+        Ok(unescaped)
+    }
+
+    // escape each `%` that doesn't start an escape sequence. these come from
+    // keys written before segments were escaped.
+    fn escape_legacy(s: &str) -> String {
+        let mut escaped = String::default();
+        for (i, c) in s.char_indices() {
+            let rest = &s[i..];
+            if c == KEY_ESCAPE
+                && !rest.starts_with(ESCAPED_SEPARATOR)
+                && !rest.starts_with(ESCAPED_ESCAPE)
+            {
+                escaped.push_str(ESCAPED_ESCAPE);
+            } else {
+                escaped.push(c);
+            }
+        }
+        escaped
+    }
+
+    /// returns the unescaped segment names in the key
+    pub fn segments(&self) -> Vec<String> {
+        self.parts
+            .iter()
+            .filter(|p| !p.is_empty())
+            .map(|p| Self::unescape_segment(p).unwrap_or_else(|_| p.clone()))
+            .collect()
+    }
+
     /// true if this key is a branch
     pub fn is_branch(&self) -> bool {
         self.parts.last().unwrap().is_empty()
@@ -40,13 +138,13 @@ impl Key {
         let _ = self.parts.pop();
         self.parts.append(&mut moar.parts[1..].iter().map(|s| s.to_string()).collect::<Vec<_>>());
         self.s = self.parts.join(&KEY_SEPARATOR.to_string());
+        self.legacy = None;
         Ok(())
     }
 
     /// true if this path is a branch and the passed in path is achild of it
     /// treu if this path is a leaf and the passed in path is the same path
     pub fn parent_of(&self, other: &Self) -> bool {
-        //println!("\t{} is a {}", self, if self.is_leaf() { "leaf" } else { "branch" });
         if self.is_leaf() {
             self == other
         } else {
@@ -70,7 +168,6 @@ impl Key {
                 }
             }
 
-            //println!("\t{:?} {} with {:?}", other_parts, if other_parts.starts_with(&self_parts) { "starts" } else { "does not start" }, self_parts);
             other_parts.starts_with(&self_parts)
         }
     }
@@ -103,7 +200,7 @@ impl Key {
             let _ = parts.pop();
             parts.push("".to_string());
             let s = parts.join(&KEY_SEPARATOR.to_string());
-            Self { parts, s }
+            Self { parts, s, legacy: None }
         }
     }
 
@@ -137,7 +234,7 @@ impl Key {
         }
 
         let s = parts.join(&KEY_SEPARATOR.to_string());
-        Self { parts, s }
+        Self { parts, s, legacy: None }
     }
 
     /// return the key as a &str
@@ -150,7 +247,33 @@ impl Default for Key {
     fn default() -> Self {
         let parts = vec!["".to_string(), "".to_string()];
         let s = parts.join(&KEY_SEPARATOR.to_string());
-        Self { parts, s }
+        Self { parts, s, legacy: None }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts == other.parts
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.parts.hash(state);
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts.cmp(&other.parts)
     }
 }
 
@@ -169,8 +292,10 @@ impl EncodingInfo for Key {
 impl From<Key> for Vec<u8> {
     fn from(val: Key) -> Self {
         let mut v = Vec::default();
-        // convert the path to a string and encode it as varbytes
-        v.append(&mut Varbytes(val.to_string().as_bytes().to_vec()).into());
+        // convert the path to a string and encode it as varbytes. keys parsed
+        // from the unescaped form keep it so their bytes don't change.
+        let s = val.legacy.clone().unwrap_or_else(|| val.to_string());
+        v.append(&mut Varbytes(s.as_bytes().to_vec()).into());
         v
     }
 }
@@ -236,9 +361,11 @@ impl TryFrom<String> for Key {
             }
             filtered
         };
-        let parts = filtered.split(KEY_SEPARATOR).map(|s| s.to_string()).collect::<Vec<_>>();
+        let escaped = Self::escape_legacy(&filtered);
+        let legacy = if escaped != filtered { Some(filtered) } else { None };
+        let parts = escaped.split(KEY_SEPARATOR).map(|s| s.to_string()).collect::<Vec<_>>();
         let s = parts.join(&KEY_SEPARATOR.to_string());
-        Ok(Self { parts, s })
+        Ok(Self { parts, s, legacy })
     }
 }
 
@@ -254,6 +381,58 @@ impl fmt::Display for Key {
     }
 }
 
+/// the key pattern segment that matches any one segment
+pub const KEY_WILDCARD: &str = "*";
+
+/// A KeyPattern matches key-paths. It is written like a key where a "*"
+/// segment matches any one segment. A branch pattern matches every key under
+/// the branches it matches and a leaf pattern only matches leaves.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct KeyPattern(Key);
+
+impl KeyPattern {
+    /// true if the key matches the pattern
+    pub fn matches(&self, key: &Key) -> bool {
+        let pattern = self.0.segments();
+        let segments = key.segments();
+        let len_ok = if self.0.is_branch() {
+            pattern.len() <= segments.len()
+        } else {
+            key.is_leaf() && pattern.len() == segments.len()
+        };
+        len_ok
+            && pattern
+                .iter()
+                .zip(segments.iter())
+                .all(|(p, s)| p == KEY_WILDCARD || p == s)
+    }
+
+    /// get the pattern as a key
+    pub fn as_key(&self) -> &Key {
+        &self.0
+    }
+}
+
+impl From<Key> for KeyPattern {
+    fn from(key: Key) -> Self {
+        Self(key)
+    }
+}
+
+impl TryFrom<&str> for KeyPattern {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Ok(Self(Key::try_from(s)?))
+    }
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,4 +579,88 @@ mod tests {
         let b = Key::try_from("/foo/bar").unwrap();
         assert_eq!(b.as_ref(), "/foo/bar");
     }
+
+    #[test]
+    fn test_escaped_segments() {
+        let k = Key::from_segments(&["users", "alice/bob%"], false).unwrap();
+        assert!(k.is_leaf());
+        assert_eq!(2, k.len());
+        assert_eq!(format!("{}", k), "/users/alice%2Fbob%25".to_string());
+        assert_eq!(k.segments(), vec!["users".to_string(), "alice/bob%".to_string()]);
+        let k2 = Key::try_from(k.to_string()).unwrap();
+        assert_eq!(k, k2);
+        assert_eq!(k2.segments(), k.segments());
+    }
+
+    #[test]
+    fn test_escaped_branch() {
+        let k = Key::from_segments(&["a/b"], true).unwrap();
+        assert!(k.is_branch());
+        assert_eq!(format!("{}", k), "/a%2Fb/".to_string());
+        assert_eq!(Key::from_segments::<&str>(&[], true).unwrap(), Key::default());
+    }
+
+    #[test]
+    fn test_empty_segment() {
+        assert!(Key::from_segments(&["foo", ""], false).is_err());
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        assert!(Key::unescape_segment("foo%").is_err());
+        assert!(Key::unescape_segment("foo%2").is_err());
+        assert!(Key::unescape_segment("foo%41").is_err());
+        assert!(Key::unescape_segment("foo%2f").is_err());
+        assert_eq!(Key::unescape_segment("foo%2F%25").unwrap(), "foo/%".to_string());
+    }
+
+    #[test]
+    fn test_unescaped_percent() {
+        // keys from before escaping parse as the escaped key
+        let k = Key::try_from("/foo/100%/%41").unwrap();
+        assert_eq!(format!("{}", k), "/foo/100%25/%2541".to_string());
+        assert_eq!(k, Key::try_from("/foo/100%25/%2541").unwrap());
+        assert_eq!(
+            k.segments(),
+            vec!["foo".to_string(), "100%".to_string(), "%41".to_string()]
+        );
+
+        // but keep the bytes they were encoded with
+        let v: Vec<u8> = k.clone().into();
+        let k2 = Key::try_from(v.as_slice()).unwrap();
+        assert_eq!(k, k2);
+        assert_eq!(v, Vec::<u8>::from(k2));
+        let escaped: Vec<u8> = Key::try_from("/foo/100%25/%2541").unwrap().into();
+        assert_ne!(v, escaped);
+    }
+
+    #[test]
+    fn test_segments_round_trip() {
+        for s in ["/foo/100%", "/foo/100%25", "/a%2Fb/%/", "/%2541", "/x%2f"] {
+            let k = Key::try_from(s).unwrap();
+            assert_eq!(k, Key::from_segments(&k.segments(), k.is_branch()).unwrap());
+        }
+
+        // the legacy and escaped forms of different names stay different
+        let bare = Key::try_from("/foo/100%").unwrap();
+        let escaped = Key::try_from("/foo/100%2525").unwrap();
+        assert_ne!(bare.segments(), escaped.segments());
+    }
+
+    #[test]
+    fn test_key_pattern() {
+        let k = |s: &str| Key::try_from(s).unwrap();
+        let p = KeyPattern::try_from("/users/*/").unwrap();
+        assert!(p.matches(&k("/users/alice/name")));
+        assert!(p.matches(&k("/users/bob/")));
+        assert!(!p.matches(&k("/users/")));
+        assert!(!p.matches(&k("/groups/alice/name")));
+
+        let p = KeyPattern::try_from("/users/*/name").unwrap();
+        assert!(p.matches(&k("/users/alice/name")));
+        assert!(!p.matches(&k("/users/alice/name/")));
+        assert!(!p.matches(&k("/users/alice/email")));
+
+        assert!(KeyPattern::default().matches(&k("/anything")));
+    }
 }
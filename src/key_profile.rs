@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Key profiles restrict which key-paths are legal beyond what [`Key`] parsing
+//! requires: how deep they go, how long their segments are, which characters
+//! the segments use and which unicode normalization form they are in. Two
+//! implementations that agree on a profile agree on which keys are legal, so
+//! a key that looks the same but is encoded differently can't slip through
+//! one and be rejected by the other. Profiles are opt-in: the log checks the
+//! ops in appended entries against its profile, if it has one, and the
+//! [`CheckKeys`] stage checks them when verifying.
+use crate::{
+    error::KeyError,
+    pipeline::{Stage, Verification},
+    Entry, Error, Key,
+};
+use std::collections::BTreeSet;
+
+/// The characters allowed in key segments
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Charset {
+    /// any character
+    #[default]
+    Any,
+    /// any character that isn't a control character
+    Printable,
+    /// the unreserved url characters: ascii letters, digits and `-._~`
+    UrlSafe,
+    /// only the given characters
+    Only(BTreeSet<char>),
+}
+
+impl Charset {
+    /// true if the character is in the set
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Printable => !c.is_control(),
+            Self::UrlSafe => c.is_ascii_alphanumeric() || "-._~".contains(c),
+            Self::Only(chars) => chars.contains(&c),
+        }
+    }
+}
+
+/// The unicode normalization forms
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Normalization {
+    /// canonical composition
+    Nfc,
+    /// canonical decomposition
+    Nfd,
+    /// compatibility composition
+    Nfkc,
+    /// compatibility decomposition
+    Nfkd,
+}
+
+impl Normalization {
+    /// true if the string is in the normalization form
+    pub fn is_normalized(&self, s: &str) -> bool {
+        match self {
+            Self::Nfc => unicode_normalization::is_nfc(s),
+            Self::Nfd => unicode_normalization::is_nfd(s),
+            Self::Nfkc => unicode_normalization::is_nfkc(s),
+            Self::Nfkd => unicode_normalization::is_nfkd(s),
+        }
+    }
+}
+
+/// The rules key-paths are checked against. The default profile allows any
+/// key that parses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyProfile {
+    /// the maximum number of segments in a key-path
+    pub max_depth: usize,
+    /// the maximum number of characters in an unescaped segment
+    pub max_segment_len: usize,
+    /// the characters allowed in unescaped segments
+    pub charset: Charset,
+    /// the normalization form the segments must be in, if any
+    pub normalization: Option<Normalization>,
+}
+
+impl Default for KeyProfile {
+    fn default() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            max_segment_len: usize::MAX,
+            charset: Charset::Any,
+            normalization: None,
+        }
+    }
+}
+
+impl KeyProfile {
+    /// a profile for logs shared between implementations: at most 32
+    /// segments of at most 255 printable characters in NFC form
+    pub fn interop() -> Self {
+        Self {
+            max_depth: 32,
+            max_segment_len: 255,
+            charset: Charset::Printable,
+            normalization: Some(Normalization::Nfc),
+        }
+    }
+
+    /// set the maximum number of segments in a key-path
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// set the maximum number of characters in a segment
+    pub fn with_max_segment_len(mut self, max_segment_len: usize) -> Self {
+        self.max_segment_len = max_segment_len;
+        self
+    }
+
+    /// set the characters allowed in segments
+    pub fn with_charset(mut self, charset: Charset) -> Self {
+        self.charset = charset;
+        self
+    }
+
+    /// set the normalization form the segments must be in
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = Some(normalization);
+        self
+    }
+
+    /// check the key-path against the profile
+    pub fn check(&self, key: &Key) -> Result<(), Error> {
+        let segments = key.segments();
+        if segments.len() > self.max_depth {
+            return Err(KeyError::TooDeep(key.to_string(), self.max_depth).into());
+        }
+        for segment in &segments {
+            if segment.chars().count() > self.max_segment_len {
+                return Err(KeyError::SegmentTooLong(segment.clone(), self.max_segment_len).into());
+            }
+            if let Some(c) = segment.chars().find(|&c| !self.charset.contains(c)) {
+                return Err(KeyError::ForbiddenChar(segment.clone(), c).into());
+            }
+            if let Some(form) = self.normalization {
+                if !form.is_normalized(segment) {
+                    return Err(KeyError::NotNormalized(segment.clone(), form).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// parse the key-path and check it against the profile
+    pub fn try_key(&self, s: &str) -> Result<Key, Error> {
+        let key = Key::try_from(s)?;
+        self.check(&key)?;
+        Ok(key)
+    }
+
+    /// check the key-paths of the ops and lock scripts in the entry
+    pub fn check_entry(&self, entry: &Entry) -> Result<(), Error> {
+        for op in entry.ops() {
+            self.check(op.path_ref())?;
+        }
+        for lock in entry.locks() {
+            self.check(lock.path_ref())?;
+        }
+        Ok(())
+    }
+}
+
+/// Rejects entries with key-paths that don't match the profile
+#[derive(Clone, Debug, Default)]
+pub struct CheckKeys(pub KeyProfile);
+
+impl Stage for CheckKeys {
+    fn name(&self) -> &str {
+        "keys"
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        self.0.check_entry(v.entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry, log,
+        pipeline::{Apply, Pipeline, Structure},
+        Op, Script, Value, WaccEngine,
+    };
+    use multicid::Vlad;
+
+    #[test]
+    fn test_key_profile() {
+        let p = KeyProfile::default();
+        p.try_key("/any/thing\u{7}/goes").unwrap();
+
+        let p = KeyProfile::interop().with_max_depth(2).with_max_segment_len(5);
+        p.try_key("/keys/one").unwrap();
+        assert!(matches!(
+            p.try_key("/a/b/c"),
+            Err(Error::Key(KeyError::TooDeep(_, 2)))
+        ));
+        assert!(matches!(
+            p.try_key("/toolong"),
+            Err(Error::Key(KeyError::SegmentTooLong(_, 5)))
+        ));
+        assert!(matches!(
+            p.try_key("/be\u{7}l"),
+            Err(Error::Key(KeyError::ForbiddenChar(_, '\u{7}')))
+        ));
+        // "é" composed is NFC, "e" plus the combining accent is not
+        p.try_key("/caf\u{e9}").unwrap();
+        assert!(matches!(
+            p.try_key("/cafe\u{301}"),
+            Err(Error::Key(KeyError::NotNormalized(_, Normalization::Nfc)))
+        ));
+        let p = p.with_normalization(Normalization::Nfd);
+        p.try_key("/cafe\u{301}").unwrap();
+
+        let p = KeyProfile::default().with_charset(Charset::UrlSafe);
+        p.try_key("/a-b.c_d~e").unwrap();
+        assert!(p.try_key("/a b").is_err());
+        let p = p.with_charset(Charset::Only(BTreeSet::from(['a', 'b'])));
+        p.try_key("/ab/ba").unwrap();
+        assert!(p.try_key("/abc").is_err());
+    }
+
+    #[test]
+    fn test_log_key_profile() {
+        let profile = KeyProfile::default().with_charset(Charset::UrlSafe);
+        let builder = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default());
+        let e1 = builder
+            .clone()
+            .add_op(&Op::Update("/a-b".try_into().unwrap(), Value::Nil))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        profile.check_entry(&e1).unwrap();
+
+        // the log rejects entries that don't match its profile when appending
+        let mut log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .with_key_profile(&profile)
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        assert_eq!(Some(&profile), log.key_profile());
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/a b".try_into().unwrap(), Value::Nil))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert!(matches!(
+            log.try_append(&e2),
+            Err(Error::Key(KeyError::ForbiddenChar(_, ' ')))
+        ));
+
+        // and the stage rejects them when verifying
+        let bad = builder
+            .add_op(&Op::Update("/a b".try_into().unwrap(), Value::Nil))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&bad)
+            .try_build()
+            .unwrap();
+        let p = Pipeline::new()
+            .with_stage(Structure)
+            .with_stage(CheckKeys(profile))
+            .with_stage(Apply);
+        let results: Vec<_> = log.verify_with_pipeline(p, &WaccEngine).collect();
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
+    }
+}
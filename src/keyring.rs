@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Keyrings stored in the namespace. Each public key is stored in a leaf
+//! directly under the keyring branch and the leaf is named after the
+//! fingerprint of the key so the same key always lands at the same path:
+//!
+//! ```text
+//! ─┬─ "<branch>/"
+//!  ├─── "<fingerprint>"     the encoded public key
+//!  ╰─── "<fingerprint>"     the encoded public key
+//! ```
+use crate::{error::KeyError, Error, Key, Kvp, Op, Value};
+use multibase::Base;
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+use multikey::Multikey;
+use multitrait::TryDecodeFrom;
+
+/// the hash codec used for key fingerprints
+pub const FINGERPRINT_CODEC: Codec = Codec::Sha3256;
+
+/// the base used to encode fingerprints in leaf names
+pub const FINGERPRINT_BASE: Base = Base::Base32Lower;
+
+/// get the fingerprint of the key, the hash of the encoded key
+pub fn fingerprint(key: &Multikey) -> Result<Multihash, Error> {
+    let bytes: Vec<u8> = key.clone().into();
+    Ok(mh::Builder::new_from_bytes(FINGERPRINT_CODEC, &bytes)?.try_build()?)
+}
+
+/// get the path of the leaf the key is stored at under the branch
+pub fn key_path(branch: &Key, key: &Multikey) -> Result<Key, Error> {
+    if !branch.is_branch() {
+        return Err(KeyError::NotABranch.into());
+    }
+    let fp: Vec<u8> = fingerprint(key)?.into();
+    let mut path = branch.clone();
+    path.push(format!("/{}", multibase::encode(FINGERPRINT_BASE, fp)))?;
+    Ok(path)
+}
+
+/// get the ops that store the keys under the branch
+pub fn import_keys(branch: &Key, keys: &[Multikey]) -> Result<Vec<Op>, Error> {
+    keys.iter()
+        .map(|key| Ok(Op::Update(key_path(branch, key)?, Value::Data(key.clone().into()))))
+        .collect()
+}
+
+/// get the keys stored directly under the branch. values under the branch
+/// that aren't keys stored at their fingerprint are skipped.
+pub fn export_keys(kvp: &Kvp, branch: &Key) -> Result<Vec<Multikey>, Error> {
+    if !branch.is_branch() {
+        return Err(KeyError::NotABranch.into());
+    }
+    let mut keys = Vec::default();
+    for (path, value) in kvp.iter() {
+        if !path.is_leaf() || path.branch() != *branch {
+            continue;
+        }
+        let key = match value {
+            Value::Data(b) => match Multikey::try_decode_from(b) {
+                Ok((key, ptr)) if ptr.is_empty() => key,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if key_path(branch, &key)? == *path {
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multikey::EncodedMultikey;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_import_export() {
+        let key = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0"
+        )
+        .unwrap()
+        .to_inner();
+        let branch = Key::try_from("/keyring/").unwrap();
+
+        let ops = import_keys(&branch, &[key.clone()]).unwrap();
+        assert_eq!(1, ops.len());
+        assert!(branch.parent_of(ops[0].path_ref()));
+        // the leaf name only depends on the key
+        assert_eq!(ops, import_keys(&branch, &[key.clone()]).unwrap());
+
+        let mut pairs: BTreeMap<Key, Value> = ops
+            .into_iter()
+            .filter_map(|op| match op {
+                Op::Update(k, v) => Some((k, v)),
+                _ => None,
+            })
+            .collect();
+        // values that aren't keys at their fingerprint are skipped
+        pairs.insert(Key::try_from("/keyring/other").unwrap(), Value::Data(key.clone().into()));
+        pairs.insert(Key::try_from("/keyring/junk").unwrap(), Value::Str("junk".to_string()));
+        let kvp = Kvp::from(pairs);
+        assert_eq!(vec![key], export_keys(&kvp, &branch).unwrap());
+
+        assert!(export_keys(&kvp, &Key::try_from("/leaf").unwrap()).is_err());
+    }
+}
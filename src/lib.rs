@@ -8,17 +8,117 @@
     unused_qualifications
 )]
 
+/// Write access control lists for the namespace
+pub mod acl;
+pub use acl::{Acl, AclCheck, AclRecord};
+
+/// Anchoring log heads to external chains and notaries
+pub mod anchors;
+pub use anchors::{AnchorCheck, AnchorProvider, ChainAnchor, ChainClient};
+
+/// Arbitrary implementations for fuzzing
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+
+/// Content-addressed block storage
+pub mod blockstore;
+pub use blockstore::{BlockScriptResolver, BlockStore, MemoryBlockStore};
+
+/// CAR archive import and export
+#[cfg(feature = "dag_cbor")]
+pub mod car;
+
+/// Caching of compiled scripts
+pub mod cache;
+pub use cache::{CachingEngine, CompilingEngine, ScriptCache};
+
+/// Multibase strings with an integrity checksum
+pub mod checked;
+
+/// Signed checkpoints of verified log state
+pub mod checkpoint;
+pub use checkpoint::{Checkpoint, EncodedCheckpoint};
+
+/// Compression of encoded entries and logs
+#[cfg(feature = "zstd")]
+pub mod compress;
+
+/// Handling of ops in one entry that change the same key
+pub mod conflict;
+pub use conflict::{OpConflicts, RejectConflicts};
+
+/// DAG-CBOR encoding of entries and logs
+#[cfg(feature = "dag_cbor")]
+pub mod dag_cbor;
+
+/// Delegation of control over parts of a log to other keys
+pub mod delegation;
+pub use delegation::{Delegation, Delegations};
+
+/// DID documents projected from the log namespace
+pub mod did;
+pub use did::DidDocument;
+
+/// Selective disclosure of values committed to in the log
+pub mod disclosure;
+pub use disclosure::Disclosure;
+
+/// Graphviz DOT output of the log structure
+pub mod dot;
+
+/// Script execution engines
+pub mod engine;
+pub use engine::{DispatchEngine, EntryPoints, ScriptEngine, WaccEngine};
+
 /// Provenance log entry related functions
 pub mod entry;
-pub use entry::{EncodedEntry, Entry};
+pub use entry::{EncodedEntry, Entry, EntryRef, UnsignedEntry};
+
+/// Equivocation detection and proofs
+pub mod equivocation;
+pub use equivocation::EquivocationProof;
 
 /// Errors produced by this library
 pub mod error;
 pub use error::Error;
 
+/// Fork detection and fork choice rules
+pub mod fork;
+pub use fork::{Branch, FirstSeen, Fork, ForkChoice, LongestChain, PriorityKey};
+
+/// Host functions made available to the scripts
+pub mod host;
+pub use host::{HostEngine, HostFunction, HostFunctions};
+
+/// HTTP gateway handlers for serving logs
+#[cfg(feature = "http")]
+pub mod http;
+
+/// Pure-Rust wasm interpreter script engine
+#[cfg(feature = "wasm-interp")]
+pub mod interp;
+#[cfg(feature = "wasm-interp")]
+pub use interp::InterpEngine;
+
+/// Change-data-capture journal of the namespace
+pub mod journal;
+pub use journal::{Change, Journal};
+
 /// Key-path used in the Kvp
 pub mod key;
-pub use key::Key;
+pub use key::{Key, KeyPattern};
+
+/// Key profiles restricting the legal key-paths
+pub mod key_profile;
+pub use key_profile::{CheckKeys, KeyProfile};
+
+/// Keyrings stored under a branch of the namespace
+pub mod keyring;
+pub use keyring::{export_keys, import_keys};
+
+/// Size limits on entries
+pub mod limits;
+pub use limits::Limits;
 
 /// Lipmaa numbering for sequence numbers
 pub mod lipmaa;
@@ -28,30 +128,150 @@ pub use lipmaa::Lipmaa;
 pub mod log;
 pub use log::{EncodedLog, Log};
 
+/// Memory-mapped read-only logs
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "mmap")]
+pub use mmap::MmapLog;
+
+/// Deterministic signers and lock scripts for tests
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "mock")]
+pub use mock::{MockLock, MockSigner};
+
+/// Verification observers
+pub mod observer;
+pub use observer::{Observer, VerifyEvent};
+
 /// Ops for the plog virtual namespace
 pub mod op;
 pub use op::{Op, OpId};
 
+/// Log replication over libp2p
+#[cfg(feature = "p2p")]
+pub mod p2p;
+#[cfg(feature = "p2p")]
+pub use p2p::SyncBehaviour;
+
 /// The virtual key-value pair store
 pub mod pairs;
 pub use pairs::Kvp;
 
+/// Composable verification stages
+pub mod pipeline;
+pub use pipeline::{Pipeline, Stage};
+
+/// Lock script generation from declarative policies
+pub mod policy;
+pub use policy::{Compiler, Condition, Policy, Rule};
+
+/// Append-only log files with crash recovery
+pub mod plogfile;
+pub use plogfile::PlogFile;
+
+/// Namespace layouts for device and identity logs
+pub mod profiles;
+
+/// Typed entry proofs
+pub mod proof;
+pub use proof::{join_proofs, split_proofs, Proof};
+
+/// Protobuf messages for cross-language consumers
+#[cfg(feature = "proto")]
+pub mod proto;
+
+/// Signed receipts chaining the results of verification
+pub mod receipt;
+pub use receipt::VerificationReceipt;
+
+/// Many logs keyed by their vlad with collision detection
+pub mod registry;
+pub use registry::Registry;
+
+/// Resolving keys by fingerprint during verification
+pub mod resolver;
+pub use resolver::{
+    verify_plog_ref, KeyResolver, LogResolver, ResolvingEngine, ScriptResolver,
+    ScriptResolvingEngine, UnresolvedPolicy,
+};
+
+/// Key rotation entries
+pub mod rotation;
+
+/// Redaction-safe display of sensitive bytes
+pub mod safe;
+pub use safe::{SafeBytes, SafeDisplay};
+
 /// Script related functions
 pub mod script;
 pub use script::{EncodedScript, Script, ScriptId};
 
+/// Sealing values for recipients
+pub mod seal;
+pub use seal::Recipient;
+
 /// Serde serialization
 #[cfg(feature = "serde")]
 pub mod serde;
 
+/// SQLite persistence of logs
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteLogStore;
+
 /// The parameter and return value stack type 
 pub mod stack;
 pub use stack::Stk;
 
+/// Strict decoding of canonical encodings
+pub mod strict;
+pub use strict::StrictDecode;
+
+/// Incremental decoding from readers
+pub mod stream;
+pub use stream::Decoder;
+
+/// Log synchronization primitives
+pub mod sync;
+pub use sync::{MissingRange, SyncMessage, SyncPayload};
+
+/// Metrics emitted from the verify and append paths
+pub mod telemetry;
+
+/// Time anchors and timestamp inference
+pub mod timestamp;
+pub use timestamp::TimeAnchor;
+
+/// Staging the changes for the next entry like a key-value store
+pub mod transaction;
+pub use transaction::EntryTransaction;
+
+/// Transport profiles for entry pre-flight checks
+pub mod transport;
+pub use transport::Profile;
+
+/// Tree view of the namespace in a Kvp
+pub mod tree;
+pub use tree::Node;
+
 /// Entry Value related functions
 pub mod value;
 pub use value::{Value, ValueId};
 
+/// Verification configuration and cancellation
+pub mod verify;
+pub use verify::{CancelToken, VerifyConfig, VerifyReport};
+
+/// Subscriptions to the changes committed to a log
+pub mod watch;
+pub use watch::KvpEvent;
+
+/// Witness co-signatures over entries
+pub mod witness;
+pub use witness::WitnessPolicy;
+
 /// ...and in the darkness bind them
 pub mod prelude {
     pub use super::*;
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Limits on the size of entries. They are checked when an entry is built
+//! and when it is appended to a log so a single entry can't bloat the log or
+//! exhaust the memory of the replicas verifying it.
+use crate::{error::EntryError, Entry, Error, Op};
+
+/// the default maximum number of ops in an entry
+pub const DEFAULT_MAX_OPS: usize = 1024;
+
+/// the default maximum encoded size of a value in an op
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 1024 * 1024;
+
+/// the default maximum encoded size of an entry
+pub const DEFAULT_MAX_ENTRY_BYTES: usize = 4 * 1024 * 1024;
+
+/// the default maximum number of lock scripts in an entry
+pub const DEFAULT_MAX_LOCKS: usize = 64;
+
+/// The size limits for entries
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// the maximum number of ops in an entry
+    pub max_ops: usize,
+    /// the maximum encoded size of a value in an op
+    pub max_value_bytes: usize,
+    /// the maximum encoded size of an entry, including the proof
+    pub max_entry_bytes: usize,
+    /// the maximum number of lock scripts in an entry
+    pub max_locks: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_ops: DEFAULT_MAX_OPS,
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+            max_locks: DEFAULT_MAX_LOCKS,
+        }
+    }
+}
+
+impl Limits {
+    /// limits that allow entries of any size
+    pub fn unlimited() -> Self {
+        Self {
+            max_ops: usize::MAX,
+            max_value_bytes: usize::MAX,
+            max_entry_bytes: usize::MAX,
+            max_locks: usize::MAX,
+        }
+    }
+
+    /// check the limits that don't depend on the proof: the number of ops
+    /// and locks and the size of the values
+    pub fn check_contents(&self, entry: &Entry) -> Result<(), Error> {
+        let ops = entry.ops().count();
+        if ops > self.max_ops {
+            return Err(EntryError::TooManyOps(ops, self.max_ops).into());
+        }
+        let locks = entry.locks().count();
+        if locks > self.max_locks {
+            return Err(EntryError::TooManyLocks(locks, self.max_locks).into());
+        }
+        for op in entry.ops() {
+            if let Op::Update(key, value) = op {
+                let len = Vec::<u8>::from(value.clone()).len();
+                if len > self.max_value_bytes {
+                    return Err(EntryError::ValueTooLarge(key.clone(), len, self.max_value_bytes).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// check all of the limits against the entry
+    pub fn check(&self, entry: &Entry) -> Result<(), Error> {
+        self.check_contents(entry)?;
+        let len = entry.canonical_bytes().len();
+        if len > self.max_entry_bytes {
+            return Err(EntryError::EntryTooLarge(len, self.max_entry_bytes).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Script, Value};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_ops: 2,
+            max_value_bytes: 16,
+            ..Default::default()
+        };
+        let builder = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .with_limits(&limits)
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("foo".to_string())));
+        let e1 = builder.try_build(|_| Ok(Vec::default())).unwrap();
+
+        // too many ops
+        let b = builder
+            .clone()
+            .add_op(&Op::Noop("/bar".try_into().unwrap()))
+            .add_op(&Op::Noop("/baz".try_into().unwrap()));
+        assert!(matches!(
+            b.try_build(|_| Ok(Vec::default())),
+            Err(Error::Entry(EntryError::TooManyOps(3, 2)))
+        ));
+
+        // a value that is too large
+        let b = builder
+            .clone()
+            .add_op(&Op::Update("/big".try_into().unwrap(), Value::Data(vec![0; 32])));
+        assert!(matches!(
+            b.try_build(|_| Ok(Vec::default())),
+            Err(Error::Entry(EntryError::ValueTooLarge(..)))
+        ));
+
+        // a proof that makes the entry too large
+        let b = builder.clone().with_limits(&Limits {
+            max_entry_bytes: e1.canonical_bytes().len(),
+            ..limits
+        });
+        assert!(matches!(
+            b.try_build(|_| Ok(vec![0; 8])),
+            Err(Error::Entry(EntryError::EntryTooLarge(..)))
+        ));
+
+        // the log enforces its own limits on appended entries
+        let mut log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .with_limits(&Limits {
+                max_ops: 0,
+                ..Default::default()
+            })
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Noop("/bar".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert!(matches!(
+            log.try_append(&e2),
+            Err(Error::Entry(EntryError::TooManyOps(1, 0)))
+        ));
+    }
+}
@@ -1,13 +1,29 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{entry, error::LogError, Entry, Error, Kvp, Script, Stk};
+use crate::{
+    checkpoint,
+    entry::{self, ENTRY_VERSION, ENTRY_VERSION_PATHS},
+    error::{decode_field, EntryError, LogError, VerifyError, VerifyPhase},
+    key_profile::{CheckKeys, KeyProfile},
+    observer::{NoopObserver, Observer, VerifyEvent},
+    pipeline::{Pipeline, Verification},
+    script::validate::Validator,
+    telemetry,
+    watch::Subscribers,
+    witness::Witnesses,
+    CancelToken, Checkpoint, Entry, EntryPoints, Error, Key, Kvp, Limits, Lipmaa, Script,
+    ScriptEngine, Stk, Value, VerifyConfig, VerifyReport, WaccEngine,
+};
 use core::fmt;
 use multibase::Base;
 use multicid::{Cid, Vlad};
 use multicodec::Codec;
 use multitrait::{Null, TryDecodeFrom};
 use multiutil::{BaseEncoded, CodecInfo, EncodingInfo, Varuint};
-use std::collections::BTreeMap;
-use wacc::{prelude::StoreLimitsBuilder, vm, Stack};
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
 
 /// the multicodec provenance log codec
 pub const SIGIL: Codec = Codec::ProvenanceLog;
@@ -15,6 +31,13 @@ pub const SIGIL: Codec = Codec::ProvenanceLog;
 /// the current version of provenance entries this supports
 pub const LOG_VERSION: u64 = 1;
 
+/// the first log version that encodes the checkpoint of a compacted log
+pub const CHECKPOINT_LOG_VERSION: u64 = 2;
+
+/// the log version that stores each distinct lock and unlock script once in
+/// a table and has the entries refer to them by index
+pub const SCRIPT_TABLE_LOG_VERSION: u64 = 3;
+
 /// a base encoded provenance log
 pub type EncodedLog = BaseEncoded<Log>;
 
@@ -33,12 +56,223 @@ pub struct Log {
     pub vlad: Vlad,
     /// The lock script for the first entry
     pub first_lock: Script,
-    /// The first entry in the log
-    pub foot: Cid,
-    /// The latest entry in the log
-    pub head: Cid,
+    /// The first entry in the log, read with [`Log::foot`] and set with
+    /// [`Log::set_foot_checked`] so the log links are re-validated
+    pub(crate) foot: Cid,
+    /// The latest entry in the log, read with [`Log::head`] and set with
+    /// [`Log::set_head_checked`] so the log links are re-validated
+    pub(crate) head: Cid,
     /// Entry objects are stored in a hashmap indexed by their Cid
     pub entries: Entries,
+    /// The checkpoint the log was compacted to, if any. The entries before it
+    /// are only kept if they are needed to prove continuity to the foot.
+    pub checkpoint: Option<Checkpoint>,
+    /// The names of the functions called in the scripts when verifying
+    pub(crate) entry_points: EntryPoints,
+    /// The subscribers to changes committed to the log
+    pub(crate) subscribers: Subscribers,
+    /// The verified state at the head of the log
+    pub(crate) verified: HeadState,
+    /// The index of entry cids by seqno
+    pub(crate) seqnos: SeqnoIndex,
+    /// The witness signatures over the entries
+    pub(crate) witnesses: Witnesses,
+    /// The size limits checked on appended entries
+    pub(crate) limits: Limits,
+    /// The validator the scripts in appended entries are checked with
+    pub(crate) validator: Validator,
+    /// The profile the key-paths in entries are checked against, if any
+    pub(crate) key_profile: Option<KeyProfile>,
+    /// The database file the log was opened from
+    #[cfg(feature = "sqlite")]
+    pub(crate) store: crate::sqlite::StorePath,
+}
+
+/// The table of distinct lock and unlock scripts in a version 3 Log. The
+/// entries are encoded without their scripts followed by the indices of
+/// their scripts in the table. The cids and proofs of the entries are over
+/// their native encoding so the entries are rebuilt on decode and checked
+/// against the cids they are stored under.
+#[derive(Clone, Default)]
+pub(crate) struct ScriptTable(pub(crate) Vec<Script>);
+
+impl From<&Entries> for ScriptTable {
+    fn from(entries: &Entries) -> Self {
+        let mut scripts: Vec<Script> = Vec::default();
+        for entry in entries.values() {
+            for script in entry.locks.iter().chain(std::iter::once(&entry.unlock)) {
+                if !scripts.contains(script) {
+                    scripts.push(script.clone());
+                }
+            }
+        }
+        Self(scripts)
+    }
+}
+
+impl From<ScriptTable> for Vec<u8> {
+    fn from(val: ScriptTable) -> Self {
+        let mut v = Vec::default();
+        // add in the number of scripts
+        v.append(&mut Varuint(val.0.len()).into());
+        // add in the scripts
+        val.0.into_iter().for_each(|s| v.append(&mut s.into()));
+        v
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for ScriptTable {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the number of scripts
+        let (num_scripts, mut ptr) = Varuint::<usize>::try_decode_from(bytes)?;
+        let mut scripts = Vec::default();
+        for i in 0..*num_scripts {
+            let (script, p) = decode_field(bytes, ptr, format_args!("[{}]", i), |p| {
+                Script::try_decode_from(p)
+            })?;
+            scripts.push(script);
+            ptr = p;
+        }
+        Ok((Self(scripts), ptr))
+    }
+}
+
+impl ScriptTable {
+    fn index(&self, script: &Script) -> Varuint<usize> {
+        // every script in the entries is in the table
+        Varuint(self.0.iter().position(|s| s == script).unwrap_or_default())
+    }
+
+    fn script(&self, i: usize) -> Result<Script, Error> {
+        Ok(self.0.get(i).ok_or(LogError::InvalidScriptIndex(i))?.clone())
+    }
+
+    /// encode the entry without its scripts followed by their indices
+    pub(crate) fn encode_entry(&self, entry: &Entry) -> Vec<u8> {
+        let mut stripped = entry.clone();
+        stripped.locks = Vec::default();
+        stripped.unlock = Script::default();
+        let mut v: Vec<u8> = stripped.into();
+        // add in the lock script indices
+        v.append(&mut Varuint(entry.locks.len()).into());
+        entry
+            .locks
+            .iter()
+            .for_each(|lock| v.append(&mut self.index(lock).into()));
+        // add in the unlock script index
+        v.append(&mut self.index(&entry.unlock).into());
+        v
+    }
+
+    /// decode an entry encoded by [`ScriptTable::encode_entry`] and check
+    /// that the rebuilt entry has the given cid
+    pub(crate) fn decode_entry<'a>(
+        &self,
+        cid: &Cid,
+        bytes: &'a [u8],
+    ) -> Result<(Entry, &'a [u8]), Error> {
+        let (mut entry, ptr) = Entry::try_decode_from(bytes)?;
+        // decode the lock script indices
+        let (num_locks, mut ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        entry.locks = Vec::default();
+        for _ in 0..*num_locks {
+            let (i, p) = Varuint::<usize>::try_decode_from(ptr)?;
+            entry.locks.push(self.script(*i)?);
+            ptr = p;
+        }
+        // decode the unlock script index
+        let (i, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        entry.unlock = self.script(*i)?;
+        entry.cached_cid = entry::CidCache::default();
+        if entry.cid() != *cid {
+            return Err(LogError::EntryCidMismatch.into());
+        }
+        Ok((entry, ptr))
+    }
+}
+
+/// The index of the entry cids in a Log by seqno. It is rebuilt when the
+/// entries are decoded or changed through the Log methods and lookups fall
+/// back to a scan of the entries if they were changed directly. It is
+/// ignored when comparing Logs.
+#[derive(Clone, Default)]
+pub(crate) struct SeqnoIndex(BTreeMap<u64, Cid>);
+
+impl From<&Entries> for SeqnoIndex {
+    fn from(entries: &Entries) -> Self {
+        Self(
+            entries
+                .iter()
+                .map(|(cid, e)| (e.seqno(), cid.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl PartialEq for SeqnoIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The verified kvp state and lock scripts at the head of a Log so appending
+/// an entry only has to verify the new entry. Like the subscribers it isn't
+/// cloned along with the Log and is ignored when comparing Logs.
+#[derive(Default)]
+pub(crate) struct HeadState(Option<Verified>);
+
+struct Verified {
+    /// the cid of the last verified entry, null if there are none
+    head: Cid,
+    /// the seqno of the last verified entry
+    seqno: Option<u64>,
+    /// the kvp state after the last verified entry
+    kvp: BTreeMap<Key, Value>,
+    /// the lock scripts that govern the next entry
+    locks: Vec<Script>,
+}
+
+impl Verified {
+    /// build the iterator that verifies the entries on top of this state
+    fn verify_iter<'a, P: Borrow<Pipeline>>(
+        &self,
+        entries: &'a [Entry],
+        entry_points: &'a EntryPoints,
+        pipeline: P,
+        engine: &'a dyn ScriptEngine,
+        observer: &'a dyn Observer,
+        config: VerifyConfig,
+    ) -> VerifyIter<'a, P> {
+        VerifyIter {
+            engine,
+            observer,
+            entry_points,
+            pipeline,
+            entries: entries.iter().collect(),
+            seqno: 0,
+            prev_seqno: self.seqno,
+            kvp: Kvp::from(self.kvp.clone()),
+            lock_scripts: self.locks.clone(),
+            config,
+            error: None,
+            phase_times: HashMap::default(),
+            fuel: BTreeMap::default(),
+        }
+    }
+}
+
+impl Clone for HeadState {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for HeadState {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
 impl CodecInfo for Log {
@@ -68,8 +302,12 @@ impl From<Log> for Vec<u8> {
         let mut v = Vec::default();
         // add in the provenance log sigil
         v.append(&mut SIGIL.into());
-        // add in the version
-        v.append(&mut Varuint(val.version).into());
+        // add in the version, older versions can't encode the checkpoint
+        let version = match val.checkpoint {
+            Some(_) => val.version.max(CHECKPOINT_LOG_VERSION),
+            None => val.version,
+        };
+        v.append(&mut Varuint(version).into());
         // add in the vlad
         v.append(&mut val.vlad.clone().into());
         // add in the lock script for the first entry
@@ -78,13 +316,34 @@ impl From<Log> for Vec<u8> {
         v.append(&mut val.foot.clone().into());
         // add in the head cid
         v.append(&mut val.head.clone().into());
-        // add in the entry count
-        v.append(&mut Varuint(val.entries.len()).into());
-        // add in the entries
-        val.entries.iter().for_each(|(cid, entry)| {
-            v.append(&mut cid.clone().into());
-            v.append(&mut entry.clone().into());
-        });
+        if version >= SCRIPT_TABLE_LOG_VERSION {
+            // add in the script table
+            let scripts = ScriptTable::from(&val.entries);
+            v.append(&mut scripts.clone().into());
+            // add in the entry count
+            v.append(&mut Varuint(val.entries.len()).into());
+            // add in the entries with their scripts replaced by indices
+            val.entries.iter().for_each(|(cid, entry)| {
+                v.append(&mut cid.clone().into());
+                v.append(&mut scripts.encode_entry(entry));
+            });
+        } else {
+            // add in the entry count
+            v.append(&mut Varuint(val.entries.len()).into());
+            // add in the entries
+            val.entries.iter().for_each(|(cid, entry)| {
+                v.append(&mut cid.clone().into());
+                v.append(&mut entry.clone().into());
+            });
+        }
+        if version >= CHECKPOINT_LOG_VERSION {
+            // add in the checkpoint count
+            v.append(&mut Varuint(usize::from(val.checkpoint.is_some())).into());
+            // add in the checkpoint
+            if let Some(cp) = val.checkpoint {
+                v.append(&mut cp.into());
+            }
+        }
         v
     }
 }
@@ -118,6 +377,13 @@ impl<'a> TryDecodeFrom<'a> for Log {
         let (foot, ptr) = Cid::try_decode_from(ptr)?;
         // decode the head cid if there is one
         let (head, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the script table if there is one
+        let (scripts, ptr) = if version >= SCRIPT_TABLE_LOG_VERSION {
+            let (scripts, ptr) = decode_field(bytes, ptr, "scripts", ScriptTable::try_decode_from)?;
+            (Some(scripts), ptr)
+        } else {
+            (None, ptr)
+        };
         // decode the number of entries
         let (num_entries, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
         // decode the entries
@@ -126,9 +392,22 @@ impl<'a> TryDecodeFrom<'a> for Log {
             _ => {
                 let mut entries = Entries::new();
                 let mut p = ptr;
-                for _ in 0..*num_entries {
-                    let (cid, ptr) = Cid::try_decode_from(p)?;
-                    let (entry, ptr) = Entry::try_decode_from(ptr)?;
+                for i in 0..*num_entries {
+                    let (cid, ptr) = decode_field(
+                        bytes,
+                        p,
+                        format_args!("entries[{}].cid", i),
+                        Cid::try_decode_from,
+                    )?;
+                    let (entry, ptr) = decode_field(
+                        bytes,
+                        ptr,
+                        format_args!("entries[{}]", i),
+                        |p| match &scripts {
+                            Some(scripts) => scripts.decode_entry(&cid, p),
+                            None => Entry::try_decode_from(p),
+                        },
+                    )?;
                     if entries.insert(cid.clone(), entry).is_some() {
                         return Err(LogError::DuplicateEntry(cid).into());
                     }
@@ -137,6 +416,21 @@ impl<'a> TryDecodeFrom<'a> for Log {
                 (entries, p)
             }
         };
+        // decode the checkpoint if there is one
+        let (checkpoint, ptr) = if version >= CHECKPOINT_LOG_VERSION {
+            let (num_checkpoints, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+            match *num_checkpoints {
+                0 => (None, ptr),
+                1 => {
+                    let (cp, ptr) = Checkpoint::try_decode_from(ptr)?;
+                    (Some(cp), ptr)
+                }
+                _ => return Err(LogError::CheckpointMismatch.into()),
+            }
+        } else {
+            (None, ptr)
+        };
+        let seqnos = SeqnoIndex::from(&entries);
         Ok((
             Self {
                 version,
@@ -145,6 +439,17 @@ impl<'a> TryDecodeFrom<'a> for Log {
                 foot,
                 head,
                 entries,
+                checkpoint,
+                entry_points: EntryPoints::default(),
+                subscribers: Subscribers::default(),
+                verified: HeadState::default(),
+                seqnos,
+                witnesses: Witnesses::default(),
+                limits: Limits::default(),
+                validator: Validator::default(),
+                key_profile: None,
+                #[cfg(feature = "sqlite")]
+                store: Default::default(),
             },
             ptr,
         ))
@@ -185,285 +490,915 @@ impl<'a> Iterator for EntryIter<'a> {
     }
 }
 
-struct VerifyIter<'a> {
+struct VerifyIter<'a, P = Pipeline> {
+    engine: &'a dyn ScriptEngine,
+    observer: &'a dyn Observer,
+    entry_points: &'a EntryPoints,
+    pipeline: P,
     entries: Vec<&'a Entry>,
     seqno: usize,
-    prev_seqno: usize,
-    kvp: Kvp<'a>,
+    prev_seqno: Option<u64>,
+    kvp: Kvp,
     lock_scripts: Vec<Script>,
+    config: VerifyConfig,
     error: Option<Error>,
+    phase_times: HashMap<VerifyPhase, Duration>,
+    fuel: BTreeMap<u64, u64>,
 }
 
-impl<'a> Iterator for VerifyIter<'a> {
-    type Item = Result<(usize, Entry, Kvp<'a>), Error>;
+impl<'a, P: Borrow<Pipeline>> Iterator for VerifyIter<'a, P> {
+    type Item = Result<(usize, Entry, Kvp), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        //println!("iter::next({})", self.seqno);
         let entry = match self.entries.get(self.seqno) {
             Some(e) => *e,
             None => return None,
         };
 
-        // this is the check count if successful
-        let mut count = 0;
-
-        // set up the stacks
-        let mut pstack = Stk::default();
-        let mut rstack = Stk::default();
-
-        // check the seqno meet the criteria
-        if self.seqno > 0 && self.seqno != self.prev_seqno + 1 {
-            // set our index out of range
-            self.seqno = self.entries.len();
-            // set the error state
-            self.error = Some(LogError::InvalidSeqno.into());
-            return Some(Err(self.error.clone().unwrap()));
-        }
-
-        // 'unlock:
-        let mut result = {
-            // run the unlock script using the entry as the kvp to get the
-            // stack in the vm::Context set up.
-            let unlock_ctx = vm::Context {
-                current: entry,  // limit the available data to just the entry
-                proposed: entry, // limit the available data to just the entry
-                pstack: &mut pstack,
-                rstack: &mut rstack,
-                check_count: 0,
-                write_idx: 0,
-                context: entry.context().to_string(),
-                log: Vec::default(),
-                limiter: StoreLimitsBuilder::new()
-                    .memory_size(1 << 16)
-                    .instances(2)
-                    .memories(1)
-                    .build(),
-            };
+        self.observer.observe(&VerifyEvent::EntryStarted { entry });
 
-            let mut instance = match vm::Builder::new()
-                .with_context(unlock_ctx)
-                .with_bytes(entry.unlock.clone())
-                .try_build()
-            {
-                Ok(i) => i,
-                Err(e) => {
-                    // set our index out of range
-                    self.seqno = self.entries.len();
-                    self.error = Some(LogError::Wacc(e).into());
-                    return Some(Err(self.error.clone().unwrap()));
-                }
-            };
-            //print!("running unlock script from seqno: {}...", self.seqno);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "verify_entry",
+            vlad = ?entry.vlad_ref(),
+            seqno = entry.seqno(),
+            cid = ?entry.cid()
+        )
+        .entered();
+
+        let deadline = self.config.entry_budget.map(|b| Instant::now() + b);
+        let mut v = Verification {
+            engine: self.engine,
+            observer: self.observer,
+            entry_points: self.entry_points,
+            entry,
+            prev_seqno: self.prev_seqno,
+            kvp: &mut self.kvp,
+            lock_scripts: &mut self.lock_scripts,
+            pstack: Stk::default(),
+            rstack: Stk::default(),
+            count: 0,
+            applied: false,
+            cancel: self.config.cancel.as_ref(),
+            deadline,
+            verify_deadline: self.config.deadline,
+            fuel: self.config.fuel,
+            fuel_consumed: 0,
+            logs: Vec::default(),
+        };
 
-            // run the unlock script
-            if let Some(e) = instance.run("for_great_justice").err() {
+        // run each of the stages in order, checking for cancellation first
+        for stage in self.pipeline.borrow().stages() {
+            let start = Instant::now();
+            let result = v.check_interrupted().and_then(|_| stage.run(&mut v));
+            *self.phase_times.entry(stage.phase()).or_default() += start.elapsed();
+            if let Some(e) = result.err() {
+                self.observer.observe(&VerifyEvent::EntryFailed {
+                    entry,
+                    stage: stage.name(),
+                    error: &e,
+                    log: &v.logs,
+                });
                 // set our index out of range
                 self.seqno = self.entries.len();
-                self.error = Some(LogError::Wacc(e).into());
+                telemetry::entry_failed();
+                #[cfg(feature = "tracing")]
+                tracing::warn!(stage = stage.name(), error = %e, "entry failed to verify");
+                // interruptions aren't failures of the entry
+                let e = match e {
+                    Error::Cancelled(_) | Error::EntryTimeout(_) | Error::DeadlineExceeded(_) => e,
+                    e => VerifyError {
+                        seqno: entry.seqno(),
+                        entry_cid: entry.cid(),
+                        phase: stage.phase(),
+                        source: Box::new(e),
+                    }
+                    .into(),
+                };
+                self.error = Some(e);
                 return Some(Err(self.error.clone().unwrap()));
             }
+        }
 
-            //println!("SUCCEEDED!");
+        // this is the check count from the lock script that succeeded
+        let count = v.count;
+        self.fuel.insert(entry.seqno(), v.fuel_consumed);
+        telemetry::entry_verified(self.kvp.len());
+        #[cfg(feature = "tracing")]
+        tracing::debug!(count, fuel = v.fuel_consumed, kvp = self.kvp.len(), "entry verified");
+        self.observer.observe(&VerifyEvent::EntryVerified {
+            entry,
+            count,
+            fuel: v.fuel_consumed,
+            log: &v.logs,
+        });
 
-            true
-        };
+        // update the seqno
+        self.prev_seqno = Some(entry.seqno());
+        self.seqno += 1;
 
-        /*
-        println!("values:");
-        println!("{:?}", pstack.clone());
-        println!("return:");
-        println!("{:?}", rstack.clone());
-        */
-
-        if !result {
-            // set our index out of range
-            self.seqno = self.entries.len();
-            self.error = Some(
-                LogError::VerifyFailed(format!(
-                    "unlock script failed\nvalues:\n{:?}\nreturn:\n{:?}",
-                    rstack, pstack
-                ))
-                .into(),
-            );
-            return Some(Err(self.error.clone().unwrap()));
-        }
-
-        /*
-        // set the entry to look into for proof and message values
-        if let Some(e) = self.kvp.set_entry(entry).err() {
-            // set our index out of range
-            self.seqno = self.entries.len();
-            self.error = Some(LogError::KvpSetEntryFailed(e.to_string()).into());
-            return Some(Err(self.error.clone().unwrap()));
-        }
-        */
-
-        // if this is the first entry, then we need to apply the
-        // mutation ops
-        if self.seqno == 0 {
-            //println!("applying kvp ops for seqno 0");
-            if let Some(e) = self.kvp.apply_entry_ops(entry).err() {
-                // set our index out of range
-                self.seqno = self.entries.len();
-                self.error = Some(LogError::UpdateKvpFailed(e.to_string()).into());
-                return Some(Err(self.error.clone().unwrap()));
-            }
+        // return the check count, validated entry, and kvp state
+        Some(Ok((count, entry.clone(), self.kvp.clone())))
+    }
+}
+
+impl Log {
+    /// get an iterator over the entries in from head to foot
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        // get a list of Entry references, sort them by seqno
+        let mut entries: Vec<&Entry> = self.entries.values().collect();
+        entries.sort();
+        EntryIter {
+            entries,
+            current: 0,
         }
+    }
 
-        // 'lock:
-        result = false;
+    /// The default pipeline with the key profile stage, if the log has one
+    fn pipeline(&self) -> Pipeline {
+        match &self.key_profile {
+            Some(profile) => Pipeline::default().insert_before("apply", CheckKeys(profile.clone())),
+            None => Pipeline::default(),
+        }
+    }
 
-        // build the set of lock scripts to run in order from root to longest branch to leaf
-        let locks = match entry.sort_locks(&self.lock_scripts) {
-            Ok(l) => l,
-            Err(e) => {
-                // set our index out of range
-                self.seqno = self.entries.len();
-                self.error = Some(e);
-                return Some(Err(self.error.clone().unwrap()));
-            }
-        };
+    /// Verifies all entries in the log
+    pub fn verify(&self) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_ {
+        self.verify_with_engine(&WaccEngine)
+    }
 
-        // run each of the lock scripts
-        for lock in locks {
-            // NOTE: clone the kvp and stacks each time
-            let lock_kvp = self.kvp.clone();
-            let mut lock_pstack = pstack.clone();
-            let mut lock_rstack = rstack.clone();
+    /// Verifies all entries in the log running the scripts with the given engine
+    pub fn verify_with_engine<'a>(
+        &'a self,
+        engine: &'a dyn ScriptEngine,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + 'a {
+        self.verify_with_pipeline(self.pipeline(), engine)
+    }
 
-            {
-                let lock_ctx = vm::Context {
-                    current: &lock_kvp,
-                    proposed: entry,
-                    pstack: &mut lock_pstack,
-                    rstack: &mut lock_rstack,
-                    check_count: 0,
-                    write_idx: 0,
-                    context: entry.context().to_string(), // set the branch path for branch()
-                    log: Vec::default(),
-                    limiter: StoreLimitsBuilder::new()
-                        .memory_size(1 << 16)
-                        .instances(2)
-                        .memories(1)
-                        .build(),
-                };
+    /// Verifies all entries in the log by passing each one through the stages
+    /// of the given pipeline
+    pub fn verify_with_pipeline<'a>(
+        &'a self,
+        pipeline: Pipeline,
+        engine: &'a dyn ScriptEngine,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + 'a {
+        self.verify_with_observer(pipeline, engine, &NoopObserver)
+    }
 
-                let mut instance = match vm::Builder::new()
-                    .with_context(lock_ctx)
-                    .with_bytes(lock.clone())
-                    .try_build()
-                {
-                    Ok(i) => i,
-                    Err(e) => {
-                        // set our index out of range
-                        self.seqno = self.entries.len();
-                        self.error = Some(LogError::Wacc(e).into());
-                        return Some(Err(self.error.clone().unwrap()));
-                    }
-                };
-                //print!("running lock script from seqno: {}...", self.seqno);
-
-                // run the unlock script
-                if let Some(e) = instance.run("move_every_zig").err() {
-                    // set our index out of range
-                    self.seqno = self.entries.len();
-                    self.error = Some(LogError::Wacc(e).into());
-                    return Some(Err(self.error.clone().unwrap()));
+    /// Verifies all entries in the log with the given pipeline and engine,
+    /// calling the observer with the events emitted along the way
+    pub fn verify_with_observer<'a>(
+        &'a self,
+        pipeline: Pipeline,
+        engine: &'a dyn ScriptEngine,
+        observer: &'a dyn Observer,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + 'a {
+        self.verify_with_config(pipeline, engine, observer, VerifyConfig::default())
+    }
+
+    /// Verifies all entries in the log like [`Log::verify`] but stops with
+    /// [`Error::Cancelled`] when the token is cancelled and with
+    /// [`Error::DeadlineExceeded`] once the deadline has passed, so a large
+    /// or misbehaving log can't tie up the thread verifying it
+    pub fn verify_with_cancel(
+        &self,
+        cancel: &CancelToken,
+        deadline: Option<Instant>,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + '_ {
+        let mut config = VerifyConfig::new().with_cancel_token(cancel);
+        config.deadline = deadline;
+        self.verify_with_config(self.pipeline(), &WaccEngine, &NoopObserver, config)
+    }
+
+    /// Verifies all entries in the log with the given pipeline, engine and
+    /// observer using the cancellation token and time budget in the config.
+    /// Verification always starts from the foot. The checkpoint of a
+    /// compacted log is only trusted through [`Log::verify_from_checkpoint`].
+    pub fn verify_with_config<'a>(
+        &'a self,
+        pipeline: Pipeline,
+        engine: &'a dyn ScriptEngine,
+        observer: &'a dyn Observer,
+        config: VerifyConfig,
+    ) -> impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + 'a {
+        self.verify_iter(pipeline, engine, observer, config, None)
+    }
+
+    /// Verifies all entries in the log and sums up the check counts, the
+    /// final state and the time spent in each phase
+    pub fn verify_all(&self) -> Result<VerifyReport, VerifyError> {
+        let mut vi = self.verify_iter(
+            self.pipeline(),
+            &WaccEngine,
+            &NoopObserver,
+            VerifyConfig::default(),
+            None,
+        );
+        let mut report = VerifyReport {
+            entries: 0,
+            counts: BTreeMap::default(),
+            fuel: BTreeMap::default(),
+            kvp: vi.kvp.clone(),
+            locks: vi.lock_scripts.clone(),
+            head: Cid::null(),
+            phase_times: HashMap::default(),
+        };
+        let mut interrupted = None;
+        for ret in vi.by_ref() {
+            let (count, entry, kvp) = match ret {
+                Ok(r) => r,
+                Err(Error::Verify(e)) => return Err(e),
+                Err(e) => {
+                    interrupted = Some(e);
+                    break;
                 }
+            };
+            report.entries += 1;
+            report.counts.insert(entry.seqno(), count);
+            report.kvp = kvp;
+            report.locks = entry.locks.clone();
+            report.head = entry.cid();
+        }
+        // the iterator doesn't wrap errors that aren't failures of the entry
+        // so wrap them here with the entry that was being verified
+        if let Some(e) = interrupted {
+            let (seqno, entry_cid) = vi
+                .entries
+                .get(report.entries)
+                .map(|e| (e.seqno(), e.cid()))
+                .unwrap_or((report.entries as u64, Cid::null()));
+            return Err(VerifyError {
+                seqno,
+                entry_cid,
+                phase: VerifyPhase::Internal,
+                source: Box::new(e),
+            });
+        }
+        report.phase_times = vi.phase_times;
+        report.fuel = vi.fuel;
+        Ok(report)
+    }
+
+    /// build the verification iterator, starting from the checkpoint if given
+    fn verify_iter<'a, P: Borrow<Pipeline>>(
+        &'a self,
+        pipeline: P,
+        engine: &'a dyn ScriptEngine,
+        observer: &'a dyn Observer,
+        config: VerifyConfig,
+        checkpoint: Option<&Checkpoint>,
+    ) -> VerifyIter<'a, P> {
+        // get a list of Entry objects after the checkpoint, sort them by seqno
+        let mut entries: Vec<&Entry> = self
+            .entries
+            .values()
+            .filter(|e| checkpoint.map(|cp| e.seqno() > cp.seqno).unwrap_or(true))
+            .collect();
+        entries.sort();
+        match checkpoint {
+            Some(cp) => VerifyIter {
+                engine,
+                observer,
+                entry_points: &self.entry_points,
+                pipeline,
+                entries,
+                seqno: 0,
+                prev_seqno: Some(cp.seqno),
+                kvp: cp.kvp(),
+                lock_scripts: cp.locks.clone(),
+                config,
+                error: None,
+                phase_times: HashMap::default(),
+                fuel: BTreeMap::default(),
+            },
+            None => VerifyIter {
+                engine,
+                observer,
+                entry_points: &self.entry_points,
+                pipeline,
+                entries,
+                seqno: 0,
+                prev_seqno: None,
+                kvp: Kvp::default(),
+                lock_scripts: vec![self.first_lock.clone()],
+                config,
+                error: None,
+                phase_times: HashMap::default(),
+                fuel: BTreeMap::default(),
+            },
+        }
+    }
 
-                //println!("SUCCEEDED!");
+    /// Verifies the entries in the log that come after the given checkpoint,
+    /// starting from the kvp state and lock scripts stored in it. The
+    /// checkpoint is only trusted if the `verify_proof` closure accepts its
+    /// proof, see [`Checkpoint::verify_proof`].
+    pub fn verify_from_checkpoint<'a, F>(
+        &'a self,
+        checkpoint: &Checkpoint,
+        verify_proof: F,
+        pipeline: Pipeline,
+        engine: &'a dyn ScriptEngine,
+    ) -> Result<impl Iterator<Item = Result<(usize, Entry, Kvp), Error>> + 'a, Error>
+    where
+        F: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        checkpoint.verify_proof(verify_proof)?;
+        // make sure the checkpoint was taken from an entry in this log
+        let entry = self
+            .entries
+            .get(&checkpoint.head)
+            .ok_or(LogError::EntryNotFound(checkpoint.head.clone()))?;
+        if entry.seqno() != checkpoint.seqno || *entry.vlad_ref() != checkpoint.vlad {
+            return Err(LogError::CheckpointMismatch.into());
+        }
+        Ok(self.verify_iter(
+            pipeline,
+            engine,
+            &NoopObserver,
+            VerifyConfig::default(),
+            Some(checkpoint),
+        ))
+    }
+
+    /// Verifies the log up to the entry with the given seqno and creates a
+    /// checkpoint of the resulting state. The `gen_proof` closure is called to
+    /// generate the proof over the serialized checkpoint.
+    pub fn checkpoint_at<F>(&self, seqno: u64, gen_proof: F) -> Result<Checkpoint, Error>
+    where
+        F: FnMut(&mut Checkpoint) -> Result<Vec<u8>, Error>,
+    {
+        self.checkpoint_at_with_pipeline(seqno, Pipeline::default(), &WaccEngine, gen_proof)
+    }
+
+    /// Verifies the log up to the entry with the given seqno using the given
+    /// pipeline and engine and creates a checkpoint of the resulting state
+    pub fn checkpoint_at_with_pipeline<F>(
+        &self,
+        seqno: u64,
+        pipeline: Pipeline,
+        engine: &dyn ScriptEngine,
+        gen_proof: F,
+    ) -> Result<Checkpoint, Error>
+    where
+        F: FnMut(&mut Checkpoint) -> Result<Vec<u8>, Error>,
+    {
+        for ret in self.verify_with_pipeline(pipeline, engine) {
+            let (_, entry, kvp) = ret?;
+            if entry.seqno() == seqno {
+                return checkpoint::Builder::new(&entry, &kvp).try_build(gen_proof);
             }
+        }
+        Err(LogError::SeqnoNotFound(seqno).into())
+    }
 
-            // break out of this loop as soon as a lock script succeeds
-            if let Some(v) = lock_rstack.top() {
-                match v {
-                    vm::Value::Success(c) => {
-                        count = c;
-                        result = true;
-                        break;
-                    }
-                    _ => result = false,
-                }
+    /// Switch the log to the script table encoding of
+    /// [`SCRIPT_TABLE_LOG_VERSION`]. The entries and their cids don't change.
+    pub fn use_script_table(&mut self) {
+        self.version = self.version.max(SCRIPT_TABLE_LOG_VERSION);
+    }
+
+    /// Compacts the log by discarding the entries before the entry with the
+    /// given seqno. A checkpoint of the state just before that entry is
+    /// created with the `gen_proof` closure and stored in the log so that it
+    /// can still be verified. The entries on the lipmaa path from the
+    /// checkpoint back to the foot are kept to prove the continuity of the log.
+    /// The log is verified from the foot first, so a log that is already
+    /// compacted can't be compacted any further.
+    pub fn compact<F>(&mut self, keep_from_seqno: u64, gen_proof: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Checkpoint) -> Result<Vec<u8>, Error>,
+    {
+        self.compact_with_pipeline(keep_from_seqno, Pipeline::default(), &WaccEngine, gen_proof)
+    }
+
+    /// Compacts the log verifying it with the given pipeline and engine
+    pub fn compact_with_pipeline<F>(
+        &mut self,
+        keep_from_seqno: u64,
+        pipeline: Pipeline,
+        engine: &dyn ScriptEngine,
+        gen_proof: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut Checkpoint) -> Result<Vec<u8>, Error>,
+    {
+        // there is nothing to discard if the log is already compacted past it
+        let seqno = match keep_from_seqno.checked_sub(1) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if let Some(cp) = &self.checkpoint {
+            if cp.seqno >= seqno {
+                return Ok(());
             }
         }
 
-        if result {
-            // if the entry verifies, apply it's mutataions to the kvp
-            // the 0th entry has already been applied at this point so no
-            // need to do it here
-            if self.seqno > 0 {
-                if let Some(e) = self.kvp.apply_entry_ops(entry).err() {
-                    // set our index out of range
-                    self.seqno = self.entries.len();
-                    self.error = Some(LogError::UpdateKvpFailed(e.to_string()).into());
-                    return Some(Err(self.error.clone().unwrap()));
+        let checkpoint = self.checkpoint_at_with_pipeline(seqno, pipeline, engine, gen_proof)?;
+
+        // keep the entries on the path from the checkpoint back to the foot
+        let mut keep: BTreeSet<Cid> = self
+            .continuity_path(&checkpoint.head, &self.foot)?
+            .into_iter()
+            .collect();
+        // and every entry from the given seqno on
+        keep.extend(
+            self.entries
+                .iter()
+                .filter(|(_, e)| e.seqno() >= keep_from_seqno)
+                .map(|(cid, _)| cid.clone()),
+        );
+
+        self.entries.retain(|cid, _| keep.contains(cid));
+        self.reindex();
+        self.checkpoint = Some(checkpoint);
+        self.version = self.version.max(CHECKPOINT_LOG_VERSION);
+        Ok(())
+    }
+
+    /// Checks that the foot is reachable from the checkpoint, or the head if
+    /// the log isn't compacted, by following the lipmaa and prev links
+    pub fn verify_continuity(&self) -> Result<(), Error> {
+        let from = match &self.checkpoint {
+            Some(cp) => &cp.head,
+            None => &self.head,
+        };
+        self.continuity_path(from, &self.foot).map(|_| ())
+    }
+
+    /// Checks the structure of the log without running any scripts: the log
+    /// and entry versions, the vlad of each entry, the cid each entry is
+    /// stored under, the prev links and seqnos from the head to the foot and
+    /// the lipmaa links of the entries that are in the log.
+    pub fn validate_structure(&self) -> Result<(), Error> {
+        if self.version > SCRIPT_TABLE_LOG_VERSION {
+            return Err(LogError::InvalidVersion(self.version).into());
+        }
+        for (cid, entry) in &self.entries {
+            if entry.version != ENTRY_VERSION && entry.version != ENTRY_VERSION_PATHS {
+                return Err(EntryError::InvalidVersion(entry.version as usize).into());
+            }
+            if entry.vlad != self.vlad {
+                return Err(LogError::VladMismatch(entry.seqno()).into());
+            }
+            if *cid != entry.cid() {
+                return Err(LogError::EntryCidMismatch.into());
+            }
+            let seqno = entry.seqno();
+            if seqno.is_lipmaa() == entry.lipmaa.is_null() {
+                return Err(LogError::InvalidLipmaaLink(seqno).into());
+            }
+            // the lipmaa target may have been compacted away
+            if let Some(target) = self.entries.get(&entry.lipmaa) {
+                if target.seqno() != seqno.lipmaa() {
+                    return Err(LogError::InvalidLipmaaLink(seqno).into());
                 }
             }
-            // update the lock script to validate the next entry
-            self.lock_scripts.clone_from(&entry.locks);
-            // update the seqno
-            self.prev_seqno = self.seqno;
-            self.seqno += 1;
-        } else {
-            // set our index out of range
-            self.seqno = self.entries.len();
-            self.error = Some(
-                LogError::VerifyFailed(format!(
-                    "unlock script failed\nvalues:\n{:?}\nreturn:\n{:?}",
-                    rstack, pstack
-                ))
-                .into(),
-            );
-            return Some(Err(self.error.clone().unwrap()));
         }
+        self.check_links(&self.head, &self.foot)
+    }
 
-        // return the check count, validated entry, and kvp state
-        Some(Ok((count, entry.clone(), self.kvp.clone())))
+    /// Get the entries on the shortest path from one entry back to an earlier
+    /// one, both included. The path follows the lipmaa links when they don't
+    /// jump past the earlier entry so it takes O(log n) hops.
+    pub fn lipmaa_path(&self, from: &Cid, to: &Cid) -> Result<impl Iterator<Item = &Entry>, Error> {
+        let path = self.continuity_path(from, to)?;
+        Ok(path.into_iter().filter_map(|cid| self.entries.get(&cid)))
     }
-}
 
-impl Log {
-    /// get an iterator over the entries in from head to foot
-    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
-        // get a list of Entry references, sort them by seqno
-        let mut entries: Vec<&Entry> = self.entries.values().collect();
-        entries.sort();
-        EntryIter {
-            entries,
-            current: 0,
+    /// walk from one entry back to an earlier one preferring the lipmaa links
+    /// over the prev links when the entry they point to is in the log and
+    /// isn't before the earlier one, checking the cids and seqnos along the
+    /// way. returns the cids on the path.
+    fn continuity_path(&self, from: &Cid, foot: &Cid) -> Result<Vec<Cid>, Error> {
+        let foot_seqno = self
+            .entries
+            .get(foot)
+            .ok_or(LogError::EntryNotFound(foot.clone()))?
+            .seqno();
+        let mut path = Vec::default();
+        let mut c = from.clone();
+        loop {
+            let entry = self
+                .entries
+                .get(&c)
+                .ok_or(LogError::EntryNotFound(c.clone()))?;
+            if c != entry.cid() {
+                return Err(LogError::EntryCidMismatch.into());
+            }
+            path.push(c.clone());
+            if c == *foot {
+                return Ok(path);
+            }
+            if entry.seqno() <= foot_seqno {
+                return Err(LogError::BrokenEntryLinks.into());
+            }
+            let (next, seqno) = if !entry.lipmaa.is_null()
+                && entry.seqno().lipmaa() >= foot_seqno
+                && self.entries.contains_key(&entry.lipmaa)
+            {
+                (entry.lipmaa.clone(), entry.seqno().lipmaa())
+            } else {
+                let seqno = entry
+                    .seqno()
+                    .checked_sub(1)
+                    .ok_or(LogError::BrokenEntryLinks)?;
+                (entry.prev(), seqno)
+            };
+            if next.is_null() {
+                return Err(LogError::BrokenEntryLinks.into());
+            }
+            let next_entry = self.entries.get(&next).ok_or(LogError::BrokenPrevLink)?;
+            if next_entry.seqno() != seqno {
+                return Err(LogError::InvalidSeqno.into());
+            }
+            c = next;
         }
     }
 
-    /// Verifies all entries in the log
-    pub fn verify(&self) -> impl Iterator<Item = Result<(usize, Entry, Kvp<'_>), Error>> {
-        // get a list of Entry objects, sort them by seqno
-        let mut entries: Vec<&Entry> = self.entries.values().collect();
-        entries.sort();
-        VerifyIter {
-            entries,
-            seqno: 0,
-            prev_seqno: 0,
-            kvp: Kvp::default(),
-            lock_scripts: vec![self.first_lock.clone()],
-            error: None,
+    /// get the entry with the seqno
+    pub fn get_by_seqno(&self, seqno: u64) -> Option<&Entry> {
+        match self.seqnos.0.get(&seqno).and_then(|cid| self.entries.get(cid)) {
+            Some(entry) if entry.seqno() == seqno => Some(entry),
+            // the entries were changed directly
+            _ => self.entries.values().find(|e| e.seqno() == seqno),
+        }
+    }
+
+    /// get the latest entry in the log
+    pub fn head_entry(&self) -> Option<&Entry> {
+        self.entries.get(&self.head)
+    }
+
+    /// get the first entry in the log
+    pub fn foot_entry(&self) -> Option<&Entry> {
+        self.entries.get(&self.foot)
+    }
+
+    /// get the number of entries in the log
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// true if the log has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// rebuild the seqno index after the entries were changed
+    pub(crate) fn reindex(&mut self) {
+        self.seqnos = SeqnoIndex::from(&self.entries);
+    }
+
+    /// get the cid of the first entry in the log
+    pub fn foot(&self) -> &Cid {
+        &self.foot
+    }
+
+    /// get the cid of the latest entry in the log
+    pub fn head(&self) -> &Cid {
+        &self.head
+    }
+
+    /// Set the head of the log after checking that the foot is reachable from
+    /// the new head by following the prev links and that the seqno decreases
+    /// by one at each step
+    pub fn set_head_checked(&mut self, cid: &Cid) -> Result<(), Error> {
+        self.check_links(cid, &self.foot)?;
+        self.head = cid.clone();
+        Ok(())
+    }
+
+    /// Set the foot of the log after checking that the new foot is reachable
+    /// from the head by following the prev links and that the seqno decreases
+    /// by one at each step
+    pub fn set_foot_checked(&mut self, cid: &Cid) -> Result<(), Error> {
+        self.check_links(&self.head, cid)?;
+        self.foot = cid.clone();
+        Ok(())
+    }
+
+    /// walk the prev links from the head to the foot checking the cids and the
+    /// seqno ordering along the way
+    fn check_links(&self, head: &Cid, foot: &Cid) -> Result<(), Error> {
+        let mut c = head.clone();
+        let mut entry = self
+            .entries
+            .get(&c)
+            .ok_or(LogError::EntryNotFound(c.clone()))?;
+        loop {
+            if c != entry.cid() {
+                return Err(LogError::EntryCidMismatch.into());
+            }
+            if c == *foot {
+                return Ok(());
+            }
+            // the entries before the checkpoint may have been discarded
+            if self.checkpoint.as_ref().map(|cp| cp.head == c).unwrap_or(false) {
+                return self.continuity_path(&c, foot).map(|_| ());
+            }
+            let prev = entry.prev();
+            if prev.is_null() {
+                return Err(LogError::BrokenEntryLinks.into());
+            }
+            let prev_entry = self
+                .entries
+                .get(&prev)
+                .ok_or(LogError::BrokenPrevLink)?;
+            if prev_entry.seqno() + 1 != entry.seqno() {
+                return Err(LogError::InvalidSeqno.into());
+            }
+            c = prev;
+            entry = prev_entry;
         }
     }
 
     /// Try to add an entry to the p.log
     pub fn try_append(&mut self, entry: &Entry) -> Result<(), Error> {
-        let cid = entry.cid();
-        let mut plog = self.clone();
-        plog.entries.insert(cid.clone(), entry.clone());
-        let vi = plog.verify();
+        self.try_append_with_observer(entry, &NoopObserver)
+    }
+
+    /// Try to add an entry to the p.log, calling the observer with the
+    /// events emitted while verifying the entry
+    pub fn try_append_with_observer(
+        &mut self,
+        entry: &Entry,
+        observer: &dyn Observer,
+    ) -> Result<(), Error> {
+        self.try_append_all_with_observer(std::slice::from_ref(entry), observer)
+    }
+
+    /// Try to add a sequence of entries to the p.log in one pass. Each entry
+    /// must link to the one before it and the first to the head. If any of
+    /// them fails to verify none of them are added.
+    pub fn try_append_all(&mut self, entries: &[Entry]) -> Result<(), Error> {
+        self.try_append_all_with_observer(entries, &NoopObserver)
+    }
+
+    /// Try to add a sequence of entries to the p.log, calling the observer
+    /// with the events emitted while verifying them
+    pub fn try_append_all_with_observer(
+        &mut self,
+        entries: &[Entry],
+        observer: &dyn Observer,
+    ) -> Result<(), Error> {
+        let pipeline = self.pipeline();
+        let config = VerifyConfig::default();
+        self.try_append_all_with_config(entries, &pipeline, &WaccEngine, observer, config)
+    }
+
+    /// Try to add a sequence of entries to the p.log, passing each one
+    /// through the stages of the given pipeline and running the scripts with
+    /// the given engine, observer and config. The head state is verified
+    /// with them too if it isn't already known.
+    pub fn try_append_all_with_config(
+        &mut self,
+        entries: &[Entry],
+        pipeline: &Pipeline,
+        engine: &dyn ScriptEngine,
+        observer: &dyn Observer,
+        config: VerifyConfig,
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+
+        // reject entries that were built against a head other than ours or
+        // that are over the size limits, carry invalid scripts or use keys
+        // the profile doesn't allow
+        let mut head = self.head.clone();
+        for entry in entries {
+            self.limits.check(entry)?;
+            self.validator.validate_entry(entry, &self.entry_points)?;
+            if let Some(profile) = &self.key_profile {
+                profile.check_entry(entry)?;
+            }
+            if *entry.prev_ref() != head {
+                return Err(LogError::StaleHead(entry.prev()).into());
+            }
+            head = entry.cid();
+        }
+        let last = match entries.last() {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+
+        // only verify the new entries on top of the verified head state
+        let state = match self.verified.0.take() {
+            Some(state) if state.head == self.head => state,
+            _ => self.verify_head_state_with(pipeline, engine, config.clone())?,
+        };
+        let entry_points = &self.entry_points;
+        let vi = state.verify_iter(entries, entry_points, pipeline, engine, observer, config);
+        let mut kvp = None;
         for ret in vi {
-            if let Some(e) = ret.err() {
-                return Err(LogError::VerifyFailed(e.to_string()).into());
+            match ret {
+                Ok((_, _, k)) => kvp = Some(k),
+                Err(e) => {
+                    self.verified.0 = Some(state);
+                    return Err(e);
+                }
             }
         }
-        self.entries.insert(cid.clone(), entry.clone());
-        self.head = cid;
+        let next = Verified {
+            head: last.cid(),
+            seqno: Some(last.seqno()),
+            kvp: kvp
+                .iter()
+                .flat_map(|k| k.iter())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            locks: last.locks.clone(),
+        };
+
+        // commit the entries and move the head once
+        for entry in entries {
+            self.entries.insert(entry.cid(), entry.clone());
+            self.seqnos.0.insert(entry.seqno(), entry.cid());
+        }
+        self.head = next.head.clone();
+        self.verified.0 = Some(next);
+        for entry in entries {
+            self.notify(entry);
+        }
+        telemetry::appended(entries.len(), start.elapsed());
         Ok(())
     }
+
+    /// Verify a candidate entry against the head of the p.log without adding
+    /// it and get the kvp state the p.log would have after it. This lets the
+    /// effect of an entry be previewed, and script failures caught, before
+    /// it is signed and appended.
+    pub fn simulate(&self, entry: &Entry) -> Result<Kvp, Error> {
+        let config = VerifyConfig::default();
+        self.simulate_with_config(entry, &self.pipeline(), &WaccEngine, config)
+    }
+
+    /// Verify a candidate entry against the head of the p.log like
+    /// [`Log::simulate`] with the given pipeline, engine and config
+    pub fn simulate_with_config(
+        &self,
+        entry: &Entry,
+        pipeline: &Pipeline,
+        engine: &dyn ScriptEngine,
+        config: VerifyConfig,
+    ) -> Result<Kvp, Error> {
+        if *entry.prev_ref() != self.head {
+            return Err(LogError::StaleHead(entry.prev()).into());
+        }
+        let computed;
+        let state = match &self.verified.0 {
+            Some(state) if state.head == self.head => state,
+            _ => {
+                computed = self.verify_head_state_with(pipeline, engine, config.clone())?;
+                &computed
+            }
+        };
+        let entries = std::slice::from_ref(entry);
+        let entry_points = &self.entry_points;
+        let vi = state.verify_iter(entries, entry_points, pipeline, engine, &NoopObserver, config);
+        let mut kvp = BTreeMap::default();
+        for ret in vi {
+            let (_, _, k) = ret?;
+            kvp = k.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        }
+        Ok(Kvp::from(kvp))
+    }
+
+    /// get the verified value at the key in the state at the head
+    pub(crate) fn head_value(&self, key: &Key) -> Result<Option<Value>, Error> {
+        match &self.verified.0 {
+            Some(state) if state.head == self.head => Ok(state.kvp.get(key).cloned()),
+            _ => Ok(self.verify_head_state()?.kvp.get(key).cloned()),
+        }
+    }
+
+    /// get the verified kvp state at the head
+    pub(crate) fn head_kvp(&self) -> Result<Kvp, Error> {
+        match &self.verified.0 {
+            Some(state) if state.head == self.head => Ok(Kvp::from(state.kvp.clone())),
+            _ => Ok(Kvp::from(self.verify_head_state()?.kvp)),
+        }
+    }
+
+    /// Get the names of the functions called in the scripts
+    pub fn entry_points(&self) -> &EntryPoints {
+        &self.entry_points
+    }
+
+    /// Get the size limits checked on appended entries
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the size limits checked on appended entries
+    pub fn set_limits(&mut self, limits: &Limits) {
+        self.limits = *limits;
+    }
+
+    /// Get the validator the scripts in appended entries are checked with
+    pub fn validator(&self) -> &Validator {
+        &self.validator
+    }
+
+    /// Set the validator the scripts in appended entries are checked with
+    pub fn set_validator(&mut self, validator: &Validator) {
+        self.validator = validator.clone();
+    }
+
+    /// Get the profile the key-paths in entries are checked against
+    pub fn key_profile(&self) -> Option<&KeyProfile> {
+        self.key_profile.as_ref()
+    }
+
+    /// Set the profile the key-paths in appended and verified entries are
+    /// checked against, None to allow any key-path
+    pub fn set_key_profile(&mut self, key_profile: Option<&KeyProfile>) {
+        self.key_profile = key_profile.cloned();
+    }
+
+    /// Set the names of the functions called in the scripts. The verified
+    /// head state is dropped since it was verified with the old names.
+    pub fn set_entry_points(&mut self, entry_points: &EntryPoints) {
+        self.entry_points = entry_points.clone();
+        self.verified.0 = None;
+    }
+
+    /// Drop the verified head state and verify every entry in the log again.
+    /// The head state is kept up to date by [`Log::try_append`] so this is
+    /// only needed after the entries have been changed directly.
+    pub fn force_full_verify(&mut self) -> Result<(), Error> {
+        self.verified.0 = None;
+        let state = self.verify_head_state()?;
+        self.verified.0 = Some(state);
+        Ok(())
+    }
+
+    /// Drop the verified head state and verify the entries after the
+    /// checkpoint of a compacted log, once the `verify_proof` closure accepts
+    /// the checkpoint proof. The discarded entries can't be verified from the
+    /// foot so this is needed before entries can be appended to the log.
+    pub fn force_verify_from_checkpoint<F>(&mut self, verify_proof: F) -> Result<(), Error>
+    where
+        F: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        self.verified.0 = None;
+        let cp = self.checkpoint.as_ref().ok_or(LogError::CheckpointMismatch)?;
+        let start = Verified {
+            head: cp.head.clone(),
+            seqno: Some(cp.seqno),
+            kvp: cp.kvp.clone(),
+            locks: cp.locks.clone(),
+        };
+        let vi = self.verify_from_checkpoint(cp, verify_proof, self.pipeline(), &WaccEngine)?;
+        let state = self.head_state(start, vi)?;
+        self.verified.0 = Some(state);
+        Ok(())
+    }
+
+    /// verify the log from the foot to get the state at the head
+    fn verify_head_state(&self) -> Result<Verified, Error> {
+        let pipeline = self.pipeline();
+        self.verify_head_state_with(&pipeline, &WaccEngine, VerifyConfig::default())
+    }
+
+    /// verify the log from the foot with the given pipeline, engine and
+    /// config to get the state at the head
+    fn verify_head_state_with(
+        &self,
+        pipeline: &Pipeline,
+        engine: &dyn ScriptEngine,
+        config: VerifyConfig,
+    ) -> Result<Verified, Error> {
+        let start = Verified {
+            head: Cid::null(),
+            seqno: None,
+            kvp: BTreeMap::default(),
+            locks: vec![self.first_lock.clone()],
+        };
+        let vi = self.verify_iter(pipeline, engine, &NoopObserver, config, None);
+        self.head_state(start, vi)
+    }
+
+    /// get the state at the head by running the verification iterator on top
+    /// of the starting state
+    fn head_state(
+        &self,
+        mut state: Verified,
+        vi: impl Iterator<Item = Result<(usize, Entry, Kvp), Error>>,
+    ) -> Result<Verified, Error> {
+        for ret in vi {
+            let (_, entry, kvp) = ret?;
+            state = Verified {
+                head: entry.cid(),
+                seqno: Some(entry.seqno()),
+                kvp: kvp.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                locks: entry.locks.clone(),
+            };
+        }
+        if state.head != self.head {
+            return Err(LogError::BrokenEntryLinks.into());
+        }
+        Ok(state)
+    }
 }
 
 /// Builder for Log objects
@@ -476,6 +1411,11 @@ pub struct Builder {
     foot: Option<Cid>,
     head: Option<Cid>,
     entries: Entries,
+    checkpoint: Option<Checkpoint>,
+    entry_points: EntryPoints,
+    limits: Limits,
+    validator: Validator,
+    key_profile: Option<KeyProfile>,
 }
 
 impl Builder {
@@ -511,6 +1451,49 @@ impl Builder {
         self
     }
 
+    /// Set the checkpoint of a compacted log. The log is built with at least
+    /// version [`CHECKPOINT_LOG_VERSION`] which older decoders reject.
+    pub fn with_checkpoint(mut self, checkpoint: &Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint.clone());
+        self.version = self.version.max(CHECKPOINT_LOG_VERSION);
+        self
+    }
+
+    /// Store each distinct lock and unlock script once in a table when the
+    /// log is encoded. Logs with the same scripts in every entry are much
+    /// smaller. The log is built with version [`SCRIPT_TABLE_LOG_VERSION`]
+    /// which older decoders reject.
+    pub fn with_script_table(mut self) -> Self {
+        self.version = SCRIPT_TABLE_LOG_VERSION;
+        self
+    }
+
+    /// Set the names of the functions called in the scripts
+    pub fn with_entry_points(mut self, entry_points: &EntryPoints) -> Self {
+        self.entry_points = entry_points.clone();
+        self
+    }
+
+    /// Set the size limits checked on entries appended to the log
+    pub fn with_limits(mut self, limits: &Limits) -> Self {
+        self.limits = *limits;
+        self
+    }
+
+    /// Set the validator the scripts in entries appended to the log are
+    /// checked with
+    pub fn with_validator(mut self, validator: &Validator) -> Self {
+        self.validator = validator.clone();
+        self
+    }
+
+    /// Set the profile the key-paths in entries appended to and verified in
+    /// the log are checked against
+    pub fn with_key_profile(mut self, key_profile: &KeyProfile) -> Self {
+        self.key_profile = Some(key_profile.clone());
+        self
+    }
+
     /// Set the passed in entries to the existin entries
     pub fn with_entries(mut self, entries: &Entries) -> Self {
         self.entries.append(&mut entries.clone());
@@ -541,6 +1524,7 @@ impl Builder {
         let foot = self.foot.clone().ok_or(LogError::MissingFoot)?;
         let head = self.head.clone().ok_or(LogError::MissingHead)?;
         let entries = self.entries.clone();
+        let checkpoint = self.checkpoint.clone();
         if entries.is_empty() {
             return Err(LogError::MissingEntries.into());
         } else {
@@ -548,7 +1532,9 @@ impl Builder {
             // they are all connected
             let mut c = head.clone();
             let f = foot.clone();
-            while c != f {
+            let cp = checkpoint.as_ref().map(|cp| cp.head.clone());
+            // the entries before the checkpoint are checked below
+            while c != f && Some(&c) != cp.as_ref() {
                 if let Some(entry) = entries.get(&c) {
                     if c != entry.cid() {
                         return Err(LogError::EntryCidMismatch.into());
@@ -562,21 +1548,37 @@ impl Builder {
                 }
             }
         }
-        Ok(Log {
+        let seqnos = SeqnoIndex::from(&entries);
+        let log = Log {
             version,
             vlad,
             first_lock,
             foot,
             head,
             entries,
-        })
+            checkpoint,
+            entry_points: self.entry_points.clone(),
+            subscribers: Subscribers::default(),
+            verified: HeadState::default(),
+            seqnos,
+            witnesses: Witnesses::default(),
+            limits: self.limits,
+            validator: self.validator.clone(),
+            key_profile: self.key_profile.clone(),
+            #[cfg(feature = "sqlite")]
+            store: Default::default(),
+        };
+        if log.checkpoint.is_some() {
+            log.verify_continuity()?;
+        }
+        Ok(log)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Key, Op, Value};
+    use crate::{error::KvpError, Disclosure, Op, SyncPayload};
     use multicid::{cid, vlad};
     use multihash::mh;
     use multikey::{EncodedMultikey, Multikey, Views};
@@ -614,6 +1616,353 @@ mod tests {
         assert_eq!(log.iter().next(), None);
     }
 
+    #[test]
+    fn test_set_head_foot_checked() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let mut log = Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        assert!(log.set_head_checked(&Cid::default()).is_err());
+        assert_eq!(log.head(), &e2.cid());
+        log.set_head_checked(&e1.cid()).unwrap();
+        assert_eq!(log.head(), &e1.cid());
+        // the foot can't be moved past the head
+        assert!(log.set_foot_checked(&e2.cid()).is_err());
+        log.set_head_checked(&e2.cid()).unwrap();
+        log.set_foot_checked(&e2.cid()).unwrap();
+        assert_eq!(log.foot(), &e2.cid());
+    }
+
+    #[test]
+    fn test_script_table() {
+        // the same big lock and unlock scripts in every entry
+        let lock = Script::Bin(Key::default(), vec![0x61; 1024]);
+        let unlock = Script::Bin(Key::default(), vec![0x62; 1024]);
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&unlock)
+            .add_lock(&lock)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&unlock)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&unlock)
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let builder = Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&lock)
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3);
+
+        let log = builder.try_build().unwrap();
+        let plain: Vec<u8> = log.clone().into();
+        let log = builder.with_script_table().try_build().unwrap();
+        assert_eq!(SCRIPT_TABLE_LOG_VERSION, log.version);
+        let bytes: Vec<u8> = log.clone().into();
+        assert!(bytes.len() < plain.len() / 2);
+
+        // the entries are rebuilt with their scripts
+        let decoded = Log::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(log, decoded);
+        assert_eq!(e3, *decoded.entries.get(&e3.cid()).unwrap());
+        let streamed = Log::decode_from_reader(std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(log, streamed);
+
+        // an existing log can switch to the table
+        let mut log = Log::try_from(plain.as_slice()).unwrap();
+        log.use_script_table();
+        assert_eq!(bytes, Vec::<u8>::from(log));
+    }
+
+    #[test]
+    fn test_try_append_stale_head() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut log = Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        // two writers build an entry against the same head
+        let e2a = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_seqno(1)
+            .with_prev(log.head())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Noop("/a".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2b = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_seqno(1)
+            .with_prev(log.head())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Noop("/b".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        // the first one wins the race, the head moves on without verifying
+        // because the default scripts can't run
+        log.entries.insert(e2a.cid(), e2a.clone());
+        log.set_head_checked(&e2a.cid()).unwrap();
+
+        match log.try_append(&e2b) {
+            Err(Error::Log(LogError::StaleHead(head))) => assert_eq!(e1.cid(), head),
+            _ => panic!("expected a stale head error"),
+        }
+        assert_eq!(log.head(), &e2a.cid());
+    }
+
+    #[test]
+    fn test_compact() {
+        let structural = || {
+            Pipeline::new()
+                .with_stage(crate::pipeline::Structure)
+                .with_stage(crate::pipeline::Apply)
+        };
+
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_lock(&Script::default())
+            .add_op(&Op::Update("/seqno".try_into().unwrap(), Value::Str("0".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for seqno in 1..6u64 {
+            let mut b = entry::Builder::from(entries.last().unwrap())
+                .with_unlock(&Script::default())
+                .add_op(&Op::Update("/seqno".try_into().unwrap(), Value::Str(seqno.to_string())));
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(Vec::default())).unwrap());
+        }
+
+        let mut log = entries
+            .iter()
+            .fold(
+                Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+
+        // logs without a checkpoint keep the old encoding
+        assert_eq!(LOG_VERSION, log.version);
+        log.compact_with_pipeline(5, structural(), &WaccEngine, |_| Ok(Vec::default()))
+            .unwrap();
+        assert_eq!(CHECKPOINT_LOG_VERSION, log.version);
+
+        // the foot and the lipmaa path 4 -> 1 -> 0 are kept along with 5
+        assert_eq!(4, log.entries.len());
+        assert!(!log.entries.contains_key(&entries[2].cid()));
+        assert!(!log.entries.contains_key(&entries[3].cid()));
+        assert_eq!(log.foot(), &entries[0].cid());
+        assert_eq!(4, log.checkpoint.as_ref().unwrap().seqno());
+        log.verify_continuity().unwrap();
+        assert_eq!(vec![entries[5].cid()], log.heads());
+
+        // the checkpoint isn't trusted by default so verifying from the foot
+        // stops at the first discarded entry
+        let results: Vec<_> = log.verify_with_pipeline(structural(), &WaccEngine).collect();
+        assert!(results.last().unwrap().is_err());
+        // a forged checkpoint is rejected by the proof check
+        let cp = log.checkpoint.clone().unwrap();
+        let reject = |_: &[u8], _: &[u8]| -> Result<(), Error> {
+            Err(LogError::CheckpointMismatch.into())
+        };
+        assert!(log
+            .verify_from_checkpoint(&cp, reject, structural(), &WaccEngine)
+            .is_err());
+
+        // only the entries after a trusted checkpoint are verified
+        let results: Vec<_> = log
+            .verify_from_checkpoint(&cp, |_, _| Ok(()), structural(), &WaccEngine)
+            .unwrap()
+            .collect();
+        assert_eq!(1, results.len());
+        let (_, entry, kvp) = results[0].clone().unwrap();
+        assert_eq!(entries[5], entry);
+        assert_eq!(
+            Some(Value::Str("5".to_string())),
+            kvp.iter()
+                .find(|(k, _)| k.to_string() == "/seqno")
+                .map(|(_, v)| v.clone())
+        );
+
+        // the compacted log survives encoding
+        let v: Vec<u8> = log.clone().into();
+        assert_eq!(log, Log::try_from(v.as_slice()).unwrap());
+
+        // and can be rebuilt from its parts
+        let rebuilt = Builder::new()
+            .with_vlad(&log.vlad)
+            .with_first_lock(&log.first_lock)
+            .with_foot(log.foot())
+            .with_head(log.head())
+            .with_entries(&log.entries)
+            .with_checkpoint(log.checkpoint.as_ref().unwrap())
+            .try_build()
+            .unwrap();
+        assert_eq!(log, rebuilt);
+    }
+
+    #[test]
+    fn test_validate_structure() {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for seqno in 1..5u64 {
+            let mut b = entry::Builder::from(entries.last().unwrap()).with_unlock(&Script::default());
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(Vec::default())).unwrap());
+        }
+        let log = entries
+            .iter()
+            .fold(
+                Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+        log.validate_structure().unwrap();
+
+        // a lipmaa link to the wrong entry
+        let bad = entry::Builder::from(&entries[3])
+            .with_unlock(&Script::default())
+            .with_lipmaa(&entries[2].cid())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut broken = log.clone();
+        broken.entries.remove(&entries[4].cid());
+        broken.entries.insert(bad.cid(), bad.clone());
+        broken.set_head_checked(&bad.cid()).unwrap();
+        assert!(matches!(
+            broken.validate_structure(),
+            Err(Error::Log(LogError::InvalidLipmaaLink(4)))
+        ));
+
+        // an entry stored under the wrong cid
+        let mut broken = log.clone();
+        broken.entries.insert(entries[4].cid(), entries[3].clone());
+        assert!(matches!(
+            broken.validate_structure(),
+            Err(Error::Log(LogError::EntryCidMismatch))
+        ));
+    }
+
+    #[test]
+    fn test_seqno_index() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut log = Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        assert_eq!(2, log.len());
+        assert_eq!(Some(&e1), log.get_by_seqno(0));
+        assert_eq!(Some(&e2), log.get_by_seqno(1));
+        assert_eq!(None, log.get_by_seqno(2));
+        assert_eq!(Some(&e1), log.foot_entry());
+        assert_eq!(Some(&e2), log.head_entry());
+
+        // the index survives encoding
+        let v: Vec<u8> = log.clone().into();
+        let decoded = Log::try_from(v.as_slice()).unwrap();
+        assert_eq!(Some(&e2), decoded.get_by_seqno(1));
+
+        // entries added directly are still found
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        log.entries.insert(e3.cid(), e3.clone());
+        assert_eq!(Some(&e3), log.get_by_seqno(2));
+    }
+
+    #[test]
+    fn test_lipmaa_path() {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for seqno in 1..14u64 {
+            let mut b = entry::Builder::from(entries.last().unwrap()).with_unlock(&Script::default());
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(Vec::default())).unwrap());
+        }
+        let log = entries
+            .iter()
+            .fold(
+                Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+
+        let seqnos = |from: usize, to: usize| -> Vec<u64> {
+            log.lipmaa_path(&entries[from].cid(), &entries[to].cid())
+                .unwrap()
+                .map(|e| e.seqno())
+                .collect()
+        };
+        // 13 -> 4 -> 1 -> 0 follows the lipmaa links
+        assert_eq!(vec![13, 4, 1, 0], seqnos(13, 0));
+        // the links that would jump past the end are skipped
+        assert_eq!(vec![13, 12, 8, 7, 6, 5], seqnos(13, 5));
+        assert_eq!(vec![7], seqnos(7, 7));
+        // the path only goes backwards
+        assert!(log.lipmaa_path(&entries[0].cid(), &entries[3].cid()).is_err());
+    }
+
     #[test]
     fn test_builder() {
         let ephemeral = EncodedMultikey::try_from(
@@ -836,6 +2185,378 @@ mod tests {
             }
         }
     }
+
+    // the malformed transitions from a valid log to a next entry
+    #[derive(Clone, Copy, Debug)]
+    enum Mutation {
+        SkippedSeqno,
+        WrongPrev,
+        NullPrev,
+        StaleKey,
+        TamperedProof,
+        TruncatedProof,
+        EmptyProof,
+        ReorderedOps,
+        TamperedOp,
+        DroppedOp,
+    }
+
+    // the outcome the model expects appending an entry to have
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Outcome {
+        Appended,
+        StaleHead,
+        BadSeqno,
+        LockFailed,
+        Rejected,
+    }
+
+    const MUTATIONS: &[Mutation] = &[
+        Mutation::SkippedSeqno,
+        Mutation::WrongPrev,
+        Mutation::NullPrev,
+        Mutation::StaleKey,
+        Mutation::TamperedProof,
+        Mutation::TruncatedProof,
+        Mutation::EmptyProof,
+        Mutation::ReorderedOps,
+        Mutation::TamperedOp,
+        Mutation::DroppedOp,
+    ];
+
+    // the model of a log with two entries where /pubkey is key2 and the
+    // entries can only be signed by the current /pubkey
+    struct Model {
+        log: Log,
+        unlock: Script,
+        key1: Multikey,
+        key2: Multikey,
+    }
+
+    fn sign(e: &Entry, key: &Multikey) -> Vec<u8> {
+        let ev: Vec<u8> = e.clone().into();
+        key.sign_view().unwrap().sign(&ev, false, None).unwrap().into()
+    }
+
+    impl Model {
+        fn new() -> Self {
+            let mk = |s: &str| EncodedMultikey::try_from(s).unwrap().to_inner();
+            let ephemeral = mk("fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0");
+            let key1 = mk("fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8");
+            let key2 = mk("fba2480260874657374206b65790101203f4c94407de791e53b4df12ef1d5534d1b19ff2ccfccba4ccc4722b6e5e8ea07");
+            let cid = cid::Builder::new(Codec::Cidv1)
+                .with_target_codec(Codec::DagCbor)
+                .with_hash(
+                    &mh::Builder::new_from_bytes(Codec::Sha3512, b"for great justice, move every zig!")
+                        .unwrap()
+                        .try_build()
+                        .unwrap(),
+                )
+                .try_build()
+                .unwrap();
+            let vlad = vlad::Builder::default()
+                .with_signing_key(&ephemeral)
+                .with_cid(&cid)
+                .try_build()
+                .unwrap();
+            let lock = load_script(&Key::default(), "lock.wast");
+            let unlock = load_script(&Key::default(), "unlock.wast");
+
+            let e0 = entry::Builder::default()
+                .with_vlad(&vlad)
+                .add_lock(&lock)
+                .with_unlock(&unlock)
+                .add_op(&get_key_update_op("/ephemeral", &ephemeral))
+                .add_op(&get_key_update_op("/pubkey", &key1))
+                .try_build(|e| Ok(sign(e, &ephemeral)))
+                .unwrap();
+            let e1 = entry::Builder::from(&e0)
+                .with_unlock(&unlock)
+                .add_op(&Op::Delete("/ephemeral".try_into().unwrap()))
+                .add_op(&get_key_update_op("/pubkey", &key2))
+                .try_build(|e| Ok(sign(e, &key1)))
+                .unwrap();
+            let log = Builder::new()
+                .with_vlad(&vlad)
+                .with_first_lock(&load_script(&Key::default(), "first.wast"))
+                .append_entry(&e0)
+                .append_entry(&e1)
+                .try_build()
+                .unwrap();
+            Self {
+                log,
+                unlock,
+                key1,
+                key2,
+            }
+        }
+
+        fn head_entry(&self) -> &Entry {
+            &self.log.entries[&self.log.head]
+        }
+
+        // the builder for a valid next entry
+        fn next(&self) -> entry::Builder {
+            entry::Builder::from(self.head_entry())
+                .with_unlock(&self.unlock)
+                .add_op(&Op::Update("/a".try_into().unwrap(), Value::Str("1".to_string())))
+                .add_op(&Op::Update("/b".try_into().unwrap(), Value::Str("2".to_string())))
+        }
+
+        fn valid(&self) -> Entry {
+            self.next().try_build(|e| Ok(sign(e, &self.key2))).unwrap()
+        }
+
+        fn mutate(&self, m: Mutation) -> Entry {
+            let resign = |b: entry::Builder| b.try_build(|e| Ok(sign(e, &self.key2))).unwrap();
+            // change the entry after it was signed
+            let tamper = |f: &dyn Fn(&mut Entry)| {
+                let mut e = self.valid();
+                f(&mut e);
+                e.cached_cid = entry::CidCache::default();
+                e
+            };
+            let seqno = self.head_entry().seqno();
+            match m {
+                Mutation::SkippedSeqno => resign(self.next().with_seqno(seqno + 2)),
+                Mutation::WrongPrev => resign(self.next().with_prev(&self.log.foot)),
+                Mutation::NullPrev => resign(self.next().with_prev(&Cid::null())),
+                Mutation::StaleKey => self.next().try_build(|e| Ok(sign(e, &self.key1))).unwrap(),
+                Mutation::TamperedProof => tamper(&|e| {
+                    let last = e.proof.len() - 1;
+                    e.proof[last] ^= 0xff;
+                }),
+                Mutation::TruncatedProof => tamper(&|e| {
+                    e.proof.pop();
+                }),
+                Mutation::EmptyProof => tamper(&|e| e.proof.clear()),
+                Mutation::ReorderedOps => tamper(&|e| e.ops.reverse()),
+                Mutation::TamperedOp => tamper(&|e| {
+                    e.ops[0] = Op::Update("/a".try_into().unwrap(), Value::Str("9".to_string()))
+                }),
+                Mutation::DroppedOp => tamper(&|e| {
+                    e.ops.pop();
+                }),
+            }
+        }
+
+        // the outcome of appending the valid entry, or the mutated one
+        fn expected(&self, m: Option<Mutation>) -> Outcome {
+            match m {
+                None => Outcome::Appended,
+                Some(Mutation::WrongPrev | Mutation::NullPrev) => Outcome::StaleHead,
+                Some(Mutation::SkippedSeqno) => Outcome::BadSeqno,
+                // the rest aren't signed over by the current /pubkey
+                Some(_) => Outcome::LockFailed,
+            }
+        }
+
+        // append the entry to a copy of the log and classify the result
+        fn transition(&self, entry: &Entry) -> (Log, Outcome, Option<Error>) {
+            let mut log = self.log.clone();
+            let err = match log.try_append(entry) {
+                Ok(()) => return (log, Outcome::Appended, None),
+                Err(e) => e,
+            };
+            let outcome = match &err {
+                Error::Log(LogError::StaleHead(_)) => Outcome::StaleHead,
+                Error::Verify(ve)
+                    if ve.phase == VerifyPhase::Structure
+                        && matches!(*ve.source, Error::Log(LogError::InvalidSeqno)) =>
+                {
+                    Outcome::BadSeqno
+                }
+                Error::Verify(ve)
+                    if ve.phase == VerifyPhase::Lock
+                        && ve.seqno == 2
+                        && ve.entry_cid == entry.cid()
+                        && matches!(*ve.source, Error::Log(LogError::VerifyFailed(_))) =>
+                {
+                    Outcome::LockFailed
+                }
+                // rejected for a reason the model doesn't expect
+                _ => Outcome::Rejected,
+            };
+            (log, outcome, Some(err))
+        }
+    }
+
+    #[test]
+    fn test_incremental_append() {
+        let mut model = Model::new();
+        let e2 = model.valid();
+        model.log.try_append(&e2).unwrap();
+        let cached = |log: &Log| log.verified.0.as_ref().map(|s| s.head.clone());
+        assert_eq!(Some(e2.cid()), cached(&model.log));
+
+        // the next entry is verified against the cached state
+        let e3 = model.valid();
+        model.log.try_append(&e3).unwrap();
+        assert_eq!(Some(e3.cid()), cached(&model.log));
+
+        // a rejected entry leaves the log and the cached state alone
+        let bad = model.mutate(Mutation::TamperedProof);
+        assert!(model.log.try_append(&bad).is_err());
+        assert_eq!(model.log.head(), &e3.cid());
+        assert_eq!(Some(e3.cid()), cached(&model.log));
+
+        // the cached state matches verifying every entry
+        model.log.force_full_verify().unwrap();
+        assert_eq!(Some(e3.cid()), cached(&model.log));
+    }
+
+    #[test]
+    fn test_apply_sync_batch() {
+        let mut model = Model::new();
+        let mut replica = model.log.clone();
+        let e2 = model.valid();
+        model.log.try_append(&e2).unwrap();
+        let e3 = model.valid();
+        model.log.try_append(&e3).unwrap();
+
+        // the replica announces its head and gets the entries it lacks
+        let resp = model.log.respond(&replica.have()).unwrap().unwrap();
+        let batch = match resp.payload {
+            SyncPayload::Entries(entries) => entries,
+            _ => panic!("expected an entries message"),
+        };
+        assert_eq!(vec![e2, e3.clone()], batch);
+        assert_eq!(2, replica.apply_sync_batch(&batch).unwrap());
+        assert_eq!(&e3.cid(), replica.head());
+        // applying the batch again does nothing
+        assert_eq!(0, replica.apply_sync_batch(&batch).unwrap());
+
+        // a batch with a bad entry isn't applied
+        let bad = model.mutate(Mutation::TamperedProof);
+        assert!(replica.apply_sync_batch(&[bad]).is_err());
+        assert_eq!(&e3.cid(), replica.head());
+    }
+
+    #[test]
+    fn test_simulate() {
+        let mut model = Model::new();
+        let head = model.log.head().clone();
+        let e2 = model.valid();
+
+        // simulating doesn't change the log
+        let kvp = model.log.simulate(&e2).unwrap();
+        assert_eq!(&head, model.log.head());
+        assert_eq!(Some(&Value::Str("1".to_string())), kvp.get_value(&"/a".try_into().unwrap()));
+
+        // the simulated state is the state after appending the entry
+        model.log.try_append(&e2).unwrap();
+        let (_, _, appended) = model.log.verify().last().unwrap().unwrap();
+        assert_eq!(
+            kvp.iter().collect::<Vec<_>>(),
+            appended.iter().collect::<Vec<_>>()
+        );
+
+        // script failures and stale entries are caught
+        let bad = model.mutate(Mutation::TamperedProof);
+        assert!(matches!(model.log.simulate(&bad), Err(Error::Verify(_))));
+        assert!(matches!(
+            model.log.simulate(&e2),
+            Err(Error::Log(LogError::StaleHead(_)))
+        ));
+    }
+
+    #[test]
+    fn test_verify_all() {
+        let mut model = Model::new();
+        let e2 = model.valid();
+        model.log.try_append(&e2).unwrap();
+
+        let report = model.log.verify_all().unwrap();
+        assert_eq!(2, report.entries);
+        assert_eq!(vec![0, 1], report.counts.keys().copied().collect::<Vec<_>>());
+        assert_eq!(&report.head, model.log.head());
+        assert_eq!(e2.locks, report.locks);
+        let b = "/b".try_into().unwrap();
+        assert_eq!(Some(&Value::Str("2".to_string())), report.kvp.get_value(&b));
+        assert!(report.phase_times.contains_key(&VerifyPhase::Lock));
+
+        // the first failure is reported with the entry and phase
+        let bad = model.mutate(Mutation::TamperedProof);
+        model.log.entries.insert(bad.cid(), bad.clone());
+        model.log.set_head_checked(&bad.cid()).unwrap();
+        let e = model.log.verify_all().unwrap_err();
+        assert_eq!(2, e.seqno);
+        assert_eq!(bad.cid(), e.entry_cid);
+    }
+
+    #[test]
+    fn test_verify_disclosure() {
+        let mut model = Model::new();
+        let key = Key::try_from("/email").unwrap();
+        let d = Disclosure::new(&Value::Str("zig@example.com".to_string()));
+        let e2 = model
+            .next()
+            .add_op(&d.commit_op(&key).unwrap())
+            .try_build(|e| Ok(sign(e, &model.key2)))
+            .unwrap();
+        model.log.try_append(&e2).unwrap();
+
+        model.log.verify_disclosure(&key, &d).unwrap();
+        let other = Disclosure::new(&d.value);
+        assert!(model.log.verify_disclosure(&key, &other).is_err());
+        assert!(matches!(
+            model.log.verify_disclosure(&"/missing".try_into().unwrap(), &d),
+            Err(Error::Kvp(KvpError::KeyNotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn test_append_all() {
+        let mut model = Model::new();
+        let head = model.log.head().clone();
+        let e2 = model.valid();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&model.unlock)
+            .try_build(|e| Ok(sign(e, &model.key2)))
+            .unwrap();
+        // the lipmaa link of seqno 4 is the entry with seqno 1
+        let bad = entry::Builder::from(&e3)
+            .with_unlock(&model.unlock)
+            .with_lipmaa(&head)
+            .try_build(|e| Ok(sign(e, &model.key1)))
+            .unwrap();
+
+        // entries that don't link to each other are rejected
+        assert!(matches!(
+            model.log.try_append_all(&[e3.clone(), e2.clone()]),
+            Err(Error::Log(LogError::StaleHead(_)))
+        ));
+
+        // one bad entry rolls back the whole batch
+        match model.log.try_append_all(&[e2.clone(), e3.clone(), bad]) {
+            Err(Error::Verify(e)) => assert_eq!(4, e.seqno),
+            _ => panic!("expected a verify error"),
+        }
+        assert_eq!(&head, model.log.head());
+        assert_eq!(2, model.log.entries.len());
+
+        model.log.try_append_all(&[e2, e3.clone()]).unwrap();
+        assert_eq!(&e3.cid(), model.log.head());
+        assert_eq!(4, model.log.entries.len());
+    }
+
+    #[test]
+    fn test_rejected_transitions() {
+        let model = Model::new();
+        let valid = model.valid();
+        let (log, outcome, err) = model.transition(&valid);
+        assert_eq!(model.expected(None), outcome, "{:?}", err);
+        assert_eq!(&valid.cid(), log.head());
+
+        for m in MUTATIONS {
+            let entry = model.mutate(*m);
+            let (log, outcome, err) = model.transition(&entry);
+            assert_eq!(model.expected(Some(*m)), outcome, "{:?} failed with {:?}", m, err);
+            // a rejected entry leaves the log alone
+            assert_eq!(model.log.head(), log.head());
+            assert_eq!(model.log.entries.len(), log.entries.len());
+        }
+    }
 }
 
 /*
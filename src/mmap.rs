@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Read-only access to large logs through a memory map. Opening the log only
+//! walks the encoded bytes to find where each entry is, using [`EntryRef`]
+//! so the ops, scripts and proofs aren't decoded. Entries are decoded when
+//! they are asked for and the whole log is only materialized, e.g. to verify
+//! it, with [`MmapLog::to_log`].
+use crate::{
+    error::LogError,
+    log::{ScriptTable, CHECKPOINT_LOG_VERSION, SCRIPT_TABLE_LOG_VERSION, SIGIL},
+    Checkpoint, Entry, EntryRef, Error, Log, Script,
+};
+use memmap2::Mmap;
+use multicid::{Cid, Vlad};
+use multicodec::Codec;
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+use std::{collections::BTreeMap, fs::File, ops::Range, path::Path};
+
+/// A Log whose entries are decoded on demand from a memory map
+pub struct MmapLog {
+    map: Mmap,
+    version: u64,
+    vlad: Vlad,
+    first_lock: Script,
+    foot: Cid,
+    head: Cid,
+    // the script table of a version 3 log
+    scripts: Option<ScriptTable>,
+    // the seqno and the location of the encoded entry by cid
+    entries: BTreeMap<Cid, (u64, Range<usize>)>,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl MmapLog {
+    /// map the file and find the entries in it
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|e| LogError::ReadFailed(e.to_string()))?;
+        // the file must not be changed while it is mapped
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| LogError::ReadFailed(e.to_string()))?;
+        Self::index(map)
+    }
+
+    fn index(map: Mmap) -> Result<Self, Error> {
+        let bytes: &[u8] = &map;
+        let offset = |ptr: &[u8]| bytes.len() - ptr.len();
+        // decode the header the same way the log is decoded
+        let (sigil, ptr) = Codec::try_decode_from(bytes)?;
+        if sigil != SIGIL {
+            return Err(LogError::MissingSigil.into());
+        }
+        let (version, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let version = version.to_inner();
+        let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+        let (first_lock, ptr) = Script::try_decode_from(ptr)?;
+        let (foot, ptr) = Cid::try_decode_from(ptr)?;
+        let (head, ptr) = Cid::try_decode_from(ptr)?;
+        let (scripts, ptr) = if version >= SCRIPT_TABLE_LOG_VERSION {
+            let (scripts, ptr) = ScriptTable::try_decode_from(ptr)?;
+            (Some(scripts), ptr)
+        } else {
+            (None, ptr)
+        };
+
+        // skip over the entries, recording where they are
+        let (num_entries, mut ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..*num_entries {
+            let (cid, p) = Cid::try_decode_from(ptr)?;
+            let start = offset(p);
+            let (seqno, p) = match &scripts {
+                // entries stored through a script table must be rebuilt
+                Some(scripts) => {
+                    let (entry, p) = scripts.decode_entry(&cid, p)?;
+                    (entry.seqno(), p)
+                }
+                None => {
+                    let (entry, p) = EntryRef::try_decode_from(p)?;
+                    (entry.seqno(), p)
+                }
+            };
+            if entries.insert(cid.clone(), (seqno, start..offset(p))).is_some() {
+                return Err(LogError::DuplicateEntry(cid).into());
+            }
+            ptr = p;
+        }
+
+        let checkpoint = if version >= CHECKPOINT_LOG_VERSION {
+            let (num_checkpoints, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+            match *num_checkpoints {
+                0 => None,
+                1 => Some(Checkpoint::try_decode_from(ptr)?.0),
+                _ => return Err(LogError::CheckpointMismatch.into()),
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            version,
+            vlad,
+            first_lock,
+            foot,
+            head,
+            scripts,
+            entries,
+            checkpoint,
+            map,
+        })
+    }
+
+    /// get the version of the log format
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// get the vlad of the log
+    pub fn vlad(&self) -> &Vlad {
+        &self.vlad
+    }
+
+    /// get the lock script for the first entry
+    pub fn first_lock(&self) -> &Script {
+        &self.first_lock
+    }
+
+    /// get the cid of the foot entry
+    pub fn foot(&self) -> &Cid {
+        &self.foot
+    }
+
+    /// get the cid of the head entry
+    pub fn head(&self) -> &Cid {
+        &self.head
+    }
+
+    /// get the checkpoint the log was compacted to, if any
+    pub fn checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    /// get the number of entries in the log
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// check if the log has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// get the cids of the entries in seqno order
+    pub fn cids(&self) -> Vec<Cid> {
+        let mut cids: Vec<(u64, &Cid)> = self.entries.iter().map(|(c, (s, _))| (*s, c)).collect();
+        cids.sort();
+        cids.into_iter().map(|(_, c)| c.clone()).collect()
+    }
+
+    /// get a borrowed view of the entry with the cid without decoding its
+    /// ops, scripts and proof. the entries of a log with a script table
+    /// aren't stored in their native encoding so they must be decoded with
+    /// [`MmapLog::get`].
+    pub fn entry_ref(&self, cid: &Cid) -> Result<Option<EntryRef<'_>>, Error> {
+        if self.scripts.is_some() {
+            return Err(LogError::InvalidVersion(self.version).into());
+        }
+        let range = match self.entries.get(cid) {
+            Some((_, range)) => range.clone(),
+            None => return Ok(None),
+        };
+        let entry = EntryRef::try_from(&self.map[range])?;
+        if entry.cid() != *cid {
+            return Err(LogError::EntryCidMismatch.into());
+        }
+        Ok(Some(entry))
+    }
+
+    /// decode the entry with the cid
+    pub fn get(&self, cid: &Cid) -> Result<Option<Entry>, Error> {
+        let range = match self.entries.get(cid) {
+            Some((_, range)) => range.clone(),
+            None => return Ok(None),
+        };
+        let entry = match &self.scripts {
+            Some(scripts) => scripts.decode_entry(cid, &self.map[range])?.0,
+            None => Entry::try_from(&self.map[range])?,
+        };
+        if entry.cid() != *cid {
+            return Err(LogError::EntryCidMismatch.into());
+        }
+        Ok(Some(entry))
+    }
+
+    /// decode the whole log so it can be verified
+    pub fn to_log(&self) -> Result<Log, Error> {
+        Log::try_from(&self.map[..])
+    }
+}
+
+impl Log {
+    /// open the encoded log in the file through a read-only memory map. the
+    /// entries are only decoded when they are asked for.
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<MmapLog, Error> {
+        MmapLog::open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Op, Value};
+
+    #[test]
+    fn test_open_mmap() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Data(vec![0; 1024])))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Delete("/foo".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let builder = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2);
+
+        for log in [builder.try_build().unwrap(), builder.with_script_table().try_build().unwrap()] {
+            let path = std::env::temp_dir().join(format!("plog-{}.log", rand::random::<u64>()));
+            std::fs::write(&path, Vec::<u8>::from(log.clone())).unwrap();
+
+            let mapped = Log::open_mmap(&path).unwrap();
+            assert_eq!(2, mapped.len());
+            assert_eq!(&log.head, mapped.head());
+            assert_eq!(vec![e1.cid(), e2.cid()], mapped.cids());
+            assert_eq!(Some(e2.clone()), mapped.get(&e2.cid()).unwrap());
+            assert_eq!(None, mapped.get(&Cid::default()).unwrap());
+            if log.version < SCRIPT_TABLE_LOG_VERSION {
+                let e = mapped.entry_ref(&e1.cid()).unwrap().unwrap();
+                assert_eq!(e1, e.to_owned().unwrap());
+            }
+            assert_eq!(log, mapped.to_log().unwrap());
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Deterministic stand-ins for real signatures and lock scripts so that tests
+//! can exercise building, appending and verifying logs without keys, wasm
+//! files or the wacc runtime.
+use crate::{
+    engine::{LOCK_FUNC, UNLOCK_FUNC},
+    Entry, Error, Key, Script, ScriptEngine, Stk,
+};
+use multicodec::Codec;
+use multihash::mh;
+use wacc::{vm, Pairs, Stack};
+
+/// A MockSigner generates deterministic proofs by hashing a secret with the
+/// entry. Use it as the `gen_proof` closure: `.try_build(|e| signer.sign(e))`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MockSigner {
+    secret: Vec<u8>,
+}
+
+impl MockSigner {
+    /// create a signer with the given secret
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// generate the proof for the entry
+    pub fn sign(&self, entry: &Entry) -> Result<Vec<u8>, Error> {
+        let mut e = entry.clone();
+        e.proof = Vec::default();
+        self.sign_bytes(&Vec::<u8>::from(e))
+    }
+
+    // hash the secret and the entry bytes without the proof
+    fn sign_bytes(&self, entry: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut b = self.secret.clone();
+        b.extend_from_slice(entry);
+        Ok(mh::Builder::new_from_bytes(Codec::Sha2256, &b)?
+            .try_build()?
+            .into())
+    }
+}
+
+/// the outcome of running a mock lock script
+enum Outcome {
+    Valid,
+    Invalid,
+    Signed(MockSigner),
+    Custom(Box<dyn Fn(&Script, &dyn Pairs) -> bool>),
+}
+
+/// A MockLock is a ScriptEngine that doesn't run the scripts. The unlock
+/// script pushes the proof on the stack and the lock script succeeds or
+/// fails according to the outcome the engine was created with.
+pub struct MockLock {
+    outcome: Outcome,
+}
+
+impl MockLock {
+    /// every lock script succeeds
+    pub fn always_valid() -> Self {
+        Self {
+            outcome: Outcome::Valid,
+        }
+    }
+
+    /// every lock script fails
+    pub fn always_invalid() -> Self {
+        Self {
+            outcome: Outcome::Invalid,
+        }
+    }
+
+    /// lock scripts succeed if the entry proof was made by the signer
+    pub fn signed_by(signer: &MockSigner) -> Self {
+        Self {
+            outcome: Outcome::Signed(signer.clone()),
+        }
+    }
+
+    /// lock scripts succeed if the closure returns true when called with the
+    /// lock script and the proposed entry
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn(&Script, &dyn Pairs) -> bool + 'static,
+    {
+        Self {
+            outcome: Outcome::Custom(Box::new(f)),
+        }
+    }
+
+    /// get a placeholder script for the given path that doesn't need any wasm
+    pub fn script(path: &Key) -> Script {
+        Script::Bin(path.clone(), b"mock".to_vec())
+    }
+
+    fn check(&self, script: &Script, proposed: &dyn Pairs) -> Result<bool, Error> {
+        Ok(match &self.outcome {
+            Outcome::Valid => true,
+            Outcome::Invalid => false,
+            Outcome::Signed(signer) => match (proposed.get("/entry/"), proposed.get("/entry/proof")) {
+                (
+                    Some(vm::Value::Bin { data: entry, .. }),
+                    Some(vm::Value::Bin { data: proof, .. }),
+                ) => signer.sign_bytes(&entry)? == proof,
+                _ => false,
+            },
+            Outcome::Custom(f) => f(script, proposed),
+        })
+    }
+}
+
+impl ScriptEngine for MockLock {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        _current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        _context: &Key,
+    ) -> Result<(), Error> {
+        match func {
+            UNLOCK_FUNC => {
+                if let Some(proof) = proposed.get("/entry/proof") {
+                    pstack.push(proof);
+                }
+            }
+            LOCK_FUNC => {
+                if self.check(script, proposed)? {
+                    rstack.push(vm::Value::Success(0));
+                } else {
+                    rstack.push(vm::Value::Failure("mock lock failed".to_string()));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, observer::NoopObserver, pipeline::Pipeline, Log, VerifyConfig};
+    use multicid::Vlad;
+
+    fn signed_log(signer: &MockSigner) -> Log {
+        let root = Key::default();
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&MockLock::script(&root))
+            .add_lock(&MockLock::script(&root))
+            .try_build(|e| signer.sign(e))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&MockLock::script(&root))
+            .try_build(|e| signer.sign(e))
+            .unwrap();
+        log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&MockLock::script(&root))
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_mock_lock() {
+        let signer = MockSigner::new(b"secret");
+        let log = signed_log(&signer);
+
+        let results: Vec<_> = log.verify_with_engine(&MockLock::signed_by(&signer)).collect();
+        assert_eq!(2, results.len());
+        assert!(results.iter().all(|r| r.is_ok()));
+
+        assert!(log.verify_with_engine(&MockLock::always_valid()).all(|r| r.is_ok()));
+        assert!(log.verify_with_engine(&MockLock::always_invalid()).any(|r| r.is_err()));
+
+        // a proof from another signer is rejected
+        let other = MockSigner::new(b"other");
+        assert!(log.verify_with_engine(&MockLock::signed_by(&other)).any(|r| r.is_err()));
+
+        // programmable outcomes
+        let engine = MockLock::from_fn(|_, proposed| {
+            matches!(proposed.get("/entry/seqno"), Some(vm::Value::Bin { data, .. }) if data == vec![0])
+        });
+        let results: Vec<_> = log.verify_with_engine(&engine).collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_append_with_engine() {
+        let signer = MockSigner::new(b"secret");
+        let mut log = signed_log(&signer);
+        let next = |log: &Log| {
+            entry::Builder::from(log.entries.get(log.head()).unwrap())
+                .with_unlock(&MockLock::script(&Key::default()))
+                .try_build(|e| signer.sign(e))
+                .unwrap()
+        };
+
+        // the mock scripts only run in the mock engine
+        let e3 = next(&log);
+        assert!(log.try_append(&e3).is_err());
+        let engine = MockLock::signed_by(&signer);
+        let pipeline = Pipeline::default();
+        let config = VerifyConfig::default();
+        log.try_append_all_with_config(&[e3.clone()], &pipeline, &engine, &NoopObserver, config)
+            .unwrap();
+        assert_eq!(&e3.cid(), log.head());
+
+        // and the next entry is verified with it on top of the head state
+        let e4 = next(&log);
+        assert!(log.simulate(&e4).is_err());
+        let config = VerifyConfig::default();
+        log.simulate_with_config(&e4, &pipeline, &engine, config.clone())
+            .unwrap();
+        log.try_append_all_with_config(&[e4], &pipeline, &engine, &NoopObserver, config)
+            .unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{Entry, Error, Kvp, Script, Stk};
+
+/// The structured events emitted while verifying a Log
+#[derive(Debug)]
+pub enum VerifyEvent<'a> {
+    /// verification of the entry started
+    EntryStarted {
+        /// the entry
+        entry: &'a Entry,
+    },
+    /// the unlock script in the entry ran and set up the stacks
+    UnlockRan {
+        /// the entry
+        entry: &'a Entry,
+        /// the parameter stack after the unlock script ran
+        pstack: &'a Stk,
+        /// the return stack after the unlock script ran
+        rstack: &'a Stk,
+        /// the lines logged by the unlock script
+        log: &'a [String],
+    },
+    /// a lock script governing the entry was evaluated
+    LockEvaluated {
+        /// the entry
+        entry: &'a Entry,
+        /// the lock script
+        lock: &'a Script,
+        /// the return stack after the lock script ran
+        rstack: &'a Stk,
+        /// true if the lock script succeeded
+        succeeded: bool,
+        /// the lines logged by the lock script
+        log: &'a [String],
+    },
+    /// a lock script governing the entry succeeded
+    LockMatched {
+        /// the entry
+        entry: &'a Entry,
+        /// the lock script
+        lock: &'a Script,
+        /// the check count from the lock script
+        count: usize,
+    },
+    /// the entry's mutation ops were applied to the kvp
+    OpsApplied {
+        /// the entry
+        entry: &'a Entry,
+        /// the kvp state after the ops were applied
+        kvp: &'a Kvp,
+    },
+    /// the entry passed every stage
+    EntryVerified {
+        /// the entry
+        entry: &'a Entry,
+        /// the check count from the lock script that succeeded
+        count: usize,
+        /// the fuel consumed by the scripts run for the entry
+        fuel: u64,
+        /// the lines logged by all of the scripts run for the entry
+        log: &'a [String],
+    },
+    /// the entry failed a stage
+    EntryFailed {
+        /// the entry
+        entry: &'a Entry,
+        /// the name of the stage that failed
+        stage: &'a str,
+        /// the error from the stage
+        error: &'a Error,
+        /// the lines logged by all of the scripts run for the entry
+        log: &'a [String],
+    },
+}
+
+/// An Observer is called with the events emitted while verifying a Log. It
+/// can be used to drive progress reporting, collect metrics or debug failing
+/// scripts.
+pub trait Observer {
+    /// called for each verification event
+    fn observe(&self, event: &VerifyEvent<'_>);
+}
+
+/// An Observer that ignores every event
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {
+    fn observe(&self, _event: &VerifyEvent<'_>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, error::LogError, log, pipeline, Op, Pipeline, Value, WaccEngine};
+    use multicid::Vlad;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: RefCell<Vec<String>>,
+    }
+
+    impl Observer for Recorder {
+        fn observe(&self, event: &VerifyEvent<'_>) {
+            let s = match event {
+                VerifyEvent::EntryStarted { entry } => format!("started {}", entry.seqno()),
+                VerifyEvent::UnlockRan { .. } => "unlock".to_string(),
+                VerifyEvent::LockEvaluated { .. } => "lock".to_string(),
+                VerifyEvent::LockMatched { .. } => "matched".to_string(),
+                VerifyEvent::OpsApplied { kvp, .. } => format!("applied {}", kvp.len()),
+                VerifyEvent::EntryVerified { entry, .. } => format!("verified {}", entry.seqno()),
+                VerifyEvent::EntryFailed { stage, .. } => format!("failed {}", stage),
+            };
+            self.events.borrow_mut().push(s);
+        }
+    }
+
+    struct Fail;
+
+    impl pipeline::Stage for Fail {
+        fn name(&self) -> &str {
+            "fail"
+        }
+
+        fn run(&self, v: &mut pipeline::Verification<'_, '_>) -> Result<(), Error> {
+            if v.entry.seqno() > 0 {
+                return Err(LogError::VerifyFailed("fail".to_string()).into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("foo".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        let recorder = Recorder::default();
+        let p = Pipeline::new()
+            .with_stage(pipeline::Structure)
+            .with_stage(pipeline::Apply)
+            .with_stage(Fail);
+        let results: Vec<_> = log
+            .verify_with_observer(p, &WaccEngine, &recorder)
+            .collect();
+        assert_eq!(2, results.len());
+        assert_eq!(
+            vec![
+                "started 0",
+                "applied 1",
+                "verified 0",
+                "started 1",
+                "applied 1",
+                "failed fail"
+            ],
+            *recorder.events.borrow()
+        );
+    }
+}
@@ -1,7 +1,11 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{error::OpError, Error, Key, Value};
+use crate::{
+    error::{decode_field, OpError},
+    Error, Key, Kvp, Value,
+};
 use core::fmt;
 use multitrait::{EncodeInto, TryDecodeFrom};
+use std::collections::BTreeMap;
 
 /// the identifiers for the operations performed on the namespace in each entry
 #[repr(u8)]
@@ -119,10 +123,15 @@ pub enum Op {
 impl Op {
     /// get the key in the op 
     pub fn path(&self) -> Key {
+        self.path_ref().clone()
+    }
+
+    /// get a reference to the key in the op
+    pub fn path_ref(&self) -> &Key {
         match self {
-            Self::Noop(p) => p.clone(),
-            Self::Delete(p) => p.clone(),
-            Self::Update(p, _) => p.clone()
+            Self::Noop(p) => p,
+            Self::Delete(p) => p,
+            Self::Update(p, _) => p
         }
     }
 }
@@ -174,19 +183,19 @@ impl<'a> TryDecodeFrom<'a> for Op {
 
     fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
         // decode the operation id
-        let (id, ptr) = OpId::try_decode_from(bytes)?;
+        let (id, ptr) = decode_field(bytes, bytes, "id", OpId::try_decode_from)?;
         let (v, ptr) = match id {
             OpId::Noop => {
-                let (key, ptr) = Key::try_decode_from(ptr)?;
+                let (key, ptr) = decode_field(bytes, ptr, "key", Key::try_decode_from)?;
                 (Self::Noop(key), ptr)
             }
             OpId::Delete => {
-                let (key, ptr) = Key::try_decode_from(ptr)?;
+                let (key, ptr) = decode_field(bytes, ptr, "key", Key::try_decode_from)?;
                 (Self::Delete(key), ptr)
             }
             OpId::Update => {
-                let (key, ptr) = Key::try_decode_from(ptr)?;
-                let (value, ptr) = Value::try_decode_from(ptr)?;
+                let (key, ptr) = decode_field(bytes, ptr, "key", Key::try_decode_from)?;
+                let (value, ptr) = decode_field(bytes, ptr, "value", Value::try_decode_from)?;
                 (Self::Update(key, value), ptr)
             }
         };
@@ -204,3 +213,67 @@ impl fmt::Debug for Op {
         }
     }
 }
+
+/// get the ops that change the current state into the desired state: an
+/// Update for every key that is missing or has a different value and a
+/// Delete for every key that isn't in the desired state. the ops are in key
+/// order and keys that already have the desired value get no op.
+pub fn diff(current: &Kvp, desired: &BTreeMap<Key, Value>) -> Vec<Op> {
+    let deletes = current
+        .iter()
+        .filter(|(key, _)| !desired.contains_key(key))
+        .map(|(key, _)| Op::Delete(key.clone()));
+    let updates = desired
+        .iter()
+        .filter(|(key, value)| current.get_value(key) != Some(value))
+        .map(|(key, value)| Op::Update(key.clone(), value.clone()));
+    let mut ops: Vec<Op> = deletes.chain(updates).collect();
+    ops.sort_by(|a, b| a.path_ref().cmp(b.path_ref()));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let key = |s: &str| -> Key { s.try_into().unwrap() };
+        let current = Kvp::from(BTreeMap::from([
+            (key("/a"), Value::Str("a".to_string())),
+            (key("/b"), Value::Str("b".to_string())),
+            (key("/c"), Value::Str("c".to_string())),
+        ]));
+        let desired = BTreeMap::from([
+            (key("/a"), Value::Str("a".to_string())),
+            (key("/b"), Value::Str("bb".to_string())),
+            (key("/d"), Value::Data(vec![1, 2, 3])),
+        ]);
+        let ops = diff(&current, &desired);
+        assert_eq!(
+            vec![
+                Op::Update(key("/b"), Value::Str("bb".to_string())),
+                Op::Delete(key("/c")),
+                Op::Update(key("/d"), Value::Data(vec![1, 2, 3])),
+            ],
+            ops
+        );
+
+        // applying the ops gives the desired state
+        let mut state: BTreeMap<Key, Value> =
+            current.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        for op in ops {
+            match op {
+                Op::Update(k, v) => {
+                    state.insert(k, v);
+                }
+                Op::Delete(k) => {
+                    state.remove(&k);
+                }
+                Op::Noop(_) => {}
+            }
+        }
+        assert_eq!(desired, state);
+        assert!(diff(&Kvp::from(desired.clone()), &desired).is_empty());
+    }
+}
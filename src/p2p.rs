@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Replication of logs over libp2p. [`SyncBehaviour`] announces the head of
+//! a log on a gossipsub topic named after its vlad and serves the
+//! [`SyncMessage`] requests of other replicas over a request-response
+//! protocol. A replica that hears about a head it doesn't have sends its
+//! own have message to the announcer and applies the entries it gets back,
+//! so replicas converge without a central server.
+use crate::{Error, Log, SyncMessage, SyncPayload};
+use ::serde::{Deserialize, Serialize};
+use libp2p::{
+    gossipsub::{self, IdentTopic, MessageAuthenticity},
+    identity::Keypair,
+    request_response::{self, cbor, ProtocolSupport},
+    swarm::NetworkBehaviour,
+    PeerId, StreamProtocol,
+};
+use multibase::Base;
+use multicid::Vlad;
+
+/// the request-response protocol the sync messages are exchanged over
+pub const SYNC_PROTOCOL: StreamProtocol = StreamProtocol::new("/plog/sync/1.0.0");
+
+fn p2p_err<S: ToString>(s: S) -> Error {
+    Error::P2p(s.to_string())
+}
+
+/// get the gossipsub topic the heads of the log with the vlad are announced on
+pub fn topic(vlad: &Vlad) -> IdentTopic {
+    let vlad: Vec<u8> = vlad.clone().into();
+    IdentTopic::new(format!("/plog/{}", multibase::encode(Base::Base32Lower, vlad)))
+}
+
+/// An encoded [`SyncMessage`] sent over the sync protocol. An empty frame is
+/// the response when there is nothing to send back.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SyncFrame(pub Vec<u8>);
+
+impl From<SyncMessage> for SyncFrame {
+    fn from(msg: SyncMessage) -> Self {
+        Self(msg.into())
+    }
+}
+
+/// The libp2p behaviour that replicates logs
+#[allow(missing_docs)]
+#[derive(NetworkBehaviour)]
+pub struct SyncBehaviour {
+    pub gossipsub: gossipsub::Behaviour,
+    pub sync: cbor::Behaviour<SyncFrame, SyncFrame>,
+}
+
+impl SyncBehaviour {
+    /// create the behaviour for the peer with the keypair
+    pub fn new(keypair: &Keypair) -> Result<Self, Error> {
+        let gossipsub = gossipsub::Behaviour::new(
+            MessageAuthenticity::Signed(keypair.clone()),
+            gossipsub::Config::default(),
+        )
+        .map_err(p2p_err)?;
+        let sync = cbor::Behaviour::new(
+            [(SYNC_PROTOCOL, ProtocolSupport::Full)],
+            request_response::Config::default(),
+        );
+        Ok(Self { gossipsub, sync })
+    }
+
+    /// subscribe to the head announcements of the log
+    pub fn subscribe(&mut self, log: &Log) -> Result<(), Error> {
+        self.gossipsub
+            .subscribe(&topic(&log.vlad))
+            .map_err(p2p_err)?;
+        Ok(())
+    }
+
+    /// announce the head of the log, e.g. after appending to it
+    pub fn announce(&mut self, log: &Log) -> Result<(), Error> {
+        let have: Vec<u8> = log.have().into();
+        self.gossipsub
+            .publish(topic(&log.vlad), have)
+            .map_err(p2p_err)?;
+        Ok(())
+    }
+
+    /// send a sync message to the peer
+    pub fn request(&mut self, peer: &PeerId, msg: SyncMessage) -> request_response::OutboundRequestId {
+        self.sync.send_request(peer, msg.into())
+    }
+
+    /// handle an event from the behaviour for the log and return the number
+    /// of entries appended to it. heads we don't have are fetched from the
+    /// peer that announced them, requests are answered from the log and
+    /// entry batches are verified and appended.
+    pub fn handle_event(&mut self, log: &mut Log, event: SyncBehaviourEvent) -> Result<usize, Error> {
+        match event {
+            SyncBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message,
+                ..
+            }) => {
+                let msg = SyncMessage::try_from(message.data.as_slice())?;
+                if msg.vlad != log.vlad {
+                    return Ok(0);
+                }
+                if let SyncPayload::Have(cids) = &msg.payload {
+                    if cids.iter().any(|c| !log.entries.contains_key(c)) {
+                        self.request(&propagation_source, log.have());
+                    }
+                }
+                Ok(0)
+            }
+            SyncBehaviourEvent::Sync(request_response::Event::Message { message, .. }) => {
+                match message {
+                    request_response::Message::Request {
+                        request, channel, ..
+                    } => {
+                        let msg = SyncMessage::try_from(request.0.as_slice())?;
+                        let resp = match log.respond(&msg)? {
+                            Some(resp) => resp.into(),
+                            None => SyncFrame::default(),
+                        };
+                        self.sync
+                            .send_response(channel, resp)
+                            .map_err(|_| p2p_err("the sync response couldn't be sent"))?;
+                        Ok(0)
+                    }
+                    request_response::Message::Response { response, .. } => {
+                        if response.0.is_empty() {
+                            return Ok(0);
+                        }
+                        let msg = SyncMessage::try_from(response.0.as_slice())?;
+                        match msg.payload {
+                            SyncPayload::Entries(entries) if msg.vlad == log.vlad => {
+                                log.apply_sync_batch(&entries)
+                            }
+                            _ => Ok(0),
+                        }
+                    }
+                }
+            }
+            _ => Ok(0),
+        }
+    }
+}
@@ -1,20 +1,41 @@
 // SPDX-License-Identifier: FSL-1.1
 use crate::{error::KvpError, Entry, Error, Key, Op, Value};
-use std::{collections::BTreeMap, fmt};
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+/// the key under which an entry may commit to the root hash of the resulting
+/// kvp state. it is excluded from the root hash calculation.
+pub const STATE_ROOT_KEY: &str = "/state/root";
+
+/// domain separation prefix for the leaf hashes in the state merkle tree
+const LEAF_PREFIX: u8 = 0x00;
+
+/// domain separation prefix for the node hashes in the state merkle tree
+const NODE_PREFIX: u8 = 0x01;
+
+/// the values keys had before they were changed, None for the keys that
+/// weren't set. undoing a change only touches the keys it changed.
+type Delta = BTreeMap<Key, Option<Value>>;
 
 /// Kvp is the virtual key-value pair storage system that builds up the state
-/// encoded in provenance logs as time series of verifiable state changes.
+/// encoded in provenance logs as time series of verifiable state changes. It
+/// shares ownership of the current entry so it doesn't borrow from the log it
+/// was built from and can be kept around after verification.
 #[derive(Clone, Debug, Default)]
-pub struct Kvp<'a> {
+pub struct Kvp {
     /// the key-value pair store itself
     kvp: BTreeMap<Key, Value>,
     /// the entry so we can expose it as part of the key-vale store
-    entry: Option<&'a Entry>,
-    /// this stores state snapshots from just before applying an entry.
-    undo: Vec<(Option<&'a Entry>, BTreeMap<Key, Value>)>,
+    entry: Option<Arc<Entry>>,
+    /// this stores the reverse deltas back to the state from just before
+    /// applying an entry
+    undo: Vec<(Option<Arc<Entry>>, Delta)>,
+    /// this stores the deltas that were reverted by undo_entry
+    redo: Vec<(Option<Arc<Entry>>, Delta)>,
 }
 
-impl<'a> wacc::Pairs for Kvp<'a> {
+impl wacc::Pairs for Kvp {
     fn get(&self, key: &str) -> Option<wacc::Value> {
         let k = match Key::try_from(key) {
             Ok(k) => k,
@@ -26,10 +47,12 @@ impl<'a> wacc::Pairs for Kvp<'a> {
                     Value::Nil => Some(wacc::Value::Bin { hint: key.to_string(), data: Vec::default() }),
                     Value::Str(ref s) => Some(wacc::Value::Str { hint: key.to_string(), data: s.clone() }),
                     Value::Data(ref v) => Some(wacc::Value::Bin { hint: key.to_string(), data: v.clone() }),
+                    // sealed values are opaque to the scripts
+                    Value::Encrypted { .. } | Value::PlogRef { .. } => Some(wacc::Value::Bin { hint: key.to_string(), data: (*v).clone().into() }),
                 }
             }
             None => {
-                if let Some(entry) = self.entry {
+                if let Some(entry) = &self.entry {
                     entry.get(key)
                 } else {
                     None
@@ -48,37 +71,58 @@ impl<'a> wacc::Pairs for Kvp<'a> {
             wacc::Value::Bin { hint: _, data: ref v } => Value::Data(v.clone()),
             _ => return None
         };
-        match self.kvp.insert(k, v) {
+        match self.insert(k, v) {
             Some(Value::Nil) => Some(wacc::Value::Bin { hint: key.to_string(), data: Vec::default() }),
             Some(Value::Str(s)) => Some(wacc::Value::Str { hint: key.to_string(), data: s }),
             Some(Value::Data(v)) => Some(wacc::Value::Bin { hint: key.to_string(), data: v }),
+            Some(v @ (Value::Encrypted { .. } | Value::PlogRef { .. })) => Some(wacc::Value::Bin { hint: key.to_string(), data: v.into() }),
             None => None
         }
     }
 }
 
-impl<'a> fmt::Display for Kvp<'a> {
+impl fmt::Display for Kvp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for (k, v) in self.kvp.iter() {
             match v {
                 Value::Nil => writeln!(f, "'{}' -> nil", k)?,
                 Value::Str(s) => writeln!(f, "'{}' -> {}", k, s)?,
                 Value::Data(v) => writeln!(f, "'{}' -> data of length: {}", k, v.len())?,
+                Value::Encrypted { .. } | Value::PlogRef { .. } => writeln!(f, "'{}' -> {}", k, v)?,
             }
         }
         write!(f, "")
     }
 }
 
-impl<'a> Kvp<'a> {
+impl From<BTreeMap<Key, Value>> for Kvp {
+    fn from(kvp: BTreeMap<Key, Value>) -> Self {
+        Self {
+            kvp,
+            ..Default::default()
+        }
+    }
+}
+
+impl Kvp {
     /// get an iterator over the keys and values
     pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
         self.kvp.iter()
     }
 
+    /// get the value for the key
+    pub fn get_value(&self, key: &Key) -> Option<&Value> {
+        self.kvp.get(key)
+    }
+
+    /// get the entry values are also looked up in
+    pub fn entry(&self) -> Option<&Entry> {
+        self.entry.as_deref()
+    }
+
     /// sets the entry to look for values in as well
-    pub fn set_entry(&mut self, entry: &'a Entry) -> Result<Option<u64>, Error> {
-        match self.entry {
+    pub fn set_entry(&mut self, entry: &Entry) -> Result<Option<u64>, Error> {
+        match &self.entry {
             // if this is the first entry processed, make sure the entry's seqno is 0
             None => {
                 if entry.seqno() != 0 {
@@ -97,57 +141,185 @@ impl<'a> Kvp<'a> {
         self.snapshot();
 
         // update the entry
-        self.entry = Some(entry);
+        self.entry = Some(Arc::new(entry.clone()));
         Ok(self.seqno())
     }
 
     /// function for processing the operatios in a given entry and updating the
     /// state of the key-value pair store.
-    pub fn apply_entry_ops(&mut self, entry: &'a Entry) -> Result<(), Error> {
+    pub fn apply_entry_ops(&mut self, entry: &Entry) -> Result<(), Error> {
+        self.apply_entry_ops_reporting(entry)?;
+        Ok(())
+    }
+
+    /// apply the operations in the entry like apply_entry_ops and return the
+    /// keys they changed with the values before and after, None if the key
+    /// wasn't set. keys the ops left with the value they had aren't included.
+    pub fn apply_entry_ops_reporting(
+        &mut self,
+        entry: &Entry,
+    ) -> Result<Vec<(Key, Option<Value>, Option<Value>)>, Error> {
         // insert the op mutations and record an undo snapshot with the current
         // seqno so when this is undone, we're back in the proper state
-        self.insert_op_mutations(entry)?;
-        Ok(())
+        let prior = self.insert_op_mutations(entry)?;
+        // applying a new entry starts a new history so there is nothing to redo
+        self.redo.clear();
+        Ok(prior
+            .into_iter()
+            .filter_map(|(key, old)| {
+                let new = self.kvp.get(&key).cloned();
+                (old != new).then_some((key, old, new))
+            })
+            .collect())
     }
 
     /// get the seqno of the current entry if there is one
     pub fn seqno(&self) -> Option<u64> {
-        self.entry.map(|entry| entry.seqno)
+        self.entry.as_ref().map(|entry| entry.seqno)
     }
 
     /// function to undo the last apply_entry
     pub fn undo_entry(&mut self) -> Result<Option<u64>, Error> {
         // revert the kvp state to just before this entry was added
-        if let Some((entry, kvp)) = self.undo.pop() {
-            self.kvp = kvp;
-            self.entry = entry;
+        if let Some((entry, delta)) = self.undo.pop() {
+            let delta = self.revert(delta);
+            let current = std::mem::replace(&mut self.entry, entry);
+            self.redo.push((current, delta));
             Ok(self.seqno())
         } else {
             Err(KvpError::EmptyUndoStack.into())
         }
     }
 
-    /// function to take a state snapshot and push it onto the undo stack
+    /// function to redo the last undo_entry
+    pub fn redo_entry(&mut self) -> Result<Option<u64>, Error> {
+        // restore the kvp state to just after the undone entry was added
+        if let Some((entry, delta)) = self.redo.pop() {
+            let delta = self.revert(delta);
+            let current = std::mem::replace(&mut self.entry, entry);
+            self.undo.push((current, delta));
+            Ok(self.seqno())
+        } else {
+            Err(KvpError::EmptyRedoStack.into())
+        }
+    }
+
+    /// function to take a state snapshot and push it onto the undo stack. the
+    /// snapshot starts empty and records the old value of each key the first
+    /// time it is changed so it costs O(changed keys) instead of a full copy.
     pub(crate) fn snapshot(&mut self) {
-        self.undo.push((self.entry, self.kvp.clone()));
+        self.undo.push((self.entry.clone(), Delta::default()));
+    }
+
+    // record the value of the key in the latest snapshot before changing it
+    fn record(&mut self, key: &Key) {
+        if let Some((_, delta)) = self.undo.last_mut() {
+            if !delta.contains_key(key) {
+                delta.insert(key.clone(), self.kvp.get(key).cloned());
+            }
+        }
+    }
+
+    // set the value of the key, recording the old value for undo
+    fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        self.record(&key);
+        self.kvp.insert(key, value)
     }
 
-    /// function to add the op mutations to the kvp
-    pub(crate) fn insert_op_mutations(&mut self, entry: &Entry) -> Result<(), Error> {
+    // remove the key, recording the old value for undo
+    fn remove(&mut self, key: &Key) -> Option<Value> {
+        self.record(key);
+        self.kvp.remove(key)
+    }
+
+    // restore the values in the delta and return the delta that reverses it
+    fn revert(&mut self, delta: Delta) -> Delta {
+        delta
+            .into_iter()
+            .map(|(key, value)| {
+                let current = match value {
+                    Some(v) => self.kvp.insert(key.clone(), v),
+                    None => self.kvp.remove(&key),
+                };
+                (key, current)
+            })
+            .collect()
+    }
+
+    /// function to add the op mutations to the kvp, returns the keys the ops
+    /// touched in order with the values they had before
+    pub(crate) fn insert_op_mutations(
+        &mut self,
+        entry: &Entry,
+    ) -> Result<Vec<(Key, Option<Value>)>, Error> {
+        let mut prior: Vec<(Key, Option<Value>)> = Vec::default();
         // process the mutation operations
         for op in entry.ops() {
+            if !matches!(op, Op::Noop(_)) && !prior.iter().any(|(k, _)| k == op.path_ref()) {
+                prior.push((op.path(), self.kvp.get(op.path_ref()).cloned()));
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                seqno = entry.seqno(),
+                op = crate::OpId::from(op).as_str(),
+                path = %op.path_ref(),
+                "applying op"
+            );
             match op {
                 Op::Update(k, v) => {
-                    self.kvp.insert(k.clone(), v.clone());
+                    self.insert(k.clone(), v.clone());
                 }
                 Op::Delete(k) => {
-                    self.kvp.remove(k);
+                    self.remove(k);
                 }
                 Op::Noop(_) => {}
             }
         }
 
-        Ok(())
+        Ok(prior)
+    }
+
+    /// calculates a deterministic merkle root over the sorted key-value pairs
+    /// using the given hash codec. the leaves are the hashes of the encoded
+    /// key and value, odd nodes are promoted to the next level unchanged and
+    /// the root of an empty store is the hash of no data. the value stored
+    /// under STATE_ROOT_KEY is excluded so entries can commit to the root.
+    pub fn root_hash(&self, codec: Codec) -> Result<Multihash, Error> {
+        let root_key = Key::try_from(STATE_ROOT_KEY)?;
+        let mut level = Vec::with_capacity(self.kvp.len());
+        for (k, v) in self.kvp.iter().filter(|(k, _)| **k != root_key) {
+            let mut b = vec![LEAF_PREFIX];
+            b.append(&mut k.clone().into());
+            b.append(&mut v.clone().into());
+            level.push(Self::hash(codec, &b)?);
+        }
+
+        if level.is_empty() {
+            return Self::hash(codec, &[]);
+        }
+
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            for pair in level.chunks(2) {
+                match pair {
+                    [l, r] => {
+                        let mut b = vec![NODE_PREFIX];
+                        b.append(&mut l.clone().into());
+                        b.append(&mut r.clone().into());
+                        next.push(Self::hash(codec, &b)?);
+                    }
+                    [l] => next.push(l.clone()),
+                    _ => unreachable!(),
+                }
+            }
+            level = next;
+        }
+
+        Ok(level.remove(0))
+    }
+
+    fn hash(codec: Codec, bytes: &[u8]) -> Result<Multihash, Error> {
+        Ok(mh::Builder::new_from_bytes(codec, bytes)?.try_build()?)
     }
 
     /// returns the number of key-value pairs in the virtual store
@@ -164,6 +336,11 @@ impl<'a> Kvp<'a> {
     pub fn undo_len(&self) -> usize {
         self.undo.len()
     }
+
+    /// returns the number of entries in the redo stack
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +348,7 @@ mod tests {
     use super::*;
     use crate::{entry, Script};
     use multicid::Vlad;
+    use wacc::Pairs;
 
     #[test]
     fn test_default() {
@@ -188,6 +366,121 @@ mod tests {
         let _ = p.undo_entry().unwrap();
     }
 
+    #[test]
+    #[should_panic]
+    fn test_bad_redo() {
+        let mut p = Kvp::default();
+        // this should panic because nothing has been undone
+        let _ = p.redo_entry().unwrap();
+    }
+
+    #[test]
+    fn test_redo_entry() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/one".try_into().unwrap(),
+                Value::Str("foo".to_string()),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/one".try_into().unwrap(),
+                Value::Str("bar".to_string()),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let mut p = Kvp::default();
+        let _ = p.set_entry(&e1).unwrap();
+        p.apply_entry_ops(&e1).unwrap();
+        let _ = p.set_entry(&e2).unwrap();
+        p.apply_entry_ops(&e2).unwrap();
+        assert_eq!(p.seqno(), Some(1));
+        assert_eq!(p.undo_len(), 2);
+        // the snapshots only hold the keys the entries changed
+        assert!(p.undo.iter().all(|(_, delta)| delta.len() == 1));
+
+        // step back twice
+        assert_eq!(p.undo_entry().unwrap(), Some(0));
+        assert_eq!(
+            p.kvp.get(&"/one".try_into().unwrap()),
+            Some(&Value::Str("foo".to_string()))
+        );
+        assert_eq!(p.undo_entry().unwrap(), None);
+        assert_eq!(p.redo_len(), 2);
+        assert!(p.is_empty());
+
+        // and forward again
+        assert_eq!(p.redo_entry().unwrap(), Some(0));
+        assert_eq!(p.redo_entry().unwrap(), Some(1));
+        assert_eq!(
+            p.kvp.get(&"/one".try_into().unwrap()),
+            Some(&Value::Str("bar".to_string()))
+        );
+        assert_eq!(p.undo_len(), 2);
+        assert_eq!(p.redo_len(), 0);
+
+        // applying a new entry clears the redo stack
+        let _ = p.undo_entry().unwrap();
+        assert_eq!(p.redo_len(), 1);
+        let _ = p.set_entry(&e2).unwrap();
+        p.apply_entry_ops(&e2).unwrap();
+        assert_eq!(p.redo_len(), 0);
+    }
+
+    #[test]
+    fn test_root_hash() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/one".try_into().unwrap(),
+                Value::Str("foo".to_string()),
+            ))
+            .add_op(&Op::Update(
+                "/two".try_into().unwrap(),
+                Value::Data(vec![1, 2, 3]),
+            ))
+            .add_op(&Op::Update(
+                "/three".try_into().unwrap(),
+                Value::Nil,
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                STATE_ROOT_KEY.try_into().unwrap(),
+                Value::Data(vec![0xff]),
+            ))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let empty = Kvp::default().root_hash(Codec::Sha3256).unwrap();
+
+        let mut p = Kvp::default();
+        let _ = p.set_entry(&e1).unwrap();
+        p.apply_entry_ops(&e1).unwrap();
+        let root1 = p.root_hash(Codec::Sha3256).unwrap();
+        assert_ne!(empty, root1);
+        assert_eq!(root1, p.clone().root_hash(Codec::Sha3256).unwrap());
+
+        // committing to the root doesn't change the root
+        let _ = p.set_entry(&e2).unwrap();
+        p.apply_entry_ops(&e2).unwrap();
+        assert_eq!(root1, p.root_hash(Codec::Sha3256).unwrap());
+
+        // changing a value changes the root
+        p.kvp.insert("/two".try_into().unwrap(), Value::Data(vec![1, 2, 4]));
+        assert_ne!(root1, p.root_hash(Codec::Sha3256).unwrap());
+    }
+
     #[test]
     #[should_panic]
     fn test_same_seqno() {
@@ -215,6 +508,26 @@ mod tests {
         p.apply_entry_ops(&e2).unwrap();
     }
 
+    #[test]
+    fn test_outlives_entry() {
+        // the kvp keeps its entry alive so it can be stored past the entry
+        let p = {
+            let e1 = entry::Builder::default()
+                .with_vlad(&Vlad::default())
+                .with_unlock(&Script::default())
+                .add_op(&Op::Update("/one".try_into().unwrap(), Value::Str("foo".into())))
+                .try_build(|_| Ok(Vec::default()))
+                .unwrap();
+            let mut p = Kvp::default();
+            let _ = p.set_entry(&e1).unwrap();
+            p.apply_entry_ops(&e1).unwrap();
+            p
+        };
+        assert_eq!(Some(0), p.entry().map(|e| e.seqno()));
+        assert!(p.get("/entry/seqno").is_some());
+        assert_eq!(Some(&Value::Str("foo".into())), p.get_value(&"/one".try_into().unwrap()));
+    }
+
     #[test]
     fn test_one_entry() {
         let entry = entry::Builder::default()
@@ -265,6 +578,48 @@ mod tests {
         assert_eq!(p.kvp.get(&"/one".try_into().unwrap()), None);
     }
 
+    #[test]
+    fn test_apply_entry_ops_reporting() {
+        let key = |s: &str| -> Key { s.try_into().unwrap() };
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(key("/one"), Value::Str("foo".to_string())))
+            .add_op(&Op::Update(key("/two"), Value::Str("bar".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(key("/one"), Value::Str("baz".to_string())))
+            .add_op(&Op::Delete(key("/two")))
+            .add_op(&Op::Update(key("/three"), Value::Str("qux".to_string())))
+            .add_op(&Op::Delete(key("/three")))
+            .add_op(&Op::Noop(key("/four")))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let mut p = Kvp::default();
+        let _ = p.set_entry(&e1).unwrap();
+        let changes = p.apply_entry_ops_reporting(&e1).unwrap();
+        assert_eq!(2, changes.len());
+        assert_eq!((key("/one"), None, Some(Value::Str("foo".to_string()))), changes[0]);
+
+        // keys set and removed again by the same entry aren't reported
+        let _ = p.set_entry(&e2).unwrap();
+        let changes = p.apply_entry_ops_reporting(&e2).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    key("/one"),
+                    Some(Value::Str("foo".to_string())),
+                    Some(Value::Str("baz".to_string()))
+                ),
+                (key("/two"), Some(Value::Str("bar".to_string())), None),
+            ],
+            changes
+        );
+    }
+
     /*
     #[test]
     fn test_entries() {
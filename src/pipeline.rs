@@ -0,0 +1,486 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{
+    error::{LogError, ScriptError, VerifyPhase},
+    observer::{Observer, VerifyEvent},
+    telemetry, CancelToken, Entry, EntryPoints, Error, Kvp, Script, ScriptEngine, Stk,
+};
+use std::time::Instant;
+use wacc::{vm, Stack};
+
+/// The verification state for a single Entry that is passed through each of
+/// the stages in a Pipeline
+pub struct Verification<'a, 'b> {
+    /// the engine used to run the scripts
+    pub engine: &'b dyn ScriptEngine,
+    /// the observer notified of the verification events
+    pub observer: &'b dyn Observer,
+    /// the names of the functions called in the scripts
+    pub entry_points: &'b EntryPoints,
+    /// the entry being verified
+    pub entry: &'a Entry,
+    /// the seqno of the previously verified entry, None for the first entry
+    pub prev_seqno: Option<u64>,
+    /// the kvp state, the apply stage adds the entry's mutations to it
+    pub kvp: &'b mut Kvp,
+    /// the lock scripts from the previous entry that govern this entry
+    pub lock_scripts: &'b mut Vec<Script>,
+    /// the parameter stack set up by the unlock script
+    pub pstack: Stk,
+    /// the return stack set up by the unlock script
+    pub rstack: Stk,
+    /// the check count from the lock script that succeeded
+    pub count: usize,
+    /// true if the entry's mutations have already been applied to the kvp
+    pub applied: bool,
+    /// the token checked for cancellation
+    pub cancel: Option<&'b CancelToken>,
+    /// the time by which the entry must be verified
+    pub deadline: Option<Instant>,
+    /// the time by which the whole verification must be done
+    pub verify_deadline: Option<Instant>,
+    /// the fuel the scripts run for the entry may consume
+    pub fuel: Option<u64>,
+    /// the fuel consumed by the scripts run for the entry so far
+    pub fuel_consumed: u64,
+    /// the lines logged by the scripts run for the entry
+    pub logs: Vec<String>,
+}
+
+impl Verification<'_, '_> {
+    /// check if the verification has been cancelled or the entry has run out
+    /// of time. stages that run more than one script call this between them.
+    pub fn check_interrupted(&self) -> Result<(), Error> {
+        if self.cancel.map(|c| c.is_cancelled()).unwrap_or(false) {
+            return Err(Error::Cancelled(self.entry.seqno()));
+        }
+        if self.deadline.map(|d| Instant::now() > d).unwrap_or(false) {
+            return Err(Error::EntryTimeout(self.entry.seqno()));
+        }
+        if self.verify_deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+            return Err(Error::DeadlineExceeded(self.entry.seqno()));
+        }
+        Ok(())
+    }
+
+    /// get the fuel left for the scripts run for the entry, None if there
+    /// is no budget
+    pub fn fuel_left(&self) -> Option<u64> {
+        self.fuel.map(|f| f.saturating_sub(self.fuel_consumed))
+    }
+
+    /// add the fuel consumed by a script, failing if the entry is over its
+    /// budget
+    pub fn consume_fuel(&mut self, fuel: u64) -> Result<(), Error> {
+        self.fuel_consumed = self.fuel_consumed.saturating_add(fuel);
+        match self.fuel {
+            Some(budget) if self.fuel_consumed > budget => {
+                Err(ScriptError::OutOfFuel(self.fuel_consumed, budget).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// apply the entry's mutations to the kvp if they haven't been already
+    pub fn apply_ops(&mut self) -> Result<(), Error> {
+        if !self.applied {
+            self.kvp
+                .apply_entry_ops(self.entry)
+                .map_err(|e| LogError::UpdateKvpFailed(e.to_string()))?;
+            self.applied = true;
+            self.observer.observe(&VerifyEvent::OpsApplied {
+                entry: self.entry,
+                kvp: &*self.kvp,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A Stage is one step in verifying an Entry. Stages run in order and the
+/// first one to return an error fails the verification of the Entry.
+pub trait Stage {
+    /// the name of the stage
+    fn name(&self) -> &str;
+
+    /// the verification phase reported when the stage fails
+    fn phase(&self) -> VerifyPhase {
+        VerifyPhase::Stage(self.name().to_string())
+    }
+
+    /// run this stage
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error>;
+}
+
+/// Checks the structure of the entry relative to the previous entry
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Structure;
+
+impl Stage for Structure {
+    fn name(&self) -> &str {
+        "structure"
+    }
+
+    fn phase(&self) -> VerifyPhase {
+        VerifyPhase::Structure
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        // check the seqno meet the criteria
+        if let Some(prev_seqno) = v.prev_seqno {
+            if v.entry.seqno() != prev_seqno + 1 {
+                return Err(LogError::InvalidSeqno.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the unlock script in the entry to set up the stacks
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unlock;
+
+impl Stage for Unlock {
+    fn name(&self) -> &str {
+        "unlock"
+    }
+
+    fn phase(&self) -> VerifyPhase {
+        VerifyPhase::Unlock
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        // run the unlock script using the entry as the kvp to get the
+        // stacks set up. limit the available data to just the entry.
+        let mut log = Vec::default();
+        let fuel = v.fuel_left();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("run_script", stage = "unlock", path = %v.entry.unlock.path_ref())
+            .entered();
+        let start = Instant::now();
+        let result = v.engine.run_metered(
+            &v.entry.unlock,
+            &v.entry_points.unlock,
+            v.entry,
+            v.entry,
+            &mut v.pstack,
+            &mut v.rstack,
+            &v.entry.context(),
+            &mut log,
+            fuel,
+        );
+        telemetry::script_ran("unlock", start.elapsed());
+        #[cfg(feature = "tracing")]
+        tracing::debug!(ok = result.is_ok(), "unlock script ran");
+        v.logs.extend(log.iter().cloned());
+        v.consume_fuel(result?)?;
+        v.observer.observe(&VerifyEvent::UnlockRan {
+            entry: v.entry,
+            pstack: &v.pstack,
+            rstack: &v.rstack,
+            log: &log,
+        });
+        Ok(())
+    }
+}
+
+/// Runs the lock scripts from the previous entry that govern the entry
+/// until one of them succeeds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lock;
+
+impl Stage for Lock {
+    fn name(&self) -> &str {
+        "lock"
+    }
+
+    fn phase(&self) -> VerifyPhase {
+        VerifyPhase::Lock
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        // if this is the first entry, then we need to apply the mutation ops
+        // so that the first lock script can check them
+        if v.prev_seqno.is_none() {
+            v.apply_ops()?;
+        }
+
+        // build the set of lock scripts to run in order from root to longest branch to leaf
+        let locks = v.entry.sort_locks(v.lock_scripts.as_slice())?;
+
+        // run each of the lock scripts
+        for lock in locks {
+            v.check_interrupted()?;
+
+            // NOTE: clone the kvp and stacks each time
+            let lock_kvp = v.kvp.clone();
+            let mut lock_pstack = v.pstack.clone();
+            let mut lock_rstack = v.rstack.clone();
+
+            let mut log = Vec::default();
+            let fuel = v.fuel_left();
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("run_script", stage = "lock", path = %lock.path_ref())
+                .entered();
+            let start = Instant::now();
+            let result = v.engine.run_metered(
+                &lock,
+                &v.entry_points.lock,
+                &lock_kvp,
+                v.entry,
+                &mut lock_pstack,
+                &mut lock_rstack,
+                &v.entry.context(),
+                &mut log,
+                fuel,
+            );
+            telemetry::script_ran("lock", start.elapsed());
+            v.logs.extend(log.iter().cloned());
+            v.consume_fuel(result?)?;
+
+            let count = match lock_rstack.top() {
+                Some(vm::Value::Success(c)) => Some(c),
+                _ => None,
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(succeeded = count.is_some(), "lock script ran");
+            v.observer.observe(&VerifyEvent::LockEvaluated {
+                entry: v.entry,
+                lock: &lock,
+                rstack: &lock_rstack,
+                succeeded: count.is_some(),
+                log: &log,
+            });
+
+            // stop as soon as a lock script succeeds
+            if let Some(c) = count {
+                v.count = c;
+                v.observer.observe(&VerifyEvent::LockMatched {
+                    entry: v.entry,
+                    lock: &lock,
+                    count: c,
+                });
+                return Ok(());
+            }
+        }
+
+        Err(LogError::VerifyFailed(format!(
+            "lock script failed\nvalues:\n{:?}\nreturn:\n{:?}\nlog:\n{}",
+            v.rstack,
+            v.pstack,
+            v.logs.join("\n")
+        ))
+        .into())
+    }
+}
+
+/// Applies the entry's mutations to the kvp and sets up the lock scripts for
+/// verifying the next entry
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Apply;
+
+impl Stage for Apply {
+    fn name(&self) -> &str {
+        "apply"
+    }
+
+    fn phase(&self) -> VerifyPhase {
+        VerifyPhase::Apply
+    }
+
+    fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+        v.apply_ops()?;
+        // update the lock script to validate the next entry
+        v.lock_scripts.clone_from(&v.entry.locks);
+        Ok(())
+    }
+}
+
+/// A Pipeline is the ordered list of stages each Entry passes through when
+/// verifying a Log. The default pipeline is structure, unlock, lock, apply.
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+            .with_stage(Structure)
+            .with_stage(Unlock)
+            .with_stage(Lock)
+            .with_stage(Apply)
+    }
+}
+
+impl Pipeline {
+    /// create a new, empty pipeline
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::default(),
+        }
+    }
+
+    /// add a stage to the end of the pipeline
+    pub fn with_stage<S: Stage + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// insert a stage before the named stage, or at the end if there is no
+    /// stage with that name
+    pub fn insert_before<S: Stage + 'static>(mut self, name: &str, stage: S) -> Self {
+        let idx = self.position(name).unwrap_or(self.stages.len());
+        self.stages.insert(idx, Box::new(stage));
+        self
+    }
+
+    /// insert a stage after the named stage, or at the end if there is no
+    /// stage with that name
+    pub fn insert_after<S: Stage + 'static>(mut self, name: &str, stage: S) -> Self {
+        let idx = self
+            .position(name)
+            .map(|i| i + 1)
+            .unwrap_or(self.stages.len());
+        self.stages.insert(idx, Box::new(stage));
+        self
+    }
+
+    /// remove the named stage
+    pub fn without_stage(mut self, name: &str) -> Self {
+        self.stages.retain(|s| s.name() != name);
+        self
+    }
+
+    /// get an iterator over the stages
+    pub fn stages(&self) -> impl Iterator<Item = &dyn Stage> {
+        self.stages.iter().map(|s| s.as_ref())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|s| s.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Key, Op, Value, WaccEngine};
+    use multicid::Vlad;
+    use std::cell::RefCell;
+    use wacc::Pairs;
+
+    struct NoFoo;
+
+    impl Stage for NoFoo {
+        fn name(&self) -> &str {
+            "no-foo"
+        }
+
+        fn run(&self, v: &mut Verification<'_, '_>) -> Result<(), Error> {
+            let foo = Key::try_from("/foo").unwrap();
+            if v.entry.ops().any(|op| op.path() == foo) {
+                return Err(LogError::VerifyFailed("no foo allowed".to_string()).into());
+            }
+            Ok(())
+        }
+    }
+
+    // records the functions called and succeeds when "lock" is called
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl ScriptEngine for Recorder {
+        fn run(
+            &self,
+            _script: &Script,
+            func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            self.calls.borrow_mut().push(func.to_string());
+            if func == "lock" {
+                rstack.push(vm::Value::Success(0));
+            }
+            Ok(())
+        }
+    }
+
+    fn test_log() -> log::Log {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/bar".try_into().unwrap(), Value::Str("bar".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("foo".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_stages() {
+        let names: Vec<&str> = Pipeline::default().stages().map(|s| s.name()).collect();
+        assert_eq!(vec!["structure", "unlock", "lock", "apply"], names);
+    }
+
+    #[test]
+    fn test_insert_stages() {
+        let p = Pipeline::default()
+            .insert_before("unlock", NoFoo)
+            .without_stage("lock");
+        let names: Vec<&str> = p.stages().map(|s| s.name()).collect();
+        assert_eq!(vec!["structure", "no-foo", "unlock", "apply"], names);
+    }
+
+    #[test]
+    fn test_structural_pipeline() {
+        let log = test_log();
+        let p = Pipeline::new().with_stage(Structure).with_stage(Apply);
+        let results: Vec<_> = log.verify_with_pipeline(p, &WaccEngine).collect();
+        assert_eq!(2, results.len());
+        let (_, _, kvp) = results.last().unwrap().clone().unwrap();
+        assert_eq!(2, kvp.len());
+    }
+
+    #[test]
+    fn test_custom_stage() {
+        let log = test_log();
+        let p = Pipeline::new()
+            .with_stage(Structure)
+            .with_stage(NoFoo)
+            .with_stage(Apply);
+        let mut results = log.verify_with_pipeline(p, &WaccEngine);
+        assert!(results.next().unwrap().is_ok());
+        match results.next().unwrap() {
+            Err(Error::Verify(e)) => {
+                assert_eq!(1, e.seqno);
+                assert_eq!(VerifyPhase::Stage("no-foo".to_string()), e.phase);
+            }
+            _ => panic!("expected a verify error"),
+        }
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let mut log = test_log();
+        assert_eq!(EntryPoints::default(), *log.entry_points());
+        log.set_entry_points(&EntryPoints::new("unlock", "lock"));
+        let engine = Recorder::default();
+        assert_eq!(2, log.verify_with_engine(&engine).filter(|r| r.is_ok()).count());
+        assert_eq!(vec!["unlock", "lock", "unlock", "lock"], *engine.calls.borrow());
+    }
+}
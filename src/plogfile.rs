@@ -0,0 +1,537 @@
+// SPDX-License-Identifier: FSL-1.1
+//! An append-only file format for logs. The file starts with a header and
+//! is followed by records, each a varuint kind, the varbytes payload and a
+//! checksum, the first four bytes of the sha2-256 hash of the kind and
+//! payload bytes before it:
+//!
+//! ```text
+//! "plogfile" | varuint(version) | vlad | first lock script
+//! (varuint(kind) | varbytes(payload) | checksum)*
+//! ```
+//!
+//! Version 1 files have no record checksums. They are still read and
+//! appended to without them.
+//!
+//! Entry records hold the encoded entries in the order they were appended.
+//! Every [`DEFAULT_INDEX_INTERVAL`] entries an index record lists the seqnos
+//! and file offsets of the entries since the last index so readers can seek
+//! to an entry. Checkpoint records hold the checkpoints the log was
+//! compacted to.
+//!
+//! Each append is synced to disk before it returns. A failed append is cut
+//! back off the end of the file and if that fails too the file refuses any
+//! more appends. A crash part way through an append leaves a torn record at
+//! the end of the file which is truncated away when the file is opened
+//! again. A bad record followed by more records isn't a torn write, the file
+//! is corrupt and opening it fails.
+use crate::{error::LogError, log, Checkpoint, Entry, Error, Log, Script};
+use multicid::Vlad;
+use multitrait::TryDecodeFrom;
+use multiutil::{Varbytes, Varuint};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// the magic bytes at the start of every plogfile
+pub const MAGIC: &[u8] = b"plogfile";
+
+/// the current version of the plogfile format
+pub const PLOGFILE_VERSION: u64 = 2;
+
+// the first version with record checksums
+const CHECKSUM_VERSION: u64 = 2;
+
+// the number of bytes in a record checksum
+const CHECKSUM_LEN: usize = 4;
+
+/// the number of entries between index records by default
+pub const DEFAULT_INDEX_INTERVAL: usize = 64;
+
+/// the kind of record holding an entry
+pub const ENTRY_RECORD: u64 = 1;
+
+/// the kind of record holding an index of entry offsets
+pub const INDEX_RECORD: u64 = 2;
+
+/// the kind of record holding a checkpoint
+pub const CHECKPOINT_RECORD: u64 = 3;
+
+// the most bytes the kind and length varuints of a record take up
+const MAX_PREFIX_LEN: u64 = 20;
+
+fn read_err<S: ToString>(s: S) -> Error {
+    LogError::ReadFailed(s.to_string()).into()
+}
+
+fn write_err<S: ToString>(s: S) -> Error {
+    LogError::WriteFailed(s.to_string()).into()
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut sum = [0u8; CHECKSUM_LEN];
+    sum.copy_from_slice(&Sha256::digest(bytes)[..CHECKSUM_LEN]);
+    sum
+}
+
+fn record(version: u64, kind: u64, payload: Vec<u8>) -> Vec<u8> {
+    let mut v: Vec<u8> = Varuint(kind).into();
+    v.append(&mut Varbytes(payload).into());
+    if version >= CHECKSUM_VERSION {
+        let sum = checksum(&v);
+        v.extend_from_slice(&sum);
+    }
+    v
+}
+
+// the ways a record can fail to decode
+enum BadRecord {
+    // the record runs past the end of the bytes, as a torn write leaves it
+    Torn,
+    // the record is all there but it isn't valid
+    Corrupt,
+}
+
+// split a record into its kind, its payload and the bytes after it,
+// checking the checksum if the version has them
+fn decode_record(version: u64, bytes: &[u8]) -> Result<(u64, &[u8], &[u8]), BadRecord> {
+    // a prefix that doesn't decode is torn if the bytes ran out part way
+    let bad_prefix = |ptr: &[u8]| {
+        if ptr.len() < MAX_PREFIX_LEN as usize {
+            BadRecord::Torn
+        } else {
+            BadRecord::Corrupt
+        }
+    };
+    let (kind, ptr) = Varuint::<u64>::try_decode_from(bytes).map_err(|_| bad_prefix(bytes))?;
+    let (len, ptr) = Varuint::<usize>::try_decode_from(ptr).map_err(|_| bad_prefix(bytes))?;
+    let sum_len = if version >= CHECKSUM_VERSION { CHECKSUM_LEN } else { 0 };
+    if ptr.len() < sum_len || *len > ptr.len() - sum_len {
+        return Err(BadRecord::Torn);
+    }
+    let (payload, ptr) = ptr.split_at(*len);
+    let (sum, rest) = ptr.split_at(sum_len);
+    if sum_len > 0 {
+        let end = bytes.len() - ptr.len();
+        if checksum(&bytes[..end]) != sum {
+            return Err(BadRecord::Corrupt);
+        }
+    }
+    Ok((*kind, payload, rest))
+}
+
+// encode the seqnos and offsets of the entries as an index record payload
+fn encode_index(offsets: &[(u64, u64)]) -> Vec<u8> {
+    let mut v: Vec<u8> = Varuint(offsets.len()).into();
+    offsets.iter().for_each(|(seqno, offset)| {
+        v.append(&mut Varuint(*seqno).into());
+        v.append(&mut Varuint(*offset).into());
+    });
+    v
+}
+
+/// A log stored in an append-only file
+pub struct PlogFile {
+    file: File,
+    version: u64,
+    vlad: Vlad,
+    first_lock: Script,
+    // the length of the file after the last complete record
+    len: u64,
+    // the seqno and offset of every entry record, in file order
+    offsets: Vec<(u64, u64)>,
+    // the number of entries in offsets already covered by an index record
+    indexed: usize,
+    index_interval: usize,
+    checkpoint: Option<Checkpoint>,
+    truncated: u64,
+    // set when a failed append couldn't be cut back off the file
+    poisoned: bool,
+}
+
+impl PlogFile {
+    /// create a new plogfile for the log with the vlad and first lock
+    /// script, failing if the file already exists
+    pub fn create<P: AsRef<Path>>(path: P, vlad: &Vlad, first_lock: &Script) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create_new(true)
+            .open(path)
+            .map_err(write_err)?;
+        let mut header = MAGIC.to_vec();
+        header.append(&mut Varuint(PLOGFILE_VERSION).into());
+        header.append(&mut vlad.clone().into());
+        header.append(&mut first_lock.clone().into());
+        file.write_all(&header).map_err(write_err)?;
+        file.sync_all().map_err(write_err)?;
+        Ok(Self {
+            file,
+            version: PLOGFILE_VERSION,
+            vlad: vlad.clone(),
+            first_lock: first_lock.clone(),
+            len: header.len() as u64,
+            offsets: Vec::default(),
+            indexed: 0,
+            index_interval: DEFAULT_INDEX_INTERVAL,
+            checkpoint: None,
+            truncated: 0,
+            poisoned: false,
+        })
+    }
+
+    /// open an existing plogfile, truncating a torn record left at the end of
+    /// the file by a crash. a bad record anywhere else fails with
+    /// [`LogError::ReadFailed`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(read_err)?;
+        let mut bytes = Vec::default();
+        file.read_to_end(&mut bytes).map_err(read_err)?;
+
+        // the header must be intact
+        let ptr = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| read_err("missing plogfile magic"))?;
+        let (version, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let version = *version;
+        if version > PLOGFILE_VERSION {
+            return Err(LogError::InvalidVersion(version).into());
+        }
+        let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+        let (first_lock, mut ptr) = Script::try_decode_from(ptr)?;
+
+        let mut offsets = Vec::default();
+        let mut indexed = 0;
+        let mut checkpoint = None;
+        loop {
+            let offset = (bytes.len() - ptr.len()) as u64;
+            if ptr.is_empty() {
+                break;
+            }
+            // a record running past the end of the file is torn and a bad
+            // record is only torn if it is the last one
+            let corrupt = || read_err(format!("corrupt record at offset {}", offset));
+            let (kind, payload, rest) = match decode_record(version, ptr) {
+                Ok(r) => r,
+                Err(BadRecord::Torn) => break,
+                Err(BadRecord::Corrupt) => return Err(corrupt()),
+            };
+            let valid = match kind {
+                ENTRY_RECORD => match Entry::try_from(payload) {
+                    Ok(entry) => {
+                        offsets.push((entry.seqno(), offset));
+                        true
+                    }
+                    Err(_) => false,
+                },
+                // an index must list the entries since the last one
+                INDEX_RECORD if payload == encode_index(&offsets[indexed..]) => {
+                    indexed = offsets.len();
+                    true
+                }
+                CHECKPOINT_RECORD => match Checkpoint::try_from(payload) {
+                    Ok(cp) => {
+                        checkpoint = Some(cp);
+                        true
+                    }
+                    Err(_) => false,
+                },
+                _ => false,
+            };
+            if !valid {
+                if rest.is_empty() {
+                    break;
+                }
+                return Err(corrupt());
+            }
+            ptr = rest;
+        }
+
+        // drop the torn tail
+        let len = (bytes.len() - ptr.len()) as u64;
+        let truncated = ptr.len() as u64;
+        if truncated > 0 {
+            file.set_len(len).map_err(write_err)?;
+            file.sync_all().map_err(write_err)?;
+        }
+        Ok(Self {
+            file,
+            version,
+            vlad,
+            first_lock,
+            len,
+            offsets,
+            indexed,
+            index_interval: DEFAULT_INDEX_INTERVAL,
+            checkpoint,
+            truncated,
+            poisoned: false,
+        })
+    }
+
+    /// set the number of entries between index records
+    pub fn with_index_interval(mut self, interval: usize) -> Self {
+        self.index_interval = interval.max(1);
+        self
+    }
+
+    /// get the number of bytes of torn records truncated when opening
+    pub fn truncated(&self) -> u64 {
+        self.truncated
+    }
+
+    /// get the number of entries in the file
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// check if the file has no entries
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    // append the record and sync it to disk. if either fails whatever part
+    // of the record was written is cut off again so the next append doesn't
+    // land after a torn record, and if that fails the file is poisoned.
+    fn append_record(&mut self, kind: u64, payload: Vec<u8>) -> Result<u64, Error> {
+        if self.poisoned {
+            return Err(write_err("a failed append left the plogfile in an unknown state"));
+        }
+        let offset = self.len;
+        let rec = record(self.version, kind, payload);
+        let written = self
+            .file
+            .write_all(&rec)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            let restored = self
+                .file
+                .set_len(self.len)
+                .and_then(|_| self.file.sync_all());
+            if restored.is_err() {
+                self.poisoned = true;
+            }
+            return Err(write_err(e));
+        }
+        self.len += rec.len() as u64;
+        Ok(offset)
+    }
+
+    /// append the entry, writing an index record every index interval
+    pub fn append(&mut self, entry: &Entry) -> Result<(), Error> {
+        if entry.vlad() != self.vlad {
+            return Err(LogError::VladMismatch(entry.seqno()).into());
+        }
+        let offset = self.append_record(ENTRY_RECORD, entry.canonical_bytes())?;
+        self.offsets.push((entry.seqno(), offset));
+        if self.offsets.len() - self.indexed >= self.index_interval {
+            let index = encode_index(&self.offsets[self.indexed..]);
+            self.append_record(INDEX_RECORD, index)?;
+            self.indexed = self.offsets.len();
+        }
+        Ok(())
+    }
+
+    /// append the checkpoint the log was compacted to
+    pub fn append_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        self.append_record(CHECKPOINT_RECORD, checkpoint.clone().into())?;
+        self.checkpoint = Some(checkpoint.clone());
+        Ok(())
+    }
+
+    // read the record at the offset, the kind and length prefix are read
+    // first so only the one record is read
+    fn read_record(&mut self, offset: u64) -> Result<(u64, Vec<u8>), Error> {
+        self.file.seek(SeekFrom::Start(offset)).map_err(read_err)?;
+        let mut prefix = vec![0u8; (self.len - offset).min(MAX_PREFIX_LEN) as usize];
+        self.file.read_exact(&mut prefix).map_err(read_err)?;
+        let (kind, ptr) = Varuint::<u64>::try_decode_from(&prefix)?;
+        let (len, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let start = offset + (prefix.len() - ptr.len()) as u64;
+        if start + *len as u64 > self.len {
+            return Err(read_err("truncated record"));
+        }
+        self.file.seek(SeekFrom::Start(start)).map_err(read_err)?;
+        let mut payload = vec![0u8; *len];
+        self.file.read_exact(&mut payload).map_err(read_err)?;
+        Ok((*kind, payload))
+    }
+
+    /// read the entry with the seqno, the last one appended if there is more
+    /// than one
+    pub fn get(&mut self, seqno: u64) -> Result<Option<Entry>, Error> {
+        let offset = match self.offsets.iter().rev().find(|(s, _)| *s == seqno) {
+            Some((_, offset)) => *offset,
+            None => return Ok(None),
+        };
+        match self.read_record(offset)? {
+            (ENTRY_RECORD, payload) => Ok(Some(Entry::try_from(payload.as_slice())?)),
+            _ => Err(read_err("index doesn't point at an entry")),
+        }
+    }
+
+    /// read the whole log. the foot is the first entry appended and the head
+    /// is the last.
+    pub fn to_log(&mut self) -> Result<Log, Error> {
+        let mut entries = Vec::default();
+        for (_, offset) in self.offsets.clone() {
+            match self.read_record(offset)? {
+                (ENTRY_RECORD, payload) => entries.push(Entry::try_from(payload.as_slice())?),
+                _ => return Err(read_err("index doesn't point at an entry")),
+            }
+        }
+        let foot = entries.first().ok_or(LogError::MissingEntries)?.cid();
+        let head = entries.last().ok_or(LogError::MissingEntries)?.cid();
+        let entries: log::Entries = entries.into_iter().map(|e| (e.cid(), e)).collect();
+        let mut builder = log::Builder::new()
+            .with_vlad(&self.vlad)
+            .with_first_lock(&self.first_lock)
+            .with_foot(&foot)
+            .with_head(&head)
+            .with_entries(&entries);
+        if let Some(cp) = &self.checkpoint {
+            builder = builder.with_checkpoint(cp);
+        }
+        builder.try_build()
+    }
+}
+
+impl Log {
+    /// write the log to a new plogfile, appending the entries from the foot
+    /// to the head
+    pub fn to_plogfile<P: AsRef<Path>>(&self, path: P) -> Result<PlogFile, Error> {
+        let mut file = PlogFile::create(path, &self.vlad, &self.first_lock)?;
+        for entry in self.iter() {
+            file.append(entry)?;
+        }
+        if let Some(cp) = &self.checkpoint {
+            file.append_checkpoint(cp)?;
+        }
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, Op};
+
+    #[test]
+    fn test_plogfile_recovery() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Noop("/a".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("plog-{}.plog", rand::random::<u64>()));
+        let mut file = log.to_plogfile(&path).unwrap();
+        assert_eq!(2, file.len());
+        assert_eq!(Some(e2.clone()), file.get(1).unwrap());
+        assert_eq!(log, file.to_log().unwrap());
+        // the file can't be created twice
+        assert!(PlogFile::create(&path, &Vlad::default(), &Script::default()).is_err());
+        drop(file);
+
+        // a crash leaves half of the next record behind
+        let mut torn = record(PLOGFILE_VERSION, ENTRY_RECORD, e3.canonical_bytes());
+        torn.truncate(torn.len() / 2);
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&torn)
+            .unwrap();
+
+        let mut file = PlogFile::open(&path).unwrap().with_index_interval(1);
+        assert_eq!(torn.len() as u64, file.truncated());
+        assert_eq!(log, file.to_log().unwrap());
+
+        // appending carries on after the last complete record
+        file.append(&e3).unwrap();
+        drop(file);
+        let mut file = PlogFile::open(&path).unwrap();
+        assert_eq!(0, file.truncated());
+        assert_eq!(3, file.len());
+        assert_eq!(e3.cid(), file.to_log().unwrap().head);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_plogfile_corruption() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("plog-{}.plog", rand::random::<u64>()));
+        let mut file = log.to_plogfile(&path).unwrap();
+        let first = file.offsets[0].1 as usize;
+        file.append(&e2).unwrap();
+        drop(file);
+        let good = std::fs::read(&path).unwrap();
+
+        // a flipped bit in the last record is a torn write
+        let mut bytes = good.clone();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut file = PlogFile::open(&path).unwrap();
+        assert!(file.truncated() > 0);
+        assert_eq!(log, file.to_log().unwrap());
+        drop(file);
+
+        // the same in a record followed by others is corruption and the file
+        // is left as it is
+        let mut bytes = good.clone();
+        bytes[first + 4] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            PlogFile::open(&path),
+            Err(Error::Log(LogError::ReadFailed(_)))
+        ));
+        assert_eq!(bytes, std::fs::read(&path).unwrap());
+
+        // an append that fails is cut back off, and when that fails too the
+        // file refuses to append again
+        std::fs::write(&path, &good).unwrap();
+        let mut file = PlogFile::open(&path).unwrap();
+        file.file = File::open(&path).unwrap();
+        assert!(file.append(&e2).is_err());
+        assert!(file.poisoned);
+        assert_eq!(2, file.len());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,563 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Declarative lock policies compiled to lock scripts. A policy is a list of
+//! rules, one per line, each giving the condition that must be met to change
+//! the keys under a path:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! /    -> threshold(2, [pubkey:/k1, pubkey:/k2, preimage:/hash])
+//! /foo -> preimage:/hash
+//! ```
+//!
+//! `pubkey:<key>` checks a signature by the public key at the key,
+//! `preimage:<key>` checks a preimage of the hash at the key and
+//! `threshold(n, [...])` requires at least n of the listed conditions. The
+//! [`Compiler`] emits a lock script for each rule and a sample unlock script
+//! that pushes the proofs for it.
+//!
+//! A rule with a single check is met by the entry proof. A rule with more
+//! than one check needs a proof for each check, in the order the checks are
+//! listed, joined with [`crate::proof::join_proofs`]. The unlock script
+//! pushes them from `/entry/proof/<n>` so the proof for the first check is on
+//! top of the stack. Each check uses up its proof whether it is met or not,
+//! so an empty proof can be given for the checks that aren't met.
+//!
+//! The rhai backend only emits the scripts. This crate has no engine that
+//! runs rhai scripts so they need an engine from elsewhere.
+use crate::{error::ScriptError, EntryPoints, Error, Key, Script};
+use core::fmt;
+
+/// the key of the entry the signatures are checked over
+const ENTRY_KEY: &str = "/entry/";
+
+/// the key of the entry proof
+const PROOF_KEY: &str = "/entry/proof";
+
+// the key of the proof for the n-th check of a joined proof
+fn proof_key(n: usize) -> String {
+    format!("{}/{}", PROOF_KEY, n)
+}
+
+fn policy_err<S: ToString>(s: S) -> Error {
+    ScriptError::InvalidPolicy(s.to_string()).into()
+}
+
+/// A condition that must be met by an entry
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Condition {
+    /// the proof is a signature by the public key at the key
+    Pubkey(Key),
+    /// the proof is the preimage of the hash at the key
+    Preimage(Key),
+    /// at least the given number of the conditions are met
+    Threshold(usize, Vec<Condition>),
+}
+
+impl Condition {
+    /// the number of pubkey and preimage checks in the condition
+    fn leaves(&self) -> usize {
+        match self {
+            Self::Pubkey(_) | Self::Preimage(_) => 1,
+            Self::Threshold(_, conditions) => conditions.iter().map(|c| c.leaves()).sum(),
+        }
+    }
+}
+
+// split the list on the commas that aren't inside of a nested list
+fn split_list(s: &str) -> Vec<&str> {
+    let mut items = Vec::default();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&s[start..]);
+    items
+}
+
+impl TryFrom<&str> for Condition {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let s = s.trim();
+        if let Some(key) = s.strip_prefix("pubkey:") {
+            Ok(Self::Pubkey(Key::try_from(key.trim())?))
+        } else if let Some(key) = s.strip_prefix("preimage:") {
+            Ok(Self::Preimage(Key::try_from(key.trim())?))
+        } else if let Some(args) = s.strip_prefix("threshold(").and_then(|a| a.strip_suffix(')')) {
+            let (n, list) = args
+                .split_once(',')
+                .ok_or_else(|| policy_err(format!("missing threshold list in {}", s)))?;
+            let n: usize = n
+                .trim()
+                .parse()
+                .map_err(|_| policy_err(format!("invalid threshold in {}", s)))?;
+            let list = list
+                .trim()
+                .strip_prefix('[')
+                .and_then(|l| l.strip_suffix(']'))
+                .ok_or_else(|| policy_err(format!("invalid threshold list in {}", s)))?;
+            let conditions = split_list(list)
+                .into_iter()
+                .map(Self::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            if n == 0 || n > conditions.len() {
+                return Err(policy_err(format!("threshold {} out of range in {}", n, s)));
+            }
+            Ok(Self::Threshold(n, conditions))
+        } else {
+            Err(policy_err(format!("unknown condition {}", s)))
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pubkey(key) => write!(f, "pubkey:{}", key),
+            Self::Preimage(key) => write!(f, "preimage:{}", key),
+            Self::Threshold(n, conditions) => {
+                let list: Vec<String> = conditions.iter().map(|c| c.to_string()).collect();
+                write!(f, "threshold({}, [{}])", n, list.join(", "))
+            }
+        }
+    }
+}
+
+/// A Rule gives the condition for changing the keys under a path
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    /// the path the lock script governs
+    pub path: Key,
+    /// the condition the entry must meet
+    pub condition: Condition,
+}
+
+impl TryFrom<&str> for Rule {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let (path, condition) = s
+            .split_once("->")
+            .ok_or_else(|| policy_err(format!("missing -> in {}", s.trim())))?;
+        Ok(Self {
+            path: Key::try_from(path.trim())?,
+            condition: Condition::try_from(condition)?,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.path, self.condition)
+    }
+}
+
+/// A Policy is the list of rules for a log
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Policy {
+    /// the rules in the order they were declared
+    pub rules: Vec<Rule>,
+}
+
+impl TryFrom<&str> for Policy {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let rules = s
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(Rule::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { rules })
+    }
+}
+
+/// The VM the compiled scripts are for. Both backends call the same host
+/// functions, only the script language differs. There is no engine for the
+/// rhai scripts in this crate.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Backend {
+    /// wast text modules run by the wacc vm
+    #[default]
+    Wast,
+    /// rhai scripts calling the same host functions as the wacc vm
+    Rhai,
+}
+
+/// Compiles policy rules to lock and unlock scripts
+#[derive(Clone, Debug, Default)]
+pub struct Compiler {
+    backend: Backend,
+    entry_points: EntryPoints,
+}
+
+// the string constants in the data segment of a wast module
+#[derive(Default)]
+struct Strings(Vec<(String, usize)>);
+
+impl Strings {
+    // get the offset of the string, adding it if it isn't there yet
+    fn offset(&mut self, s: &str) -> usize {
+        if let Some((_, off)) = self.0.iter().find(|(t, _)| t == s) {
+            return *off;
+        }
+        let off = self.0.last().map(|(t, off)| off + t.len()).unwrap_or(0);
+        self.0.push((s.to_string(), off));
+        off
+    }
+
+    fn data(&self) -> String {
+        self.0
+            .iter()
+            .map(|(s, off)| format!("  (data (i32.const {}) \"{}\")\n", off, s))
+            .collect()
+    }
+}
+
+impl Compiler {
+    /// create a compiler for the backend
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            entry_points: EntryPoints::default(),
+        }
+    }
+
+    /// export the scripts under the given function names
+    pub fn with_entry_points(mut self, entry_points: &EntryPoints) -> Self {
+        self.entry_points = entry_points.clone();
+        self
+    }
+
+    // emit the wast expression that leaves 1 on the stack if the condition
+    // is met and 0 if it isn't. every check uses up the proof on top of the
+    // stack so the next check gets the next proof.
+    fn wast_condition(condition: &Condition, strings: &mut Strings, code: &mut String) {
+        match condition {
+            Condition::Pubkey(key) => {
+                let (koff, moff) = (strings.offset(key.as_str()), strings.offset(ENTRY_KEY));
+                code.push_str(&format!(
+                    "    ;; check_signature(\"{}\", \"{}\")\n    i32.const {}\n    i32.const {}\n    i32.const {}\n    i32.const {}\n    call $check_signature\n    call $consume\n",
+                    key, ENTRY_KEY, koff, key.as_str().len(), moff, ENTRY_KEY.len()
+                ));
+            }
+            Condition::Preimage(key) => {
+                let koff = strings.offset(key.as_str());
+                code.push_str(&format!(
+                    "    ;; check_preimage(\"{}\")\n    i32.const {}\n    i32.const {}\n    call $check_preimage\n    call $consume\n",
+                    key, koff, key.as_str().len()
+                ));
+            }
+            Condition::Threshold(n, conditions) => {
+                for (i, c) in conditions.iter().enumerate() {
+                    Self::wast_condition(c, strings, code);
+                    if i > 0 {
+                        code.push_str("    i32.add\n");
+                    }
+                }
+                code.push_str(&format!("    ;; at least {} of {}\n    i32.const {}\n    i32.ge_u\n", n, conditions.len(), n));
+            }
+        }
+    }
+
+    // emit the rhai expression that is true if the condition is met. a
+    // check that fails pops its proof like in the wast scripts.
+    fn rhai_condition(condition: &Condition) -> String {
+        match condition {
+            Condition::Pubkey(key) => format!("check_signature(\"{}\", \"{}\")", key, ENTRY_KEY),
+            Condition::Preimage(key) => format!("check_preimage(\"{}\")", key),
+            Condition::Threshold(n, conditions) => {
+                let count: Vec<String> = conditions
+                    .iter()
+                    .map(|c| match c {
+                        Condition::Threshold(..) => {
+                            format!("(if {} {{ 1 }} else {{ 0 }})", Self::rhai_condition(c))
+                        }
+                        _ => format!(
+                            "(if {} {{ 1 }} else {{ pop(); 0 }})",
+                            Self::rhai_condition(c)
+                        ),
+                    })
+                    .collect();
+                format!("({} >= {})", count.join(" + "), n)
+            }
+        }
+    }
+
+    /// compile the rule to the lock script for its path
+    pub fn lock(&self, rule: &Rule) -> Result<Script, Error> {
+        match self.backend {
+            Backend::Wast => {
+                let mut strings = Strings::default();
+                let mut code = String::default();
+                Self::wast_condition(&rule.condition, &mut strings, &mut code);
+                let wast = format!(
+                    ";; generated from the policy rule: {}\n(module\n  (import \"wacc\" \"_check_signature\" (func $check_signature (param i32 i32 i32 i32) (result i32)))\n  (import \"wacc\" \"_check_preimage\" (func $check_preimage (param i32 i32) (result i32)))\n  (import \"wacc\" \"_pop\" (func $pop (result i32)))\n\n  ;; pop the proof a failed check left on the stack\n  (func $consume (param $ok i32) (result i32)\n    local.get $ok\n    i32.eqz\n    (if (then call $pop drop))\n    local.get $ok\n  )\n\n  (func $main (export \"{}\") (param) (result i32)\n{}    return\n  )\n\n  (memory (export \"memory\") 1)\n\n{})\n",
+                    rule,
+                    self.entry_points.lock,
+                    code,
+                    strings.data()
+                );
+                Ok(Script::Code(rule.path.clone(), wast))
+            }
+            Backend::Rhai => {
+                let rhai = format!(
+                    "// generated from the policy rule: {}\nfn {}() {{\n    {}\n}}\n",
+                    rule,
+                    self.entry_points.lock,
+                    Self::rhai_condition(&rule.condition)
+                );
+                Ok(Script::Code(rule.path.clone(), rhai))
+            }
+        }
+    }
+
+    /// compile the sample unlock script for the rule. it pushes the entry
+    /// proof for a single check and the proof for each check of a joined
+    /// proof otherwise.
+    pub fn unlock(&self, rule: &Rule) -> Result<Script, Error> {
+        self.unlock_checks(&format!("the policy rule: {}", rule), rule.condition.leaves())
+    }
+
+    // compile an unlock script that pushes the proofs for the checks, the
+    // one for the first check last so it is on top of the stack
+    pub(crate) fn unlock_checks(&self, what: &str, checks: usize) -> Result<Script, Error> {
+        let keys: Vec<String> = match checks {
+            1 => vec![PROOF_KEY.to_string()],
+            n => (0..n).rev().map(proof_key).collect(),
+        };
+        match self.backend {
+            Backend::Wast => {
+                let mut strings = Strings::default();
+                let pushes: Vec<String> = keys
+                    .iter()
+                    .map(|k| {
+                        format!(
+                            "    ;; push(\"{}\")\n    i32.const {}\n    i32.const {}\n    call $push\n",
+                            k,
+                            strings.offset(k),
+                            k.len()
+                        )
+                    })
+                    .collect();
+                let wast = format!(
+                    ";; sample unlock script for {}\n(module\n  (import \"wacc\" \"_push\" (func $push (param i32 i32) (result i32)))\n\n  (func $main (export \"{}\") (param) (result i32)\n{}    return\n  )\n\n  (memory (export \"memory\") 1)\n\n{})\n",
+                    what,
+                    self.entry_points.unlock,
+                    pushes.join("    drop\n"),
+                    strings.data()
+                );
+                Ok(Script::Code(Key::default(), wast))
+            }
+            Backend::Rhai => {
+                let pushes: String = keys
+                    .iter()
+                    .map(|k| format!("    push(\"{}\");\n", k))
+                    .collect();
+                let rhai = format!(
+                    "// sample unlock script for {}\nfn {}() {{\n{}}}\n",
+                    what,
+                    self.entry_points.unlock,
+                    pushes
+                );
+                Ok(Script::Code(Key::default(), rhai))
+            }
+        }
+    }
+
+    /// compile the lock scripts for every rule in the policy
+    pub fn compile(&self, policy: &Policy) -> Result<Vec<Script>, Error> {
+        policy.rules.iter().map(|r| self.lock(r)).collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        engine::{LOCK_FUNC, UNLOCK_FUNC},
+        entry,
+        proof::join_proofs,
+        Entry, Kvp, ScriptEngine, Stk, Value, WaccEngine,
+    };
+    use multicid::Vlad;
+    use multicodec::Codec;
+    use multihash::mh;
+    use multikey::{EncodedMultikey, Multikey, Views};
+    use std::collections::BTreeMap;
+    use wacc::{vm, Stack};
+
+    pub(crate) fn keys() -> (Multikey, Multikey) {
+        let mk = |s: &str| EncodedMultikey::try_from(s).unwrap().to_inner();
+        (
+            mk("fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8"),
+            mk("fba2480260874657374206b65790101203f4c94407de791e53b4df12ef1d5534d1b19ff2ccfccba4ccc4722b6e5e8ea07"),
+        )
+    }
+
+    // the kvp with the public keys at /k1, /k2, /pubkey and /ephemeral and the
+    // hash of "preimage" at /hash
+    pub(crate) fn kvp(k1: &Multikey, k2: &Multikey) -> Kvp {
+        let public =
+            |k: &Multikey| Value::Data(k.conv_view().unwrap().to_public_key().unwrap().into());
+        let hash = mh::Builder::new_from_bytes(Codec::Sha3512, b"preimage")
+            .unwrap()
+            .try_build()
+            .unwrap();
+        let key = |s: &str| Key::try_from(s).unwrap();
+        Kvp::from(BTreeMap::from([
+            (key("/k1"), public(k1)),
+            (key("/k2"), public(k2)),
+            (key("/pubkey"), public(k1)),
+            (key("/ephemeral"), public(k2)),
+            (key("/hash"), Value::Data(hash.into())),
+        ]))
+    }
+
+    // a signature over the entry without its proof
+    pub(crate) fn sign(e: &Entry, key: &Multikey) -> Vec<u8> {
+        let ev: Vec<u8> = e.clone().into();
+        key.sign_view().unwrap().sign(&ev, false, None).unwrap().into()
+    }
+
+    // build an entry with the unlock script and the proofs made by the
+    // closure, joined if there is more than one
+    pub(crate) fn build_entry<F>(unlock: &Script, proofs: F) -> Entry
+    where
+        F: Fn(&Entry) -> Vec<Vec<u8>>,
+    {
+        entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(unlock)
+            .try_build(|e| {
+                let proofs = proofs(e);
+                Ok(match proofs.len() {
+                    1 => proofs[0].clone(),
+                    _ => join_proofs(&proofs),
+                })
+            })
+            .unwrap()
+    }
+
+    // run the unlock script of the entry and then the lock like the verify
+    // pipeline does and get if the lock succeeded
+    pub(crate) fn unlocks(lock: &Script, kvp: &Kvp, entry: &Entry) -> bool {
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+        let context = entry.context();
+        WaccEngine
+            .run(&entry.unlock, UNLOCK_FUNC, entry, entry, &mut pstack, &mut rstack, &context)
+            .unwrap();
+        let result =
+            WaccEngine.run(lock, LOCK_FUNC, kvp, entry, &mut pstack, &mut rstack, &context);
+        result.is_ok() && matches!(rstack.top(), Some(vm::Value::Success(_)))
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = Policy::try_from(
+            "# the root needs two of three\n\
+             / -> threshold(2, [pubkey:/k1, pubkey:/k2, threshold(1, [preimage:/hash])])\n\
+             \n\
+             /foo -> preimage:/hash\n",
+        )
+        .unwrap();
+        assert_eq!(2, policy.rules.len());
+        assert_eq!(
+            Condition::Preimage(Key::try_from("/hash").unwrap()),
+            policy.rules[1].condition
+        );
+        assert_eq!(
+            "/ -> threshold(2, [pubkey:/k1, pubkey:/k2, threshold(1, [preimage:/hash])])",
+            policy.rules[0].to_string()
+        );
+        assert_eq!(policy.rules[0], Rule::try_from(policy.rules[0].to_string().as_str()).unwrap());
+
+        assert!(Rule::try_from("/ pubkey:/k1").is_err());
+        assert!(Rule::try_from("/ -> signature:/k1").is_err());
+        assert!(Rule::try_from("/ -> threshold(3, [pubkey:/k1, pubkey:/k2])").is_err());
+        assert!(Rule::try_from("/ -> threshold(0, [pubkey:/k1])").is_err());
+    }
+
+    #[test]
+    fn test_compile_policy() {
+        let policy = Policy::try_from("/ -> threshold(2, [pubkey:/k1, pubkey:/k2])\n/foo -> preimage:/hash").unwrap();
+        let compiler = Compiler::default();
+        let locks = compiler.compile(&policy).unwrap();
+        assert_eq!(2, locks.len());
+
+        match &locks[0] {
+            Script::Code(path, wast) => {
+                assert_eq!(&Key::default(), path);
+                assert!(wast.contains("(export \"move_every_zig\")"));
+                assert_eq!(2, wast.matches("call $check_signature").count());
+                assert!(wast.contains("i32.ge_u"));
+                assert!(wast.contains("(data (i32.const 0) \"/k1\")"));
+                assert!(wast.contains("(data (i32.const 3) \"/entry/\")"));
+                assert!(wast.contains("(data (i32.const 10) \"/k2\")"));
+            }
+            _ => panic!("expected a code script"),
+        }
+        match &locks[1] {
+            Script::Code(path, wast) => {
+                assert_eq!(&Key::try_from("/foo").unwrap(), path);
+                assert_eq!(1, wast.matches("call $check_preimage").count());
+            }
+            _ => panic!("expected a code script"),
+        }
+
+        match compiler.unlock(&policy.rules[0]).unwrap() {
+            Script::Code(_, wast) => {
+                assert!(wast.contains("(export \"for_great_justice\")"));
+                assert_eq!(2, wast.matches("call $push").count());
+            }
+            _ => panic!("expected a code script"),
+        }
+
+        let compiler = Compiler::new(Backend::Rhai);
+        match compiler.lock(&policy.rules[0]).unwrap() {
+            Script::Code(_, rhai) => assert!(rhai.contains(
+                "((if check_signature(\"/k1\", \"/entry/\") { 1 } else { pop(); 0 }) + (if check_signature(\"/k2\", \"/entry/\") { 1 } else { pop(); 0 }) >= 2)"
+            )),
+            _ => panic!("expected a code script"),
+        }
+    }
+
+    #[test]
+    fn test_run_compiled_policy() {
+        let (k1, k2) = keys();
+        let kvp = kvp(&k1, &k2);
+        let compiler = Compiler::default();
+        let rule = Rule::try_from("/ -> threshold(2, [pubkey:/k1, pubkey:/k2])").unwrap();
+        let lock = compiler.lock(&rule).unwrap();
+        let unlock = compiler.unlock(&rule).unwrap();
+
+        // each key signs its own proof
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), sign(e, &k2)]);
+        assert!(unlocks(&lock, &kvp, &e));
+        // one signature isn't enough, nor is the same signature twice
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), Vec::default()]);
+        assert!(!unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), sign(e, &k1)]);
+        assert!(!unlocks(&lock, &kvp, &e));
+
+        // one of two skips the proof of the check that isn't met
+        let rule = Rule::try_from("/ -> threshold(1, [pubkey:/k2, preimage:/hash])").unwrap();
+        let lock = compiler.lock(&rule).unwrap();
+        let unlock = compiler.unlock(&rule).unwrap();
+        let e = build_entry(&unlock, |_| vec![Vec::default(), b"preimage".to_vec()]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |_| vec![Vec::default(), b"wrong".to_vec()]);
+        assert!(!unlocks(&lock, &kvp, &e));
+    }
+}
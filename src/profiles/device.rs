@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: FSL-1.1
+//! The device profile is for logs that track the state of a device that is
+//! controlled by an identity. The layout is:
+//!
+//! ```text
+//! ─┬─ "/"
+//!  ├─── "ephemeral"      the key that signs the first entry, deleted after
+//!  ├─── "pubkey"         the device signing key
+//!  ├─── "owner"          the vlad of the identity log that owns the device
+//!  ├─┬─ "device/"
+//!  │ ├─── "name"
+//!  │ ├─── "model"
+//!  │ ╰─── "firmware"
+//!  ╰─┬─ "endpoints/"
+//!    ╰─── "<name>"
+//! ```
+use crate::{profiles, Error, Key, Op, Script, Value};
+use multicid::Vlad;
+
+pub use super::identity::{clear_ephemeral, set_ephemeral, set_pubkey, EPHEMERAL, PUBKEY};
+
+/// the key for the vlad of the owning identity
+pub const OWNER: &str = "/owner";
+
+/// the branch with the device details
+pub const DEVICE: &str = "/device/";
+
+/// the key for the device name
+pub const NAME: &str = "/device/name";
+
+/// the key for the device model
+pub const MODEL: &str = "/device/model";
+
+/// the key for the device firmware version
+pub const FIRMWARE: &str = "/device/firmware";
+
+/// the branch with the device's network endpoints
+pub const ENDPOINTS: &str = "/endpoints/";
+
+/// set the vlad of the identity log that owns the device
+pub fn set_owner(owner: &Vlad) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(OWNER)?, Value::Data(owner.clone().into())))
+}
+
+/// set the device name
+pub fn set_name<S: AsRef<str>>(name: S) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(NAME)?, Value::Str(name.as_ref().to_string())))
+}
+
+/// set the device model
+pub fn set_model<S: AsRef<str>>(model: S) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(MODEL)?, Value::Str(model.as_ref().to_string())))
+}
+
+/// set the device firmware version
+pub fn set_firmware<S: AsRef<str>>(version: S) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(FIRMWARE)?, Value::Str(version.as_ref().to_string())))
+}
+
+/// get the key for the named endpoint
+pub fn endpoint<S: AsRef<str>>(name: S) -> Result<Key, Error> {
+    let mut key = Key::try_from(ENDPOINTS)?;
+    key.push(format!("/{}", Key::escape_segment(name)))?;
+    Ok(key)
+}
+
+/// set the address of the named endpoint
+pub fn set_endpoint<S: AsRef<str>, A: AsRef<str>>(name: S, addr: A) -> Result<Op, Error> {
+    Ok(Op::Update(endpoint(name)?, Value::Str(addr.as_ref().to_string())))
+}
+
+/// remove the named endpoint
+pub fn remove_endpoint<S: AsRef<str>>(name: S) -> Result<Op, Error> {
+    Ok(Op::Delete(endpoint(name)?))
+}
+
+/// the default lock scripts for a device log. the root lock is controlled by
+/// the device keys and the endpoints branch has its own lock so that it can
+/// be updated by the owner's delegate.
+pub fn default_locks() -> Result<Vec<Script>, Error> {
+    Ok(vec![
+        profiles::lock(&Key::try_from("/")?),
+        profiles::lock(&Key::try_from(ENDPOINTS)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_layout() {
+        assert_eq!(NAME, set_name("thermostat").unwrap().path().as_str());
+        assert_eq!(OWNER, set_owner(&Vlad::default()).unwrap().path().as_str());
+
+        let op = set_endpoint("api", "/dns/example.com/tcp/443").unwrap();
+        assert_eq!("/endpoints/api", op.path().as_str());
+        assert_eq!(op.path(), remove_endpoint("api").unwrap().path());
+
+        let locks = default_locks().unwrap();
+        assert!(locks[1].path().parent_of(&op.path()));
+        assert!(!locks[1].path().parent_of(&set_name("x").unwrap().path()));
+    }
+}
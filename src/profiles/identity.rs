@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: FSL-1.1
+//! The identity profile is for logs that track the keys controlling an
+//! identity. The layout is:
+//!
+//! ```text
+//! ─┬─ "/"
+//!  ├─── "ephemeral"      the key that signs the first entry, deleted after
+//!  ├─── "recovery"       the recovery key that overrides the pubkey
+//!  ├─── "pubkey"         the current signing key
+//!  ├─── "hash"           the hash of a preimage revealed for recovery
+//!  ╰─┬─ "delegated/"     branches delegated to other controllers
+//!    ╰─┬─ "<name>/"
+//!      ╰─── "<leaf>"
+//! ```
+use crate::{profiles, Error, Key, Op, Script, Value};
+use multihash::Multihash;
+use multikey::Multikey;
+
+/// the key for the ephemeral public key that signs the first entry
+pub const EPHEMERAL: &str = "/ephemeral";
+
+/// the key for the recovery public key
+pub const RECOVERY: &str = "/recovery";
+
+/// the key for the current signing public key
+pub const PUBKEY: &str = "/pubkey";
+
+/// the key for the hash of the recovery preimage
+pub const HASH: &str = "/hash";
+
+/// the branch under which control of sub-branches is delegated
+pub const DELEGATED: &str = "/delegated/";
+
+/// set the ephemeral public key used to sign the first entry
+pub fn set_ephemeral(public_key: &Multikey) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(EPHEMERAL)?, Value::Data(public_key.clone().into())))
+}
+
+/// delete the ephemeral public key once the first entry is signed
+pub fn clear_ephemeral() -> Result<Op, Error> {
+    Ok(Op::Delete(Key::try_from(EPHEMERAL)?))
+}
+
+/// set the recovery public key
+pub fn set_recovery(public_key: &Multikey) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(RECOVERY)?, Value::Data(public_key.clone().into())))
+}
+
+/// set the signing public key
+pub fn set_pubkey(public_key: &Multikey) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(PUBKEY)?, Value::Data(public_key.clone().into())))
+}
+
+/// set the hash of the recovery preimage
+pub fn set_hash(hash: &Multihash) -> Result<Op, Error> {
+    Ok(Op::Update(Key::try_from(HASH)?, Value::Data(hash.clone().into())))
+}
+
+/// get the branch delegated to the named controller
+pub fn delegated_branch<S: AsRef<str>>(name: S) -> Result<Key, Error> {
+    let mut key = Key::try_from(DELEGATED)?;
+    key.push(format!("/{}/", Key::escape_segment(name)))?;
+    Ok(key)
+}
+
+/// get the lock script that lets the named controller update its delegated
+/// branch by signing with the pubkey stored in the branch
+pub fn delegate<S: AsRef<str>>(name: S) -> Result<Script, Error> {
+    Ok(profiles::lock(&delegated_branch(name)?))
+}
+
+/// set a value in the branch delegated to the named controller
+pub fn set_delegated<S: AsRef<str>, L: AsRef<str>>(
+    name: S,
+    leaf: L,
+    value: Value,
+) -> Result<Op, Error> {
+    let mut key = delegated_branch(name)?;
+    key.push(format!("/{}", Key::escape_segment(leaf)))?;
+    Ok(Op::Update(key, value))
+}
+
+/// the default lock scripts for an identity log
+pub fn default_locks() -> Result<Vec<Script>, Error> {
+    Ok(vec![profiles::lock(&Key::try_from("/")?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multikey::EncodedMultikey;
+
+    #[test]
+    fn test_identity_layout() {
+        let key = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0"
+        )
+        .unwrap()
+        .to_inner();
+
+        assert_eq!(PUBKEY, set_pubkey(&key).unwrap().path().as_str());
+        assert_eq!(EPHEMERAL, clear_ephemeral().unwrap().path().as_str());
+
+        let lock = delegate("mike").unwrap();
+        assert_eq!("/delegated/mike/", lock.path().as_str());
+        let op = set_delegated("mike", "endpoint", Value::Str("/dns/example.com".to_string()))
+            .unwrap();
+        assert_eq!("/delegated/mike/endpoint", op.path().as_str());
+        assert!(lock.path().parent_of(&op.path()));
+
+        // names are escaped so they can't escape the delegated branch
+        let op = set_delegated("mike/../dave", "endpoint", Value::Nil).unwrap();
+        assert_eq!(
+            vec!["delegated", "mike/../dave"],
+            delegated_branch("mike/../dave").unwrap().segments()
+        );
+        assert!(!lock.path().parent_of(&op.path()));
+
+        let locks = default_locks().unwrap();
+        assert_eq!(1, locks.len());
+        assert_eq!("/", locks[0].path().as_str());
+    }
+}
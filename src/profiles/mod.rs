@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Opinionated namespace layouts for the common uses of provenance logs. Each
+//! profile defines the key-paths it uses, typed constructors for the ops that
+//! update them and the default lock and unlock scripts.
+pub mod device;
+pub mod identity;
+
+use crate::{Error, Key, Script};
+
+/// the lock script for the first entry, checks a signature by the ephemeral
+/// key recorded in the first entry
+pub const FIRST_LOCK_WAST: &str = include_str!("../../examples/wast/first.wast");
+
+/// the standard lock script, checks a signature by the recovery key, then a
+/// signature by the pubkey and then a preimage of the hash
+pub const LOCK_WAST: &str = include_str!("../../examples/wast/lock.wast");
+
+/// the standard unlock script, pushes the entry and proof on the stack
+pub const UNLOCK_WAST: &str = include_str!("../../examples/wast/unlock.wast");
+
+/// the lock script for the first entry at the root branch
+pub fn first_lock() -> Result<Script, Error> {
+    Ok(Script::Code(Key::try_from("/")?, FIRST_LOCK_WAST.to_string()))
+}
+
+/// the standard lock script for the given branch
+pub fn lock(branch: &Key) -> Script {
+    Script::Code(branch.clone(), LOCK_WAST.to_string())
+}
+
+/// the standard unlock script
+pub fn unlock() -> Result<Script, Error> {
+    Ok(Script::Code(Key::try_from("/")?, UNLOCK_WAST.to_string()))
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Typed entry proofs. The proof in an entry is stored as bytes so that any
+//! kind of proof can be used. Proof sorts out the well known kinds by their
+//! multicodec tag so tooling can display and inspect them without guessing
+//! the format. Proof bytes that aren't one of the known kinds are kept as
+//! Raw so proofs from newer versions still round trip.
+//!
+//! Locks that make more than one check, e.g. an m-of-n multisig, need one
+//! proof per check. Those are joined into the entry proof with
+//! [`join_proofs`] and the scripts get the n-th one at `/entry/proof/<n>`.
+use crate::Entry;
+use core::fmt;
+use multicodec::Codec;
+use multikey::{Multikey, Views};
+use multisig::Multisig;
+use multitrait::TryDecodeFrom;
+use multiutil::{Varbytes, Varuint};
+
+/// the codec tagging hash preimage proofs
+pub const PREIMAGE_CODEC: Codec = Codec::Identity;
+
+/// The typed form of the proof in an Entry
+#[derive(Clone, Debug, PartialEq)]
+pub enum Proof {
+    /// a digital signature, tagged with the multisig sigil
+    MultiSig(Multisig),
+    /// a hash preimage, tagged with [`PREIMAGE_CODEC`]
+    Preimage(Varbytes),
+    /// proof data of any other kind
+    Raw(Vec<u8>),
+}
+
+impl Proof {
+    /// get the name of the kind of proof
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::MultiSig(_) => "multisig",
+            Self::Preimage(_) => "preimage",
+            Self::Raw(_) => "raw",
+        }
+    }
+}
+
+impl From<&[u8]> for Proof {
+    fn from(bytes: &[u8]) -> Self {
+        // the known kinds must use up all of the bytes
+        if let Ok((ms, ptr)) = Multisig::try_decode_from(bytes) {
+            if ptr.is_empty() {
+                return Self::MultiSig(ms);
+            }
+        }
+        if let Ok((codec, ptr)) = Codec::try_decode_from(bytes) {
+            if codec == PREIMAGE_CODEC {
+                if let Ok((preimage, ptr)) = Varbytes::try_decode_from(ptr) {
+                    if ptr.is_empty() {
+                        return Self::Preimage(preimage);
+                    }
+                }
+            }
+        }
+        Self::Raw(bytes.to_vec())
+    }
+}
+
+impl From<Proof> for Vec<u8> {
+    fn from(proof: Proof) -> Self {
+        match proof {
+            Proof::MultiSig(ms) => ms.into(),
+            Proof::Preimage(preimage) => {
+                let mut v: Vec<u8> = PREIMAGE_CODEC.into();
+                v.append(&mut preimage.into());
+                v
+            }
+            Proof::Raw(bytes) => bytes,
+        }
+    }
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = Vec::<u8>::from(self.clone()).len();
+        write!(f, "{} ({} bytes)", self.kind(), len)
+    }
+}
+
+/// join the proofs for the checks of a lock into one entry proof. Checks
+/// that aren't met can be given an empty proof.
+pub fn join_proofs(proofs: &[Vec<u8>]) -> Vec<u8> {
+    let mut v: Vec<u8> = Varuint(proofs.len()).into();
+    for proof in proofs {
+        v.append(&mut Varbytes(proof.clone()).into());
+    }
+    v
+}
+
+/// split an entry proof made with [`join_proofs`] into the proofs for the
+/// checks, None if it isn't a joined proof
+pub fn split_proofs(proof: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let (count, mut ptr) = Varuint::<usize>::try_decode_from(proof).ok()?;
+    let mut proofs = Vec::default();
+    for _ in 0..count.to_inner() {
+        let (p, rest) = Varbytes::try_decode_from(ptr).ok()?;
+        proofs.push(p.to_inner());
+        ptr = rest;
+    }
+    // a joined proof uses up all of the bytes
+    ptr.is_empty().then_some(proofs)
+}
+
+impl Entry {
+    /// get the typed proof of the entry
+    pub fn proof(&self) -> Proof {
+        Proof::from(self.proof.as_slice())
+    }
+
+    /// true if the proof is a signature by the key over the entry with an
+    /// empty proof
+    pub fn signed_by(&self, key: &Multikey) -> bool {
+        let ms = match self.proof() {
+            Proof::MultiSig(ms) => ms,
+            _ => return false,
+        };
+        let mut e = self.clone();
+        e.proof = Vec::default();
+        let msg: Vec<u8> = e.into();
+        match key.verify_view() {
+            Ok(verify) => verify.verify(&ms, Some(&msg)).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, Script};
+    use multicid::Vlad;
+    use multikey::{EncodedMultikey, Views};
+
+    #[test]
+    fn test_proof_kinds() {
+        let key = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8"
+        )
+        .unwrap()
+        .to_inner();
+        let builder = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default());
+
+        // a signature
+        let e = builder
+            .try_build(|e| {
+                let ms = key.sign_view().unwrap().sign(&e.canonical_bytes(), false, None).unwrap();
+                Ok(ms.into())
+            })
+            .unwrap();
+        assert!(matches!(e.proof(), Proof::MultiSig(_)));
+        assert_eq!(e.proof, Vec::<u8>::from(e.proof()));
+
+        // a preimage
+        let preimage = Proof::Preimage(Varbytes(b"for great justice".to_vec()));
+        let e = builder.try_build(|_| Ok(preimage.clone().into())).unwrap();
+        assert_eq!(preimage, e.proof());
+        assert_eq!("preimage (19 bytes)", e.proof().to_string());
+
+        // anything else is kept as is
+        let e = builder.try_build(|_| Ok(b"proof".to_vec())).unwrap();
+        assert_eq!(Proof::Raw(b"proof".to_vec()), e.proof());
+        assert_eq!(e.proof, Vec::<u8>::from(e.proof()));
+    }
+
+    #[test]
+    fn test_joined_proofs() {
+        let proofs = vec![b"one".to_vec(), Vec::default(), b"three".to_vec()];
+        let joined = join_proofs(&proofs);
+        assert_eq!(Some(proofs.clone()), split_proofs(&joined));
+        assert!(split_proofs(&joined[..joined.len() - 1]).is_none());
+
+        // the scripts get each proof at its own key
+        let e = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(joined.clone()))
+            .unwrap();
+        let proof =
+            |n: usize| e.get_value(&crate::Key::try_from(format!("/entry/proof/{n}")).unwrap());
+        assert_eq!(Some(crate::Value::Data(b"three".to_vec())), proof(2));
+        assert_eq!(Some(crate::Value::Data(Vec::default())), proof(1));
+        assert!(proof(3).is_none());
+    }
+}
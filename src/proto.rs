@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Protobuf messages for consumers in other languages. The messages match
+//! `proto/plog.proto` so services in other languages can generate their own
+//! code from it. The native binary encoding stays the canonical signed form,
+//! converting a message back into an entry checks that it re-encodes to
+//! canonical bytes so the cid and proof still match.
+use crate::{
+    entry::CidCache, error::EntryError, log, Entry as NativeEntry, Error, Key, Log as NativeLog,
+    Op as NativeOp, Script as NativeScript, Value as NativeValue,
+};
+use multicid::{Cid, Vlad};
+
+/// a value in the virtual namespace
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Value {
+    /// the kind of value
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4")]
+    pub kind: Option<value::Kind>,
+}
+
+/// the kinds of [`Value`]
+pub mod value {
+    /// the kind of value
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        /// an empty value
+        #[prost(bool, tag = "1")]
+        Nil(bool),
+        /// a printable string value
+        #[prost(string, tag = "2")]
+        Str(String),
+        /// a binary blob value
+        #[prost(bytes = "vec", tag = "3")]
+        Data(Vec<u8>),
+        /// a sealed value in its native binary encoding
+        #[prost(bytes = "vec", tag = "4")]
+        Encrypted(Vec<u8>),
+        /// a reference to a value in another log in its native binary encoding
+        #[prost(bytes = "vec", tag = "5")]
+        PlogRef(Vec<u8>),
+    }
+}
+
+/// the type of an [`Op`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum OpType {
+    /// no operation
+    Noop = 0,
+    /// delete the value associated with the key
+    Delete = 1,
+    /// update/create the key value pair
+    Update = 2,
+}
+
+/// an operation on the virtual namespace
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Op {
+    /// the type of operation
+    #[prost(enumeration = "OpType", tag = "1")]
+    pub op: i32,
+    /// the key-path
+    #[prost(string, tag = "2")]
+    pub key: String,
+    /// the value of an update
+    #[prost(message, optional, tag = "3")]
+    pub value: Option<Value>,
+}
+
+/// a lock or unlock script
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Script {
+    /// the key-path the script governs
+    #[prost(string, tag = "1")]
+    pub path: String,
+    /// the script code
+    #[prost(oneof = "script::Code", tags = "2, 3, 4")]
+    pub code: Option<script::Code>,
+}
+
+/// the kinds of [`Script`] code
+pub mod script {
+    /// the script code
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Code {
+        /// compiled binary script
+        #[prost(bytes = "vec", tag = "2")]
+        Bin(Vec<u8>),
+        /// uncompiled script code
+        #[prost(string, tag = "3")]
+        Text(String),
+        /// the encoded cid of the script
+        #[prost(bytes = "vec", tag = "4")]
+        Cid(Vec<u8>),
+    }
+}
+
+/// a log entry, the vlad and cids are in their native binary encoding
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Entry {
+    /// the entry version
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    /// the encoded vlad
+    #[prost(bytes = "vec", tag = "2")]
+    pub vlad: Vec<u8>,
+    /// the encoded prev cid
+    #[prost(bytes = "vec", tag = "3")]
+    pub prev: Vec<u8>,
+    /// the encoded lipmaa cid
+    #[prost(bytes = "vec", tag = "4")]
+    pub lipmaa: Vec<u8>,
+    /// the sequence number
+    #[prost(uint64, tag = "5")]
+    pub seqno: u64,
+    /// the ops
+    #[prost(message, repeated, tag = "6")]
+    pub ops: Vec<Op>,
+    /// the lock scripts
+    #[prost(message, repeated, tag = "7")]
+    pub locks: Vec<Script>,
+    /// the unlock script
+    #[prost(message, optional, tag = "8")]
+    pub unlock: Option<Script>,
+    /// the proof
+    #[prost(bytes = "vec", tag = "9")]
+    pub proof: Vec<u8>,
+}
+
+/// a log, the entries are in seqno order
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Log {
+    /// the log version
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    /// the encoded vlad
+    #[prost(bytes = "vec", tag = "2")]
+    pub vlad: Vec<u8>,
+    /// the lock script for the first entry
+    #[prost(message, optional, tag = "3")]
+    pub first_lock: Option<Script>,
+    /// the encoded foot cid
+    #[prost(bytes = "vec", tag = "4")]
+    pub foot: Vec<u8>,
+    /// the encoded head cid
+    #[prost(bytes = "vec", tag = "5")]
+    pub head: Vec<u8>,
+    /// the entries
+    #[prost(message, repeated, tag = "6")]
+    pub entries: Vec<Entry>,
+}
+
+fn proto_err<S: ToString>(s: S) -> Error {
+    Error::Proto(s.to_string())
+}
+
+impl From<&NativeValue> for Value {
+    fn from(v: &NativeValue) -> Self {
+        let kind = match v {
+            NativeValue::Nil => value::Kind::Nil(true),
+            NativeValue::Str(s) => value::Kind::Str(s.clone()),
+            NativeValue::Data(b) => value::Kind::Data(b.clone()),
+            NativeValue::Encrypted { .. } => value::Kind::Encrypted(v.clone().into()),
+            NativeValue::PlogRef { .. } => value::Kind::PlogRef(v.clone().into()),
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+impl TryFrom<&Value> for NativeValue {
+    type Error = Error;
+
+    fn try_from(v: &Value) -> Result<Self, Self::Error> {
+        match &v.kind {
+            Some(value::Kind::Nil(_)) => Ok(Self::Nil),
+            Some(value::Kind::Str(s)) => Ok(Self::Str(s.clone())),
+            Some(value::Kind::Data(b)) => Ok(Self::Data(b.clone())),
+            Some(value::Kind::Encrypted(b)) => match Self::try_from(b.as_slice())? {
+                v @ Self::Encrypted { .. } => Ok(v),
+                _ => Err(proto_err("invalid encrypted value")),
+            },
+            Some(value::Kind::PlogRef(b)) => match Self::try_from(b.as_slice())? {
+                v @ Self::PlogRef { .. } => Ok(v),
+                _ => Err(proto_err("invalid plog reference value")),
+            },
+            None => Err(proto_err("missing value")),
+        }
+    }
+}
+
+impl From<&NativeOp> for Op {
+    fn from(op: &NativeOp) -> Self {
+        let (t, value) = match op {
+            NativeOp::Noop(_) => (OpType::Noop, None),
+            NativeOp::Delete(_) => (OpType::Delete, None),
+            NativeOp::Update(_, v) => (OpType::Update, Some(v.into())),
+        };
+        Self {
+            op: t as i32,
+            key: op.path_ref().to_string(),
+            value,
+        }
+    }
+}
+
+impl TryFrom<&Op> for NativeOp {
+    type Error = Error;
+
+    fn try_from(op: &Op) -> Result<Self, Self::Error> {
+        let key = Key::try_from(op.key.as_str())?;
+        match OpType::try_from(op.op).map_err(proto_err)? {
+            OpType::Noop => Ok(Self::Noop(key)),
+            OpType::Delete => Ok(Self::Delete(key)),
+            OpType::Update => {
+                let value = op.value.as_ref().ok_or_else(|| proto_err("missing value"))?;
+                Ok(Self::Update(key, value.try_into()?))
+            }
+        }
+    }
+}
+
+impl From<&NativeScript> for Script {
+    fn from(s: &NativeScript) -> Self {
+        let code = match s {
+            NativeScript::Bin(_, b) => script::Code::Bin(b.clone()),
+            NativeScript::Code(_, c) => script::Code::Text(c.clone()),
+            NativeScript::Cid(_, c) => script::Code::Cid(c.clone().into()),
+        };
+        Self {
+            path: s.path_ref().to_string(),
+            code: Some(code),
+        }
+    }
+}
+
+impl TryFrom<&Script> for NativeScript {
+    type Error = Error;
+
+    fn try_from(s: &Script) -> Result<Self, Self::Error> {
+        let path = Key::try_from(s.path.as_str())?;
+        match &s.code {
+            Some(script::Code::Bin(b)) => Ok(Self::Bin(path, b.clone())),
+            Some(script::Code::Text(c)) => Ok(Self::Code(path, c.clone())),
+            Some(script::Code::Cid(c)) => Ok(Self::Cid(path, Cid::try_from(c.as_slice())?)),
+            None => Err(proto_err("missing script code")),
+        }
+    }
+}
+
+impl NativeEntry {
+    /// convert the entry to its protobuf message
+    pub fn to_proto(&self) -> Entry {
+        Entry {
+            version: self.version,
+            vlad: self.vlad.clone().into(),
+            prev: self.prev.clone().into(),
+            lipmaa: self.lipmaa.clone().into(),
+            seqno: self.seqno,
+            ops: self.ops.iter().map(Op::from).collect(),
+            locks: self.locks.iter().map(Script::from).collect(),
+            unlock: Some((&self.unlock).into()),
+            proof: self.proof.clone(),
+        }
+    }
+
+    /// convert the protobuf message to an entry. the entry must re-encode to
+    /// its canonical binary form.
+    pub fn from_proto(msg: &Entry) -> Result<Self, Error> {
+        let unlock = msg.unlock.as_ref().ok_or(EntryError::MissingUnlockScript)?;
+        let entry = Self {
+            version: msg.version,
+            vlad: Vlad::try_from(msg.vlad.as_slice())?,
+            prev: Cid::try_from(msg.prev.as_slice())?,
+            lipmaa: Cid::try_from(msg.lipmaa.as_slice())?,
+            seqno: msg.seqno,
+            ops: msg.ops.iter().map(NativeOp::try_from).collect::<Result<_, _>>()?,
+            locks: msg
+                .locks
+                .iter()
+                .map(NativeScript::try_from)
+                .collect::<Result<_, _>>()?,
+            unlock: unlock.try_into()?,
+            proof: msg.proof.clone(),
+            cached_cid: CidCache::default(),
+        };
+        // decoding checks the version and the encoding
+        Self::try_from(entry.canonical_bytes().as_slice())
+    }
+}
+
+impl NativeLog {
+    /// convert the log to its protobuf message
+    pub fn to_proto(&self) -> Log {
+        Log {
+            version: self.version,
+            vlad: self.vlad.clone().into(),
+            first_lock: Some((&self.first_lock).into()),
+            foot: self.foot.clone().into(),
+            head: self.head.clone().into(),
+            entries: self.iter().map(NativeEntry::to_proto).collect(),
+        }
+    }
+
+    /// convert the protobuf message to a log
+    pub fn from_proto(msg: &Log) -> Result<Self, Error> {
+        let first_lock = msg
+            .first_lock
+            .as_ref()
+            .ok_or_else(|| proto_err("missing first lock script"))?;
+        let mut builder = log::Builder::new()
+            .with_vlad(&Vlad::try_from(msg.vlad.as_slice())?)
+            .with_first_lock(&first_lock.try_into()?)
+            .with_foot(&Cid::try_from(msg.foot.as_slice())?)
+            .with_head(&Cid::try_from(msg.head.as_slice())?);
+        for entry in &msg.entries {
+            builder = builder.append_entry(&NativeEntry::from_proto(entry)?);
+        }
+        builder.try_build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry;
+    use prost::Message;
+
+    #[test]
+    fn test_proto_round_trip() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&NativeScript::Code(Key::default(), "(module)".to_string()))
+            .add_lock(&NativeScript::Bin(Key::try_from("/foo/").unwrap(), vec![1, 2, 3]))
+            .add_op(&NativeOp::Update(
+                "/foo/bar".try_into().unwrap(),
+                NativeValue::Str("baz".to_string()),
+            ))
+            .add_op(&NativeOp::Update("/foo/nil".try_into().unwrap(), NativeValue::Nil))
+            .add_op(&NativeOp::Delete("/foo/old".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let bytes = e1.to_proto().encode_to_vec();
+        let decoded = NativeEntry::from_proto(&Entry::decode(bytes.as_slice()).unwrap()).unwrap();
+        assert_eq!(e1, decoded);
+        assert_eq!(e1.cid(), decoded.cid());
+
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&NativeScript::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        let bytes = log.to_proto().encode_to_vec();
+        let decoded = NativeLog::from_proto(&Log::decode(bytes.as_slice()).unwrap()).unwrap();
+        assert_eq!(log.head, decoded.head);
+        assert_eq!(log.entries, decoded.entries);
+
+        // an op with an unknown type is rejected
+        let mut msg = e1.to_proto();
+        msg.ops[0].op = 7;
+        assert!(NativeEntry::from_proto(&msg).is_err());
+    }
+}
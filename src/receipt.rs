@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{error::LogError, Error, Log, Pipeline, ScriptEngine};
+use core::fmt;
+use multicid::{cid, Cid};
+use multicodec::Codec;
+use multihash::{mh, Multihash};
+use multitrait::{Null, TryDecodeFrom};
+use multiutil::{Varbytes, Varuint};
+
+/// the hash codec used for the kvp state root in receipts
+pub const STATE_CODEC: Codec = Codec::Sha3256;
+
+/// A VerificationReceipt is a verifier's signed statement that it verified
+/// an entry and the state it got. Each receipt commits to the one before it
+/// so the receipts for a log form a chain. An auditor that delegates
+/// verification to a service can check the chain and later re-verify any
+/// receipt against the log to spot-check the service's work.
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct VerificationReceipt {
+    /// the seqno of the verified entry
+    pub seqno: u64,
+    /// the cid of the verified entry
+    pub entry: Cid,
+    /// the cid of the previous receipt, null for the first receipt
+    pub prev: Cid,
+    /// the check count from the lock script that succeeded
+    pub count: usize,
+    /// the root hash of the kvp state after the entry was applied
+    pub state: Multihash,
+    /// the verifier's proof over the receipt with an empty proof
+    pub proof: Vec<u8>,
+}
+
+impl VerificationReceipt {
+    /// get the cid of the receipt
+    pub fn cid(&self) -> Cid {
+        let v: Vec<u8> = self.clone().into();
+        cid::Builder::new(Codec::Cidv1)
+            .with_target_codec(Codec::Raw)
+            .with_hash(
+                &mh::Builder::new_from_bytes(Codec::Sha3512, &v)
+                    .unwrap()
+                    .try_build()
+                    .unwrap(),
+            )
+            .try_build()
+            .unwrap()
+    }
+
+    /// get the bytes the proof is generated over
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut r = self.clone();
+        r.proof = Vec::default();
+        r.into()
+    }
+}
+
+impl From<VerificationReceipt> for Vec<u8> {
+    fn from(val: VerificationReceipt) -> Self {
+        let mut v = Vec::default();
+        // add in the seqno
+        v.append(&mut Varuint(val.seqno).into());
+        // add in the entry cid
+        v.append(&mut val.entry.clone().into());
+        // add in the previous receipt cid
+        v.append(&mut val.prev.clone().into());
+        // add in the check count
+        v.append(&mut Varuint(val.count).into());
+        // add in the state root
+        v.append(&mut val.state.clone().into());
+        // add in the proof
+        v.append(&mut Varbytes(val.proof.clone()).into());
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for VerificationReceipt {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Error> {
+        let (r, _) = Self::try_decode_from(bytes)?;
+        Ok(r)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for VerificationReceipt {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the seqno
+        let (seqno, ptr) = Varuint::<u64>::try_decode_from(bytes)?;
+        let seqno = seqno.to_inner();
+        // decode the entry cid
+        let (entry, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the previous receipt cid
+        let (prev, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the check count
+        let (count, ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let count = count.to_inner();
+        // decode the state root
+        let (state, ptr) = Multihash::try_decode_from(ptr)?;
+        // decode the proof
+        let (proof, ptr) = Varbytes::try_decode_from(ptr)?;
+        let proof = proof.to_inner();
+        Ok((
+            Self {
+                seqno,
+                entry,
+                prev,
+                count,
+                state,
+                proof,
+            },
+            ptr,
+        ))
+    }
+}
+
+impl fmt::Debug for VerificationReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "receipt #{} - {:?} - count: {} - proof of length: {}",
+            self.seqno,
+            self.entry,
+            self.count,
+            self.proof.len()
+        )
+    }
+}
+
+/// Checks that the receipts form an unbroken chain and calls `check_proof`
+/// to check the verifier's proof on each one
+pub fn verify_chain<F>(receipts: &[VerificationReceipt], mut check_proof: F) -> Result<(), Error>
+where
+    F: FnMut(&VerificationReceipt) -> Result<(), Error>,
+{
+    let mut prev = Cid::null();
+    for r in receipts {
+        if r.prev != prev {
+            return Err(LogError::BrokenReceiptChain(r.seqno).into());
+        }
+        check_proof(r)?;
+        prev = r.cid();
+    }
+    Ok(())
+}
+
+impl Log {
+    /// Verifies the log and emits a receipt for each entry. The `gen_proof`
+    /// closure is called to sign each receipt. Verification stops at the first
+    /// entry that fails.
+    pub fn verify_with_receipts<F>(
+        &self,
+        pipeline: Pipeline,
+        engine: &dyn ScriptEngine,
+        mut gen_proof: F,
+    ) -> Result<Vec<VerificationReceipt>, Error>
+    where
+        F: FnMut(&mut VerificationReceipt) -> Result<Vec<u8>, Error>,
+    {
+        let mut receipts: Vec<VerificationReceipt> = Vec::default();
+        for result in self.verify_with_pipeline(pipeline, engine) {
+            let (count, entry, kvp) = result?;
+            let mut r = VerificationReceipt {
+                seqno: entry.seqno(),
+                entry: entry.cid(),
+                prev: receipts.last().map(|r| r.cid()).unwrap_or_else(Cid::null),
+                count,
+                state: kvp.root_hash(STATE_CODEC)?,
+                proof: Vec::default(),
+            };
+            r.proof = gen_proof(&mut r)?;
+            receipts.push(r);
+        }
+        Ok(receipts)
+    }
+
+    /// Re-verifies the log up to the entry in the receipt and checks that the
+    /// receipt matches the result
+    pub fn spot_check(
+        &self,
+        receipt: &VerificationReceipt,
+        pipeline: Pipeline,
+        engine: &dyn ScriptEngine,
+    ) -> Result<(), Error> {
+        for result in self.verify_with_pipeline(pipeline, engine) {
+            let (count, entry, kvp) = result?;
+            if entry.seqno() == receipt.seqno {
+                if entry.cid() != receipt.entry
+                    || count != receipt.count
+                    || kvp.root_hash(STATE_CODEC)? != receipt.state
+                {
+                    return Err(LogError::ReceiptMismatch(receipt.seqno).into());
+                }
+                return Ok(());
+            }
+        }
+        Err(LogError::SeqnoNotFound(receipt.seqno).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, pipeline, Op, Script, Value, WaccEngine};
+    use multicid::Vlad;
+
+    fn stages() -> Pipeline {
+        Pipeline::new()
+            .with_stage(pipeline::Structure)
+            .with_stage(pipeline::Apply)
+    }
+
+    #[test]
+    fn test_receipts() {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/n".try_into().unwrap(), Value::Str("0".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for n in 1..3 {
+            let e = entry::Builder::from(entries.last().unwrap())
+                .with_unlock(&Script::default())
+                .add_op(&Op::Update("/n".try_into().unwrap(), Value::Str(n.to_string())))
+                .try_build(|_| Ok(Vec::default()))
+                .unwrap();
+            entries.push(e);
+        }
+        let log = entries
+            .iter()
+            .fold(
+                log::Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+
+        let receipts = log
+            .verify_with_receipts(stages(), &WaccEngine, |r| Ok(r.signing_bytes()))
+            .unwrap();
+        assert_eq!(3, receipts.len());
+        verify_chain(&receipts, |r| {
+            if r.proof == r.signing_bytes() {
+                Ok(())
+            } else {
+                Err(LogError::VerifyFailed("bad receipt proof".to_string()).into())
+            }
+        })
+        .unwrap();
+
+        // receipts survive encoding
+        let bytes: Vec<u8> = receipts[1].clone().into();
+        assert_eq!(receipts[1], VerificationReceipt::try_from(bytes.as_slice()).unwrap());
+
+        // spot check an honest receipt
+        log.spot_check(&receipts[1], stages(), &WaccEngine).unwrap();
+
+        // a receipt claiming a different state is caught
+        let mut lying = receipts[1].clone();
+        lying.state = receipts[2].state.clone();
+        assert!(log.spot_check(&lying, stages(), &WaccEngine).is_err());
+
+        // dropping a receipt breaks the chain
+        let gapped = vec![receipts[0].clone(), receipts[2].clone()];
+        assert!(verify_chain(&gapped, |_| Ok(())).is_err());
+    }
+}
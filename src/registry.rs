@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: FSL-1.1
+//! A Registry holds many logs keyed by their vlad. A vlad is supposed to name
+//! exactly one log so a second, different log claiming a vlad that is
+//! already registered is either a bug or an attempt to hijack the identity.
+//! The registry never overwrites a log with one that doesn't extend it. It
+//! reports the collision with [`LogError::VladCollision`] instead and keeps
+//! the log it already has. A log that does extend it is verified before it
+//! replaces it.
+use crate::{error::LogError, resolver::LogResolver, Error, Log, ScriptEngine, WaccEngine};
+use multibase::Base;
+use multicid::{Cid, EncodedVlad, Vlad};
+use std::collections::BTreeMap;
+
+/// A collection of logs keyed by their vlad
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    logs: BTreeMap<Vec<u8>, Log>,
+}
+
+impl Registry {
+    /// create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the number of logs in the registry
+    pub fn len(&self) -> usize {
+        self.logs.len()
+    }
+
+    /// true if there are no logs in the registry
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty()
+    }
+
+    /// get the log with the vlad
+    pub fn get(&self, vlad: &Vlad) -> Option<&Log> {
+        self.logs.get(&Vec::<u8>::from(vlad.clone()))
+    }
+
+    /// get an iterator over the logs in the registry
+    pub fn iter(&self) -> impl Iterator<Item = &Log> {
+        self.logs.values()
+    }
+
+    /// check the log against the one registered with its vlad, if any. It is
+    /// the same log if it has the same first lock and the head of one of them
+    /// is the head of the other or one of its ancestors. Returns true if the
+    /// log is new or extends the registered one and false if it is the same
+    /// or an older copy of it.
+    pub fn check(&self, log: &Log) -> Result<bool, Error> {
+        let registered = match self.get(&log.vlad) {
+            Some(registered) => registered,
+            None => return Ok(true),
+        };
+        if registered.first_lock != log.first_lock {
+            return Err(collision(&log.vlad));
+        }
+        if has_ancestor(log, &registered.head) {
+            Ok(log.head != registered.head)
+        } else if has_ancestor(registered, &log.head) {
+            Ok(false)
+        } else {
+            // a different history under the same vlad
+            Err(collision(&log.vlad))
+        }
+    }
+
+    /// add the log to the registry or update the registered log with the
+    /// same vlad, verifying it with the [`WaccEngine`] first. A log that
+    /// collides with the registered one or fails to verify is rejected and
+    /// the registered log is kept. Returns true if the registry changed.
+    pub fn insert(&mut self, log: &Log) -> Result<bool, Error> {
+        self.insert_with_engine(log, &WaccEngine)
+    }
+
+    /// add or update the log like [`Registry::insert`] but verify it by
+    /// running the scripts with the given engine
+    pub fn insert_with_engine(
+        &mut self,
+        log: &Log,
+        engine: &dyn ScriptEngine,
+    ) -> Result<bool, Error> {
+        if !self.check(log)? {
+            return Ok(false);
+        }
+        for result in log.verify_with_engine(engine) {
+            result?;
+        }
+        self.logs.insert(log.vlad.clone().into(), log.clone());
+        Ok(true)
+    }
+
+    /// remove the log with the vlad from the registry
+    pub fn remove(&mut self, vlad: &Vlad) -> Option<Log> {
+        self.logs.remove(&Vec::<u8>::from(vlad.clone()))
+    }
+}
+
+impl LogResolver for Registry {
+    fn resolve(&self, vlad: &Vlad) -> Option<Log> {
+        self.get(vlad).cloned()
+    }
+}
+
+// walk the prev links back from the head of the log looking for the entry
+// with the cid. the walk is bounded by the number of entries so a log with
+// bad links can't loop forever.
+fn has_ancestor(log: &Log, cid: &Cid) -> bool {
+    let mut next = log.head.clone();
+    for _ in 0..=log.entries.len() {
+        if &next == cid {
+            return true;
+        }
+        match log.entries.get(&next) {
+            Some(entry) if !entry.prev_ref().is_null() => next = entry.prev(),
+            _ => return false,
+        }
+    }
+    false
+}
+
+fn collision(vlad: &Vlad) -> Error {
+    LogError::VladCollision(EncodedVlad::new(Base::Base32Lower, vlad.clone()).to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::LOCK_FUNC, entry, log, Entry, Key, Op, Script, Stk, Value};
+    use wacc::{vm, Pairs, Stack};
+
+    // passes every lock
+    struct Pass;
+
+    impl ScriptEngine for Pass {
+        fn run(
+            &self,
+            _script: &Script,
+            func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            if func == LOCK_FUNC {
+                rstack.push(vm::Value::Success(0));
+            }
+            Ok(())
+        }
+    }
+
+    fn first(lock: &Script) -> (Entry, Log) {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(lock)
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        (e1, log)
+    }
+
+    fn extend(log: &Log, prev: &Entry, value: &str) -> (Entry, Log) {
+        let e = entry::Builder::from(prev)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(Key::try_from("/a").unwrap(), Value::Str(value.to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut builder = log::Builder::new()
+            .with_vlad(&log.vlad)
+            .with_first_lock(&log.first_lock);
+        for entry in log.iter() {
+            builder = builder.append_entry(entry);
+        }
+        (e.clone(), builder.append_entry(&e).try_build().unwrap())
+    }
+
+    #[test]
+    fn test_registry() {
+        let (e1, log) = first(&Script::default());
+        let mut registry = Registry::new();
+        assert!(registry.insert_with_engine(&log, &Pass).unwrap());
+        assert_eq!(1, registry.len());
+        assert_eq!(Some(&log), registry.get(&Vlad::default()));
+        assert!(registry.resolve(&Vlad::default()).is_some());
+
+        // inserting the same log again changes nothing
+        assert!(!registry.insert_with_engine(&log, &Pass).unwrap());
+
+        // a longer copy of the log replaces it and an older one is ignored
+        let (_, longer) = extend(&log, &e1, "1");
+        assert!(registry.insert_with_engine(&longer, &Pass).unwrap());
+        assert!(!registry.insert_with_engine(&log, &Pass).unwrap());
+        assert_eq!(Some(&longer), registry.get(&Vlad::default()));
+
+        assert_eq!(Some(longer), registry.remove(&Vlad::default()));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_vlad_collision() {
+        let (e1, log) = first(&Script::default());
+        let mut registry = Registry::new();
+        registry.insert_with_engine(&log, &Pass).unwrap();
+
+        // a log with a different first lock claiming the same vlad
+        let lock = Script::Bin(Key::default(), b"hijack".to_vec());
+        let (_, hijack) = first(&lock);
+        assert!(matches!(
+            registry.insert_with_engine(&hijack, &Pass),
+            Err(Error::Log(LogError::VladCollision(_)))
+        ));
+
+        // a log with the same first lock but a different history
+        let (_, ours) = extend(&log, &e1, "ours");
+        let (_, theirs) = extend(&log, &e1, "theirs");
+        registry.insert_with_engine(&ours, &Pass).unwrap();
+        assert!(matches!(
+            registry.check(&theirs),
+            Err(Error::Log(LogError::VladCollision(_)))
+        ));
+        assert!(registry.insert_with_engine(&theirs, &Pass).is_err());
+
+        // the registered log is kept
+        assert_eq!(Some(&ours), registry.get(&Vlad::default()));
+    }
+
+    #[test]
+    fn test_fork_with_registered_head() {
+        let (e1, log) = first(&Script::default());
+        let (ours_head, ours) = extend(&log, &e1, "ours");
+        let mut registry = Registry::new();
+        registry.insert_with_engine(&ours, &Pass).unwrap();
+
+        // a fork from the first entry that also carries the registered head
+        // without descending from it
+        let (theirs_head, theirs) = extend(&log, &e1, "theirs");
+        let (_, mut fork) = extend(&theirs, &theirs_head, "more");
+        fork.entries.insert(ours_head.cid(), ours_head);
+        assert!(matches!(
+            registry.check(&fork),
+            Err(Error::Log(LogError::VladCollision(_)))
+        ));
+        assert!(registry.insert_with_engine(&fork, &Pass).is_err());
+        assert_eq!(Some(&ours), registry.get(&Vlad::default()));
+    }
+
+    #[test]
+    fn test_insert_verifies() {
+        let (e1, log) = first(&Script::default());
+        let mut registry = Registry::new();
+        registry.insert_with_engine(&log, &Pass).unwrap();
+
+        // a longer log that doesn't verify doesn't replace the registered one
+        let (_, longer) = extend(&log, &e1, "1");
+        assert!(registry.insert(&longer).is_err());
+        assert_eq!(Some(&log), registry.get(&Vlad::default()));
+    }
+}
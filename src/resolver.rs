@@ -0,0 +1,487 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{
+    error::{LogError, ScriptError, ValueError},
+    Error, Key, Log, Script, ScriptEngine, Stk, Value,
+};
+use multicid::{Cid, Vlad};
+use multihash::Multihash;
+use multikey::Multikey;
+use multitrait::TryDecodeFrom;
+use wacc::{vm, Pairs, Stack};
+
+/// A KeyResolver looks up a Multikey by its fingerprint. This allows logs to
+/// store only the fingerprint of a key in the namespace and keep the key
+/// material in an external keyring.
+pub trait KeyResolver {
+    /// get the key with the given fingerprint
+    fn resolve(&self, fingerprint: &Multihash) -> Option<Multikey>;
+}
+
+/// A ScriptEngine that resolves key fingerprints before handing values to
+/// the scripts run by another engine. Any binary value read by a script that
+/// is a fingerprint known to the resolver is replaced with the encoded key.
+pub struct ResolvingEngine<'a> {
+    engine: &'a dyn ScriptEngine,
+    resolver: &'a dyn KeyResolver,
+}
+
+impl<'a> ResolvingEngine<'a> {
+    /// create an engine that runs scripts with the given engine and resolves
+    /// key fingerprints with the given resolver
+    pub fn new(engine: &'a dyn ScriptEngine, resolver: &'a dyn KeyResolver) -> Self {
+        Self { engine, resolver }
+    }
+}
+
+impl ScriptEngine for ResolvingEngine<'_> {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let current = Resolving {
+            pairs: current,
+            resolver: self.resolver,
+        };
+        let proposed = Resolving {
+            pairs: proposed,
+            resolver: self.resolver,
+        };
+        self.engine
+            .run(script, func, &current, &proposed, pstack, rstack, context)
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let current = Resolving {
+            pairs: current,
+            resolver: self.resolver,
+        };
+        let proposed = Resolving {
+            pairs: proposed,
+            resolver: self.resolver,
+        };
+        self.engine
+            .run_with_log(script, func, &current, &proposed, pstack, rstack, context, log)
+    }
+
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        let current = Resolving {
+            pairs: current,
+            resolver: self.resolver,
+        };
+        let proposed = Resolving {
+            pairs: proposed,
+            resolver: self.resolver,
+        };
+        self.engine
+            .run_metered(script, func, &current, &proposed, pstack, rstack, context, log, fuel)
+    }
+}
+
+/// A ScriptResolver loads the scripts that lock scripts reference by cid
+/// from a content store
+pub trait ScriptResolver {
+    /// get the script with the given cid
+    fn resolve(&self, cid: &Cid) -> Option<Script>;
+}
+
+/// What to do when the script referenced by cid can't be resolved
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnresolvedPolicy {
+    /// fail the entry with [`ScriptError::Unresolved`]
+    #[default]
+    Fail,
+    /// treat the lock script as failed and go on to the next lock script
+    Skip,
+    /// stop verifying with [`ScriptError::Deferred`] so the log can be
+    /// verified again once the content store has the script
+    Defer,
+}
+
+/// A ScriptEngine that replaces scripts referenced by cid with the scripts
+/// loaded by a resolver before running them with another engine. The policy
+/// decides what happens when a script can't be resolved so that an invalid
+/// log can be told apart from an incomplete content store.
+pub struct ScriptResolvingEngine<'a> {
+    engine: &'a dyn ScriptEngine,
+    resolver: &'a dyn ScriptResolver,
+    policy: UnresolvedPolicy,
+}
+
+impl<'a> ScriptResolvingEngine<'a> {
+    /// create an engine that resolves scripts with the given resolver and
+    /// fails when a script can't be resolved
+    pub fn new(engine: &'a dyn ScriptEngine, resolver: &'a dyn ScriptResolver) -> Self {
+        Self {
+            engine,
+            resolver,
+            policy: UnresolvedPolicy::default(),
+        }
+    }
+
+    /// set the policy for scripts that can't be resolved
+    pub fn with_policy(mut self, policy: UnresolvedPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl ScriptEngine for ScriptResolvingEngine<'_> {
+    fn run(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+    ) -> Result<(), Error> {
+        let mut log = Vec::default();
+        self.run_with_log(script, func, current, proposed, pstack, rstack, context, &mut log)
+    }
+
+    fn run_with_log(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        self.run_metered(script, func, current, proposed, pstack, rstack, context, log, None)?;
+        Ok(())
+    }
+
+    fn run_metered(
+        &self,
+        script: &Script,
+        func: &str,
+        current: &dyn Pairs,
+        proposed: &dyn Pairs,
+        pstack: &mut Stk,
+        rstack: &mut Stk,
+        context: &Key,
+        log: &mut Vec<String>,
+        fuel: Option<u64>,
+    ) -> Result<u64, Error> {
+        let (path, cid) = match script {
+            Script::Cid(path, cid) => (path, cid),
+            _ => {
+                return self.engine.run_metered(
+                    script, func, current, proposed, pstack, rstack, context, log, fuel,
+                )
+            }
+        };
+        match self.resolver.resolve(cid) {
+            // the resolved script governs the path of the script referencing it
+            Some(resolved) => {
+                let resolved = match resolved {
+                    Script::Bin(_, b) => Script::Bin(path.clone(), b),
+                    Script::Code(_, c) => Script::Code(path.clone(), c),
+                    Script::Cid(..) => {
+                        return Err(ScriptError::Unresolved(cid.clone(), path.clone()).into())
+                    }
+                };
+                self.engine.run_metered(
+                    &resolved, func, current, proposed, pstack, rstack, context, log, fuel,
+                )
+            }
+            None => match self.policy {
+                UnresolvedPolicy::Fail => {
+                    Err(ScriptError::Unresolved(cid.clone(), path.clone()).into())
+                }
+                UnresolvedPolicy::Skip => {
+                    let msg = format!("skipped unresolved script for {}", path);
+                    log.push(msg.clone());
+                    rstack.push(vm::Value::Failure(msg));
+                    Ok(0)
+                }
+                UnresolvedPolicy::Defer => {
+                    Err(ScriptError::Deferred(cid.clone(), path.clone()).into())
+                }
+            },
+        }
+    }
+}
+
+/// A LogResolver looks up logs by their vlad so that [`Value::PlogRef`]
+/// values citing state in other logs can be checked
+pub trait LogResolver {
+    /// get the log with the given vlad
+    fn resolve(&self, vlad: &Vlad) -> Option<Log>;
+}
+
+/// Check the reference to another log and get the value it cites. The
+/// referenced log is verified up to the cited entry and the value at the key
+/// in the state just after the entry is returned, None if there is none.
+pub fn verify_plog_ref(value: &Value, resolver: &dyn LogResolver) -> Result<Option<Value>, Error> {
+    let (vlad, entry_cid, key) = match value {
+        Value::PlogRef {
+            vlad,
+            entry_cid,
+            key,
+        } => (vlad, entry_cid, key),
+        _ => return Err(ValueError::NotAPlogRef.into()),
+    };
+    let log = resolver.resolve(vlad).ok_or(LogError::UnresolvedLog)?;
+    if log.vlad != *vlad {
+        return Err(LogError::WrongLog.into());
+    }
+    if !log.entries.contains_key(entry_cid) {
+        return Err(LogError::EntryNotFound(entry_cid.clone()).into());
+    }
+    for ret in log.verify() {
+        let (_, entry, kvp) = ret?;
+        if entry.cid() == *entry_cid {
+            return Ok(kvp.get_value(key).cloned());
+        }
+    }
+    // the entry is before the checkpoint of a compacted log
+    Err(LogError::EntryNotFound(entry_cid.clone()).into())
+}
+
+// wraps a read-only view of pairs and resolves fingerprint values
+struct Resolving<'a> {
+    pairs: &'a dyn Pairs,
+    resolver: &'a dyn KeyResolver,
+}
+
+impl Pairs for Resolving<'_> {
+    fn get(&self, key: &str) -> Option<wacc::Value> {
+        match self.pairs.get(key) {
+            Some(wacc::Value::Bin { hint, data }) => {
+                // only values that are exactly one multihash are fingerprints
+                let mk = match Multihash::try_decode_from(&data) {
+                    Ok((fp, ptr)) if ptr.is_empty() => self.resolver.resolve(&fp),
+                    _ => None,
+                };
+                match mk {
+                    Some(mk) => Some(wacc::Value::Bin {
+                        hint,
+                        data: mk.into(),
+                    }),
+                    None => Some(wacc::Value::Bin { hint, data }),
+                }
+            }
+            v => v,
+        }
+    }
+
+    fn put(&mut self, _key: &str, _value: &wacc::Value) -> Option<wacc::Value> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kvp, Value};
+    use multicodec::Codec;
+    use multihash::mh;
+    use multikey::EncodedMultikey;
+    use std::collections::BTreeMap;
+
+    struct Keyring(Vec<(Multihash, Multikey)>);
+
+    impl KeyResolver for Keyring {
+        fn resolve(&self, fingerprint: &Multihash) -> Option<Multikey> {
+            self.0
+                .iter()
+                .find(|(fp, _)| fp == fingerprint)
+                .map(|(_, mk)| mk.clone())
+        }
+    }
+
+    #[test]
+    fn test_resolve_fingerprint() {
+        let key = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0"
+        )
+        .unwrap()
+        .to_inner();
+        let key_bytes: Vec<u8> = key.clone().into();
+        let fp = mh::Builder::new_from_bytes(Codec::Sha3256, &key_bytes)
+            .unwrap()
+            .try_build()
+            .unwrap();
+        let keyring = Keyring(vec![(fp.clone(), key)]);
+
+        let kvp = Kvp::from(BTreeMap::from([
+            (Key::try_from("/pubkey").unwrap(), Value::Data(fp.into())),
+            (Key::try_from("/other").unwrap(), Value::Data(vec![1, 2, 3])),
+        ]));
+        let pairs = Resolving {
+            pairs: &kvp,
+            resolver: &keyring,
+        };
+
+        // the fingerprint is replaced with the key
+        match pairs.get("/pubkey") {
+            Some(wacc::Value::Bin { data, .. }) => assert_eq!(key_bytes, data),
+            _ => panic!("expected a binary value"),
+        }
+        // other values are untouched
+        match pairs.get("/other") {
+            Some(wacc::Value::Bin { data, .. }) => assert_eq!(vec![1, 2, 3], data),
+            _ => panic!("expected a binary value"),
+        }
+        assert!(pairs.get("/missing").is_none());
+    }
+
+    struct Logs(Vec<Log>);
+
+    impl LogResolver for Logs {
+        fn resolve(&self, vlad: &Vlad) -> Option<Log> {
+            self.0.iter().find(|l| l.vlad == *vlad).cloned()
+        }
+    }
+
+    #[test]
+    fn test_plog_ref_errors() {
+        let e1 = crate::entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = crate::log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        let reference = |entry_cid: Cid| Value::PlogRef {
+            vlad: Vlad::default(),
+            entry_cid,
+            key: Key::try_from("/foo").unwrap(),
+        };
+
+        assert!(matches!(
+            verify_plog_ref(&Value::Nil, &Logs(vec![log.clone()])),
+            Err(Error::Value(ValueError::NotAPlogRef))
+        ));
+        assert!(matches!(
+            verify_plog_ref(&reference(e1.cid()), &Logs(vec![])),
+            Err(Error::Log(LogError::UnresolvedLog))
+        ));
+        assert!(matches!(
+            verify_plog_ref(&reference(Cid::default()), &Logs(vec![log])),
+            Err(Error::Log(LogError::EntryNotFound(_)))
+        ));
+    }
+
+    struct Store(Vec<(Cid, Script)>);
+
+    impl ScriptResolver for Store {
+        fn resolve(&self, cid: &Cid) -> Option<Script> {
+            self.0.iter().find(|(c, _)| c == cid).map(|(_, s)| s.clone())
+        }
+    }
+
+    // records the scripts it is asked to run
+    #[derive(Default)]
+    struct Recorder {
+        scripts: std::cell::RefCell<Vec<Script>>,
+    }
+
+    impl ScriptEngine for Recorder {
+        fn run(
+            &self,
+            script: &Script,
+            _func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            _rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            self.scripts.borrow_mut().push(script.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resolve_script() {
+        let cid = |b: &[u8]| {
+            multicid::cid::Builder::new(Codec::Cidv1)
+                .with_target_codec(Codec::Raw)
+                .with_hash(
+                    &mh::Builder::new_from_bytes(Codec::Sha3256, b)
+                        .unwrap()
+                        .try_build()
+                        .unwrap(),
+                )
+                .try_build()
+                .unwrap()
+        };
+        let path = Key::try_from("/foo/").unwrap();
+        let known = Script::Cid(path.clone(), cid(b"known"));
+        let missing = Script::Cid(path.clone(), cid(b"missing"));
+        let store = Store(vec![(
+            cid(b"known"),
+            Script::Bin(Key::default(), b"wasm".to_vec()),
+        )]);
+        let kvp = Kvp::default();
+        let mut pstack = Stk::default();
+        let mut rstack = Stk::default();
+
+        // resolved scripts run with the path of the referencing script
+        let recorder = Recorder::default();
+        let engine = ScriptResolvingEngine::new(&recorder, &store);
+        engine
+            .run(&known, "f", &kvp, &kvp, &mut pstack, &mut rstack, &path)
+            .unwrap();
+        assert_eq!(
+            vec![Script::Bin(path.clone(), b"wasm".to_vec())],
+            *recorder.scripts.borrow()
+        );
+
+        assert!(matches!(
+            engine.run(&missing, "f", &kvp, &kvp, &mut pstack, &mut rstack, &path),
+            Err(Error::Script(ScriptError::Unresolved(..)))
+        ));
+
+        let engine = engine.with_policy(UnresolvedPolicy::Defer);
+        assert!(matches!(
+            engine.run(&missing, "f", &kvp, &kvp, &mut pstack, &mut rstack, &path),
+            Err(Error::Script(ScriptError::Deferred(..)))
+        ));
+
+        // skipped scripts leave a failure on the return stack
+        let engine = engine.with_policy(UnresolvedPolicy::Skip);
+        engine
+            .run(&missing, "f", &kvp, &kvp, &mut pstack, &mut rstack, &path)
+            .unwrap();
+        assert!(matches!(rstack.top(), Some(vm::Value::Failure(_))));
+        assert_eq!(1, recorder.scripts.borrow().len());
+    }
+}
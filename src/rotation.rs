@@ -0,0 +1,187 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Rotating the signing key of a log. Rotation takes several coordinated ops
+//! that all have to be in the same entry: the new key replaces the current
+//! pubkey, the ephemeral key is removed if it is still there and the recovery
+//! hash is optionally replaced. The entry is proofed by the closure passed to
+//! [`Builder::try_build`] which normally signs with the old key since that is
+//! the key the lock scripts check.
+use crate::{
+    entry,
+    error::LogError,
+    profiles::{self, identity},
+    Entry, Error, Key, Log, Op, Script, Value,
+};
+use multihash::Multihash;
+use multikey::Multikey;
+
+/// Builder for the Entry that rotates the signing key of a Log
+pub struct Builder<'a> {
+    log: &'a Log,
+    old_key: Option<Multikey>,
+    new_key: Option<Multikey>,
+    recovery_hash: Option<Multihash>,
+    unlock: Option<Script>,
+}
+
+impl<'a> Builder<'a> {
+    /// create a builder for rotating the key of the given log
+    pub fn new(log: &'a Log) -> Self {
+        Self {
+            log,
+            old_key: None,
+            new_key: None,
+            recovery_hash: None,
+            unlock: None,
+        }
+    }
+
+    /// set the key being rotated out, it must be the current pubkey
+    pub fn with_old_key(mut self, key: &Multikey) -> Self {
+        self.old_key = Some(key.clone());
+        self
+    }
+
+    /// set the key being rotated in
+    pub fn with_new_key(mut self, key: &Multikey) -> Self {
+        self.new_key = Some(key.clone());
+        self
+    }
+
+    /// set the new recovery hash
+    pub fn with_recovery_hash(mut self, hash: &Multihash) -> Self {
+        self.recovery_hash = Some(hash.clone());
+        self
+    }
+
+    /// set the unlock script, the standard unlock script is used by default
+    pub fn with_unlock(mut self, unlock: &Script) -> Self {
+        self.unlock = Some(unlock.clone());
+        self
+    }
+
+    /// build the rotation Entry on top of the head of the log and call the
+    /// `gen_proof` closure to generate the proof
+    pub fn try_build<F>(&self, gen_proof: F) -> Result<Entry, Error>
+    where
+        F: FnMut(&mut Entry) -> Result<Vec<u8>, Error>,
+    {
+        let old_key = self.old_key.clone().ok_or(LogError::MissingKey)?;
+        let new_key = self.new_key.clone().ok_or(LogError::MissingKey)?;
+        if !self.log.entries.contains_key(&self.log.head) {
+            return Err(LogError::MissingHead.into());
+        }
+
+        // the old key has to be the current pubkey
+        let pubkey = Key::try_from(identity::PUBKEY)?;
+        let old_bytes: Vec<u8> = old_key.into();
+        if self.current(&pubkey) != Some(Value::Data(old_bytes)) {
+            return Err(LogError::StaleKey.into());
+        }
+
+        let mut ops = vec![identity::set_pubkey(&new_key)?];
+        let ephemeral = Key::try_from(identity::EPHEMERAL)?;
+        if self.current(&ephemeral).is_some() {
+            ops.push(identity::clear_ephemeral()?);
+        }
+        if let Some(hash) = &self.recovery_hash {
+            ops.push(identity::set_hash(hash)?);
+        }
+
+        let unlock = match &self.unlock {
+            Some(unlock) => unlock.clone(),
+            None => profiles::unlock()?,
+        };
+        entry::Builder::default()
+            .with_log(self.log)
+            .with_ops(&ops)
+            .with_unlock(&unlock)
+            .try_build(gen_proof)
+    }
+
+    // get the current value of the key by replaying the ops in the log
+    fn current(&self, key: &Key) -> Option<Value> {
+        let mut value = None;
+        for entry in self.log.iter() {
+            for op in entry.ops() {
+                match op {
+                    Op::Update(k, v) if k == key => value = Some(v.clone()),
+                    Op::Delete(k) if k == key => value = None,
+                    _ => {}
+                }
+            }
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log;
+    use multicid::Vlad;
+    use multicodec::Codec;
+    use multihash::mh;
+    use multikey::EncodedMultikey;
+
+    #[test]
+    fn test_rotation() {
+        let old_key = EncodedMultikey::try_from(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0"
+        )
+        .unwrap()
+        .to_inner();
+        // any key will do as the new key
+        let new_key = old_key.clone();
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&identity::set_ephemeral(&old_key).unwrap())
+            .add_op(&identity::set_pubkey(&old_key).unwrap())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+        let hash = mh::Builder::new_from_bytes(Codec::Sha3256, b"preimage")
+            .unwrap()
+            .try_build()
+            .unwrap();
+
+        let entry = Builder::new(&log)
+            .with_old_key(&old_key)
+            .with_new_key(&new_key)
+            .with_recovery_hash(&hash)
+            .try_build(|e| Ok(e.canonical_bytes()))
+            .unwrap();
+        assert_eq!(1, entry.seqno());
+        assert_eq!(log.head, entry.prev());
+        let paths: Vec<String> = entry.ops().map(|op| op.path().to_string()).collect();
+        assert_eq!(
+            vec![identity::PUBKEY, identity::EPHEMERAL, identity::HASH],
+            paths
+        );
+        assert!(!entry.proof.is_empty());
+
+        // a key that isn't the current pubkey can't be rotated out
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(
+                &entry::Builder::default()
+                    .with_vlad(&Vlad::default())
+                    .with_unlock(&Script::default())
+                    .try_build(|_| Ok(Vec::default()))
+                    .unwrap(),
+            )
+            .try_build()
+            .unwrap();
+        assert!(Builder::new(&log)
+            .with_old_key(&old_key)
+            .with_new_key(&new_key)
+            .try_build(|_| Ok(Vec::default()))
+            .is_err());
+    }
+}
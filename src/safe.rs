@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: FSL-1.1
+use core::fmt;
+use multicodec::Codec;
+use multihash::mh;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// the number of bytes shown by the default truncating mode
+pub const DEFAULT_TRUNCATE_LEN: usize = 8;
+
+const MODE_REVEAL: u8 = 0;
+const MODE_TRUNCATE: u8 = 1;
+const MODE_HASH: u8 = 2;
+
+static MODE: AtomicU8 = AtomicU8::new(MODE_TRUNCATE);
+static TRUNCATE_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_TRUNCATE_LEN);
+
+/// SafeDisplay controls how binary data that may be sensitive, such as
+/// Value::Data and proof bytes, is rendered in Debug and Display output so
+/// that key material and preimages don't leak into logs. The mode is process
+/// wide and defaults to truncating.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SafeDisplay {
+    /// show all of the bytes
+    Reveal,
+    /// show only the first N bytes and the total length
+    Truncate(usize),
+    /// show only a sha2-256 hash of the bytes and the total length
+    Hash,
+}
+
+impl Default for SafeDisplay {
+    fn default() -> Self {
+        Self::Truncate(DEFAULT_TRUNCATE_LEN)
+    }
+}
+
+impl SafeDisplay {
+    /// get the current display mode
+    pub fn current() -> Self {
+        match MODE.load(Ordering::Relaxed) {
+            MODE_REVEAL => Self::Reveal,
+            MODE_HASH => Self::Hash,
+            _ => Self::Truncate(TRUNCATE_LEN.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// set the display mode for the whole process
+    pub fn set(mode: Self) {
+        match mode {
+            Self::Reveal => MODE.store(MODE_REVEAL, Ordering::Relaxed),
+            Self::Truncate(len) => {
+                TRUNCATE_LEN.store(len, Ordering::Relaxed);
+                MODE.store(MODE_TRUNCATE, Ordering::Relaxed);
+            }
+            Self::Hash => MODE.store(MODE_HASH, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Wraps a byte slice so that it is displayed using the current SafeDisplay
+/// mode
+#[derive(Clone, Copy)]
+pub struct SafeBytes<'a>(pub &'a [u8]);
+
+impl SafeBytes<'_> {
+    fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+        bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl fmt::Display for SafeBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match SafeDisplay::current() {
+            SafeDisplay::Reveal => Self::write_hex(f, self.0),
+            SafeDisplay::Truncate(len) if self.0.len() <= len => Self::write_hex(f, self.0),
+            SafeDisplay::Truncate(len) => {
+                Self::write_hex(f, &self.0[..len])?;
+                write!(f, "… ({} bytes)", self.0.len())
+            }
+            SafeDisplay::Hash => {
+                match mh::Builder::new_from_bytes(Codec::Sha2256, self.0)
+                    .and_then(|b| b.try_build())
+                {
+                    Ok(mh) => {
+                        let v: Vec<u8> = mh.into();
+                        write!(f, "hash:")?;
+                        Self::write_hex(f, &v)?;
+                    }
+                    Err(_) => write!(f, "<redacted>")?,
+                }
+                write!(f, " ({} bytes)", self.0.len())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SafeBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the mode is process wide so all of the modes are checked in one test
+    #[test]
+    fn test_safe_bytes() {
+        let b = [0xde, 0xad, 0xbe, 0xef, 0x01, 0x02];
+
+        SafeDisplay::set(SafeDisplay::Truncate(2));
+        assert_eq!(SafeDisplay::Truncate(2), SafeDisplay::current());
+        assert_eq!("dead… (6 bytes)", SafeBytes(&b).to_string());
+
+        SafeDisplay::set(SafeDisplay::Reveal);
+        assert_eq!("deadbeef0102", SafeBytes(&b).to_string());
+
+        SafeDisplay::set(SafeDisplay::Hash);
+        let s = SafeBytes(&b).to_string();
+        assert!(s.starts_with("hash:"));
+        assert!(!s.contains("deadbeef"));
+        assert!(s.ends_with(" (6 bytes)"));
+
+        SafeDisplay::set(SafeDisplay::default());
+        assert_eq!("deadbeef0102", SafeBytes(&b).to_string());
+    }
+}
@@ -1,9 +1,16 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{error::ScriptError, Error, Key};
+pub mod templates;
+pub mod validate;
+
+use crate::{
+    error::{decode_field, ScriptError},
+    Error, Key,
+};
 use core::fmt;
 use multibase::Base;
-use multicid::Cid;
+use multicid::{cid, Cid, EncodedCid, Vlad};
 use multicodec::Codec;
+use multihash::mh;
 use multitrait::{EncodeInto, TryDecodeFrom};
 use multiutil::{BaseEncoded, EncodingInfo, Varbytes};
 use std::{cmp::Ordering, path::PathBuf};
@@ -14,6 +21,12 @@ pub const SIGIL: Codec = Codec::ProvenanceLogScript;
 /// a base encoded provenance script
 pub type EncodedScript = BaseEncoded<Script>;
 
+/// the domain separation tag hashed in front of vlad-scoped script cids
+pub const SCOPED_CID_TAG: &[u8] = b"plog-script";
+
+/// the hash codec used for vlad-scoped script cids
+pub const SCOPED_CID_HASH: Codec = Codec::Sha3256;
+
 /// the identifiers for the operations performed on the namespace in each entry
 #[repr(u8)]
 #[derive(Clone, Default, Eq, Hash, Ord, PartialOrd, PartialEq)]
@@ -116,6 +129,25 @@ impl fmt::Debug for ScriptId {
     }
 }
 
+/// the magic bytes at the start of every wasm binary
+pub const WASM_MAGIC: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
+
+/// the version and layer bytes of a core wasm module
+const WASM_MODULE_VERSION: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+
+/// the layer bytes of a wasm component, the two version bytes before them
+/// change as the component model evolves
+const WASM_COMPONENT_LAYER: [u8; 2] = [0x01, 0x00];
+
+/// The kinds of wasm binaries a binary script may contain
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WasmKind {
+    /// a core wasm module exporting the lock/unlock functions
+    Module,
+    /// a wasm component exporting a wit defined lock/unlock interface
+    Component,
+}
+
 /// A Script is either a binary blob, printable code, or a CID reference. These are the values
 /// stored in the virtual namespace of the log.
 #[derive(Clone, Eq, PartialEq)]
@@ -131,10 +163,81 @@ pub enum Script {
 impl Script {
     /// returns the path the script is assigned to
     pub fn path(&self) -> Key {
+        self.path_ref().clone()
+    }
+
+    /// get a reference to the path the script is associated with
+    pub fn path_ref(&self) -> &Key {
+        match self {
+            Self::Bin(p, _) => p,
+            Self::Code(p, _) => p,
+            Self::Cid(p, _) => p,
+        }
+    }
+
+    /// get the cid of the script code bound to the vlad of a log. the hash
+    /// is over a tag, the encoded vlad and the code so the same code stored
+    /// for two logs has two cids and resolvers can shard storage per log.
+    /// the path isn't included so the code can govern any path.
+    pub fn scoped_cid(&self, vlad: &Vlad) -> Result<Cid, Error> {
+        let code: &[u8] = match self {
+            Self::Bin(_, b) => b,
+            Self::Code(_, c) => c.as_bytes(),
+            Self::Cid(..) => return Err(ScriptError::MissingCode.into()),
+        };
+        let mut data = SCOPED_CID_TAG.to_vec();
+        data.append(&mut vlad.clone().into());
+        data.extend_from_slice(code);
+        Ok(cid::Builder::new(Codec::Cidv1)
+            .with_target_codec(Codec::Raw)
+            .with_hash(&mh::Builder::new_from_bytes(SCOPED_CID_HASH, &data)?.try_build()?)
+            .try_build()?)
+    }
+
+    /// check that the cid is the cid of the script code bound to the vlad
+    pub fn check_scoped_cid(&self, vlad: &Vlad, cid: &Cid) -> Result<(), Error> {
+        if self.scoped_cid(vlad)? != *cid {
+            return Err(ScriptError::ScopedCidMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// returns the kind of wasm binary in a binary script, None if this
+    /// isn't a binary script or the binary isn't wasm
+    pub fn wasm_kind(&self) -> Option<WasmKind> {
+        match self {
+            Self::Bin(_, b) if b.len() >= 8 && b[..4] == WASM_MAGIC => {
+                if b[4..8] == WASM_MODULE_VERSION {
+                    Some(WasmKind::Module)
+                } else if b[6..8] == WASM_COMPONENT_LAYER {
+                    Some(WasmKind::Component)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// get the size of the script code, or of the cid for cid references
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Bin(_, b) => b.len(),
+            Self::Code(_, c) => c.len(),
+            Self::Cid(_, c) => Vec::<u8>::from(c.clone()).len(),
+        }
+    }
+
+    /// get the printable text of the script: the WAT text of a binary
+    /// script and the code of a printable script
+    #[cfg(feature = "wasmprinter")]
+    pub fn disassemble(&self) -> Result<String, Error> {
         match self {
-            Self::Bin(p, _) => p.clone(),
-            Self::Code(p, _) => p.clone(),
-            Self::Cid(p, _) => p.clone(),
+            Self::Bin(_, b) => {
+                wasmprinter::print_bytes(b).map_err(|e| ScriptError::Disassembly(e.to_string()).into())
+            }
+            Self::Code(_, c) => Ok(c.clone()),
+            Self::Cid(..) => Err(ScriptError::MissingCode.into()),
         }
     }
 }
@@ -142,14 +245,14 @@ impl Script {
 impl Ord for Script {
     /// orders scripts by their paths
     fn cmp(&self, other: &Self) -> Ordering {
-        self.path().cmp(&other.path())
+        self.path_ref().cmp(other.path_ref())
     }
 }
 
 impl PartialOrd for Script {
     /// partial ord for script 
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.path().cmp(&other.path()))
+        Some(self.path_ref().cmp(other.path_ref()))
     }
 }
 
@@ -228,27 +331,29 @@ impl<'a> TryDecodeFrom<'a> for Script {
 
     fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
         // decode the sigil
-        let (sigil, ptr) = Codec::try_decode_from(bytes)?;
+        let (sigil, ptr) = decode_field(bytes, bytes, "sigil", Codec::try_decode_from)?;
         if sigil != SIGIL {
             return Err(ScriptError::MissingSigil.into());
         }
         // decode the value id
-        let (id, ptr) = ScriptId::try_decode_from(ptr)?;
+        let (id, ptr) = decode_field(bytes, ptr, "id", ScriptId::try_decode_from)?;
         let (v, ptr) = match id {
             ScriptId::Bin => {
-                let (k, ptr) = Key::try_decode_from(ptr)?;
-                let (b, ptr) = Varbytes::try_decode_from(ptr)?;
+                let (k, ptr) = decode_field(bytes, ptr, "path", Key::try_decode_from)?;
+                let (b, ptr) = decode_field(bytes, ptr, "bin", Varbytes::try_decode_from)?;
                 (Self::Bin(k, b.to_inner()), ptr)
             }
             ScriptId::Code => {
-                let (k, ptr) = Key::try_decode_from(ptr)?;
-                let (s, ptr) = Varbytes::try_decode_from(ptr)?;
-                let s = String::from_utf8(s.to_inner())?;
+                let (k, ptr) = decode_field(bytes, ptr, "path", Key::try_decode_from)?;
+                let (s, ptr) = decode_field(bytes, ptr, "code", |p| {
+                    let (s, p) = Varbytes::try_decode_from(p)?;
+                    Ok::<_, Error>((String::from_utf8(s.to_inner())?, p))
+                })?;
                 (Self::Code(k, s), ptr)
             }
             ScriptId::Cid => {
-                let (k, ptr) = Key::try_decode_from(ptr)?;
-                let (c, ptr) = Cid::try_decode_from(ptr)?;
+                let (k, ptr) = decode_field(bytes, ptr, "path", Key::try_decode_from)?;
+                let (c, ptr) = decode_field(bytes, ptr, "cid", Cid::try_decode_from)?;
                 (Self::Cid(k, c), ptr)
             }
         };
@@ -267,6 +372,30 @@ impl fmt::Debug for Script {
     }
 }
 
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = ScriptId::from(self);
+        let code: &[u8] = match self {
+            Self::Bin(_, b) => b,
+            Self::Code(_, c) => c.as_bytes(),
+            Self::Cid(k, c) => {
+                let cid = EncodedCid::new(Base::Base32Lower, c.clone());
+                return write!(f, "{} {} {}", k, id.as_str(), cid);
+            }
+        };
+        let hash = mh::Builder::new_from_bytes(Codec::Sha2256, code)
+            .and_then(|b| b.try_build())
+            .map_err(|_| fmt::Error)?;
+        let hash = multibase::encode(Base::Base32Lower, Vec::<u8>::from(hash));
+        match self.wasm_kind() {
+            Some(WasmKind::Module) => write!(f, "{} {} module", self.path_ref(), id.as_str())?,
+            Some(WasmKind::Component) => write!(f, "{} {} component", self.path_ref(), id.as_str())?,
+            None => write!(f, "{} {}", self.path_ref(), id.as_str())?,
+        }
+        write!(f, " ({} bytes) {}", code.len(), hash)
+    }
+}
+
 /// Builder for Scripts that helps create them from files and Cid's
 #[derive(Clone, Default)]
 pub struct Builder {
@@ -274,6 +403,8 @@ pub struct Builder {
     bin: Option<PathBuf>,
     code: Option<PathBuf>,
     cid: Option<Cid>,
+    entry_point: Option<String>,
+    validator: validate::Validator,
 }
 
 impl Builder {
@@ -307,6 +438,18 @@ impl Builder {
         self
     }
 
+    /// require binary scripts to export the function they are called through
+    pub fn with_entry_point(mut self, func: &str) -> Self {
+        self.entry_point = Some(func.to_string());
+        self
+    }
+
+    /// set the validator binary scripts are checked with
+    pub fn with_validator(mut self, validator: &validate::Validator) -> Self {
+        self.validator = validator.clone();
+        self
+    }
+
     /// Tries to build a Script from the collected data
     pub fn try_build(&self) -> Result<Script, Error> {
         let path = self.path.clone().unwrap_or_default();
@@ -314,7 +457,8 @@ impl Builder {
             let b = std::fs::read(b).map_err(|e| ScriptError::LoadingFailed(e.to_string()))?;
             if b.len() < 4 {
                 Err(ScriptError::MissingCode.into())
-            } else if b[0] == 0x00 && b[1] == 0x61 && b[2] == 0x73 && b[3] == 0x6d {
+            } else if b[..4] == WASM_MAGIC {
+                self.validator.validate(&b, self.entry_point.as_deref())?;
                 Ok(Script::Bin(path, b))
             } else {
                 Err(ScriptError::InvalidScriptMagic.into())
@@ -335,6 +479,8 @@ impl Builder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use multicid::vlad;
+    use multikey::nonce;
 
     #[test]
     fn sort_scripts() {
@@ -350,4 +496,59 @@ mod tests {
             println!("{}: {:?}", s.path(), s);
         }
     }
+
+    #[test]
+    fn wasm_kinds() {
+        let module = Script::Bin(Key::default(), b"\0asm\x01\0\0\0".to_vec());
+        assert_eq!(Some(WasmKind::Module), module.wasm_kind());
+        let component = Script::Bin(Key::default(), b"\0asm\x0d\0\x01\0".to_vec());
+        assert_eq!(Some(WasmKind::Component), component.wasm_kind());
+        assert_eq!(None, Script::default().wasm_kind());
+        assert_eq!(None, Script::Code(Key::default(), "(module)".to_string()).wasm_kind());
+    }
+
+    #[test]
+    fn scoped_cids() {
+        let vlad1 = Vlad::default();
+        let nonce = nonce::Builder::new_from_bytes(&[1; 32]).try_build().unwrap();
+        let vlad2 = vlad::Builder::default()
+            .with_nonce(&nonce)
+            .with_cid(&Cid::default())
+            .try_build()
+            .unwrap();
+        let script = Script::Code(Key::default(), "(module)".to_string());
+
+        let cid1 = script.scoped_cid(&vlad1).unwrap();
+        let cid2 = script.scoped_cid(&vlad2).unwrap();
+        assert_ne!(cid1, cid2);
+        // the path doesn't change the cid
+        let moved = Script::Code(Key::try_from("/foo/").unwrap(), "(module)".to_string());
+        assert_eq!(cid1, moved.scoped_cid(&vlad1).unwrap());
+
+        assert!(script.check_scoped_cid(&vlad1, &cid1).is_ok());
+        assert!(script.check_scoped_cid(&vlad2, &cid1).is_err());
+        assert!(Script::Cid(Key::default(), cid1).scoped_cid(&vlad1).is_err());
+    }
+
+    #[test]
+    fn display_scripts() {
+        let module = Script::Bin(Key::try_from("/foo").unwrap(), b"\0asm\x01\0\0\0".to_vec());
+        let s = module.to_string();
+        assert!(s.starts_with("/foo bin module (8 bytes) b"));
+        let code = Script::Code(Key::default(), "(module)".to_string());
+        assert!(code.to_string().starts_with("/ code (8 bytes) b"));
+        assert_ne!(module.to_string(), Script::Bin(Key::try_from("/foo").unwrap(), vec![0; 8]).to_string());
+        assert_eq!(8, code.size());
+    }
+
+    #[cfg(feature = "wasmprinter")]
+    #[test]
+    fn disassemble_scripts() {
+        let module = Script::Bin(Key::default(), b"\0asm\x01\0\0\0".to_vec());
+        assert!(module.disassemble().unwrap().contains("module"));
+        let code = Script::Code(Key::default(), "(module)".to_string());
+        assert_eq!("(module)", code.disassemble().unwrap());
+        assert!(Script::Bin(Key::default(), vec![1, 2, 3]).disassemble().is_err());
+        assert!(Script::Cid(Key::default(), Cid::default()).disassemble().is_err());
+    }
 }
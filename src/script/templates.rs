@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Ready-made lock and unlock scripts for the common ways of controlling a
+//! log. The locks check the keys of the identity profile: the public key at
+//! `/pubkey`, the first entry key at `/ephemeral` and the recovery hash at
+//! `/hash`. The functions at the top level build wacc scripts and the ones in
+//! [`rhai`] build the same scripts for the rhai backend.
+//!
+//! The locks making more than one check take one proof per check, joined
+//! with [`crate::proof::join_proofs`] in the order the checks are made, and
+//! the matching [`unlock`] pushes each of them. An empty proof stands in for
+//! a check that isn't met.
+use crate::{
+    error::ScriptError,
+    policy::{Backend, Compiler, Condition, Rule},
+    profiles::identity::{EPHEMERAL, HASH, PUBKEY},
+    Error, Key, Script,
+};
+
+fn compile(backend: Backend, path: &Key, condition: Condition) -> Result<Script, Error> {
+    Compiler::new(backend).lock(&Rule {
+        path: path.clone(),
+        condition,
+    })
+}
+
+fn pubkey() -> Result<Condition, Error> {
+    Ok(Condition::Pubkey(Key::try_from(PUBKEY)?))
+}
+
+fn preimage() -> Result<Condition, Error> {
+    Ok(Condition::Preimage(Key::try_from(HASH)?))
+}
+
+fn pubkey_lock_for(backend: Backend, path: &Key) -> Result<Script, Error> {
+    compile(backend, path, pubkey()?)
+}
+
+fn first_lock_for(backend: Backend) -> Result<Script, Error> {
+    compile(backend, &Key::default(), Condition::Pubkey(Key::try_from(EPHEMERAL)?))
+}
+
+fn preimage_lock_for(backend: Backend, path: &Key) -> Result<Script, Error> {
+    compile(backend, path, preimage()?)
+}
+
+fn multisig_lock_for(backend: Backend, path: &Key, m: usize, keys: &[Key]) -> Result<Script, Error> {
+    if m == 0 || m > keys.len() {
+        return Err(ScriptError::InvalidPolicy(format!(
+            "threshold {} out of range for {} keys",
+            m,
+            keys.len()
+        ))
+        .into());
+    }
+    let keys = keys.iter().cloned().map(Condition::Pubkey).collect();
+    compile(backend, path, Condition::Threshold(m, keys))
+}
+
+fn pubkey_and_preimage_lock_for(backend: Backend, path: &Key) -> Result<Script, Error> {
+    compile(backend, path, Condition::Threshold(2, vec![pubkey()?, preimage()?]))
+}
+
+fn pubkey_or_preimage_lock_for(backend: Backend, path: &Key) -> Result<Script, Error> {
+    compile(backend, path, Condition::Threshold(1, vec![pubkey()?, preimage()?]))
+}
+
+fn unlock_for(backend: Backend, checks: usize) -> Result<Script, Error> {
+    Compiler::new(backend).unlock_checks(&format!("{} checks", checks), checks)
+}
+
+/// lock the path with a signature by the public key at `/pubkey`
+pub fn pubkey_lock(path: &Key) -> Result<Script, Error> {
+    pubkey_lock_for(Backend::Wast, path)
+}
+
+/// lock the first entry with a signature by the ephemeral key it records at
+/// `/ephemeral`, so the first entry signs itself
+pub fn first_lock() -> Result<Script, Error> {
+    first_lock_for(Backend::Wast)
+}
+
+/// lock the path with the preimage of the recovery hash at `/hash`
+pub fn preimage_lock(path: &Key) -> Result<Script, Error> {
+    preimage_lock_for(Backend::Wast, path)
+}
+
+/// lock the path with signatures by at least m of the public keys at the keys
+pub fn multisig_lock(path: &Key, m: usize, keys: &[Key]) -> Result<Script, Error> {
+    multisig_lock_for(Backend::Wast, path, m, keys)
+}
+
+/// lock the path with both a signature by `/pubkey` and the preimage of `/hash`
+pub fn pubkey_and_preimage_lock(path: &Key) -> Result<Script, Error> {
+    pubkey_and_preimage_lock_for(Backend::Wast, path)
+}
+
+/// lock the path with either a signature by `/pubkey` or the preimage of `/hash`
+pub fn pubkey_or_preimage_lock(path: &Key) -> Result<Script, Error> {
+    pubkey_or_preimage_lock_for(Backend::Wast, path)
+}
+
+/// the unlock script for a lock making the given number of checks, e.g. 1
+/// for a pubkey lock and n for an m-of-n multisig lock
+pub fn unlock(checks: usize) -> Result<Script, Error> {
+    unlock_for(Backend::Wast, checks)
+}
+
+/// The same templates for the rhai backend
+pub mod rhai {
+    use super::*;
+
+    /// lock the path with a signature by the public key at `/pubkey`
+    pub fn pubkey_lock(path: &Key) -> Result<Script, Error> {
+        pubkey_lock_for(Backend::Rhai, path)
+    }
+
+    /// lock the first entry with a signature by the ephemeral key it records
+    pub fn first_lock() -> Result<Script, Error> {
+        first_lock_for(Backend::Rhai)
+    }
+
+    /// lock the path with the preimage of the recovery hash at `/hash`
+    pub fn preimage_lock(path: &Key) -> Result<Script, Error> {
+        preimage_lock_for(Backend::Rhai, path)
+    }
+
+    /// lock the path with signatures by at least m of the public keys at the keys
+    pub fn multisig_lock(path: &Key, m: usize, keys: &[Key]) -> Result<Script, Error> {
+        multisig_lock_for(Backend::Rhai, path, m, keys)
+    }
+
+    /// lock the path with both a signature by `/pubkey` and the preimage of `/hash`
+    pub fn pubkey_and_preimage_lock(path: &Key) -> Result<Script, Error> {
+        pubkey_and_preimage_lock_for(Backend::Rhai, path)
+    }
+
+    /// lock the path with either a signature by `/pubkey` or the preimage of `/hash`
+    pub fn pubkey_or_preimage_lock(path: &Key) -> Result<Script, Error> {
+        pubkey_or_preimage_lock_for(Backend::Rhai, path)
+    }
+
+    /// the unlock script for a lock making the given number of checks
+    pub fn unlock(checks: usize) -> Result<Script, Error> {
+        unlock_for(Backend::Rhai, checks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::tests::{build_entry, keys, kvp, sign, unlocks};
+
+    #[test]
+    fn test_templates() {
+        let root = Key::default();
+        match rhai::pubkey_or_preimage_lock(&root).unwrap() {
+            Script::Code(_, rhai) => {
+                assert!(rhai.contains("check_signature(\"/pubkey\", \"/entry/\")"));
+                assert!(rhai.contains("check_preimage(\"/hash\")"));
+                assert!(rhai.contains(">= 1"));
+            }
+            _ => panic!("expected a code script"),
+        }
+
+        let keys = vec![Key::try_from("/k1").unwrap(), Key::try_from("/k2").unwrap()];
+        assert!(multisig_lock(&root, 3, &keys).is_err());
+        assert!(rhai::multisig_lock(&root, 0, &keys).is_err());
+    }
+
+    #[test]
+    fn test_run_single_check_templates() {
+        let (k1, k2) = keys();
+        let kvp = kvp(&k1, &k2);
+        let root = Key::default();
+        let unlock = unlock(1).unwrap();
+
+        // /pubkey holds k1
+        let lock = pubkey_lock(&root).unwrap();
+        assert!(unlocks(&lock, &kvp, &build_entry(&unlock, |e| vec![sign(e, &k1)])));
+        assert!(!unlocks(&lock, &kvp, &build_entry(&unlock, |e| vec![sign(e, &k2)])));
+
+        // /ephemeral holds k2
+        let lock = first_lock().unwrap();
+        assert!(unlocks(&lock, &kvp, &build_entry(&unlock, |e| vec![sign(e, &k2)])));
+        assert!(!unlocks(&lock, &kvp, &build_entry(&unlock, |e| vec![sign(e, &k1)])));
+
+        let lock = preimage_lock(&root).unwrap();
+        assert!(unlocks(&lock, &kvp, &build_entry(&unlock, |_| vec![b"preimage".to_vec()])));
+        assert!(!unlocks(&lock, &kvp, &build_entry(&unlock, |_| vec![b"wrong".to_vec()])));
+    }
+
+    #[test]
+    fn test_run_multiple_check_templates() {
+        let (k1, k2) = keys();
+        let kvp = kvp(&k1, &k2);
+        let root = Key::default();
+        let unlock = unlock(2).unwrap();
+        let preimage = b"preimage".to_vec();
+
+        let lock = pubkey_and_preimage_lock(&root).unwrap();
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), preimage.clone()]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), b"wrong".to_vec()]);
+        assert!(!unlocks(&lock, &kvp, &e));
+
+        let lock = pubkey_or_preimage_lock(&root).unwrap();
+        let e = build_entry(&unlock, |_| vec![Vec::default(), preimage.clone()]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), Vec::default()]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |e| vec![sign(e, &k2), b"wrong".to_vec()]);
+        assert!(!unlocks(&lock, &kvp, &e));
+
+        let keys = vec![Key::try_from("/k1").unwrap(), Key::try_from("/k2").unwrap()];
+        let lock = multisig_lock(&root, 2, &keys).unwrap();
+        let e = build_entry(&unlock, |e| vec![sign(e, &k1), sign(e, &k2)]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |e| vec![sign(e, &k2), sign(e, &k1)]);
+        assert!(!unlocks(&lock, &kvp, &e));
+
+        let lock = multisig_lock(&root, 1, &keys).unwrap();
+        let e = build_entry(&unlock, |e| vec![Vec::default(), sign(e, &k2)]);
+        assert!(unlocks(&lock, &kvp, &e));
+        let e = build_entry(&unlock, |_| vec![Vec::default(), Vec::default()]);
+        assert!(!unlocks(&lock, &kvp, &e));
+    }
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Ahead-of-time validation of binary wasm scripts. A script that would only
+//! fail once the vm runs it, because it imports functions the vm doesn't
+//! link or doesn't export the function the verifier calls, is rejected when
+//! it is built and when the entry carrying it is appended instead. Floating
+//! point isn't deterministic across hosts so it is rejected by default too.
+use crate::{error::ScriptError, Entry, EntryPoints, Error, Script};
+use std::collections::BTreeSet;
+use wasmparser::{ExternalKind, Parser, Payload, WasmFeatures};
+
+/// the module the wacc vm links its functions under
+pub const WACC_MODULE: &str = "wacc";
+
+/// the functions the wacc vm links for the scripts
+pub const WACC_IMPORTS: &[&str] = &["_check_preimage", "_check_signature", "_log", "_push"];
+
+/// A Validator checks binary wasm scripts before they are run
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Validator {
+    imports: BTreeSet<(String, String)>,
+    floats: bool,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self {
+            imports: WACC_IMPORTS
+                .iter()
+                .map(|name| (WACC_MODULE.to_string(), name.to_string()))
+                .collect(),
+            floats: false,
+        }
+    }
+}
+
+impl Validator {
+    /// create a validator allowing the wacc imports and no floating point
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// also allow importing the function from the module, e.g. for an engine
+    /// that links more functions than the wacc vm
+    pub fn with_import(mut self, module: &str, name: &str) -> Self {
+        self.imports.insert((module.to_string(), name.to_string()));
+        self
+    }
+
+    /// allow or reject floating point types and instructions
+    pub fn with_floats(mut self, floats: bool) -> Self {
+        self.floats = floats;
+        self
+    }
+
+    /// validate the binary wasm. the module must only import the allowed
+    /// functions and export the entry point, if one is given. components
+    /// export wit interfaces so only their encoding is checked.
+    pub fn validate(&self, bin: &[u8], entry_point: Option<&str>) -> Result<(), Error> {
+        let features = WasmFeatures {
+            floats: true,
+            ..Default::default()
+        };
+        wasmparser::Validator::new_with_features(features)
+            .validate_all(bin)
+            .map_err(|e| ScriptError::InvalidWasm(e.to_string()))?;
+        if !self.floats {
+            // valid with floats and invalid without means it uses floats
+            let features = WasmFeatures {
+                floats: false,
+                ..Default::default()
+            };
+            if wasmparser::Validator::new_with_features(features).validate_all(bin).is_err() {
+                return Err(ScriptError::FloatingPoint.into());
+            }
+        }
+
+        let mut exported = false;
+        for payload in Parser::new(0).parse_all(bin) {
+            match payload.map_err(|e| ScriptError::InvalidWasm(e.to_string()))? {
+                Payload::Version {
+                    encoding: wasmparser::Encoding::Component,
+                    ..
+                } => return Ok(()),
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        let import = import.map_err(|e| ScriptError::InvalidWasm(e.to_string()))?;
+                        let name = (import.module.to_string(), import.name.to_string());
+                        if !self.imports.contains(&name) {
+                            return Err(ScriptError::ForbiddenImport(name.0, name.1).into());
+                        }
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export.map_err(|e| ScriptError::InvalidWasm(e.to_string()))?;
+                        if export.kind == ExternalKind::Func && Some(export.name) == entry_point {
+                            exported = true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        match entry_point {
+            Some(func) if !exported => Err(ScriptError::MissingExport(func.to_string()).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// validate the script if it is a binary wasm script. printable and cid
+    /// scripts are checked by the engine that compiles them and binaries that
+    /// aren't wasm are left to the engines that run them.
+    pub fn validate_script(&self, script: &Script, entry_point: Option<&str>) -> Result<(), Error> {
+        match script {
+            Script::Bin(_, b) if script.wasm_kind().is_some() => self.validate(b, entry_point),
+            _ => Ok(()),
+        }
+    }
+
+    /// validate the unlock and lock scripts in the entry against the entry
+    /// points they are called through
+    pub fn validate_entry(&self, entry: &Entry, entry_points: &EntryPoints) -> Result<(), Error> {
+        self.validate_script(&entry.unlock, Some(&entry_points.unlock))?;
+        for lock in entry.locks() {
+            self.validate_script(lock, Some(&entry_points.lock))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::LOCK_FUNC, Key};
+
+    // (module
+    //   (import "wacc" "_push" (func (param i32 i32) (result i32)))
+    //   (func (export "move_every_zig") (result i32) i32.const 1))
+    const LOCK: &[u8] = b"\0asm\x01\0\0\0\
+        \x01\x0b\x02\x60\x02\x7f\x7f\x01\x7f\x60\x00\x01\x7f\
+        \x02\x0e\x01\x04wacc\x05_push\x00\x00\
+        \x03\x02\x01\x01\
+        \x07\x12\x01\x0emove_every_zig\x00\x01\
+        \x0a\x06\x01\x04\x00\x41\x01\x0b";
+
+    // (module
+    //   (import "env" "_push" (func (param i32 i32) (result i32))))
+    const FORBIDDEN: &[u8] = b"\0asm\x01\0\0\0\
+        \x01\x07\x01\x60\x02\x7f\x7f\x01\x7f\
+        \x02\x0d\x01\x03env\x05_push\x00\x00";
+
+    // (module (func (result f32) f32.const 0))
+    const FLOATS: &[u8] = b"\0asm\x01\0\0\0\
+        \x01\x05\x01\x60\x00\x01\x7d\
+        \x03\x02\x01\x00\
+        \x0a\x09\x01\x07\x00\x43\x00\x00\x00\x00\x0b";
+
+    #[test]
+    fn test_validate() {
+        let v = Validator::new();
+        v.validate(LOCK, Some(LOCK_FUNC)).unwrap();
+        v.validate(LOCK, None).unwrap();
+        assert!(matches!(
+            v.validate(LOCK, Some("for_great_justice")),
+            Err(Error::Script(ScriptError::MissingExport(_)))
+        ));
+        assert!(matches!(
+            v.validate(FORBIDDEN, None),
+            Err(Error::Script(ScriptError::ForbiddenImport(_, _)))
+        ));
+        v.clone().with_import("env", "_push").validate(FORBIDDEN, None).unwrap();
+        assert!(matches!(
+            v.validate(FLOATS, None),
+            Err(Error::Script(ScriptError::FloatingPoint))
+        ));
+        v.clone().with_floats(true).validate(FLOATS, None).unwrap();
+        assert!(matches!(
+            v.validate(b"\0asm\x01\0\0\0\x01", None),
+            Err(Error::Script(ScriptError::InvalidWasm(_)))
+        ));
+
+        // only wasm binaries are validated
+        v.validate_script(&Script::Bin(Key::default(), b"mock".to_vec()), Some(LOCK_FUNC))
+            .unwrap();
+        assert!(v
+            .validate_script(&Script::Bin(Key::default(), LOCK.to_vec()), Some("nope"))
+            .is_err());
+    }
+}
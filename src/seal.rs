@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Encrypted values. A value is sealed by encrypting it with a random content
+//! key and wrapping the content key for each recipient so confidential fields
+//! can live in a publicly replicated log. Verification treats the sealed
+//! value as opaque data.
+//!
+//! Values are sealed to the public keys of the recipients and unsealed with
+//! their secret keys. Each recipient is identified by the [`fingerprint`] of
+//! its public key. The content key is wrapped for each recipient with a key
+//! derived with HKDF-SHA256 from an X25519 key agreement between a fresh
+//! ephemeral key and the recipient key. The ephemeral public key is stored
+//! with the wrapped key. Ed25519 keys are converted to their X25519 form so
+//! the keys already used for signing entries can receive sealed values.
+use crate::{error::ValueError, keyring::fingerprint, Error, Value};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use hkdf::Hkdf;
+use multicodec::Codec;
+use multikey::{Multikey, Views};
+use multitrait::TryDecodeFrom;
+use multiutil::{CodecInfo, Varbytes};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// the encryption scheme of sealed values
+pub const SEAL_SCHEME: Codec = Codec::Chacha20Poly1305;
+
+/// the HKDF info used to derive the wrapping key from the shared secret
+pub const KEY_WRAP_LABEL: &[u8] = b"provenance-log key wrap";
+
+/// the number of nonce bytes prefixed to each ciphertext
+pub const NONCE_LEN: usize = 12;
+
+// the number of bytes in the content and wrapping keys
+const KEY_LEN: usize = 32;
+
+/// The content key of a sealed value wrapped for one recipient
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Recipient {
+    /// the encoded fingerprint of the recipient public key
+    pub id: Vec<u8>,
+    /// the ephemeral X25519 public key the wrapping key was agreed with
+    pub ephemeral: Vec<u8>,
+    /// the wrapped content key, prefixed with the nonce
+    pub wrapped_key: Vec<u8>,
+}
+
+impl From<Recipient> for Vec<u8> {
+    fn from(val: Recipient) -> Self {
+        let mut v = Vec::default();
+        v.append(&mut Varbytes(val.id).into());
+        v.append(&mut Varbytes(val.ephemeral).into());
+        v.append(&mut Varbytes(val.wrapped_key).into());
+        v
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for Recipient {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        let (id, ptr) = Varbytes::try_decode_from(bytes)?;
+        let (ephemeral, ptr) = Varbytes::try_decode_from(ptr)?;
+        let (wrapped_key, ptr) = Varbytes::try_decode_from(ptr)?;
+        Ok((
+            Self {
+                id: id.to_inner(),
+                ephemeral: ephemeral.to_inner(),
+                wrapped_key: wrapped_key.to_inner(),
+            },
+            ptr,
+        ))
+    }
+}
+
+// encrypt the plaintext with a random nonce prefixed to the ciphertext
+fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut v = nonce.to_vec();
+    v.append(
+        &mut cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| ValueError::SealFailed)?,
+    );
+    Ok(v)
+}
+
+// decrypt the ciphertext prefixed with its nonce
+fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < NONCE_LEN {
+        return Err(ValueError::UnsealFailed.into());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    Ok(cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| ValueError::UnsealFailed)?)
+}
+
+// the 32 bytes of a key as an array
+fn key_array(bytes: &[u8]) -> Result<[u8; KEY_LEN], Error> {
+    <[u8; KEY_LEN]>::try_from(bytes).map_err(|_| ValueError::InvalidSealKey.into())
+}
+
+// get the public key of the key, converting secret keys to public ones
+fn public_key(key: &Multikey) -> Result<Multikey, Error> {
+    if key.attr_view()?.is_secret_key() {
+        Ok(key.conv_view()?.to_public_key()?)
+    } else {
+        Ok(key.clone())
+    }
+}
+
+// get the X25519 form of the public key
+fn x25519_public(key: &Multikey) -> Result<PublicKey, Error> {
+    let bytes = key_array(&key.data_view()?.key_bytes()?)?;
+    match key.codec() {
+        Codec::X25519Pub => Ok(PublicKey::from(bytes)),
+        // the birationally equivalent point on the montgomery curve
+        Codec::Ed25519Pub => Ok(PublicKey::from(
+            CompressedEdwardsY(bytes)
+                .decompress()
+                .ok_or(ValueError::InvalidSealKey)?
+                .to_montgomery()
+                .to_bytes(),
+        )),
+        codec => Err(ValueError::UnsupportedSealKey(codec).into()),
+    }
+}
+
+// get the X25519 form of the secret key
+fn x25519_secret(key: &Multikey) -> Result<StaticSecret, Error> {
+    let bytes = key_array(&key.data_view()?.secret_bytes()?)?;
+    match key.codec() {
+        Codec::X25519Priv => Ok(StaticSecret::from(bytes)),
+        // the scalar is the first half of the hashed seed, as in ed25519. it
+        // is clamped when it is used.
+        Codec::Ed25519Priv => Ok(StaticSecret::from(key_array(
+            &Sha512::digest(bytes)[..KEY_LEN],
+        )?)),
+        codec => Err(ValueError::UnsupportedSealKey(codec).into()),
+    }
+}
+
+// derive the key that wraps the content key from the shared secret, bound to
+// both public keys of the agreement
+fn wrapping_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> Vec<u8> {
+    let mut salt = ephemeral.as_bytes().to_vec();
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut kek = vec![0u8; KEY_LEN];
+    // the output length is always valid for sha256
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(KEY_WRAP_LABEL, &mut kek)
+        .expect("valid hkdf output length");
+    kek
+}
+
+// wrap the content key for the recipient public key
+fn wrap(key: &Multikey, content_key: &[u8]) -> Result<Recipient, Error> {
+    let key = public_key(key)?;
+    let recipient = x25519_public(&key)?;
+    let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let ephemeral = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&recipient);
+    let kek = wrapping_key(shared.as_bytes(), &ephemeral, &recipient);
+    Ok(Recipient {
+        id: fingerprint(&key)?.into(),
+        ephemeral: ephemeral.as_bytes().to_vec(),
+        wrapped_key: encrypt(&kek, content_key)?,
+    })
+}
+
+// unwrap the content key with the recipient secret key
+fn unwrap(key: &Multikey, recipient: &Recipient) -> Result<Vec<u8>, Error> {
+    let secret = x25519_secret(key)?;
+    let ephemeral = PublicKey::from(key_array(&recipient.ephemeral)?);
+    let shared = secret.diffie_hellman(&ephemeral);
+    let kek = wrapping_key(shared.as_bytes(), &ephemeral, &PublicKey::from(&secret));
+    decrypt(&kek, &recipient.wrapped_key)
+}
+
+impl Value {
+    /// seal the value so only the holders of the secret keys for the
+    /// recipient keys can read it. Only the public keys are needed, secret
+    /// keys are converted to their public keys.
+    pub fn seal(&self, recipients: &[Multikey]) -> Result<Self, Error> {
+        let mut content_key = vec![0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut content_key);
+        let recipients = recipients
+            .iter()
+            .map(|key| wrap(key, &content_key))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let plaintext: Vec<u8> = self.clone().into();
+        Ok(Self::Encrypted {
+            scheme: SEAL_SCHEME,
+            recipients,
+            ciphertext: encrypt(&content_key, &plaintext)?,
+        })
+    }
+
+    /// unseal the value with the secret key of one of its recipients
+    pub fn unseal(&self, key: &Multikey) -> Result<Self, Error> {
+        let (scheme, recipients, ciphertext) = match self {
+            Self::Encrypted {
+                scheme,
+                recipients,
+                ciphertext,
+            } => (scheme, recipients, ciphertext),
+            _ => return Err(ValueError::NotEncrypted.into()),
+        };
+        if *scheme != SEAL_SCHEME {
+            return Err(ValueError::UnsupportedScheme(*scheme).into());
+        }
+        let id: Vec<u8> = fingerprint(&public_key(key)?)?.into();
+        let recipient = recipients
+            .iter()
+            .find(|r| r.id == id)
+            .ok_or(ValueError::NotARecipient)?;
+        let content_key = unwrap(key, recipient)?;
+        Self::try_from(decrypt(&content_key, ciphertext)?.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multikey::EncodedMultikey;
+
+    #[test]
+    fn test_seal_unseal() {
+        let mk = |s: &str| EncodedMultikey::try_from(s).unwrap().to_inner();
+        let key1 = mk("fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8");
+        let key2 = mk("fba2480260874657374206b65790101203f4c94407de791e53b4df12ef1d5534d1b19ff2ccfccba4ccc4722b6e5e8ea07");
+        let key3 = mk("fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0");
+
+        let value = Value::Str("zig@example.com".to_string());
+        let sealed = value.seal(&[key1.clone(), key2.clone()]).unwrap();
+        assert!(!sealed.as_ref().windows(3).any(|w| w == b"zig"));
+
+        // the sealed value round trips through the encoding
+        let bytes: Vec<u8> = sealed.clone().into();
+        assert_eq!(sealed, Value::try_from(bytes.as_slice()).unwrap());
+
+        assert_eq!(value, sealed.unseal(&key1).unwrap());
+        assert_eq!(value, sealed.unseal(&key2).unwrap());
+        assert!(matches!(
+            sealed.unseal(&key3),
+            Err(Error::Value(ValueError::NotARecipient))
+        ));
+        assert!(matches!(
+            value.unseal(&key1),
+            Err(Error::Value(ValueError::NotEncrypted))
+        ));
+
+        // sealing only needs the public keys
+        let public = |k: &Multikey| k.conv_view().unwrap().to_public_key().unwrap();
+        let sealed = value.seal(&[public(&key1), public(&key2)]).unwrap();
+        assert_eq!(value, sealed.unseal(&key1).unwrap());
+        assert_eq!(value, sealed.unseal(&key2).unwrap());
+        assert!(sealed.unseal(&key3).is_err());
+        // a public key can't unseal
+        assert!(sealed.unseal(&public(&key1)).is_err());
+
+        // tampering with the ciphertext is caught
+        let tampered = match sealed {
+            Value::Encrypted {
+                scheme,
+                recipients,
+                mut ciphertext,
+            } => {
+                ciphertext[NONCE_LEN] ^= 0xff;
+                Value::Encrypted {
+                    scheme,
+                    recipients,
+                    ciphertext,
+                }
+            }
+            _ => panic!("expected an encrypted value"),
+        };
+        assert!(matches!(
+            tampered.unseal(&key1),
+            Err(Error::Value(ValueError::UnsealFailed))
+        ));
+    }
+}
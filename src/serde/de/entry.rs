@@ -140,6 +140,7 @@ impl<'de> Deserialize<'de> for Entry {
                     locks,
                     unlock,
                     proof,
+                    cached_cid: Default::default(),
                 })
             }
         }
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: FSL-1.1
 use crate::{
-    log::{Entries, SIGIL},
+    log::{Entries, SeqnoIndex, SIGIL},
     Entry, Log, Script,
 };
 use core::fmt;
@@ -109,6 +109,7 @@ impl<'de> Deserialize<'de> for Log {
                 let foot = foot.ok_or_else(|| Error::missing_field("foot"))?;
                 let head = head.ok_or_else(|| Error::missing_field("head"))?;
                 let entries = entries.ok_or_else(|| Error::missing_field("entries"))?;
+                let seqnos = SeqnoIndex::from(&entries);
                 Ok(Self::Value {
                     version,
                     vlad,
@@ -116,6 +117,9 @@ impl<'de> Deserialize<'de> for Log {
                     foot,
                     head,
                     entries,
+                    checkpoint: None,
+                    seqnos,
+                    ..Default::default()
                 })
             }
         }
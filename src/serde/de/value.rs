@@ -29,7 +29,7 @@ impl<'de> Deserialize<'de> for Value {
     where
         D: Deserializer<'de>,
     {
-        const VARIANTS: &[&str] = &["nil", "str", "data"];
+        const VARIANTS: &[&str] = &["nil", "str", "data", "encrypted", "plogref"];
 
         #[derive(Deserialize)]
         #[serde(field_identifier, rename_all = "lowercase")]
@@ -37,6 +37,8 @@ impl<'de> Deserialize<'de> for Value {
             Nil,
             Str,
             Data,
+            Encrypted,
+            PlogRef,
         }
 
         struct StrVisitor;
@@ -79,6 +81,52 @@ impl<'de> Deserialize<'de> for Value {
             }
         }
 
+        struct EncryptedVisitor;
+
+        impl<'de> Visitor<'de> for EncryptedVisitor {
+            type Value = Value;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "enum Value::Encrypted")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let b: EncodedVarbytes = seq
+                    .next_element()?
+                    .ok_or_else(|| Error::missing_field("encrypted"))?;
+                match Value::try_from(b.to_inner().to_inner().as_slice()) {
+                    Ok(v @ Value::Encrypted { .. }) => Ok(v),
+                    _ => Err(Error::custom("invalid encrypted value")),
+                }
+            }
+        }
+
+        struct PlogRefVisitor;
+
+        impl<'de> Visitor<'de> for PlogRefVisitor {
+            type Value = Value;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "enum Value::PlogRef")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let b: EncodedVarbytes = seq
+                    .next_element()?
+                    .ok_or_else(|| Error::missing_field("plogref"))?;
+                match Value::try_from(b.to_inner().to_inner().as_slice()) {
+                    Ok(v @ Value::PlogRef { .. }) => Ok(v),
+                    _ => Err(Error::custom("invalid plog reference value")),
+                }
+            }
+        }
+
         struct ValueVisitor;
 
         impl<'de> Visitor<'de> for ValueVisitor {
@@ -99,6 +147,8 @@ impl<'de> Deserialize<'de> for Value {
                     }
                     (Variant::Str, v) => Ok(v.tuple_variant(1, StrVisitor)?),
                     (Variant::Data, v) => Ok(v.tuple_variant(1, DataVisitor)?),
+                    (Variant::Encrypted, v) => Ok(v.tuple_variant(1, EncryptedVisitor)?),
+                    (Variant::PlogRef, v) => Ok(v.tuple_variant(1, PlogRefVisitor)?),
                 }
             }
         }
@@ -5,7 +5,7 @@ mod ser;
 
 #[cfg(test)]
 mod tests {
-    use crate::{entry, Key, Op, Script, Value};
+    use crate::{entry, log, Key, Log, Op, Script, Value};
     use multicid::{cid, vlad};
     use multicodec::Codec;
     use multihash::mh;
@@ -97,6 +97,36 @@ mod tests {
         assert_eq!(v, serde_cbor::from_slice(b.as_slice()).unwrap());
     }
 
+    #[test]
+    fn test_value_encrypted_round_trip() {
+        let v = Value::Encrypted {
+            scheme: crate::seal::SEAL_SCHEME,
+            recipients: vec![crate::Recipient {
+                id: vec![1, 2, 3],
+                ephemeral: vec![0; 32],
+                wrapped_key: vec![4, 5, 6],
+            }],
+            ciphertext: vec![7, 8, 9],
+        };
+        let s = serde_json::to_string(&v).unwrap();
+        assert_eq!(v, serde_json::from_str(&s).unwrap());
+        let b = serde_cbor::to_vec(&v).unwrap();
+        assert_eq!(v, serde_cbor::from_slice(b.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_value_plog_ref_round_trip() {
+        let v = Value::PlogRef {
+            vlad: multicid::Vlad::default(),
+            entry_cid: multicid::Cid::default(),
+            key: "/employees/alice".try_into().unwrap(),
+        };
+        let s = serde_json::to_string(&v).unwrap();
+        assert_eq!(v, serde_json::from_str(&s).unwrap());
+        let b = serde_cbor::to_vec(&v).unwrap();
+        assert_eq!(v, serde_cbor::from_slice(b.as_slice()).unwrap());
+    }
+
     #[test]
     fn test_op_default_compact() {
         let o = Op::default();
@@ -498,4 +528,45 @@ mod tests {
             ],
         );
     }
+
+    fn test_log() -> Log {
+        let e1 = entry::Builder::default()
+            .with_vlad(&multicid::Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/move".try_into().unwrap(), Value::Str("zig!".into())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Delete("/move".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        log::Builder::new()
+            .with_vlad(&multicid::Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_log_json_round_trip() {
+        let log = test_log();
+        let s = serde_json::to_string(&log).unwrap();
+        let log2: Log = serde_json::from_str(&s).unwrap();
+        assert_eq!(log, log2);
+        assert_eq!(2, log2.entries.len());
+        // the seqno index is rebuilt
+        assert_eq!(log.head, log2.get_by_seqno(1).unwrap().cid());
+    }
+
+    #[test]
+    fn test_log_cbor_round_trip() {
+        let log = test_log();
+        let v = serde_cbor::to_vec(&log).unwrap();
+        let log2: Log = serde_cbor::from_slice(v.as_slice()).unwrap();
+        assert_eq!(log, log2);
+        assert_eq!(2, log2.entries.len());
+    }
 }
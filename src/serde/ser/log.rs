@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{log::SIGIL, Log};
+use crate::{log::SIGIL, Entry, Log};
+use multicid::Cid;
 use serde::ser::{self, SerializeStruct};
 
 /// Serialize instance of [`crate::Log`]
@@ -9,11 +10,14 @@ impl ser::Serialize for Log {
         S: ser::Serializer,
     {
         if serializer.is_human_readable() {
-            let mut ss = serializer.serialize_struct(SIGIL.as_str(), 4)?;
+            let entries: Vec<(&Cid, &Entry)> = self.entries.iter().collect();
+            let mut ss = serializer.serialize_struct(SIGIL.as_str(), 6)?;
+            ss.serialize_field("version", &self.version)?;
             ss.serialize_field("vlad", &self.vlad)?;
             ss.serialize_field("first_lock", &self.first_lock)?;
             ss.serialize_field("foot", &self.foot)?;
             ss.serialize_field("head", &self.head)?;
+            ss.serialize_field("entries", &entries)?;
             ss.end()
         } else {
             let v: Vec<u8> = self.clone().into();
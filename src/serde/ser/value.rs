@@ -50,6 +50,30 @@ impl ser::Serialize for Value {
                     ss.serialize_field(&Varbytes::encoded_new(self.encoding(), b.clone()))?;
                     ss.end()
                 }
+                // sealed values are serialized in their native binary encoding
+                Self::Encrypted { .. } => {
+                    let mut ss = serializer.serialize_tuple_variant(
+                        "value",
+                        ValueId::Encrypted.code() as u32,
+                        ValueId::Encrypted.as_str(),
+                        1,
+                    )?;
+                    let v: Vec<u8> = self.clone().into();
+                    ss.serialize_field(&Varbytes::encoded_new(self.encoding(), v))?;
+                    ss.end()
+                }
+                // so are references to other logs
+                Self::PlogRef { .. } => {
+                    let mut ss = serializer.serialize_tuple_variant(
+                        "value",
+                        ValueId::PlogRef.code() as u32,
+                        ValueId::PlogRef.as_str(),
+                        1,
+                    )?;
+                    let v: Vec<u8> = self.clone().into();
+                    ss.serialize_field(&Varbytes::encoded_new(self.encoding(), v))?;
+                    ss.end()
+                }
             }
         } else {
             let v: Vec<u8> = self.clone().into();
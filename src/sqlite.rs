@@ -0,0 +1,548 @@
+// SPDX-License-Identifier: FSL-1.1
+//! SQLite persistence of logs. A [`SqliteLogStore`] keeps a log in a single
+//! database file with the entries indexed by cid and seqno, the log header
+//! and the verification checkpoints. Saving a log only writes the entries
+//! past the last stored seqno so appending doesn't rewrite the whole log. A
+//! store holds one log and saving a log with another vlad to it fails.
+//! Loading a compacted log only reads the entries after its checkpoint and
+//! the ones on the path from the checkpoint back to the foot. The store is
+//! also a [`BlockStore`].
+//!
+//! [`Log::open`] loads a log from an existing file and remembers the file so
+//! that [`Log::flush`] can write the entries appended since.
+use crate::{
+    blockstore::raw_cid, log, BlockStore, Checkpoint, Entry, Error, Lipmaa, Log, Script,
+};
+use multicid::{Cid, Vlad};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS header (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        version INTEGER NOT NULL,
+        vlad BLOB NOT NULL,
+        first_lock BLOB NOT NULL,
+        foot BLOB NOT NULL,
+        head BLOB NOT NULL,
+        checkpoint BLOB
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        cid BLOB PRIMARY KEY,
+        seqno INTEGER NOT NULL,
+        entry BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS entries_seqno ON entries (seqno);
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        cid BLOB PRIMARY KEY,
+        seqno INTEGER NOT NULL,
+        checkpoint BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS checkpoints_seqno ON checkpoints (seqno);
+    CREATE TABLE IF NOT EXISTS blocks (
+        cid BLOB PRIMARY KEY,
+        block BLOB NOT NULL
+    );
+";
+
+fn storage_err<S: ToString>(s: S) -> Error {
+    Error::Storage(s.to_string())
+}
+
+// seqnos and versions are stored as signed sqlite integers
+fn to_sql_int(i: u64) -> Result<i64, Error> {
+    i64::try_from(i).map_err(storage_err)
+}
+
+fn from_sql_int(i: i64) -> Result<u64, Error> {
+    u64::try_from(i).map_err(storage_err)
+}
+
+/// A SqliteLogStore persists a log in a SQLite database
+pub struct SqliteLogStore {
+    conn: Connection,
+}
+
+impl SqliteLogStore {
+    /// open the store in the database file, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::init(Connection::open(path).map_err(storage_err)?)
+    }
+
+    /// open the store in an existing database file, failing if there is no
+    /// file rather than creating it
+    pub fn open_existing<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Self::init(Connection::open_with_flags(path, flags).map_err(storage_err)?)
+    }
+
+    /// open a store that only lives in memory
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory().map_err(storage_err)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(SCHEMA).map_err(storage_err)?;
+        Ok(Self { conn })
+    }
+
+    /// get the entry with the cid
+    pub fn get_entry(&self, cid: &Cid) -> Result<Option<Entry>, Error> {
+        let cid: Vec<u8> = cid.clone().into();
+        let bytes: Option<Vec<u8>> = self
+            .conn
+            .query_row("SELECT entry FROM entries WHERE cid = ?1", params![cid], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(storage_err)?;
+        bytes.map(|b| Entry::try_from(b.as_slice())).transpose()
+    }
+
+    /// get the entries with the seqno, there is more than one if the log
+    /// forked at the seqno
+    pub fn get_entries_by_seqno(&self, seqno: u64) -> Result<Vec<Entry>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT entry FROM entries WHERE seqno = ?1")
+            .map_err(storage_err)?;
+        let rows = stmt
+            .query_map(params![to_sql_int(seqno)?], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(storage_err)?;
+        let mut entries = Vec::default();
+        for bytes in rows {
+            entries.push(Entry::try_from(bytes.map_err(storage_err)?.as_slice())?);
+        }
+        Ok(entries)
+    }
+
+    /// get the highest seqno of the stored entries
+    pub fn max_seqno(&self) -> Result<Option<u64>, Error> {
+        let seqno: Option<i64> = self
+            .conn
+            .query_row("SELECT MAX(seqno) FROM entries", [], |row| row.get(0))
+            .map_err(storage_err)?;
+        seqno.map(from_sql_int).transpose()
+    }
+
+    /// store the entry
+    pub fn put_entry(&self, entry: &Entry) -> Result<(), Error> {
+        let cid: Vec<u8> = entry.cid().into();
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO entries (cid, seqno, entry) VALUES (?1, ?2, ?3)",
+                params![cid, to_sql_int(entry.seqno())?, entry.canonical_bytes()],
+            )
+            .map_err(storage_err)?;
+        Ok(())
+    }
+
+    /// store the checkpoint and return its cid
+    pub fn put_checkpoint(&self, checkpoint: &Checkpoint) -> Result<Cid, Error> {
+        let bytes: Vec<u8> = checkpoint.clone().into();
+        let cid = raw_cid(&bytes)?;
+        let c: Vec<u8> = cid.clone().into();
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO checkpoints (cid, seqno, checkpoint) VALUES (?1, ?2, ?3)",
+                params![c, to_sql_int(checkpoint.seqno())?, bytes],
+            )
+            .map_err(storage_err)?;
+        Ok(cid)
+    }
+
+    /// get the stored checkpoint with the cid
+    pub fn get_checkpoint(&self, cid: &Cid) -> Result<Option<Checkpoint>, Error> {
+        let cid: Vec<u8> = cid.clone().into();
+        let bytes: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "SELECT checkpoint FROM checkpoints WHERE cid = ?1",
+                params![cid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_err)?;
+        bytes.map(|b| Checkpoint::try_from(b.as_slice())).transpose()
+    }
+
+    /// get the stored checkpoint with the highest seqno
+    pub fn latest_checkpoint(&self) -> Result<Option<Checkpoint>, Error> {
+        let bytes: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "SELECT checkpoint FROM checkpoints ORDER BY seqno DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_err)?;
+        bytes.map(|b| Checkpoint::try_from(b.as_slice())).transpose()
+    }
+
+    /// save the log, writing the header, its checkpoint and the entries with
+    /// seqnos past the highest stored seqno in one transaction. fails if the
+    /// store already holds a log with a different vlad.
+    pub fn save(&mut self, log: &Log) -> Result<(), Error> {
+        let vlad: Vec<u8> = log.vlad.clone().into();
+        let tx = self.conn.transaction().map_err(storage_err)?;
+        let stored: Option<Vec<u8>> = tx
+            .query_row("SELECT vlad FROM header WHERE id = 0", [], |row| row.get(0))
+            .optional()
+            .map_err(storage_err)?;
+        if stored.is_some_and(|stored| stored != vlad) {
+            return Err(storage_err("the store holds a log with a different vlad"));
+        }
+        let max_seqno: Option<i64> = tx
+            .query_row("SELECT MAX(seqno) FROM entries", [], |row| row.get(0))
+            .map_err(storage_err)?;
+        for entry in log.entries.values() {
+            let seqno = to_sql_int(entry.seqno())?;
+            if max_seqno.is_some_and(|max| seqno <= max) {
+                continue;
+            }
+            let cid: Vec<u8> = entry.cid().into();
+            tx.execute(
+                "INSERT OR IGNORE INTO entries (cid, seqno, entry) VALUES (?1, ?2, ?3)",
+                params![cid, seqno, entry.canonical_bytes()],
+            )
+            .map_err(storage_err)?;
+        }
+        let checkpoint = match &log.checkpoint {
+            Some(cp) => {
+                let bytes: Vec<u8> = cp.clone().into();
+                let cid: Vec<u8> = raw_cid(&bytes)?.into();
+                tx.execute(
+                    "INSERT OR IGNORE INTO checkpoints (cid, seqno, checkpoint) VALUES (?1, ?2, ?3)",
+                    params![cid, to_sql_int(cp.seqno())?, bytes],
+                )
+                .map_err(storage_err)?;
+                Some(cid)
+            }
+            None => None,
+        };
+        let first_lock: Vec<u8> = log.first_lock.clone().into();
+        let foot: Vec<u8> = log.foot.clone().into();
+        let head: Vec<u8> = log.head.clone().into();
+        tx.execute(
+            "INSERT OR REPLACE INTO header (id, version, vlad, first_lock, foot, head, checkpoint)
+             VALUES (0, ?1, ?2, ?3, ?4, ?5, ?6)",
+            params![to_sql_int(log.version)?, vlad, first_lock, foot, head, checkpoint],
+        )
+        .map_err(storage_err)?;
+        tx.commit().map_err(storage_err)
+    }
+
+    // get the entries on the path from the entry with the cid back to the
+    // foot, taking the lipmaa links that stay in the log like compacting does
+    fn continuity_path(&self, from: &Cid, foot: &Cid) -> Result<Vec<Entry>, Error> {
+        let missing = || storage_err("an entry on the path to the foot is missing");
+        let foot_seqno = self.get_entry(foot)?.ok_or_else(missing)?.seqno();
+        let mut path = Vec::default();
+        let mut next = self.get_entry(from)?.ok_or_else(missing)?;
+        loop {
+            let seqno = next.seqno();
+            if next.cid() == *foot {
+                path.push(next);
+                return Ok(path);
+            }
+            let lipmaa = if next.lipmaa_ref().is_null() || seqno.lipmaa() < foot_seqno {
+                None
+            } else {
+                self.get_entry(next.lipmaa_ref())?
+            };
+            let prev = match lipmaa {
+                Some(entry) => entry,
+                None => self.get_entry(next.prev_ref())?.ok_or_else(missing)?,
+            };
+            // the seqnos go down on the way to the foot
+            if prev.seqno() >= seqno || prev.seqno() < foot_seqno {
+                return Err(storage_err("the entries don't link back to the foot"));
+            }
+            path.push(next);
+            next = prev;
+        }
+    }
+
+    /// load the log in the store. the entries of a compacted log from before
+    /// its checkpoint aren't read unless they prove the continuity of the log.
+    pub fn load(&self) -> Result<Log, Error> {
+        type Header = (i64, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>);
+        let header: Option<Header> = self
+            .conn
+            .query_row(
+                "SELECT version, vlad, first_lock, foot, head, checkpoint FROM header WHERE id = 0",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(storage_err)?;
+        let (version, vlad, first_lock, foot, head, checkpoint) =
+            header.ok_or_else(|| storage_err("the store has no log"))?;
+
+        let foot = Cid::try_from(foot.as_slice())?;
+        let mut builder = log::Builder::new()
+            .with_vlad(&Vlad::try_from(vlad.as_slice())?)
+            .with_first_lock(&Script::try_from(first_lock.as_slice())?)
+            .with_foot(&foot)
+            .with_head(&Cid::try_from(head.as_slice())?);
+        let mut entries = log::Entries::new();
+        // read the entries after the checkpoint, or all of them
+        let mut after = -1;
+        if let Some(cid) = checkpoint {
+            let cp = self
+                .get_checkpoint(&Cid::try_from(cid.as_slice())?)?
+                .ok_or_else(|| storage_err("the log checkpoint is missing"))?;
+            for entry in self.continuity_path(&cp.head, &foot)? {
+                entries.insert(entry.cid(), entry);
+            }
+            after = to_sql_int(cp.seqno())?;
+            builder = builder.with_checkpoint(&cp);
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT entry FROM entries WHERE seqno > ?1 ORDER BY seqno")
+            .map_err(storage_err)?;
+        let rows = stmt
+            .query_map(params![after], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(storage_err)?;
+        for bytes in rows {
+            let entry = Entry::try_from(bytes.map_err(storage_err)?.as_slice())?;
+            entries.insert(entry.cid(), entry);
+        }
+        let mut log = builder.with_entries(&entries).try_build()?;
+        log.version = from_sql_int(version)?;
+        Ok(log)
+    }
+}
+
+impl BlockStore for SqliteLogStore {
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, Error> {
+        let cid: Vec<u8> = cid.clone().into();
+        self.conn
+            .query_row("SELECT block FROM blocks WHERE cid = ?1", params![cid], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(storage_err)
+    }
+
+    fn put(&mut self, cid: &Cid, block: &[u8]) -> Result<(), Error> {
+        let cid: Vec<u8> = cid.clone().into();
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO blocks (cid, block) VALUES (?1, ?2)",
+                params![cid, block],
+            )
+            .map_err(storage_err)?;
+        Ok(())
+    }
+}
+
+/// The database file a Log was opened from. Like the subscribers it is
+/// ignored when comparing Logs.
+#[derive(Clone, Default)]
+pub(crate) struct StorePath(Option<PathBuf>);
+
+impl PartialEq for StorePath {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Log {
+    /// load the log from an existing SQLite database file. the log
+    /// remembers the file so [`Log::flush`] can save the entries appended
+    /// since.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut log = SqliteLogStore::open_existing(path.as_ref())?.load()?;
+        log.store = StorePath(Some(path.as_ref().to_path_buf()));
+        Ok(log)
+    }
+
+    /// save the log to the SQLite database file and remember the file so
+    /// [`Log::flush`] can save the entries appended later
+    pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        SqliteLogStore::open(path.as_ref())?.save(self)?;
+        self.store = StorePath(Some(path.as_ref().to_path_buf()));
+        Ok(())
+    }
+
+    /// save the changes to the log to the database file it was opened from
+    pub fn flush(&self) -> Result<(), Error> {
+        let path = self
+            .store
+            .0
+            .as_ref()
+            .ok_or_else(|| storage_err("the log wasn't opened from a file"))?;
+        SqliteLogStore::open_existing(path)?.save(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{checkpoint, entry, Kvp, Op};
+    use multicid::vlad;
+    use multikey::nonce;
+
+    fn entries() -> Vec<Entry> {
+        chain(&Vlad::default(), 3)
+    }
+
+    // a chain of n entries with the lipmaa links set
+    fn chain(vlad: &Vlad, n: u64) -> Vec<Entry> {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(vlad)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap()];
+        for seqno in 1..n {
+            let mut b = entry::Builder::from(entries.last().unwrap())
+                .with_unlock(&Script::default())
+                .add_op(&Op::Noop("/a".try_into().unwrap()));
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(b"proof".to_vec())).unwrap());
+        }
+        entries
+    }
+
+    fn log_of(entries: &[Entry]) -> Log {
+        entries
+            .iter()
+            .fold(log::Builder::new(), |b, e| b.append_entry(e))
+            .with_vlad(&entries[0].vlad())
+            .with_first_lock(&Script::default())
+            .try_build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let entries = entries();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&entries[0])
+            .append_entry(&entries[1])
+            .try_build()
+            .unwrap();
+
+        let mut store = SqliteLogStore::open_in_memory().unwrap();
+        assert!(store.load().is_err());
+        store.save(&log).unwrap();
+        assert_eq!(log, store.load().unwrap());
+        assert_eq!(Some(1), store.max_seqno().unwrap());
+        assert_eq!(Some(entries[1].clone()), store.get_entry(&entries[1].cid()).unwrap());
+        assert_eq!(vec![entries[0].clone()], store.get_entries_by_seqno(0).unwrap());
+
+        // checkpoints are cached by seqno
+        let cp = checkpoint::Builder::new(&entries[1], &Kvp::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let cid = store.put_checkpoint(&cp).unwrap();
+        assert!(Some(cp.clone()) == store.get_checkpoint(&cid).unwrap());
+        assert!(Some(cp) == store.latest_checkpoint().unwrap());
+
+        // the store holds blocks too
+        let cid = raw_cid(b"block").unwrap();
+        store.put(&cid, b"block").unwrap();
+        assert!(store.has(&cid).unwrap());
+    }
+
+    #[test]
+    fn test_open_flush() {
+        let entries = entries();
+        let mut log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&entries[0])
+            .append_entry(&entries[1])
+            .try_build()
+            .unwrap();
+        assert!(log.flush().is_err());
+
+        let path = std::env::temp_dir().join(format!("plog-{}.db", rand::random::<u64>()));
+        log.save_to(&path).unwrap();
+        let mut opened = Log::open(&path).unwrap();
+        assert_eq!(log, opened);
+
+        // only the new entry is written on flush
+        opened.entries.insert(entries[2].cid(), entries[2].clone());
+        opened.set_head_checked(&entries[2].cid()).unwrap();
+        opened.flush().unwrap();
+        let reopened = Log::open(&path).unwrap();
+        assert_eq!(opened, reopened);
+        assert_eq!(3, reopened.entries.len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_vlad_mismatch() {
+        let mut store = SqliteLogStore::open_in_memory().unwrap();
+        let log = log_of(&entries());
+        store.save(&log).unwrap();
+
+        let nonce = nonce::Builder::new_from_bytes(&[1; 32]).try_build().unwrap();
+        let other = vlad::Builder::default()
+            .with_nonce(&nonce)
+            .with_cid(&Cid::default())
+            .try_build()
+            .unwrap();
+        assert!(matches!(
+            store.save(&log_of(&chain(&other, 4))),
+            Err(Error::Storage(_))
+        ));
+        assert_eq!(log, store.load().unwrap());
+    }
+
+    #[test]
+    fn test_load_compacted() {
+        // the checkpoint at seqno 4 links back to the foot through its lipmaa
+        // link to seqno 1 so seqnos 2 and 3 aren't needed
+        let entries = chain(&Vlad::default(), 6);
+        let mut store = SqliteLogStore::open_in_memory().unwrap();
+        store.save(&log_of(&entries)).unwrap();
+
+        let cp = checkpoint::Builder::new(&entries[4], &Kvp::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let kept: log::Entries = [0, 1, 4, 5]
+            .into_iter()
+            .map(|i| (entries[i].cid(), entries[i].clone()))
+            .collect();
+        let compacted = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .with_foot(&entries[0].cid())
+            .with_head(&entries[5].cid())
+            .with_entries(&kept)
+            .with_checkpoint(&cp)
+            .try_build()
+            .unwrap();
+        store.save(&compacted).unwrap();
+        let loaded = store.load().unwrap();
+        assert_eq!(kept, loaded.entries);
+        assert!(loaded.verify_continuity().is_ok());
+    }
+
+    #[test]
+    fn test_open_missing() {
+        let path = std::env::temp_dir().join(format!("plog-{}.db", rand::random::<u64>()));
+        assert!(Log::open(&path).is_err());
+        assert!(!path.exists());
+    }
+}
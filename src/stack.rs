@@ -1,8 +1,17 @@
 // SPDX-License-Identifier: FSL-1.1
+use crate::SafeBytes;
 use log::info;
 use std::fmt;
 use wacc::{Stack, vm::Value};
 
+/// format a stack value with any binary data redacted
+fn safe_value(value: &Value) -> String {
+    match value {
+        Value::Bin { hint, data } => format!("Bin({}) {}", hint, SafeBytes(data)),
+        v => format!("{:?}", v),
+    }
+}
+
 /// Stack is used for both the parameter and return value stacks in the WACC vm
 #[derive(Clone, Default)]
 pub struct Stk {
@@ -12,7 +21,7 @@ pub struct Stk {
 impl Stack for Stk {
     /// push a value onto the stack
     fn push(&mut self, value: Value) {
-        info!(" push: {:?}", &value);
+        info!(" push: {}", safe_value(&value));
         self.stack.push(value);
         info!("stack:\n{:?}", &self);
     }
@@ -21,7 +30,7 @@ impl Stack for Stk {
     fn pop(&mut self) -> Option<Value> {
         match self.stack.pop() {
             ref r @ Some(ref v) => {
-                info!("  pop: {:?}", &v);
+                info!("  pop: {}", safe_value(v));
                 info!("stack:\n{:?}", &self);
                 r.clone()
             }
@@ -61,10 +70,10 @@ const MAX_STR_WIDTH: usize = 32;
 impl fmt::Debug for Stk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let values = self.stack.iter().rev().map(|v| {
-            let mut s = format!("{:?}", v);
-            if s.len() >= MAX_STR_WIDTH {
-                let (trunc, _) = s.split_at_mut(MAX_STR_WIDTH - 1);
-                s = trunc.to_string();
+            let mut s = safe_value(v);
+            // truncate on a char boundary, the redacted values contain '…'
+            if s.chars().count() >= MAX_STR_WIDTH {
+                s = s.chars().take(MAX_STR_WIDTH - 1).collect();
                 s.push('…');
             }
             s
@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{
+    error::LogError,
+    log::{Entries, ScriptTable, CHECKPOINT_LOG_VERSION, SCRIPT_TABLE_LOG_VERSION, SIGIL},
+    Checkpoint, Entry, Error, Log, Script,
+};
+use multicid::{Cid, Vlad};
+use multicodec::Codec;
+use multitrait::TryDecodeFrom;
+use multiutil::Varuint;
+use std::io::Read;
+
+/// the minimum number of bytes read from the reader at a time
+const MIN_READ: usize = 4096;
+
+/// A Decoder parses Entry and Log objects incrementally from a reader. Only
+/// the bytes of the object currently being decoded are buffered, so large
+/// logs can be loaded from disk or a socket with bounded memory. Bytes read
+/// past the end of an object are kept for the next one.
+pub struct Decoder<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    /// create a decoder that reads from the given reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::default(),
+            eof: false,
+        }
+    }
+
+    /// decode the next entry
+    pub fn entry(&mut self) -> Result<Entry, Error> {
+        self.decode(|b| Entry::try_decode_from(b))
+    }
+
+    /// decode the next log
+    pub fn log(&mut self) -> Result<Log, Error> {
+        // decode the sigil
+        let sigil = self.decode(|b| Ok(Codec::try_decode_from(b)?))?;
+        if sigil != SIGIL {
+            return Err(LogError::MissingSigil.into());
+        }
+        // decode the version
+        let version = self.decode(|b| Ok(Varuint::<u64>::try_decode_from(b)?))?;
+        let version = version.to_inner();
+        // decode the vlad
+        let vlad = self.decode(|b| Ok(Vlad::try_decode_from(b)?))?;
+        // decode the lock script for the first entry
+        let first_lock = self.decode(|b| Script::try_decode_from(b))?;
+        // decode the foot cid
+        let foot = self.decode(|b| Ok(Cid::try_decode_from(b)?))?;
+        // decode the head cid
+        let head = self.decode(|b| Ok(Cid::try_decode_from(b)?))?;
+        // decode the script table one script at a time if there is one
+        let scripts = if version >= SCRIPT_TABLE_LOG_VERSION {
+            let num_scripts = self.decode(|b| Ok(Varuint::<usize>::try_decode_from(b)?))?;
+            let mut scripts = Vec::default();
+            for _ in 0..*num_scripts {
+                scripts.push(self.decode(|b| Script::try_decode_from(b))?);
+            }
+            Some(ScriptTable(scripts))
+        } else {
+            None
+        };
+        // decode the number of entries
+        let num_entries = self.decode(|b| Ok(Varuint::<usize>::try_decode_from(b)?))?;
+        // decode the entries one at a time
+        let mut entries = Entries::new();
+        for _ in 0..*num_entries {
+            let cid = self.decode(|b| Ok(Cid::try_decode_from(b)?))?;
+            let entry = match &scripts {
+                Some(scripts) => self.decode(|b| scripts.decode_entry(&cid, b))?,
+                None => self.entry()?,
+            };
+            if entries.insert(cid.clone(), entry).is_some() {
+                return Err(LogError::DuplicateEntry(cid).into());
+            }
+        }
+        // decode the checkpoint if there is one
+        let checkpoint = if version >= CHECKPOINT_LOG_VERSION {
+            let num_checkpoints = self.decode(|b| Ok(Varuint::<usize>::try_decode_from(b)?))?;
+            match *num_checkpoints {
+                0 => None,
+                1 => Some(self.decode(|b| Checkpoint::try_decode_from(b))?),
+                _ => return Err(LogError::CheckpointMismatch.into()),
+            }
+        } else {
+            None
+        };
+        let mut log = Log {
+            version,
+            vlad,
+            first_lock,
+            foot,
+            head,
+            entries,
+            checkpoint,
+            ..Default::default()
+        };
+        log.reindex();
+        Ok(log)
+    }
+
+    /// get the reader back along with any bytes read past the last object
+    pub fn into_inner(self) -> (R, Vec<u8>) {
+        (self.reader, self.buf)
+    }
+
+    /// decode an object from the buffered bytes, reading more bytes until
+    /// there are enough or the reader is exhausted
+    fn decode<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: for<'b> Fn(&'b [u8]) -> Result<(T, &'b [u8]), Error>,
+    {
+        loop {
+            if !self.buf.is_empty() {
+                match f(&self.buf) {
+                    Ok((t, ptr)) => {
+                        let consumed = self.buf.len() - ptr.len();
+                        self.buf.drain(..consumed);
+                        return Ok(t);
+                    }
+                    Err(e) if self.eof => return Err(e),
+                    Err(_) => {}
+                }
+            } else if self.eof {
+                return Err(LogError::ReadFailed("unexpected end of input".to_string()).into());
+            }
+            self.fill()?;
+        }
+    }
+
+    /// read at least as many bytes as are buffered so the number of decode
+    /// attempts is logarithmic in the size of the object
+    fn fill(&mut self) -> Result<(), Error> {
+        let want = self.buf.len().max(MIN_READ);
+        let start = self.buf.len();
+        self.buf.resize(start + want, 0);
+        let mut filled = start;
+        while filled < start + want {
+            match self.reader.read(&mut self.buf[filled..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.buf.truncate(filled);
+                    return Err(LogError::ReadFailed(e.to_string()).into());
+                }
+            }
+        }
+        self.buf.truncate(filled);
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// decode an Entry incrementally from a reader. the reader may be read
+    /// past the end of the entry, use a [`Decoder`] to decode a sequence of
+    /// objects from the same reader.
+    pub fn decode_from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Decoder::new(reader).entry()
+    }
+}
+
+impl Log {
+    /// decode a Log incrementally from a reader, buffering only one entry at
+    /// a time
+    pub fn decode_from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Decoder::new(reader).log()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Op, Value};
+    use std::io::Cursor;
+
+    // a reader that only returns a few bytes at a time
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.bytes.len()).min(3);
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    fn entries() -> Vec<Entry> {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Data(vec![0; 10_000])))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&Op::Delete("/foo".try_into().unwrap()))
+            .try_build(|_| Ok(b"proof".to_vec()))
+            .unwrap();
+        vec![e1, e2]
+    }
+
+    #[test]
+    fn test_entry_from_reader() {
+        let entries = entries();
+        let mut bytes: Vec<u8> = entries[0].clone().into();
+        bytes.append(&mut entries[1].clone().into());
+
+        assert_eq!(entries[0], Entry::decode_from_reader(Cursor::new(&bytes)).unwrap());
+
+        // a sequence of entries
+        let mut decoder = Decoder::new(Trickle { bytes: &bytes });
+        assert_eq!(entries[0], decoder.entry().unwrap());
+        assert_eq!(entries[1], decoder.entry().unwrap());
+        assert!(decoder.entry().is_err());
+    }
+
+    #[test]
+    fn test_log_from_reader() {
+        let entries = entries();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&entries[0])
+            .append_entry(&entries[1])
+            .try_build()
+            .unwrap();
+        let bytes: Vec<u8> = log.clone().into();
+
+        assert_eq!(log, Log::decode_from_reader(Trickle { bytes: &bytes }).unwrap());
+        assert_eq!(log, Log::decode_from_reader(Cursor::new(&bytes)).unwrap());
+
+        // a truncated log fails
+        assert!(Log::decode_from_reader(Cursor::new(&bytes[..bytes.len() - 1])).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::Error;
+use multitrait::TryDecodeFrom;
+
+/// StrictDecode decodes an object and rejects any bytes that aren't the one
+/// canonical encoding of it. Over-long varints, trailing bytes and anything
+/// else that doesn't re-encode to the exact same bytes is an error, so a
+/// given logical object has exactly one accepted byte representation and
+/// thus one cid.
+pub trait StrictDecode: Sized {
+    /// decode the object from all of the bytes, rejecting non-canonical
+    /// encodings
+    fn try_decode_strict(bytes: &[u8]) -> Result<Self, Error>;
+}
+
+impl<T> StrictDecode for T
+where
+    T: for<'a> TryDecodeFrom<'a, Error = Error> + Clone + Into<Vec<u8>>,
+{
+    fn try_decode_strict(bytes: &[u8]) -> Result<Self, Error> {
+        let (t, ptr) = T::try_decode_from(bytes)?;
+        if !ptr.is_empty() {
+            return Err(Error::TrailingBytes(ptr.len()));
+        }
+        let canonical: Vec<u8> = t.clone().into();
+        if canonical != bytes {
+            return Err(Error::NonCanonical);
+        }
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, Op, Script, Value};
+
+    #[test]
+    fn test_strict_decode() {
+        let op = Op::Update("/foo".try_into().unwrap(), Value::Str("bar".to_string()));
+        let bytes: Vec<u8> = op.clone().into();
+        assert_eq!(op, Op::try_decode_strict(&bytes).unwrap());
+
+        // trailing bytes are rejected
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Op::try_from(trailing.as_slice()).is_ok());
+        assert!(matches!(
+            Op::try_decode_strict(&trailing),
+            Err(Error::TrailingBytes(1))
+        ));
+
+        // an over-long varint for the key length is rejected
+        let key = Key::try_from("/foo").unwrap();
+        let bytes: Vec<u8> = key.clone().into();
+        assert_eq!(key, Key::try_decode_strict(&bytes).unwrap());
+        let mut padded = vec![0x84, 0x00];
+        padded.extend_from_slice(b"/foo");
+        assert!(Key::try_decode_strict(&padded).is_err());
+
+        let script = Script::default();
+        let bytes: Vec<u8> = script.clone().into();
+        assert_eq!(script, Script::try_decode_strict(&bytes).unwrap());
+    }
+}
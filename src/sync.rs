@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Primitives for synchronizing replicas of a log over any transport. A
+//! replica announces its head with a have message, asks for the seqno ranges
+//! it is missing with a want message and receives entry batches in return:
+//!
+//! ```text
+//! vlad | varuint(kind) | varuint(count) | (cid | (first, last) | entry)*
+//! ```
+use crate::{error::LogError, Entry, Error, Lipmaa, Log};
+use multicid::{Cid, Vlad};
+use multitrait::{Null, TryDecodeFrom};
+use multiutil::Varuint;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the kind of a have message
+pub const HAVE_MESSAGE: u64 = 1;
+
+/// the kind of a want message
+pub const WANT_MESSAGE: u64 = 2;
+
+/// the kind of an entries message
+pub const ENTRIES_MESSAGE: u64 = 3;
+
+/// The payload of a sync message
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyncPayload {
+    /// the cids of the heads the sender has
+    Have(Vec<Cid>),
+    /// the inclusive seqno ranges the sender wants
+    Want(Vec<(u64, u64)>),
+    /// a batch of entries
+    Entries(Vec<Entry>),
+}
+
+/// A SyncMessage is exchanged between replicas of the log with the vlad
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncMessage {
+    /// the vlad of the log being synchronized
+    pub vlad: Vlad,
+    /// the payload of the message
+    pub payload: SyncPayload,
+}
+
+impl From<SyncMessage> for Vec<u8> {
+    fn from(val: SyncMessage) -> Self {
+        let mut v: Vec<u8> = val.vlad.into();
+        match val.payload {
+            SyncPayload::Have(cids) => {
+                v.append(&mut Varuint(HAVE_MESSAGE).into());
+                v.append(&mut Varuint(cids.len()).into());
+                cids.into_iter().for_each(|cid| v.append(&mut cid.into()));
+            }
+            SyncPayload::Want(ranges) => {
+                v.append(&mut Varuint(WANT_MESSAGE).into());
+                v.append(&mut Varuint(ranges.len()).into());
+                ranges.into_iter().for_each(|(first, last)| {
+                    v.append(&mut Varuint(first).into());
+                    v.append(&mut Varuint(last).into());
+                });
+            }
+            SyncPayload::Entries(entries) => {
+                v.append(&mut Varuint(ENTRIES_MESSAGE).into());
+                v.append(&mut Varuint(entries.len()).into());
+                entries.into_iter().for_each(|e| v.append(&mut e.into()));
+            }
+        }
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SyncMessage {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (msg, _) = Self::try_decode_from(bytes)?;
+        Ok(msg)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for SyncMessage {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        let (vlad, ptr) = Vlad::try_decode_from(bytes)?;
+        let (kind, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let (count, mut ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+        let payload = match *kind {
+            HAVE_MESSAGE => {
+                let mut cids = Vec::default();
+                for _ in 0..*count {
+                    let (cid, p) = Cid::try_decode_from(ptr)?;
+                    cids.push(cid);
+                    ptr = p;
+                }
+                SyncPayload::Have(cids)
+            }
+            WANT_MESSAGE => {
+                let mut ranges = Vec::default();
+                for _ in 0..*count {
+                    let (first, p) = Varuint::<u64>::try_decode_from(ptr)?;
+                    let (last, p) = Varuint::<u64>::try_decode_from(p)?;
+                    ranges.push((*first, *last));
+                    ptr = p;
+                }
+                SyncPayload::Want(ranges)
+            }
+            ENTRIES_MESSAGE => {
+                let mut entries = Vec::default();
+                for _ in 0..*count {
+                    let (entry, p) = Entry::try_decode_from(ptr)?;
+                    entries.push(entry);
+                    ptr = p;
+                }
+                SyncPayload::Entries(entries)
+            }
+            k => return Err(LogError::InvalidSyncMessage(k).into()),
+        };
+        Ok((Self { vlad, payload }, ptr))
+    }
+}
+
+/// A MissingRange is a run of consecutive seqnos absent from a Log along with
+/// the cids of the missing entries that are already known from the prev and
+/// lipmaa links of the entries that are present
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MissingRange {
+    /// the first missing seqno
+    pub first: u64,
+    /// the last missing seqno, inclusive
+    pub last: u64,
+    /// the known cids of missing entries in the range indexed by seqno
+    pub cids: BTreeMap<u64, Cid>,
+}
+
+impl Log {
+    /// Computes the seqno ranges that are absent between the foot, or the
+    /// checkpoint of a compacted log, and the claimed head. The cids of the
+    /// missing entries that are referenced by the prev and lipmaa links of
+    /// the entries that are present are included so a sync client can fetch
+    /// them directly by cid and request the rest by seqno.
+    pub fn missing_ranges(&self) -> Result<Vec<MissingRange>, Error> {
+        let head = self
+            .entries
+            .get(&self.head)
+            .ok_or(LogError::EntryNotFound(self.head.clone()))?;
+        let start = self.checkpoint.as_ref().map(|cp| cp.seqno() + 1).unwrap_or(0);
+
+        // gather the seqnos present and the cids linked to by the entries
+        let mut present = BTreeSet::new();
+        let mut known = BTreeMap::new();
+        for entry in self.entries.values() {
+            present.insert(entry.seqno());
+            if entry.seqno() > 0 && !entry.prev_ref().is_null() {
+                known.insert(entry.seqno() - 1, entry.prev());
+            }
+            if !entry.lipmaa.is_null() {
+                known.insert(entry.seqno().lipmaa(), entry.lipmaa.clone());
+            }
+        }
+
+        // group the missing seqnos into ranges
+        let mut ranges: Vec<MissingRange> = Vec::default();
+        for seqno in (start..head.seqno()).filter(|s| !present.contains(s)) {
+            match ranges.last_mut() {
+                Some(r) if r.last + 1 == seqno => r.last = seqno,
+                _ => ranges.push(MissingRange {
+                    first: seqno,
+                    last: seqno,
+                    ..Default::default()
+                }),
+            }
+            if let Some(cid) = known.get(&seqno) {
+                ranges.last_mut().unwrap().cids.insert(seqno, cid.clone());
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Get the entries after the entry with the cid up to the head, in seqno
+    /// order. These are the entries a replica whose head is the cid lacks.
+    /// A null cid gets every entry.
+    pub fn missing_since(&self, cid: &Cid) -> Result<Vec<Entry>, Error> {
+        if cid.is_null() {
+            return Ok(self.iter().cloned().collect());
+        }
+        if !self.entries.contains_key(cid) {
+            return Err(LogError::EntryNotFound(cid.clone()).into());
+        }
+        // walk the prev links back from the head to the cid
+        let mut entries = Vec::default();
+        let mut c = self.head.clone();
+        while c != *cid {
+            let entry = self.entries.get(&c).ok_or(LogError::BrokenPrevLink)?;
+            entries.push(entry.clone());
+            c = entry.prev();
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Get the have message announcing the head of the log
+    pub fn have(&self) -> SyncMessage {
+        SyncMessage {
+            vlad: self.vlad.clone(),
+            payload: SyncPayload::Have(vec![self.head.clone()]),
+        }
+    }
+
+    /// Get the want message asking for the seqno ranges missing from the log
+    pub fn want(&self) -> Result<SyncMessage, Error> {
+        Ok(SyncMessage {
+            vlad: self.vlad.clone(),
+            payload: SyncPayload::Want(
+                self.missing_ranges()?
+                    .iter()
+                    .map(|r| (r.first, r.last))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Get the response to a sync message from another replica. A have
+    /// message is answered with the entries after the first head we know
+    /// and a want message with the entries in the ranges. There is nothing
+    /// to answer if we don't know any of the heads or the message is a batch
+    /// of entries, which is applied with [`Log::apply_sync_batch`].
+    pub fn respond(&self, msg: &SyncMessage) -> Result<Option<SyncMessage>, Error> {
+        if msg.vlad != self.vlad {
+            return Err(LogError::WrongLog.into());
+        }
+        let entries = match &msg.payload {
+            SyncPayload::Have(cids) => match cids.iter().find(|c| self.entries.contains_key(c)) {
+                Some(cid) => self.missing_since(cid)?,
+                None => return Ok(None),
+            },
+            SyncPayload::Want(ranges) => self
+                .iter()
+                .filter(|e| ranges.iter().any(|(f, l)| (*f..=*l).contains(&e.seqno())))
+                .cloned()
+                .collect(),
+            SyncPayload::Entries(_) => return Ok(None),
+        };
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SyncMessage {
+            vlad: self.vlad.clone(),
+            payload: SyncPayload::Entries(entries),
+        }))
+    }
+
+    /// Verify and append the entries in a batch from another replica that
+    /// aren't already in the log and return how many were appended. The new
+    /// entries must continue from the head and if any of them fails to
+    /// verify none of them are appended.
+    pub fn apply_sync_batch(&mut self, batch: &[Entry]) -> Result<usize, Error> {
+        let mut entries: Vec<Entry> = batch
+            .iter()
+            .filter(|e| !self.entries.contains_key(&e.cid()))
+            .cloned()
+            .collect();
+        entries.sort_by_key(|e| e.seqno());
+        self.try_append_all(&entries)?;
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Script};
+
+    #[test]
+    fn test_missing_ranges() {
+        let mut entries = vec![entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap()];
+        for seqno in 1..10u64 {
+            let mut b = entry::Builder::from(entries.last().unwrap()).with_unlock(&Script::default());
+            if seqno.is_lipmaa() {
+                b = b.with_lipmaa(&entries[seqno.lipmaa() as usize].cid());
+            }
+            entries.push(b.try_build(|_| Ok(Vec::default())).unwrap());
+        }
+
+        // a log with every entry has nothing missing
+        let full = entries
+            .iter()
+            .fold(
+                log::Builder::new()
+                    .with_vlad(&Vlad::default())
+                    .with_first_lock(&Script::default()),
+                |b, e| b.append_entry(e),
+            )
+            .try_build()
+            .unwrap();
+        assert!(full.missing_ranges().unwrap().is_empty());
+
+        // a partially synced log with only 0, 4 and 9
+        let mut partial = full.clone();
+        let keep: Vec<Cid> = [0usize, 4, 9].iter().map(|i| entries[*i].cid()).collect();
+        partial.entries.retain(|cid, _| keep.contains(cid));
+
+        let ranges = partial.missing_ranges().unwrap();
+        assert_eq!(2, ranges.len());
+        assert_eq!((1, 3), (ranges[0].first, ranges[0].last));
+        assert_eq!((5, 8), (ranges[1].first, ranges[1].last));
+
+        // 4 links to 3 and has a lipmaa link to 1, 9 links to 8
+        assert_eq!(
+            BTreeMap::from([(1, entries[1].cid()), (3, entries[3].cid())]),
+            ranges[0].cids
+        );
+        assert_eq!(BTreeMap::from([(8, entries[8].cid())]), ranges[1].cids);
+    }
+
+    #[test]
+    fn test_sync_messages() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3)
+            .try_build()
+            .unwrap();
+
+        assert_eq!(vec![e2.clone(), e3.clone()], log.missing_since(&e1.cid()).unwrap());
+        assert!(log.missing_since(&e3.cid()).unwrap().is_empty());
+        assert_eq!(3, log.missing_since(&Cid::null()).unwrap().len());
+
+        // a replica with the first entry gets the rest
+        let have = SyncMessage {
+            vlad: Vlad::default(),
+            payload: SyncPayload::Have(vec![e1.cid()]),
+        };
+        let bytes: Vec<u8> = have.clone().into();
+        assert_eq!(have, SyncMessage::try_from(bytes.as_slice()).unwrap());
+        let resp = log.respond(&have).unwrap().unwrap();
+        assert_eq!(SyncPayload::Entries(vec![e2.clone(), e3.clone()]), resp.payload);
+        let bytes: Vec<u8> = resp.clone().into();
+        assert_eq!(resp, SyncMessage::try_from(bytes.as_slice()).unwrap());
+
+        // wanted ranges are answered by seqno
+        let want = SyncMessage {
+            vlad: Vlad::default(),
+            payload: SyncPayload::Want(vec![(1, 1)]),
+        };
+        let bytes: Vec<u8> = want.clone().into();
+        assert_eq!(want, SyncMessage::try_from(bytes.as_slice()).unwrap());
+        let resp = log.respond(&want).unwrap().unwrap();
+        assert_eq!(SyncPayload::Entries(vec![e2.clone()]), resp.payload);
+
+        // an up to date replica gets nothing
+        assert_eq!(None, log.respond(&log.have()).unwrap());
+    }
+}
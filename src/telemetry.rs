@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Metrics emitted from the verify and append paths. With the `metrics`
+//! feature the values are recorded through the [metrics] facade so whatever
+//! recorder the application installs, e.g. a prometheus exporter, picks them
+//! up. Without it the calls compile to nothing.
+//!
+//! [metrics]: https://docs.rs/metrics
+use std::time::Duration;
+
+/// counter of the entries that verified
+pub const ENTRIES_VERIFIED: &str = "plog_entries_verified_total";
+
+/// counter of the entries that failed to verify
+pub const ENTRIES_FAILED: &str = "plog_entries_failed_total";
+
+/// histogram of the seconds spent running a script, labeled with the stage
+/// that ran it
+pub const SCRIPT_SECONDS: &str = "plog_script_seconds";
+
+/// histogram of the number of pairs in the kvp after an entry is applied
+pub const KVP_SIZE: &str = "plog_kvp_size";
+
+/// counter of the entries appended to logs
+pub const ENTRIES_APPENDED: &str = "plog_entries_appended_total";
+
+/// histogram of the seconds spent appending entries to a log
+pub const APPEND_SECONDS: &str = "plog_append_seconds";
+
+/// record an entry that verified leaving the kvp with the number of pairs
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn entry_verified(kvp_size: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(ENTRIES_VERIFIED).increment(1);
+        metrics::histogram!(KVP_SIZE).record(kvp_size as f64);
+    }
+}
+
+/// record an entry that failed to verify
+pub(crate) fn entry_failed() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ENTRIES_FAILED).increment(1);
+}
+
+/// record the time a stage spent running a script
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn script_ran(stage: &'static str, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!(SCRIPT_SECONDS, "stage" => stage).record(elapsed.as_secs_f64());
+}
+
+/// record entries appended to a log and the time it took
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn appended(count: usize, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(ENTRIES_APPENDED).increment(count as u64);
+        metrics::histogram!(APPEND_SECONDS).record(elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{error::LogError, Error, Key, Log, Op, Value};
+use core::fmt;
+use multicid::Cid;
+use multitrait::{Null, TryDecodeFrom};
+use multiutil::{Varbytes, Varuint};
+
+/// the reserved branch under which time anchors are recorded
+pub const TIME_ANCHORS_BRANCH: &str = "/time/";
+
+/// A TimeAnchor is an externally verifiable statement about the current time,
+/// such as an NTS signed time response or a blockchain block hash, that is
+/// recorded in the log under the reserved "/time/" branch. Embedding an anchor
+/// in an entry proves the entry was created after the anchor time. If the
+/// external source attested to an existing entry (e.g. the NTS request nonce
+/// was the entry cid) then the anchor also proves that entry, and every entry
+/// before it, existed before the anchor time.
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct TimeAnchor {
+    /// the kind of external source, e.g. "nts" or "btc"
+    pub source: String,
+    /// the time asserted by the anchor in seconds since the unix epoch
+    pub timestamp: u64,
+    /// the cid of the entry the external source attested to, null if the
+    /// anchor doesn't commit to any entry in the log
+    pub attests: Cid,
+    /// the source specific proof data (e.g. the signed response or the header)
+    pub proof: Vec<u8>,
+}
+
+impl TimeAnchor {
+    /// get the op that records this anchor under the reserved branch with the
+    /// given name
+    pub fn to_op<S: AsRef<str>>(&self, name: S) -> Result<Op, Error> {
+        let mut key = Key::try_from(TIME_ANCHORS_BRANCH)?;
+        key.push(format!("/{}", Key::escape_segment(name)))?;
+        Ok(Op::Update(key, Value::Data(self.clone().into())))
+    }
+
+    /// try to get the time anchor recorded by an op, returns None if the op
+    /// isn't an update under the reserved branch
+    pub fn from_op(op: &Op) -> Option<Result<Self, Error>> {
+        let branch = Key::try_from(TIME_ANCHORS_BRANCH).ok()?;
+        match op {
+            Op::Update(key, Value::Data(b)) if branch.parent_of(key) => {
+                Some(Self::try_from(b.as_slice()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<TimeAnchor> for Vec<u8> {
+    fn from(val: TimeAnchor) -> Self {
+        let mut v = Vec::default();
+        // add in the source
+        v.append(&mut Varbytes(val.source.as_bytes().to_vec()).into());
+        // add in the timestamp
+        v.append(&mut Varuint(val.timestamp).into());
+        // add in the attested cid
+        v.append(&mut val.attests.clone().into());
+        // add in the proof
+        v.append(&mut Varbytes(val.proof.clone()).into());
+        v
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for TimeAnchor {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Error> {
+        let (anchor, _) = Self::try_decode_from(bytes)?;
+        Ok(anchor)
+    }
+}
+
+impl<'a> TryDecodeFrom<'a> for TimeAnchor {
+    type Error = Error;
+
+    fn try_decode_from(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), Self::Error> {
+        // decode the source
+        let (source, ptr) = Varbytes::try_decode_from(bytes)?;
+        let source = String::from_utf8(source.to_inner())?;
+        // decode the timestamp
+        let (timestamp, ptr) = Varuint::<u64>::try_decode_from(ptr)?;
+        let timestamp = timestamp.to_inner();
+        // decode the attested cid
+        let (attests, ptr) = Cid::try_decode_from(ptr)?;
+        // decode the proof
+        let (proof, ptr) = Varbytes::try_decode_from(ptr)?;
+        let proof = proof.to_inner();
+        Ok((
+            Self {
+                source,
+                timestamp,
+                attests,
+                proof,
+            },
+            ptr,
+        ))
+    }
+}
+
+impl fmt::Debug for TimeAnchor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {:?} - proof of length: {}",
+            self.source,
+            self.timestamp,
+            self.attests,
+            self.proof.len()
+        )
+    }
+}
+
+/// The bounds on when an entry was created as inferred from the time anchors
+/// recorded in the log
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TimestampBounds {
+    /// the entry was created after this time
+    pub not_before: Option<u64>,
+    /// the entry was created before this time
+    pub not_after: Option<u64>,
+}
+
+impl Log {
+    /// get every time anchor in the log along with the seqno of the entry it
+    /// was recorded in
+    pub fn time_anchors(&self) -> Result<Vec<(u64, TimeAnchor)>, Error> {
+        let mut anchors = Vec::default();
+        for entry in self.iter() {
+            for op in entry.ops() {
+                if let Some(anchor) = TimeAnchor::from_op(op) {
+                    anchors.push((entry.seqno(), anchor?));
+                }
+            }
+        }
+        Ok(anchors)
+    }
+
+    /// infer the bounds on the creation time of the entry with the given cid
+    /// using the time anchors in the log. the `verify` closure is called for
+    /// each anchor to check its source specific proof and anchors that fail
+    /// are ignored.
+    pub fn timestamp_bounds<F>(&self, cid: &Cid, mut verify: F) -> Result<TimestampBounds, Error>
+    where
+        F: FnMut(&TimeAnchor) -> bool,
+    {
+        let seqno = self
+            .entries
+            .get(cid)
+            .ok_or(LogError::EntryNotFound(cid.clone()))?
+            .seqno();
+
+        let mut bounds = TimestampBounds::default();
+        for (anchor_seqno, anchor) in self.time_anchors()? {
+            if !verify(&anchor) {
+                continue;
+            }
+
+            // the anchor was recorded in or before the entry so the entry was
+            // created after the anchor time
+            if anchor_seqno <= seqno {
+                bounds.not_before = bounds.not_before.max(Some(anchor.timestamp));
+            }
+
+            // the anchor attests to the entry or a later one so the entry
+            // existed before the anchor time
+            if !anchor.attests.is_null() {
+                if let Some(attested) = self.entries.get(&anchor.attests) {
+                    if attested.seqno() >= seqno {
+                        bounds.not_after = match bounds.not_after {
+                            Some(t) => Some(t.min(anchor.timestamp)),
+                            None => Some(anchor.timestamp),
+                        };
+                    }
+                }
+            }
+        }
+        Ok(bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_anchor_roundtrip() {
+        let anchor = TimeAnchor {
+            source: "nts".to_string(),
+            timestamp: 1_700_000_000,
+            attests: Cid::null(),
+            proof: b"signed time".to_vec(),
+        };
+        let v: Vec<u8> = anchor.clone().into();
+        assert_eq!(anchor, TimeAnchor::try_from(v.as_slice()).unwrap());
+
+        let op = anchor.to_op("pool.ntp.org").unwrap();
+        assert_eq!(op.path().to_string(), "/time/pool.ntp.org".to_string());
+        assert_eq!(anchor, TimeAnchor::from_op(&op).unwrap().unwrap());
+        assert!(TimeAnchor::from_op(&Op::default()).is_none());
+    }
+
+    #[test]
+    fn test_timestamp_bounds() {
+        let a1 = TimeAnchor {
+            source: "btc".to_string(),
+            timestamp: 100,
+            ..Default::default()
+        };
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&a1.to_op("btc").unwrap())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        // an anchor over the first entry
+        let a2 = TimeAnchor {
+            source: "nts".to_string(),
+            timestamp: 200,
+            attests: e1.cid(),
+            ..Default::default()
+        };
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .add_op(&a2.to_op("nts").unwrap())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let e3 = entry::Builder::from(&e2)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .append_entry(&e3)
+            .try_build()
+            .unwrap();
+
+        assert_eq!(2, log.time_anchors().unwrap().len());
+        assert_eq!(
+            log.timestamp_bounds(&e1.cid(), |_| true).unwrap(),
+            TimestampBounds {
+                not_before: Some(100),
+                not_after: Some(200),
+            }
+        );
+        assert_eq!(
+            log.timestamp_bounds(&e3.cid(), |_| true).unwrap(),
+            TimestampBounds {
+                not_before: Some(200),
+                not_after: None,
+            }
+        );
+        // ignore the nts anchor
+        assert_eq!(
+            log.timestamp_bounds(&e1.cid(), |a| a.source != "nts").unwrap(),
+            TimestampBounds {
+                not_before: Some(100),
+                not_after: None,
+            }
+        );
+    }
+}
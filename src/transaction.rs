@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Staging the changes for the next entry as if the log were a key-value
+//! store. An [`EntryTransaction`] starts from the verified state at the head
+//! of a log, reads back its own writes and turns them into the ops of an
+//! [`entry::Builder`] for the next entry when it is committed.
+use crate::{entry, op, Error, Key, Kvp, Log, Op, Value};
+use std::collections::BTreeMap;
+
+/// The changes staged for the entry after the head of a log
+pub struct EntryTransaction<'a> {
+    log: &'a Log,
+    base: Kvp,
+    state: BTreeMap<Key, Value>,
+}
+
+impl<'a> EntryTransaction<'a> {
+    /// start a transaction on top of the state at the head of the log
+    pub fn new(log: &'a Log) -> Result<Self, Error> {
+        Ok(Self::with_state(log, &log.head_kvp()?))
+    }
+
+    /// start a transaction on top of a state at the head of the log the
+    /// caller already has, e.g. the kvp from the last entry of
+    /// [`Log::verify_with_engine`], so the log isn't verified again
+    pub fn with_state(log: &'a Log, kvp: &Kvp) -> Self {
+        let state = kvp.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        Self {
+            log,
+            base: kvp.clone(),
+            state,
+        }
+    }
+
+    /// get the value at the key including the staged changes
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.state.get(key)
+    }
+
+    /// stage setting the key to the value, returns the value it had
+    pub fn set(&mut self, key: &Key, value: &Value) -> Option<Value> {
+        self.state.insert(key.clone(), value.clone())
+    }
+
+    /// stage deleting the key, returns the value it had
+    pub fn delete(&mut self, key: &Key) -> Option<Value> {
+        self.state.remove(key)
+    }
+
+    /// drop the staged changes
+    pub fn rollback(&mut self) {
+        self.state = self.base.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    }
+
+    /// get the ops that make the staged changes, see [`op::diff`]. changes
+    /// that were undone again before committing don't get ops.
+    pub fn ops(&self) -> Vec<Op> {
+        op::diff(&self.base, &self.state)
+    }
+
+    /// true if the staged changes don't change the state
+    pub fn is_empty(&self) -> bool {
+        self.ops().is_empty()
+    }
+
+    /// get the builder for the entry after the head of the log with the ops
+    /// that make the staged changes. the unlock script and any lock script
+    /// changes are added to it before it is built and signed.
+    pub fn commit(self) -> entry::Builder {
+        self.ops()
+            .iter()
+            .fold(entry::Builder::default().with_log(self.log), |b, op| b.add_op(op))
+    }
+}
+
+impl Log {
+    /// start staging the changes for the next entry
+    pub fn transaction(&self) -> Result<EntryTransaction<'_>, Error> {
+        EntryTransaction::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{log, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_transaction() {
+        let key = |s: &str| -> Key { s.try_into().unwrap() };
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(key("/a"), Value::Str("a".to_string())))
+            .add_op(&Op::Update(key("/b"), Value::Str("b".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        // the state at the head
+        let mut kvp = Kvp::default();
+        let _ = kvp.set_entry(&e1).unwrap();
+        kvp.apply_entry_ops(&e1).unwrap();
+
+        let mut tx = EntryTransaction::with_state(&log, &kvp);
+        assert_eq!(Some(&Value::Str("a".to_string())), tx.get(&key("/a")));
+        assert!(tx.is_empty());
+
+        // the writes are read back right away
+        tx.set(&key("/c"), &Value::Str("c".to_string()));
+        assert_eq!(Some(&Value::Str("c".to_string())), tx.get(&key("/c")));
+        tx.delete(&key("/a"));
+        assert_eq!(None, tx.get(&key("/a")));
+        // a change that is undone again doesn't get an op
+        tx.set(&key("/b"), &Value::Str("bb".to_string()));
+        tx.set(&key("/b"), &Value::Str("b".to_string()));
+        assert_eq!(
+            vec![Op::Delete(key("/a")), Op::Update(key("/c"), Value::Str("c".to_string()))],
+            tx.ops()
+        );
+
+        let e2 = tx
+            .commit()
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert_eq!(e1.cid(), e2.prev());
+        assert_eq!(1, e2.seqno());
+        assert_eq!(2, e2.ops().count());
+
+        let mut tx = EntryTransaction::with_state(&log, &kvp);
+        tx.delete(&key("/b"));
+        tx.rollback();
+        assert!(tx.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Descriptors of what the common transports can carry so an entry can be
+//! checked before it is handed to a channel that can't deliver it.
+use crate::{error::EntryError, Entry, Error, Op, ScriptId, ValueId};
+
+/// The limits of a transport
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Profile {
+    /// the name of the transport
+    pub name: &'static str,
+    /// the largest encoded entry the transport carries, None if unlimited
+    pub max_entry_bytes: Option<usize>,
+    /// the kinds of values the transport carries
+    pub value_ids: &'static [ValueId],
+    /// the kinds of scripts the transport carries
+    pub script_ids: &'static [ScriptId],
+}
+
+const ALL_VALUES: &[ValueId] = &[
+    ValueId::Nil,
+    ValueId::Str,
+    ValueId::Data,
+    ValueId::Encrypted,
+];
+const ALL_SCRIPTS: &[ScriptId] = &[ScriptId::Bin, ScriptId::Code, ScriptId::Cid];
+
+/// a version 40 QR code with low error correction in byte mode. inline
+/// binary scripts rarely fit so only code and cid scripts are allowed.
+pub const QR: Profile = Profile {
+    name: "qr",
+    max_entry_bytes: Some(2953),
+    value_ids: ALL_VALUES,
+    script_ids: &[ScriptId::Code, ScriptId::Cid],
+};
+
+/// an NTAG216 NFC tag, scripts must be referenced by cid
+pub const NFC: Profile = Profile {
+    name: "nfc",
+    max_entry_bytes: Some(888),
+    value_ids: ALL_VALUES,
+    script_ids: &[ScriptId::Cid],
+};
+
+/// a gossipsub message with the default maximum transmit size
+pub const GOSSIP: Profile = Profile {
+    name: "gossip",
+    max_entry_bytes: Some(1024 * 1024),
+    value_ids: ALL_VALUES,
+    script_ids: ALL_SCRIPTS,
+};
+
+/// an HTTP request body, limited only by the server
+pub const HTTP: Profile = Profile {
+    name: "http",
+    max_entry_bytes: None,
+    value_ids: ALL_VALUES,
+    script_ids: ALL_SCRIPTS,
+};
+
+impl Profile {
+    /// check that the entry can be carried by the transport
+    pub fn check(&self, entry: &Entry) -> Result<(), Error> {
+        let violation = |reason: String| -> Error {
+            EntryError::ProfileViolation(format!("{}: {}", self.name, reason)).into()
+        };
+        if let Some(max) = self.max_entry_bytes {
+            let len = entry.canonical_bytes().len();
+            if len > max {
+                return Err(violation(format!("{} bytes is over the {} byte limit", len, max)));
+            }
+        }
+        for op in entry.ops() {
+            if let Op::Update(k, v) = op {
+                let id = ValueId::from(v);
+                if !self.value_ids.contains(&id) {
+                    return Err(violation(format!("{} values are not allowed at {}", id.as_str(), k)));
+                }
+            }
+        }
+        for script in entry.locks().chain(std::iter::once(&entry.unlock)) {
+            let id = ScriptId::from(script);
+            if !self.script_ids.contains(&id) {
+                return Err(violation(format!(
+                    "{} scripts are not allowed at {}",
+                    id.as_str(),
+                    script.path_ref()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// check that the entry can be carried by the transport
+    pub fn check_profile(&self, profile: &Profile) -> Result<(), Error> {
+        profile.check(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, Key, Script, Value};
+    use multicid::{Cid, Vlad};
+    use multitrait::Null;
+
+    #[test]
+    fn test_check_profile() {
+        let small = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::Cid(Key::default(), Cid::null()))
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Str("bar".to_string())))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        for profile in [&QR, &NFC, &GOSSIP, &HTTP] {
+            assert!(small.check_profile(profile).is_ok());
+        }
+
+        let big = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::Code(Key::default(), "(module)".to_string()))
+            .add_op(&Op::Update("/foo".try_into().unwrap(), Value::Data(vec![0; 4096])))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert!(big.check_profile(&QR).is_err());
+        assert!(big.check_profile(&GOSSIP).is_ok());
+        assert!(big.check_profile(&HTTP).is_ok());
+
+        // code scripts can't go over nfc
+        let code = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::Code(Key::default(), "(module)".to_string()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        assert!(code.check_profile(&QR).is_ok());
+        assert!(code.check_profile(&NFC).is_err());
+    }
+}
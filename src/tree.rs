@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: FSL-1.1
+//! A tree view of the virtual namespace in a [`Kvp`] so it can be rendered
+//! as nested branches without re-parsing the key-paths.
+use crate::{Key, Kvp, Value};
+use std::collections::BTreeMap;
+
+/// A branch in the namespace with the branches and leaves under it
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Node {
+    /// the key-path of the branch
+    pub branch: Key,
+    /// the value stored at the branch key-path itself, if any
+    pub value: Option<Value>,
+    /// the branches under this one by segment name
+    pub children: BTreeMap<String, Node>,
+    /// the values of the leaves under this branch by segment name
+    pub leaves: BTreeMap<String, Value>,
+}
+
+impl Node {
+    /// create an empty node for the branch
+    pub fn new(branch: &Key) -> Self {
+        Self {
+            branch: branch.clone(),
+            value: None,
+            children: BTreeMap::default(),
+            leaves: BTreeMap::default(),
+        }
+    }
+
+    /// true if there is nothing stored at or under the branch
+    pub fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty() && self.leaves.is_empty()
+    }
+
+    /// get the node for the branch under this one, None if there is nothing
+    /// stored under it
+    pub fn get(&self, branch: &Key) -> Option<&Node> {
+        let depth = self.branch.segments().len();
+        branch
+            .segments()
+            .get(depth..)?
+            .iter()
+            .try_fold(self, |node, segment| node.children.get(segment))
+    }
+
+    // insert the value at the key given as segments below this node
+    fn insert(&mut self, segments: &[String], is_leaf: bool, value: &Value) {
+        match segments {
+            [] => self.value = Some(value.clone()),
+            [name] if is_leaf => {
+                self.leaves.insert(name.clone(), value.clone());
+            }
+            [name, rest @ ..] => {
+                let mut path = self.branch.segments();
+                path.push(name.clone());
+                // the segments come from a valid key
+                let branch = Key::from_segments(&path, true).unwrap_or_default();
+                self.children
+                    .entry(name.clone())
+                    .or_insert_with(|| Node::new(&branch))
+                    .insert(rest, is_leaf, value);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+impl TryFrom<&Node> for serde_json::Value {
+    type Error = serde_json::Error;
+
+    fn try_from(node: &Node) -> Result<Self, Self::Error> {
+        serde_json::to_value(node)
+    }
+}
+
+impl Kvp {
+    /// get the key-value pairs as a tree of the branches in the namespace
+    pub fn tree(&self) -> Node {
+        let mut root = Node::new(&Key::default());
+        for (key, value) in self.iter() {
+            root.insert(&key.segments(), key.is_leaf(), value);
+        }
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree() {
+        let key = |s: &str| -> Key { s.try_into().unwrap() };
+        let kvp = Kvp::from(BTreeMap::from([
+            (key("/name"), Value::Str("plog".to_string())),
+            (key("/keys/"), Value::Nil),
+            (key("/keys/pubkey"), Value::Data(vec![1, 2, 3])),
+            (key("/keys/backup/one"), Value::Data(vec![4, 5, 6])),
+        ]));
+        let root = kvp.tree();
+        assert_eq!(key("/"), root.branch);
+        assert_eq!(1, root.leaves.len());
+        assert_eq!(1, root.children.len());
+
+        let keys = root.get(&key("/keys/")).unwrap();
+        assert_eq!(key("/keys/"), keys.branch);
+        assert_eq!(Some(Value::Nil), keys.value);
+        assert_eq!(Some(&Value::Data(vec![1, 2, 3])), keys.leaves.get("pubkey"));
+
+        let backup = root.get(&key("/keys/backup/")).unwrap();
+        assert_eq!(key("/keys/backup/"), backup.branch);
+        assert!(backup.children.is_empty());
+        assert_eq!(1, backup.leaves.len());
+        assert!(root.get(&key("/nope/")).is_none());
+        assert!(Kvp::default().tree().is_empty());
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    #[test]
+    fn test_tree_json() {
+        let kvp = Kvp::from(BTreeMap::from([(
+            Key::try_from("/keys/pubkey").unwrap(),
+            Value::Str("foo".to_string()),
+        )]));
+        let json = serde_json::Value::try_from(&kvp.tree()).unwrap();
+        assert_eq!("/keys/", json["children"]["keys"]["branch"]);
+        assert!(!json["children"]["keys"]["leaves"]["pubkey"].is_null());
+    }
+}
@@ -1,9 +1,11 @@
 // SPDX-License-Identifier: FSL-1.1
-use crate::{error::ValueError, Error};
+use crate::{error::ValueError, seal::Recipient, Error, Key, SafeBytes};
 use core::fmt;
 use multibase::Base;
+use multicid::{Cid, EncodedCid, Vlad};
+use multicodec::Codec;
 use multitrait::{EncodeInto, TryDecodeFrom};
-use multiutil::{EncodingInfo, Varbytes};
+use multiutil::{EncodingInfo, Varbytes, Varuint};
 
 /// the identifiers for the operations performed on the namespace in each entry
 #[repr(u8)]
@@ -16,6 +18,10 @@ pub enum ValueId {
     Str,
     /// binary data value
     Data,
+    /// encrypted value
+    Encrypted,
+    /// reference to a value in another log
+    PlogRef,
 }
 
 impl ValueId {
@@ -30,6 +36,8 @@ impl ValueId {
             Self::Nil => "nil",
             Self::Str => "str",
             Self::Data => "data",
+            Self::Encrypted => "encrypted",
+            Self::PlogRef => "plogref",
         }
     }
 }
@@ -46,6 +54,8 @@ impl From<&Value> for ValueId {
             Value::Nil => Self::Nil,
             Value::Str(_) => Self::Str,
             Value::Data(_) => Self::Data,
+            Value::Encrypted { .. } => Self::Encrypted,
+            Value::PlogRef { .. } => Self::PlogRef,
         }
     }
 }
@@ -58,6 +68,8 @@ impl TryFrom<u8> for ValueId {
             0 => Ok(Self::Nil),
             1 => Ok(Self::Str),
             2 => Ok(Self::Data),
+            3 => Ok(Self::Encrypted),
+            4 => Ok(Self::PlogRef),
             _ => Err(ValueError::InvalidValueId(c).into()),
         }
     }
@@ -96,6 +108,8 @@ impl TryFrom<&str> for ValueId {
             "nil" => Ok(Self::Nil),
             "str" => Ok(Self::Str),
             "data" => Ok(Self::Data),
+            "encrypted" => Ok(Self::Encrypted),
+            "plogref" => Ok(Self::PlogRef),
             _ => Err(ValueError::InvalidValueName(s.to_string()).into()),
         }
     }
@@ -107,8 +121,9 @@ impl fmt::Debug for ValueId {
     }
 }
 
-/// A Value is either a printable string or a binary blob. These are the values
-/// stored in the virtual namespace of the log.
+/// A Value is either a printable string, a binary blob, an encrypted value or
+/// a reference to a value in another log. These are the values stored in the
+/// virtual namespace of the log.
 #[derive(Clone, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Value {
     /// An empty value
@@ -118,6 +133,25 @@ pub enum Value {
     Str(String),
     /// A binary blob value
     Data(Vec<u8>),
+    /// A value sealed for a set of recipients, see [`Value::seal`]
+    Encrypted {
+        /// the encryption scheme
+        scheme: Codec,
+        /// the content key wrapped for each recipient
+        recipients: Vec<Recipient>,
+        /// the encrypted value
+        ciphertext: Vec<u8>,
+    },
+    /// A reference to the value at a key in the state of another log just
+    /// after one of its entries, see [`crate::resolver::verify_plog_ref`]
+    PlogRef {
+        /// the vlad of the referenced log
+        vlad: Vlad,
+        /// the cid of the entry in the referenced log
+        entry_cid: Cid,
+        /// the key of the value in the referenced log
+        key: Key,
+    },
 }
 
 impl EncodingInfo for Value {
@@ -138,6 +172,9 @@ impl AsRef<[u8]> for Value {
             Value::Nil => &[],
             Value::Str(s) => s.as_ref(),
             Value::Data(b) => b.as_ref(),
+            Value::Encrypted { ciphertext, .. } => ciphertext.as_ref(),
+            // the referenced value isn't stored here, only its key
+            Value::PlogRef { key, .. } => key.as_str().as_bytes(),
         }
     }
 }
@@ -159,6 +196,33 @@ impl From<Value> for Vec<u8> {
                 v.append(&mut Varbytes(b.clone()).into());
                 v
             }
+            Value::Encrypted {
+                scheme,
+                recipients,
+                ciphertext,
+            } => {
+                // add in the scheme
+                v.append(&mut scheme.into());
+                // add in the recipients
+                v.append(&mut Varuint(recipients.len()).into());
+                recipients
+                    .into_iter()
+                    .for_each(|r| v.append(&mut r.into()));
+                // add in the ciphertext
+                v.append(&mut Varbytes(ciphertext).into());
+                v
+            }
+            Value::PlogRef {
+                vlad,
+                entry_cid,
+                key,
+            } => {
+                // add in the vlad, entry cid and key
+                v.append(&mut vlad.into());
+                v.append(&mut entry_cid.into());
+                v.append(&mut key.into());
+                v
+            }
         }
     }
 }
@@ -189,6 +253,38 @@ impl<'a> TryDecodeFrom<'a> for Value {
                 let (b, ptr) = Varbytes::try_decode_from(ptr)?;
                 (Self::Data(b.to_inner()), ptr)
             }
+            ValueId::Encrypted => {
+                let (scheme, ptr) = Codec::try_decode_from(ptr)?;
+                let (count, mut ptr) = Varuint::<usize>::try_decode_from(ptr)?;
+                let mut recipients = Vec::default();
+                for _ in 0..count.to_inner() {
+                    let (r, p) = Recipient::try_decode_from(ptr)?;
+                    recipients.push(r);
+                    ptr = p;
+                }
+                let (ciphertext, ptr) = Varbytes::try_decode_from(ptr)?;
+                (
+                    Self::Encrypted {
+                        scheme,
+                        recipients,
+                        ciphertext: ciphertext.to_inner(),
+                    },
+                    ptr,
+                )
+            }
+            ValueId::PlogRef => {
+                let (vlad, ptr) = Vlad::try_decode_from(ptr)?;
+                let (entry_cid, ptr) = Cid::try_decode_from(ptr)?;
+                let (key, ptr) = Key::try_decode_from(ptr)?;
+                (
+                    Self::PlogRef {
+                        vlad,
+                        entry_cid,
+                        key,
+                    },
+                    ptr,
+                )
+            }
         };
         Ok((v, ptr))
     }
@@ -200,7 +296,45 @@ impl fmt::Debug for Value {
         match self {
             Self::Nil => write!(f, "{:?}", id),
             Self::Str(s) => write!(f, "{:?} - \"{}\"", id, s),
-            Self::Data(b) => write!(f, "{:?} - {:x?}", id, b.clone()),
+            Self::Data(b) => write!(f, "{:?} - {}", id, SafeBytes(b)),
+            Self::Encrypted {
+                scheme,
+                recipients,
+                ciphertext,
+            } => write!(
+                f,
+                "{:?} - {:?} for {} recipients - {}",
+                id,
+                scheme,
+                recipients.len(),
+                SafeBytes(ciphertext)
+            ),
+            Self::PlogRef { entry_cid, key, .. } => write!(
+                f,
+                "{:?} - '{}' at {}",
+                id,
+                key,
+                EncodedCid::new(Base::Base32Lower, entry_cid.clone())
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Str(s) => write!(f, "{}", s),
+            Self::Data(b) => write!(f, "{}", SafeBytes(b)),
+            Self::Encrypted { recipients, .. } => {
+                write!(f, "encrypted for {} recipients", recipients.len())
+            }
+            Self::PlogRef { entry_cid, key, .. } => write!(
+                f,
+                "'{}' at {}",
+                key,
+                EncodedCid::new(Base::Base32Lower, entry_cid.clone())
+            ),
         }
     }
 }
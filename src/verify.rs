@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{error::VerifyPhase, Kvp, Script};
+use multicid::Cid;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// A CancelToken is shared between a verification and the code that wants
+/// to stop it. Cancelling is cooperative, the verification checks the token
+/// between stages and between lock scripts and stops with
+/// [`crate::Error::Cancelled`] reporting the entry that was in progress.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// create a new token that isn't cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// cancel every verification using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// true if the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The configuration for verifying a Log
+#[derive(Clone, Debug, Default)]
+pub struct VerifyConfig {
+    /// the token checked for cancellation
+    pub cancel: Option<CancelToken>,
+    /// the wall-clock time each entry may take to verify
+    pub entry_budget: Option<Duration>,
+    /// the time by which the whole verification must be done
+    pub deadline: Option<Instant>,
+    /// the fuel the scripts run for each entry may consume
+    pub fuel: Option<u64>,
+}
+
+impl VerifyConfig {
+    /// create a config with no cancellation token, time budget, deadline or
+    /// fuel budget
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// set the token checked for cancellation
+    pub fn with_cancel_token(mut self, token: &CancelToken) -> Self {
+        self.cancel = Some(token.clone());
+        self
+    }
+
+    /// set the wall-clock time each entry may take to verify. it is checked
+    /// between scripts so a single long running script isn't interrupted.
+    pub fn with_entry_budget(mut self, budget: Duration) -> Self {
+        self.entry_budget = Some(budget);
+        self
+    }
+
+    /// set the time by which the whole verification must be done. like the
+    /// entry budget it is checked between scripts.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// set the fuel the scripts run for each entry may consume. unlike the
+    /// time budget this is deterministic, every verifier fails the same
+    /// entries, and it also stops a single long running script. it needs an
+    /// engine that meters scripts, like the `InterpEngine` of the
+    /// `wasm-interp` feature. engines that can't, like [`crate::WaccEngine`],
+    /// fail every script with [`crate::error::ScriptError::Unmetered`].
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+}
+
+/// The summary of verifying every entry in a Log
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    /// the number of entries verified
+    pub entries: usize,
+    /// the check count from the lock script that succeeded, by seqno
+    pub counts: BTreeMap<u64, usize>,
+    /// the fuel consumed by the scripts run for each entry, by seqno
+    pub fuel: BTreeMap<u64, u64>,
+    /// the kvp state after the last entry
+    pub kvp: Kvp,
+    /// the lock scripts that govern the next entry
+    pub locks: Vec<Script>,
+    /// the cid of the last verified entry, null if there are none
+    pub head: Cid,
+    /// the time spent in each verification phase across all of the entries
+    pub phase_times: HashMap<VerifyPhase, Duration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry, error::ScriptError, log, observer::NoopObserver, pipeline, EntryPoints, Error, Key,
+        Pipeline, Script, ScriptEngine, Stk, WaccEngine,
+    };
+    use multicid::Vlad;
+    use wacc::{vm, Pairs};
+
+    #[test]
+    fn test_cancel() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let e2 = entry::Builder::from(&e1)
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .append_entry(&e2)
+            .try_build()
+            .unwrap();
+        let stages = || {
+            Pipeline::new()
+                .with_stage(pipeline::Structure)
+                .with_stage(pipeline::Apply)
+        };
+
+        // a generous budget doesn't get in the way
+        let token = CancelToken::new();
+        let config = VerifyConfig::new()
+            .with_cancel_token(&token)
+            .with_entry_budget(Duration::from_secs(60));
+        let mut iter =
+            log.verify_with_config(stages(), &WaccEngine, &NoopObserver, config.clone());
+        assert!(iter.next().unwrap().is_ok());
+
+        // cancelling stops at the entry in progress
+        token.cancel();
+        assert!(matches!(iter.next(), Some(Err(Error::Cancelled(1)))));
+        assert!(iter.next().is_none());
+
+        // the token stays cancelled for new verifications
+        let mut iter = log.verify_with_config(stages(), &WaccEngine, &NoopObserver, config);
+        assert!(matches!(iter.next(), Some(Err(Error::Cancelled(0)))));
+
+        // a deadline that has passed stops at the first entry
+        let config = VerifyConfig::new().with_deadline(Instant::now());
+        let mut iter = log.verify_with_config(stages(), &WaccEngine, &NoopObserver, config);
+        assert!(matches!(iter.next(), Some(Err(Error::DeadlineExceeded(0)))));
+        assert!(iter.next().is_none());
+    }
+
+    // consumes 10 fuel for every script it runs and passes every lock
+    struct Metered;
+
+    impl ScriptEngine for Metered {
+        fn run(
+            &self,
+            _script: &Script,
+            func: &str,
+            _current: &dyn Pairs,
+            _proposed: &dyn Pairs,
+            _pstack: &mut Stk,
+            rstack: &mut Stk,
+            _context: &Key,
+        ) -> Result<(), Error> {
+            if func == "lock" {
+                rstack.push(vm::Value::Success(0));
+            }
+            Ok(())
+        }
+
+        fn run_metered(
+            &self,
+            script: &Script,
+            func: &str,
+            current: &dyn Pairs,
+            proposed: &dyn Pairs,
+            pstack: &mut Stk,
+            rstack: &mut Stk,
+            context: &Key,
+            _log: &mut Vec<String>,
+            fuel: Option<u64>,
+        ) -> Result<u64, Error> {
+            if fuel.map(|f| f < 10).unwrap_or(false) {
+                return Err(ScriptError::OutOfFuel(10, fuel.unwrap()).into());
+            }
+            self.run(script, func, current, proposed, pstack, rstack, context)?;
+            Ok(10)
+        }
+    }
+
+    #[test]
+    fn test_fuel() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .with_entry_points(&EntryPoints::new("unlock", "lock"))
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        // the unlock and the lock script each consume 10
+        let config = VerifyConfig::new().with_fuel(20);
+        let mut iter = log.verify_with_config(Pipeline::default(), &Metered, &NoopObserver, config);
+        assert!(iter.next().unwrap().is_ok());
+
+        let config = VerifyConfig::new().with_fuel(15);
+        let mut iter = log.verify_with_config(Pipeline::default(), &Metered, &NoopObserver, config);
+        match iter.next() {
+            Some(Err(Error::Verify(e))) => {
+                assert!(matches!(*e.source, Error::Script(ScriptError::OutOfFuel(10, 5))))
+            }
+            _ => panic!("expected the entry to run out of fuel"),
+        }
+    }
+}
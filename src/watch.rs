@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: FSL-1.1
+use crate::{Entry, Key, Log, Op, Value};
+use core::fmt;
+use multicid::Cid;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A KvpEvent is sent to the subscribers of a key pattern when an entry that
+/// touches a matching key is committed to the log
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KvpEvent {
+    /// the key was created or updated with the value
+    Updated {
+        /// the seqno of the entry
+        seqno: u64,
+        /// the cid of the entry
+        entry: Cid,
+        /// the key that was updated
+        key: Key,
+        /// the new value
+        value: Value,
+    },
+    /// the key was deleted
+    Deleted {
+        /// the seqno of the entry
+        seqno: u64,
+        /// the cid of the entry
+        entry: Cid,
+        /// the key that was deleted
+        key: Key,
+    },
+}
+
+impl KvpEvent {
+    /// get the key the event is about
+    pub fn key(&self) -> &Key {
+        match self {
+            Self::Updated { key, .. } => key,
+            Self::Deleted { key, .. } => key,
+        }
+    }
+}
+
+/// The subscribers to changes in a Log. Subscriptions belong to a single Log
+/// instance so they are not cloned along with the Log and are ignored when
+/// comparing Logs.
+#[derive(Default)]
+pub struct Subscribers {
+    subs: Vec<(Key, Sender<KvpEvent>)>,
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for Subscribers {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "subscribers: {}", self.subs.len())
+    }
+}
+
+impl Subscribers {
+    /// send the events for the ops in the entry to the subscribers with a
+    /// matching pattern, dropping the subscribers that have gone away
+    fn notify(&mut self, entry: &Entry) {
+        let cid = entry.cid();
+        let events: Vec<KvpEvent> = entry
+            .ops()
+            .filter_map(|op| match op {
+                Op::Update(key, value) => Some(KvpEvent::Updated {
+                    seqno: entry.seqno(),
+                    entry: cid.clone(),
+                    key: key.clone(),
+                    value: value.clone(),
+                }),
+                Op::Delete(key) => Some(KvpEvent::Deleted {
+                    seqno: entry.seqno(),
+                    entry: cid.clone(),
+                    key: key.clone(),
+                }),
+                Op::Noop(_) => None,
+            })
+            .collect();
+
+        self.subs.retain(|(pattern, tx)| {
+            events
+                .iter()
+                .filter(|e| pattern.parent_of(e.key()))
+                .all(|e| tx.send(e.clone()).is_ok())
+        });
+    }
+}
+
+impl Log {
+    /// Subscribe to the changes to the keys matching the pattern. A leaf key
+    /// matches only itself and a branch key matches every key under it. An
+    /// event is sent for every matching op in each entry committed by
+    /// [`Log::try_append`]. Dropping the receiver ends the subscription.
+    pub fn subscribe(&mut self, pattern: &Key) -> Receiver<KvpEvent> {
+        let (tx, rx) = channel();
+        self.subscribers.subs.push((pattern.clone(), tx));
+        rx
+    }
+
+    /// send the events for a committed entry to the subscribers
+    pub(crate) fn notify(&mut self, entry: &Entry) {
+        self.subscribers.notify(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, Script};
+    use multicid::Vlad;
+
+    #[test]
+    fn test_subscribe() {
+        let mut log = Log::default();
+        let keys = log.subscribe(&Key::try_from("/keys/").unwrap());
+        let pubkey = log.subscribe(&Key::try_from("/pubkey").unwrap());
+        let dropped = log.subscribe(&Key::try_from("/").unwrap());
+        drop(dropped);
+
+        let entry = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .add_op(&Op::Update(
+                "/keys/a".try_into().unwrap(),
+                Value::Str("a".to_string()),
+            ))
+            .add_op(&Op::Delete("/keys/b".try_into().unwrap()))
+            .add_op(&Op::Noop("/pubkey".try_into().unwrap()))
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        log.notify(&entry);
+
+        let events: Vec<KvpEvent> = keys.try_iter().collect();
+        assert_eq!(2, events.len());
+        assert_eq!(
+            KvpEvent::Deleted {
+                seqno: 0,
+                entry: entry.cid(),
+                key: "/keys/b".try_into().unwrap(),
+            },
+            events[1]
+        );
+        assert!(pubkey.try_iter().next().is_none());
+        // the dropped subscriber was removed
+        assert_eq!(2, log.subscribers.subs.len());
+        // subscriptions aren't cloned
+        assert_eq!(0, log.clone().subscribers.subs.len());
+    }
+}
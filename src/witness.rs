@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: FSL-1.1
+//! Third-party witness co-signatures over entries. Witnesses sign the encoded
+//! cid of an entry they have seen and the signatures are kept in a side-table
+//! of the [`Log`] so the entries and their cids don't change. A
+//! [`WitnessPolicy`] decides whether enough trusted witnesses have signed an
+//! entry before it is accepted as a head.
+use crate::{error::LogError, Error, Log};
+use multicid::Cid;
+use multikey::{Multikey, Views};
+use multisig::Multisig;
+use std::collections::BTreeMap;
+
+/// The witness signatures by entry cid. Like the seqno index it isn't part
+/// of the encoded log and is ignored when comparing Logs.
+#[derive(Clone, Default)]
+pub(crate) struct Witnesses(BTreeMap<Cid, Vec<Multisig>>);
+
+impl PartialEq for Witnesses {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// get the message a witness signs for the entry with the cid
+pub fn witness_message(cid: &Cid) -> Vec<u8> {
+    cid.clone().into()
+}
+
+/// A WitnessPolicy requires signatures from a number of trusted witnesses
+#[derive(Clone, Default)]
+pub struct WitnessPolicy {
+    threshold: usize,
+    keys: Vec<Multikey>,
+}
+
+impl WitnessPolicy {
+    /// create a policy requiring the given number of witnesses
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            keys: Vec::default(),
+        }
+    }
+
+    /// trust the witness with the public key
+    pub fn with_key(mut self, key: &Multikey) -> Self {
+        self.keys.push(key.clone());
+        self
+    }
+
+    /// count the trusted witnesses with a valid signature over the entry.
+    /// each witness is only counted once no matter how many times it signed.
+    pub fn count(&self, log: &Log, cid: &Cid) -> usize {
+        let msg = witness_message(cid);
+        self.keys
+            .iter()
+            .filter(|key| {
+                let verify = match key.verify_view() {
+                    Ok(v) => v,
+                    Err(_) => return false,
+                };
+                log.witnesses(cid)
+                    .iter()
+                    .any(|ms| verify.verify(ms, Some(&msg)).is_ok())
+            })
+            .count()
+    }
+
+    /// check that enough trusted witnesses signed the entry
+    pub fn check(&self, log: &Log, cid: &Cid) -> Result<(), Error> {
+        let count = self.count(log, cid);
+        if count < self.threshold {
+            return Err(LogError::NotEnoughWitnesses(count, self.threshold).into());
+        }
+        Ok(())
+    }
+}
+
+impl Log {
+    /// add a witness signature over the entry with the cid. the signature
+    /// isn't checked here, see [`WitnessPolicy`]. returns false if the log
+    /// already had the signature.
+    pub fn add_witness(&mut self, cid: &Cid, sig: Multisig) -> Result<bool, Error> {
+        if !self.entries.contains_key(cid) {
+            return Err(LogError::EntryNotFound(cid.clone()).into());
+        }
+        let sigs = self.witnesses.0.entry(cid.clone()).or_default();
+        if sigs.contains(&sig) {
+            return Ok(false);
+        }
+        sigs.push(sig);
+        Ok(true)
+    }
+
+    /// get the witness signatures over the entry with the cid
+    pub fn witnesses(&self, cid: &Cid) -> &[Multisig] {
+        self.witnesses.0.get(cid).map(|s| s.as_slice()).unwrap_or_default()
+    }
+
+    /// check the head of the log against the witness policy
+    pub fn check_head_witnesses(&self, policy: &WitnessPolicy) -> Result<(), Error> {
+        policy.check(self, &self.head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entry, log, Entry, Script};
+    use multicid::Vlad;
+    use multikey::EncodedMultikey;
+
+    fn witness_key(s: &str) -> Multikey {
+        EncodedMultikey::try_from(s).unwrap().to_inner()
+    }
+
+    fn sign(key: &Multikey, entry: &Entry) -> Multisig {
+        key.sign_view()
+            .unwrap()
+            .sign(&witness_message(&entry.cid()), false, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_witnesses() {
+        let e1 = entry::Builder::default()
+            .with_vlad(&Vlad::default())
+            .with_unlock(&Script::default())
+            .try_build(|_| Ok(Vec::default()))
+            .unwrap();
+        let mut log = log::Builder::new()
+            .with_vlad(&Vlad::default())
+            .with_first_lock(&Script::default())
+            .append_entry(&e1)
+            .try_build()
+            .unwrap();
+
+        let w1 = witness_key(
+            "fba2480260874657374206b6579010120cbd87095dc5863fcec46a66a1d4040a73cb329f92615e165096bd50541ee71c0",
+        );
+        let w2 = witness_key(
+            "fba2480260874657374206b6579010120d784f92e18bdba433b8b0f6cbf140bc9629ff607a59997357b40d22c3883a3b8",
+        );
+        let w3 = witness_key(
+            "fba2480260874657374206b65790101203f4c94407de791e53b4df12ef1d5534d1b19ff2ccfccba4ccc4722b6e5e8ea07",
+        );
+        assert!(log.add_witness(&e1.cid(), sign(&w1, &e1)).unwrap());
+        assert!(!log.add_witness(&e1.cid(), sign(&w1, &e1)).unwrap());
+        assert!(log.add_witness(&e1.cid(), sign(&w3, &e1)).unwrap());
+        assert_eq!(2, log.witnesses(&e1.cid()).len());
+        assert!(log.add_witness(&Cid::default(), sign(&w1, &e1)).is_err());
+
+        // only the signatures of trusted witnesses count
+        let policy = WitnessPolicy::new(2).with_key(&w1).with_key(&w2);
+        assert_eq!(1, policy.count(&log, &e1.cid()));
+        assert!(log.check_head_witnesses(&policy).is_err());
+
+        log.add_witness(&e1.cid(), sign(&w2, &e1)).unwrap();
+        log.check_head_witnesses(&policy).unwrap();
+    }
+}